@@ -0,0 +1,315 @@
+// adjust.rs - Image adjustments applied before compression
+
+use image::{imageops, ColorType, DynamicImage, Rgba};
+
+// Removes uniform-color borders (within a per-channel tolerance). The border
+// color must run along all four edges before anything is cropped, so flat
+// content that merely touches one edge is left alone. Returns None when there
+// is nothing to trim.
+pub fn trim_borders(image: &DynamicImage, tolerance: u8) -> Option<DynamicImage> {
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    if width < 3 || height < 3 {
+        return None;
+    }
+    
+    let border = *rgba.get_pixel(0, 0);
+    let matches = |pixel: &Rgba<u8>| {
+        pixel.0.iter()
+            .zip(border.0.iter())
+            .all(|(a, b)| a.abs_diff(*b) <= tolerance)
+    };
+    let row_uniform = |y: u32, left: u32, right: u32| (left..right).all(|x| matches(rgba.get_pixel(x, y)));
+    let column_uniform = |x: u32, top: u32, bottom: u32| (top..bottom).all(|y| matches(rgba.get_pixel(x, y)));
+    
+    if !(row_uniform(0, 0, width)
+        && row_uniform(height - 1, 0, width)
+        && column_uniform(0, 0, height)
+        && column_uniform(width - 1, 0, height))
+    {
+        return None;
+    }
+    
+    let mut top = 0;
+    while top < height && row_uniform(top, 0, width) {
+        top += 1;
+    }
+    if top == height {
+        // The whole image is one flat color
+        return None;
+    }
+    
+    let mut bottom = height;
+    while bottom > top && row_uniform(bottom - 1, 0, width) {
+        bottom -= 1;
+    }
+    
+    let mut left = 0;
+    while left < width && column_uniform(left, top, bottom) {
+        left += 1;
+    }
+    
+    let mut right = width;
+    while right > left && column_uniform(right - 1, top, bottom) {
+        right -= 1;
+    }
+    
+    Some(image.crop_imm(left, top, right - left, bottom - top))
+}
+
+// Share of pixels auto_levels lets clip at each end of the range, so a few specks of
+// dust or a specular highlight don't hold the stretch back
+const LEVELS_CLIP: f64 = 0.005;
+// Ranges narrower than this are left alone instead of being blown up into noise,
+// and ranges at least LEVELS_FULL_RANGE wide are already well exposed
+const LEVELS_MIN_RANGE: u8 = 32;
+const LEVELS_FULL_RANGE: u8 = 250;
+
+// Stretches the histogram so its 0.5th and 99.5th percentiles become black and
+// white. On luminance by default, which keeps the colors' balance; per channel
+// also takes out a color cast. A well-exposed image already spans the range and
+// comes back as None, as does a flat one. Transparent pixels aren't counted.
+pub fn auto_levels(image: &DynamicImage, per_channel: bool) -> Option<DynamicImage> {
+    let mut rgba = image.to_rgba8();
+    let mut histograms = [[0u64; 256]; 4];
+    for pixel in rgba.pixels().filter(|pixel| pixel.0[3] > 0) {
+        let [r, g, b, _] = pixel.0;
+        histograms[0][r as usize] += 1;
+        histograms[1][g as usize] += 1;
+        histograms[2][b as usize] += 1;
+        histograms[3][luma(r, g, b) as usize] += 1;
+    }
+    
+    let ranges: Vec<(u8, u8)> = if per_channel {
+        histograms[..3].iter().map(percentile_range).collect()
+    } else {
+        vec![percentile_range(&histograms[3]); 3]
+    };
+    let stretches = |&(low, high): &(u8, u8)| (LEVELS_MIN_RANGE..LEVELS_FULL_RANGE).contains(&(high - low));
+    if !ranges.iter().any(stretches) {
+        return None;
+    }
+    
+    let tables: Vec<[u8; 256]> = ranges.iter().map(|&(low, high)| {
+        let mut table = [0u8; 256];
+        for (value, level) in table.iter_mut().enumerate() {
+            *level = if !stretches(&(low, high)) {
+                value as u8
+            } else {
+                ((value as f32 - low as f32) * 255.0 / (high - low) as f32).round().clamp(0.0, 255.0) as u8
+            };
+        }
+        table
+    }).collect();
+    for pixel in rgba.pixels_mut() {
+        for channel in 0..3 {
+            pixel.0[channel] = tables[channel][pixel.0[channel] as usize];
+        }
+    }
+    
+    if image.color().has_alpha() {
+        Some(DynamicImage::ImageRgba8(rgba))
+    } else {
+        Some(DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).to_rgb8()))
+    }
+}
+
+// The values with LEVELS_CLIP of the counted pixels below and above them
+fn percentile_range(histogram: &[u64; 256]) -> (u8, u8) {
+    let total: u64 = histogram.iter().sum();
+    let clipped = (total as f64 * LEVELS_CLIP) as u64;
+    let low = first_past(histogram, 0..256, clipped);
+    let high = first_past(histogram, (0..256).rev(), clipped);
+    (low, high.max(low))
+}
+
+fn first_past(histogram: &[u64; 256], mut values: impl Iterator<Item = usize>, clipped: u64) -> u8 {
+    let mut seen = 0;
+    values.find(|&value| {
+        seen += histogram[value];
+        seen > clipped
+    }).unwrap_or(0) as u8
+}
+
+// BT.601 luma, on 0-255
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32).round() as u8
+}
+
+// A region in pixels of the image as decoded, before it is rotated, trimmed or resized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RedactStyle {
+    #[default]
+    Pixelate,
+    Blur,
+}
+
+// Pixelates or blurs each region, clipped to the image, strongly enough that text
+// inside can't be read: mosaic blocks are an eighth of the region's shorter side
+// and the blur sigma a quarter of it, neither under 8px. Returns the image and how
+// many regions overlapped it, or None when none did.
+pub fn redact(image: &DynamicImage, regions: &[Rect], style: RedactStyle) -> Option<(DynamicImage, usize)> {
+    let mut rgba = image.to_rgba8();
+    let (image_width, image_height) = rgba.dimensions();
+    let mut redacted = 0;
+    
+    for region in regions {
+        let x = region.x.min(image_width);
+        let y = region.y.min(image_height);
+        let width = region.width.min(image_width - x);
+        let height = region.height.min(image_height - y);
+        if width == 0 || height == 0 {
+            continue;
+        }
+        
+        let patch = imageops::crop_imm(&rgba, x, y, width, height).to_image();
+        let shorter = width.min(height);
+        let patch = match style {
+            RedactStyle::Pixelate => {
+                let block = (shorter / 8).max(8);
+                let blocks = imageops::resize(&patch, width.div_ceil(block), height.div_ceil(block), imageops::FilterType::Triangle);
+                imageops::resize(&blocks, width, height, imageops::FilterType::Nearest)
+            }
+            RedactStyle::Blur => imageops::blur(&patch, (shorter as f32 / 4.0).max(8.0)),
+        };
+        imageops::replace(&mut rgba, &patch, x as i64, y as i64);
+        redacted += 1;
+    }
+    
+    if redacted == 0 {
+        return None;
+    }
+    let redacted_image = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(rgba)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).to_rgb8())
+    };
+    Some((redacted_image, redacted))
+}
+
+// Unsharp mask: pushes each color channel away from a Gaussian blur of the
+// image by `amount` (1.0 = 100%), with `radius` as the blur sigma. Alpha is
+// left as is. Works on floats, so the result keeps the source's color type and
+// a 16-bit image keeps its precision.
+pub fn unsharp_mask(image: &DynamicImage, radius: f32, amount: f32) -> DynamicImage {
+    let mut sharpened = image.to_rgba32f();
+    let blurred = imageops::blur(&sharpened, radius);
+    
+    for (pixel, soft) in sharpened.pixels_mut().zip(blurred.pixels()) {
+        for channel in 0..3 {
+            let value = pixel.0[channel];
+            let detail = value - soft.0[channel];
+            pixel.0[channel] = (value + detail * amount).clamp(0.0, 1.0);
+        }
+    }
+    
+    to_color_type(DynamicImage::ImageRgba32F(sharpened), image.color())
+}
+
+// Lanczos3 resize in linear light: sRGB values are decoded to linear floats before
+// filtering and encoded again after. More accurate than resizing the sRGB values
+// directly, which darkens fine high-contrast detail (a black/white checkerboard
+// averages to 128 there instead of the 188 that is half the light). The result
+// keeps the source's color type.
+pub fn resize_linear(image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    let mut linear = image.to_rgba32f();
+    for pixel in linear.pixels_mut() {
+        for channel in 0..3 {
+            pixel.0[channel] = srgb_to_linear(pixel.0[channel]);
+        }
+    }
+    
+    let mut resized = imageops::resize(&linear, width, height, imageops::FilterType::Lanczos3);
+    for pixel in resized.pixels_mut() {
+        for channel in 0..3 {
+            pixel.0[channel] = linear_to_srgb(pixel.0[channel].clamp(0.0, 1.0));
+        }
+        pixel.0[3] = pixel.0[3].clamp(0.0, 1.0);
+    }
+    
+    to_color_type(DynamicImage::ImageRgba32F(resized), image.color())
+}
+
+// Converts a float working copy back to the color type it was made from
+fn to_color_type(image: DynamicImage, color: ColorType) -> DynamicImage {
+    match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(image.to_luma8()),
+        ColorType::La8 => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        ColorType::L16 => DynamicImage::ImageLuma16(image.to_luma16()),
+        ColorType::La16 => DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+        ColorType::Rgb16 => DynamicImage::ImageRgb16(image.to_rgb16()),
+        ColorType::Rgba16 => DynamicImage::ImageRgba16(image.to_rgba16()),
+        ColorType::Rgb32F => DynamicImage::ImageRgb32F(image.to_rgb32f()),
+        ColorType::Rgba32F => DynamicImage::ImageRgba32F(image.to_rgba32f()),
+        color if color.has_alpha() => DynamicImage::ImageRgba8(image.to_rgba8()),
+        _ => DynamicImage::ImageRgb8(image.to_rgb8()),
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb, RgbImage};
+    
+    // Two flat halves, darker on the left
+    fn step_edge() -> RgbImage {
+        RgbImage::from_fn(16, 8, |x, _| if x < 8 { Rgb([80, 80, 80]) } else { Rgb([170, 170, 170]) })
+    }
+    
+    #[test]
+    fn unsharp_mask_adds_contrast_at_edges() {
+        let sharpened = unsharp_mask(&DynamicImage::ImageRgb8(step_edge()), 1.0, 1.0).to_rgb8();
+        assert!(sharpened.get_pixel(7, 4)[0] < 80, "dark side of the edge got darker");
+        assert!(sharpened.get_pixel(8, 4)[0] > 170, "light side of the edge got lighter");
+    }
+    
+    #[test]
+    fn linear_resize_keeps_a_checkerboard_at_half_the_light() {
+        let checkerboard = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+            if (x + y) % 2 == 0 { Rgb([255, 255, 255]) } else { Rgb([0, 0, 0]) }
+        }));
+        let mean = |image: &DynamicImage| {
+            let gray = image.to_luma8();
+            gray.pixels().map(|pixel| pixel[0] as f64).sum::<f64>() / gray.pixels().len() as f64
+        };
+        
+        let naive = checkerboard.resize_exact(16, 16, imageops::FilterType::Lanczos3);
+        let linear = resize_linear(&checkerboard, 16, 16);
+        assert!((mean(&naive) - 128.0).abs() < 4.0, "naive mean {}", mean(&naive));
+        assert!((mean(&linear) - 188.0).abs() < 4.0, "linear mean {}", mean(&linear));
+        assert_eq!(linear.color(), ColorType::Rgb8);
+    }
+    
+    #[test]
+    fn unsharp_mask_keeps_the_color_type() {
+        let deep = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(8, 8, Rgb([1000u16, 2000, 3000])));
+        assert_eq!(unsharp_mask(&deep, 1.0, 1.0).color(), ColorType::Rgb16);
+        let gray = DynamicImage::ImageRgb8(step_edge()).grayscale();
+        assert_eq!(unsharp_mask(&gray, 1.0, 1.0).color(), ColorType::L8);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,260 @@
+// animation.rs - Multi-frame (animated) image decoding, resizing and encoding
+
+use image::codecs::gif::GifDecoder;
+use image::imageops::FilterType;
+use image::{AnimationDecoder, RgbaImage};
+use std::io::Cursor;
+#[cfg(feature = "webp")]
+use libwebp_sys::*;
+#[cfg(feature = "webp")]
+use webp::{AnimDecoder, BitstreamFeatures};
+
+pub struct AnimationFrame {
+    pub image: RgbaImage,
+    pub duration_ms: i32,
+}
+
+pub struct Animation {
+    pub width: u32,
+    pub height: u32,
+    pub loop_count: u32,
+    pub frames: Vec<AnimationFrame>,
+}
+
+#[cfg(feature = "webp")]
+pub fn is_animated_webp(data: &[u8]) -> bool {
+    BitstreamFeatures::new(data)
+        .map(|features| features.has_animation())
+        .unwrap_or(false)
+}
+
+#[cfg(feature = "webp")]
+pub fn decode_webp_animation(data: &[u8]) -> Result<Animation, Box<dyn std::error::Error>> {
+    let decoded = AnimDecoder::new(data).decode()?;
+    
+    let mut frames = Vec::with_capacity(decoded.len());
+    let mut previous_timestamp = 0;
+    let (mut width, mut height) = (0, 0);
+    
+    // libwebp reports the end timestamp of each frame, so durations are the deltas
+    for frame in &decoded {
+        width = frame.width();
+        height = frame.height();
+        let image = RgbaImage::from_raw(width, height, frame.get_image().to_vec())
+            .ok_or("Invalid animation frame data")?;
+        frames.push(AnimationFrame {
+            image,
+            duration_ms: frame.get_time_ms() - previous_timestamp,
+        });
+        previous_timestamp = frame.get_time_ms();
+    }
+    
+    if frames.is_empty() {
+        return Err("Animation contains no frames".into());
+    }
+    
+    Ok(Animation {
+        width,
+        height,
+        loop_count: decoded.loop_count,
+        frames,
+    })
+}
+
+// Without libwebp, animated WebPs are read like stills (their first frame) and
+// nothing can be written as animated WebP
+#[cfg(not(feature = "webp"))]
+pub fn is_animated_webp(_data: &[u8]) -> bool {
+    false
+}
+
+#[cfg(not(feature = "webp"))]
+pub fn decode_webp_animation(_data: &[u8]) -> Result<Animation, Box<dyn std::error::Error>> {
+    Err("WebP isn't built in".into())
+}
+
+pub fn decode_gif_animation(data: &[u8]) -> Result<Animation, Box<dyn std::error::Error>> {
+    let decoder = GifDecoder::new(Cursor::new(data))?;
+    
+    // The decoder yields frames already composited onto the full canvas
+    let mut frames = Vec::new();
+    for frame in decoder.into_frames() {
+        let frame = frame?;
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        frames.push(AnimationFrame {
+            duration_ms: (numerator / denominator.max(1)) as i32,
+            image: frame.into_buffer(),
+        });
+    }
+    
+    let (width, height) = match frames.first() {
+        Some(frame) => frame.image.dimensions(),
+        None => return Err("Animation contains no frames".into()),
+    };
+    
+    Ok(Animation {
+        width,
+        height,
+        loop_count: gif_loop_count(data),
+        frames,
+    })
+}
+
+// GIF stores extra repetitions in the NETSCAPE2.0 block (0 = forever, absent =
+// play once), while WebP stores the total number of plays (0 = forever)
+fn gif_loop_count(data: &[u8]) -> u32 {
+    const NETSCAPE: &[u8] = b"NETSCAPE2.0";
+    
+    let position = data.windows(NETSCAPE.len()).position(|window| window == NETSCAPE);
+    match position.and_then(|pos| data.get(pos + NETSCAPE.len()..pos + NETSCAPE.len() + 4)) {
+        Some(&[3, 1, low, high]) => match u16::from_le_bytes([low, high]) {
+            0 => 0,
+            repetitions => repetitions as u32 + 1,
+        },
+        _ => 1,
+    }
+}
+
+pub fn resize_animation(animation: Animation, dimensions: Option<(u32, u32)>, maintain_ratio: bool) -> Animation {
+    let (target_width, target_height) = match dimensions {
+        Some((width, height)) if maintain_ratio => fit_dimensions(animation.width, animation.height, width, height),
+        Some((width, height)) => (width.max(1), height.max(1)),
+        None => return animation,
+    };
+    
+    // Every frame shares the canvas size, so all frames get the same exact target
+    let frames = animation.frames
+        .into_iter()
+        .map(|frame| AnimationFrame {
+            image: image::imageops::resize(&frame.image, target_width, target_height, FilterType::Lanczos3),
+            duration_ms: frame.duration_ms,
+        })
+        .collect();
+    
+    Animation {
+        width: target_width,
+        height: target_height,
+        loop_count: animation.loop_count,
+        frames,
+    }
+}
+
+#[cfg(feature = "webp")]
+pub fn encode_webp_animation(
+    animation: &Animation,
+    quality: f32,
+    lossless: bool,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut config = WebPConfig::new().map_err(|_| "Failed to initialize WebP config")?;
+    config.quality = quality;
+    config.lossless = lossless as i32;
+    
+    unsafe {
+        let mut anim_options: WebPAnimEncoderOptions = std::mem::zeroed();
+        if WebPAnimEncoderOptionsInitInternal(&mut anim_options, WebPGetMuxABIVersion()) == 0 {
+            return Err("Failed to initialize WebP animation options".into());
+        }
+        anim_options.anim_params.loop_count = animation.loop_count as i32;
+        
+        let encoder = WebPAnimEncoderNewInternal(
+            animation.width as i32,
+            animation.height as i32,
+            &anim_options,
+            WebPGetMuxABIVersion(),
+        );
+        if encoder.is_null() {
+            return Err("Failed to create WebP animation encoder".into());
+        }
+        
+        // Frames are added at their start timestamp; the final NULL frame marks
+        // the end time so the last frame keeps its original duration
+        let mut timestamp = 0;
+        for frame in &animation.frames {
+            let mut picture = match WebPPicture::new() {
+                Ok(picture) => picture,
+                Err(_) => {
+                    WebPAnimEncoderDelete(encoder);
+                    return Err("Failed to initialize WebP picture".into());
+                }
+            };
+            picture.use_argb = 1;
+            picture.width = animation.width as i32;
+            picture.height = animation.height as i32;
+            
+            let imported = WebPPictureImportRGBA(
+                &mut picture,
+                frame.image.as_raw().as_ptr(),
+                (animation.width * 4) as i32,
+            );
+            let added = imported != 0 && WebPAnimEncoderAdd(encoder, &mut picture, timestamp, &config) != 0;
+            WebPPictureFree(&mut picture);
+            
+            if !added {
+                WebPAnimEncoderDelete(encoder);
+                return Err("Failed to encode WebP animation frame".into());
+            }
+            timestamp += frame.duration_ms;
+        }
+        WebPAnimEncoderAdd(encoder, std::ptr::null_mut(), timestamp, std::ptr::null());
+        
+        let mut webp_data: WebPData = std::mem::zeroed();
+        WebPDataInit(&mut webp_data);
+        let assembled = WebPAnimEncoderAssemble(encoder, &mut webp_data) != 0;
+        WebPAnimEncoderDelete(encoder);
+        
+        if !assembled {
+            return Err("Failed to assemble WebP animation".into());
+        }
+        
+        let data = std::slice::from_raw_parts(webp_data.bytes, webp_data.size).to_vec();
+        WebPDataClear(&mut webp_data);
+        Ok(data)
+    }
+}
+
+#[cfg(not(feature = "webp"))]
+pub fn encode_webp_animation(
+    _animation: &Animation,
+    _quality: f32,
+    _lossless: bool,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Err("WebP isn't built in".into())
+}
+
+// Resizes every frame of an animated WebP and re-encodes it, keeping frame
+// timing and loop count. Returns the encoded bytes and the frame count.
+pub fn process_animated_webp(
+    data: &[u8],
+    dimensions: Option<(u32, u32)>,
+    maintain_ratio: bool,
+    quality: u8,
+    lossless: bool,
+) -> Result<(Vec<u8>, usize), Box<dyn std::error::Error>> {
+    let animation = decode_webp_animation(data)?;
+    let animation = resize_animation(animation, dimensions, maintain_ratio);
+    let encoded = encode_webp_animation(&animation, quality as f32, lossless)?;
+    Ok((encoded, animation.frames.len()))
+}
+
+// Converts a (possibly animated) GIF into an animated WebP with the same frames,
+// timing and loop behavior. Returns the encoded bytes and the frame count.
+pub fn process_gif_to_webp(
+    data: &[u8],
+    dimensions: Option<(u32, u32)>,
+    maintain_ratio: bool,
+    quality: u8,
+    lossless: bool,
+) -> Result<(Vec<u8>, usize), Box<dyn std::error::Error>> {
+    let animation = decode_gif_animation(data)?;
+    let animation = resize_animation(animation, dimensions, maintain_ratio);
+    let encoded = encode_webp_animation(&animation, quality as f32, lossless)?;
+    Ok((encoded, animation.frames.len()))
+}
+
+// Largest size that fits inside the bounding box while keeping the aspect ratio
+fn fit_dimensions(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    let ratio = (max_width as f64 / width as f64).min(max_height as f64 / height as f64);
+    let new_width = (width as f64 * ratio).round().max(1.0) as u32;
+    let new_height = (height as f64 * ratio).round().max(1.0) as u32;
+    (new_width, new_height)
+}
@@ -0,0 +1,135 @@
+// archive.rs - Collecting a batch's outputs into one ZIP file
+//
+// Entries are streamed into the archive as each file finishes, so only the file
+// being written is held in memory. With atomic writes the archive is built under
+// a ".tmp" name, in the scratch folder when there is one, and only takes its real
+// name once it's complete.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+// Already-compressed formats gain nothing from deflating them again
+const STORED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "avif", "gif"];
+
+struct Archive {
+    writer: Option<ZipWriter<File>>,
+    names: HashSet<String>,
+}
+
+// Shared by all of a batch's file tasks, so one instance is created per Process run
+pub struct ZipOutput {
+    path: PathBuf,
+    temp_path: Option<PathBuf>,
+    // Entries keep their folder below this root; without one the archive is flat
+    root: Option<PathBuf>,
+    archive: Mutex<Archive>,
+}
+
+impl ZipOutput {
+    pub fn create(path: &Path, root: Option<PathBuf>, atomic: bool, scratch_dir: Option<&Path>) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = atomic.then(|| match scratch_dir {
+            Some(dir) => crate::scratch_path(dir, path),
+            None => crate::sibling_temp_path(path),
+        });
+        let file = File::create(temp_path.as_deref().unwrap_or(path))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            temp_path,
+            root,
+            archive: Mutex::new(Archive {
+                writer: Some(ZipWriter::new(file)),
+                names: HashSet::new(),
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Adds one output under the input's folder (relative to the root) and returns the
+    // entry name. A name already in the archive gets a "-2", "-3", ... suffix.
+    pub fn add(&self, input_path: &Path, file_name: &str, data: &[u8]) -> io::Result<String> {
+        let mut archive = self.archive.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let name = unique_name(&archive.names, &self.entry_name(input_path, file_name));
+        let writer = archive
+            .writer
+            .as_mut()
+            .ok_or_else(|| io::Error::other("the ZIP archive is already closed"))?;
+
+        let stored = Path::new(file_name)
+            .extension()
+            .is_some_and(|extension| STORED_EXTENSIONS.iter().any(|stored| extension.eq_ignore_ascii_case(stored)));
+        let method = if stored { CompressionMethod::Stored } else { CompressionMethod::Deflated };
+        let entry_options = SimpleFileOptions::default()
+            .compression_method(method)
+            .large_file(data.len() as u64 >= u32::MAX as u64);
+        writer.start_file(name.as_str(), entry_options)?;
+        writer.write_all(data)?;
+
+        archive.names.insert(name.clone());
+        Ok(name)
+    }
+
+    // Writes the central directory; entries added afterwards fail. Finishing twice
+    // is a no-op.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut archive = self.archive.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(writer) = archive.writer.take() else {
+            return Ok(());
+        };
+        writer.finish()?.sync_all()?;
+        if let Some(temp_path) = &self.temp_path {
+            crate::move_into_place(temp_path, &self.path)?;
+        }
+        Ok(())
+    }
+
+    fn entry_name(&self, input_path: &Path, file_name: &str) -> String {
+        let folder = self
+            .root
+            .as_deref()
+            .and_then(|root| input_path.parent()?.strip_prefix(root).ok())
+            .map(|relative| {
+                relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        folder.into_iter().chain([file_name.to_string()]).collect::<Vec<_>>().join("/")
+    }
+}
+
+fn unique_name(taken: &HashSet<String>, name: &str) -> String {
+    if !taken.contains(name) {
+        return name.to_string();
+    }
+    let (stem, extension) = match name.rfind('.').filter(|dot| !name[*dot..].contains('/')) {
+        Some(dot) => name.split_at(dot),
+        None => (name, ""),
+    };
+    (2..)
+        .map(|number| format!("{}-{}{}", stem, number, extension))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap_or_default()
+}
+
+impl std::fmt::Debug for ZipOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZipOutput")
+            .field("path", &self.path)
+            .field("root", &self.root)
+            .finish_non_exhaustive()
+    }
+}
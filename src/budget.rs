@@ -0,0 +1,71 @@
+// budget.rs - One size budget shared by a whole batch
+//
+// A first pass weighs every file, then the budget is split in proportion to the
+// weights and each image gets its share as its own target size. Files copied
+// through unchanged keep their size, so that comes off the budget first.
+
+use crate::compression::SmartCompressor;
+use crate::open_image;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Complexity is measured on a copy this size; the full image only adds time
+const ANALYSIS_EDGE: u32 = 512;
+
+// Weight of one image. Equal shares weigh everything 1.0; by detail, the weight is
+// megapixels × (1 + mean neighbour difference), so a large textured photo gets many
+// times the share of a small flat graphic. Unreadable images fall back to 1.0 and
+// fail later in the real pass.
+pub fn image_weight(path: &Path, compressor: &SmartCompressor, by_detail: bool, max_megapixels: Option<f32>) -> f64 {
+    if !by_detail {
+        return 1.0;
+    }
+    let image = match open_image(path, max_megapixels) {
+        Ok(image) => image,
+        Err(_) => return 1.0,
+    };
+
+    let megapixels = image.width() as f64 * image.height() as f64 / 1_000_000.0;
+    let analysis = compressor.analyze_image(&image.thumbnail(ANALYSIS_EDGE, ANALYSIS_EDGE));
+    megapixels.max(0.01) * (1.0 + analysis.average_complexity as f64)
+}
+
+// Per-file targets in bytes. `is_image` separates the files that are compressed from
+// the ones copied as they are; the latter get no target. Every image is guaranteed a
+// quarter of an equal share so that a flat graphic isn't starved below what any
+// encoder can reach; the rest is split by weight.
+pub fn plan_targets(
+    files: &[PathBuf],
+    budget_kb: u64,
+    by_detail: bool,
+    max_megapixels: Option<f32>,
+    is_image: impl Fn(&Path) -> bool,
+) -> HashMap<PathBuf, u64> {
+    let compressor = SmartCompressor::new(Default::default());
+    let (images, copied): (Vec<&PathBuf>, Vec<&PathBuf>) = files.iter().partition(|file| is_image(file));
+
+    let copied_bytes: u64 = copied
+        .iter()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let remaining = (budget_kb * 1024).saturating_sub(copied_bytes);
+
+    let weights: Vec<f64> = images
+        .iter()
+        .map(|file| image_weight(file, &compressor, by_detail, max_megapixels))
+        .collect();
+    let total_weight: f64 = weights.iter().sum();
+    let floor = remaining as f64 / (4.0 * images.len().max(1) as f64);
+    let weighted = remaining as f64 - floor * images.len() as f64;
+
+    images
+        .into_iter()
+        .zip(weights)
+        .map(|(file, weight)| {
+            let share = floor + weighted * weight / total_weight.max(f64::EPSILON);
+            (file.clone(), (share.floor() as u64).max(1))
+        })
+        .collect()
+}
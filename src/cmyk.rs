@@ -0,0 +1,163 @@
+// cmyk.rs - CMYK JPEG input
+//
+// image decodes CMYK JPEGs through jpeg-decoder, which always assumes the Adobe
+// convention of inverted samples. Files written without an Adobe APP14 marker
+// store plain CMYK and come out color-inverted, so those are decoded here instead.
+// Neither path applies the embedded ICC profile; the conversion is the naive one.
+
+use image::{DynamicImage, RgbImage};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub struct JpegColorInfo {
+    pub components: u8,
+    pub adobe_marker: bool,
+}
+
+impl JpegColorInfo {
+    pub fn is_cmyk(&self) -> bool {
+        self.components == 4
+    }
+}
+
+// Walks the marker segments up to the frame header; None for anything that isn't a JPEG
+pub fn jpeg_color_info(path: &Path) -> Option<JpegColorInfo> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut marker = [0u8; 2];
+    reader.read_exact(&mut marker).ok()?;
+    if marker != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut adobe_marker = false;
+    loop {
+        reader.read_exact(&mut marker).ok()?;
+        if marker[0] != 0xFF {
+            return None;
+        }
+        // Fill bytes before a marker
+        if marker[1] == 0xFF {
+            reader.seek(SeekFrom::Current(-1)).ok()?;
+            continue;
+        }
+
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length).ok()?;
+        let length = u16::from_be_bytes(length) as i64;
+        if length < 2 {
+            return None;
+        }
+
+        match marker[1] {
+            // SOF0-SOF15, excluding DHT (C4), JPG (C8) and DAC (CC)
+            0xC0..=0xCF if !matches!(marker[1], 0xC4 | 0xC8 | 0xCC) => {
+                // Precision, height and width precede the component count
+                let mut header = [0u8; 6];
+                reader.read_exact(&mut header).ok()?;
+                return Some(JpegColorInfo {
+                    components: header[5],
+                    adobe_marker,
+                });
+            }
+            0xEE if length >= 7 => {
+                let mut identifier = [0u8; 5];
+                reader.read_exact(&mut identifier).ok()?;
+                adobe_marker |= &identifier == b"Adobe";
+                reader.seek(SeekFrom::Current(length - 7)).ok()?;
+            }
+            // Start of scan without a frame header
+            0xDA => return None,
+            _ => {
+                reader.seek(SeekFrom::Current(length - 2)).ok()?;
+            }
+        }
+    }
+}
+
+// A CMYK JPEG that image would decode inverted
+pub fn needs_plain_cmyk_decode(path: &Path) -> bool {
+    jpeg_color_info(path).is_some_and(|info| info.is_cmyk() && !info.adobe_marker)
+}
+
+// Result note for CMYK input, which always loses its color profile on the way to RGB
+pub fn conversion_note(path: &Path) -> Option<String> {
+    match jpeg_color_info(path) {
+        Some(info) if info.is_cmyk() => Some(String::from("CMYK → RGB (no ICC profile)")),
+        _ => None,
+    }
+}
+
+// Decodes a CMYK JPEG that stores plain (non-inverted) samples
+pub fn decode_plain_cmyk(path: &Path) -> Result<DynamicImage, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut decoder = jpeg_decoder::Decoder::new(BufReader::new(file));
+    let samples = decoder.decode().map_err(|e| e.to_string())?;
+    let info = decoder.info().ok_or("Missing JPEG frame header")?;
+    if info.pixel_format != jpeg_decoder::PixelFormat::CMYK32 {
+        return Err(format!("Expected CMYK samples, got {:?}", info.pixel_format));
+    }
+
+    // jpeg-decoder hands back 255 - sample for every channel, which is exactly
+    // the "ink left" a plain CMYK value converts from
+    let rgb: Vec<u8> = samples
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let k = pixel[3] as u16;
+            [
+                (pixel[0] as u16 * k / 255) as u8,
+                (pixel[1] as u16 * k / 255) as u8,
+                (pixel[2] as u16 * k / 255) as u8,
+            ]
+        })
+        .collect();
+
+    RgbImage::from_raw(info.width as u32, info.height as u32, rgb)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| String::from("CMYK sample count doesn't match the frame size"))
+}
+
+
+#[cfg(all(test, feature = "mozjpeg"))]
+mod tests {
+    use super::*;
+    
+    // Pure cyan ink on every pixel, as libjpeg writes CMYK: with an Adobe APP14 marker
+    fn adobe_cmyk_jpeg() -> Vec<u8> {
+        let mut compress = mozjpeg::Compress::new(mozjpeg::ColorSpace::JCS_CMYK);
+        compress.set_size(8, 8);
+        compress.set_quality(95.0);
+        let mut started = compress.start_compress(Vec::new()).unwrap();
+        started.write_scanlines(&[255, 0, 0, 0].repeat(64)).unwrap();
+        started.finish().unwrap()
+    }
+    
+    // The same samples without the APP14 segment, i.e. plain CMYK
+    fn without_adobe_marker(data: &[u8]) -> Vec<u8> {
+        let mut output = data[0..2].to_vec();
+        let mut offset = 2;
+        while data[offset + 1] != 0xDA {
+            let end = offset + 2 + u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+            if data[offset + 1] != 0xEE {
+                output.extend_from_slice(&data[offset..end]);
+            }
+            offset = end;
+        }
+        output.extend_from_slice(&data[offset..]);
+        output
+    }
+    
+    #[test]
+    fn plain_cmyk_is_not_inverted() {
+        let path = std::env::temp_dir().join(format!("image-resizer-plain-cmyk-{}.jpg", std::process::id()));
+        std::fs::write(&path, without_adobe_marker(&adobe_cmyk_jpeg())).unwrap();
+        let needs_plain = needs_plain_cmyk_decode(&path);
+        let decoded = decode_plain_cmyk(&path);
+        std::fs::remove_file(&path).unwrap();
+        
+        assert!(needs_plain);
+        // Cyan, where the inverted reading would give red
+        let pixel = decoded.unwrap().to_rgb8().get_pixel(4, 4).0;
+        assert!(pixel[0] < 30 && pixel[1] > 225 && pixel[2] > 225, "{:?}", pixel);
+    }
+}
\ No newline at end of file
@@ -1,27 +1,45 @@
 // compression.rs - Advanced compression algorithms module with native libraries
 
-use image::{DynamicImage, ImageFormat, GenericImageView, Rgba, Pixel, RgbImage, RgbaImage};
+use image::{DynamicImage, ImageFormat, GenericImageView, Rgba, Pixel, RgbImage};
+use log::{debug, trace, warn};
 use std::io::Cursor;
 use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+use crate::density;
+use crate::icc;
+use crate::metadata;
 use crate::simple;
+use crate::thumbnail;
 
-// Native compression library imports
+// Native compression library imports, each behind the Cargo feature of the same name
+#[cfg(feature = "mozjpeg")]
 use mozjpeg::{Compress, ColorSpace, ScanMode};
-use oxipng::{Options as OxiOptions, RowFilter, StripChunks};
+#[cfg(feature = "oxipng")]
+use oxipng::{Interlacing, Options as OxiOptions, RowFilter, StripChunks};
 use indexmap::IndexSet;
-use webp::{Encoder as WebPEncoder, WebPMemory};
+#[cfg(feature = "webp")]
+use image::RgbaImage;
+#[cfg(feature = "webp")]
+use webp::{Encoder as WebPEncoder, WebPConfig};
+#[cfg(feature = "avif")]
 use ravif::{Encoder as AvifEncoder, EncodedImage};
+#[cfg(feature = "avif")]
 use imgref::ImgVec;
-use rgb::{RGB8, RGBA8};
+#[cfg(feature = "avif")]
+use rgb::RGBA8;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum CompressionAlgorithm {
     Auto,
+    AutoBest,
+    // CompressionOptions::opaque_algorithm, or transparent_algorithm for images with
+    // any transparent pixel
+    ByAlpha,
     #[default]
     Simple,
-    // JPEG algorithms
-    StandardJpeg,
-    MozJpeg,
+    // JPEG, through the encoder picked by CompressionOptions::jpeg_encoder
+    Jpeg,
     
     // PNG algorithms  
     StandardPng,
@@ -32,18 +50,116 @@ pub enum CompressionAlgorithm {
     // WebP
     WebPLossy,
     WebPLossless,
+    LosslessFirst,
     
     // Advanced
     Avif,
 }
 
+// The two JPEG code paths: image-rs is faster, MozJPEG writes smaller files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum JpegEncoder {
+    ImageRs,
+    #[default]
+    MozJpeg,
+}
+
+// MozJPEG output layout. Optimized Huffman tables cost nothing in compatibility;
+// progressive files are smaller again but some platforms reject them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum JpegMode {
+    Baseline,
+    #[default]
+    BaselineOptimized,
+    Progressive,
+}
+
+impl JpegEncoder {
+    // MozJPEG is a native library that can be left out of the build
+    pub fn is_available(&self) -> bool {
+        *self == Self::ImageRs || cfg!(feature = "mozjpeg")
+    }
+}
+
+impl JpegMode {
+    pub const ALL: [JpegMode; 3] = [Self::Baseline, Self::BaselineOptimized, Self::Progressive];
+}
+
+// Everything an encode depends on. The GUI builds one of these and nothing else,
+// so a caller filling the same fields gets the same output; knobs the GUI has no
+// control for yet keep their Default values there. A value with every field spelled
+// out, for lossless WebP at the default effort:
+//
+//     CompressionOptions {
+//         algorithm: CompressionAlgorithm::WebPLossless,
+//         quality: None,
+//         target_size: None,
+//         webp_method: 4,
+//         ..
+//     }
+//
+// tests::every_field_spelled_out builds it in full. A build without every encoder
+// never reads the fields only those encoders use.
 #[derive(Debug, Clone)]
+#[cfg_attr(not(all(feature = "mozjpeg", feature = "oxipng", feature = "webp", feature = "avif")), allow(dead_code))]
 pub struct CompressionOptions {
     pub algorithm: CompressionAlgorithm,
     pub quality: Option<u8>,
     pub target_size: Option<u64>,
     pub preserve_metadata: bool,
     pub optimize_for_web: bool,
+    pub jpeg_background: [u8; 3],
+    // LosslessFirst falls back to lossy once the lossless result exceeds this many bytes
+    pub lossless_size_limit: Option<u64>,
+    // What ByAlpha picks between; both are concrete algorithms, never an auto mode
+    pub opaque_algorithm: CompressionAlgorithm,
+    pub transparent_algorithm: CompressionAlgorithm,
+    // Separate alpha-plane quality for WebP/AVIF images with transparency; None follows quality
+    pub alpha_quality: Option<u8>,
+    // Print density written into JPEG (JFIF) and PNG (pHYs) output
+    pub dpi: Option<u16>,
+    // Lossless PNG algorithms write an exact indexed palette when the image has <= 256 colors
+    pub minimal_palette: bool,
+    // PNG algorithms write 16-bit-per-channel images as 8-bit
+    pub reduce_16bit_png: bool,
+    // 16-bit sources keep their precision: no 8-bit palette for PNG, and AVIF is
+    // encoded from the 16-bit samples instead of an 8-bit copy
+    pub keep_bit_depth: bool,
+    // OxiPNG preset 0-6; higher is smaller and much slower
+    pub oxipng_level: u8,
+    // Let OxiPNG try filters and deflate settings on all cores
+    pub oxipng_multithreaded: bool,
+    // Adam7-interlaced PNG output; renders progressively but is usually larger
+    pub interlace: bool,
+    // Target-size searches never go below this quality; missing the target there is an error
+    pub min_quality: u8,
+    // Quality probes a target-size search may encode; None searches until it converges.
+    // A capped search that found nothing under the target keeps its smallest probe.
+    pub max_search_iterations: Option<u8>,
+    // Backend for CompressionAlgorithm::Jpeg
+    pub jpeg_encoder: JpegEncoder,
+    // Scan layout of MozJPEG output
+    pub jpeg_mode: JpegMode,
+    // MozJPEG stores chroma at half resolution (4:2:0); off keeps it full (4:4:4)
+    pub jpeg_chroma_subsampling: bool,
+    // libwebp effort 0-6; higher is smaller and slower
+    pub webp_method: u8,
+    // Lossy WebP deblocking 0-100 (libwebp default 60); lower keeps edges and text crisper
+    pub webp_filter_strength: u8,
+    // Lossy WebP filter sharpness 0-7 (libwebp default 0, the sharpest); 7 is the least sharp
+    pub webp_sharpness: u8,
+    // ravif speed 1-10; lower is smaller and slower
+    pub avif_speed: u8,
+    // JPEG/WebP quality comes from adaptive_quality instead of `quality`; a target size still wins
+    pub adaptive_quality: bool,
+    // Tag JPEG, PNG and WebP output with an sRGB ICC profile
+    pub embed_srgb: bool,
+    // The source's own profile, embedded instead of sRGB when metadata is preserved
+    pub source_icc_profile: Option<Arc<Vec<u8>>>,
+    // The source's EXIF and XMP, carried over when metadata is preserved
+    pub source_metadata: Option<Arc<metadata::SourceMetadata>>,
+    // A small preview in the output's EXIF, for formats that have a place for one
+    pub embed_thumbnail: bool,
 }
 
 impl Default for CompressionOptions {
@@ -54,15 +170,60 @@ impl Default for CompressionOptions {
             target_size: None,
             preserve_metadata: false,
             optimize_for_web: true,
+            jpeg_background: [255, 255, 255],
+            lossless_size_limit: None,
+            opaque_algorithm: CompressionAlgorithm::Jpeg,
+            transparent_algorithm: CompressionAlgorithm::OxiPng,
+            alpha_quality: None,
+            dpi: None,
+            minimal_palette: false,
+            reduce_16bit_png: false,
+            keep_bit_depth: false,
+            oxipng_level: 3,
+            oxipng_multithreaded: true,
+            interlace: false,
+            min_quality: 10,
+            max_search_iterations: None,
+            jpeg_encoder: JpegEncoder::default(),
+            jpeg_mode: JpegMode::default(),
+            jpeg_chroma_subsampling: true,
+            webp_method: 4,
+            webp_filter_strength: 60,
+            webp_sharpness: 0,
+            avif_speed: 4,
+            adaptive_quality: false,
+            embed_srgb: false,
+            source_icc_profile: None,
+            source_metadata: None,
+            embed_thumbnail: false,
+        }
+    }
+}
+
+impl CompressionOptions {
+    // Profile written into the output, if any
+    pub fn output_profile(&self) -> Option<&[u8]> {
+        match &self.source_icc_profile {
+            Some(profile) if self.preserve_metadata => Some(profile.as_slice()),
+            _ if self.embed_srgb => Some(icc::srgb_profile()),
+            _ => None,
         }
     }
+    
+    // EXIF and XMP written into the output, if any
+    pub fn output_metadata(&self) -> Option<&metadata::SourceMetadata> {
+        self.source_metadata.as_deref().filter(|metadata| self.preserve_metadata && !metadata.is_empty())
+    }
 }
 
 pub struct ImageAnalysis {
     pub has_transparency: bool,
     pub color_count: usize,
+    // Part of the analysis, though no algorithm choice reads them
+    #[allow(dead_code)]
     pub has_gradients: bool,
     pub is_photograph: bool,
+    #[allow(dead_code)]
     pub dominant_colors: Vec<[u8; 3]>,
     pub average_complexity: f32,
 }
@@ -72,44 +233,331 @@ pub struct CompressionResult {
     pub format: ImageFormat,
     pub algorithm_used: CompressionAlgorithm,
     pub final_quality: Option<u8>,
+    // A target-size search hit max_search_iterations before it converged
+    pub search_capped: bool,
     pub compression_ratio: f32,
 }
 
-pub struct SmartCompressor;
+// Thresholds behind the image analysis and Auto's algorithm choice
+#[derive(Debug, Clone)]
+pub struct SmartCompressorConfig {
+    // Pixels sampled (evenly spaced) when counting unique colors
+    pub color_sample_size: usize,
+    // More unique colors than this, plus gradients, classifies an image as a photograph
+    pub photo_color_threshold: usize,
+    // Auto keeps images with at most this many colors as PNG
+    pub palette_color_threshold: usize,
+    // Neighbor color distance above which a sampled pixel counts as a gradient/edge
+    pub gradient_pixel_difference: f32,
+    // Share of neighbor comparisons that must be gradients for has_gradients
+    pub gradient_ratio: f32,
+    // Complexity analysis looks at every Nth pixel in each direction. None picks N from
+    // the image size, so about complexity_sample_target pixels are looked at whatever
+    // the resolution.
+    pub complexity_sample_stride: Option<u32>,
+    pub complexity_sample_target: u32,
+    // Upper bound on the encoders AutoBest runs per image
+    pub max_auto_best_candidates: usize,
+}
+
+impl Default for SmartCompressorConfig {
+    fn default() -> Self {
+        Self {
+            color_sample_size: 10000,
+            photo_color_threshold: 1000,
+            palette_color_threshold: 256,
+            gradient_pixel_difference: 10.0,
+            gradient_ratio: 0.1,
+            complexity_sample_stride: None,
+            complexity_sample_target: 250_000,
+            max_auto_best_candidates: 4,
+        }
+    }
+}
+
+pub struct SmartCompressor {
+    config: SmartCompressorConfig,
+}
 
 impl SmartCompressor {
-    pub fn new() -> Self {
-        Self
+    pub fn new(config: SmartCompressorConfig) -> Self {
+        Self { config }
     }
     
     pub fn compress(
         &self,
         image: &DynamicImage,
         options: CompressionOptions,
+    ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let requested = options.algorithm;
+        let result = self.compress_image(image, options);
+        
+        match &result {
+            Ok(result) => debug!(
+                "compress {}×{} with {}: {} bytes using {} (quality {:?}) in {:.0?}",
+                image.width(),
+                image.height(),
+                requested,
+                result.data.len(),
+                result.algorithm_used,
+                result.final_quality,
+                started.elapsed()
+            ),
+            Err(e) => debug!("compress with {} failed after {:.0?}: {}", requested, started.elapsed(), e),
+        }
+        result
+    }
+    
+    fn compress_image(
+        &self,
+        image: &DynamicImage,
+        options: CompressionOptions,
     ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
         let analysis = self.analyze_image(image);
         
         let algorithm = match options.algorithm {
-            CompressionAlgorithm::Auto => self.select_best_algorithm(&analysis),
+            CompressionAlgorithm::Auto => {
+                let selected = self.select_best_algorithm(&analysis);
+                debug!(
+                    "Auto selected {} (photograph {}, transparency {}, {} colors, complexity {:.2})",
+                    selected,
+                    analysis.is_photograph,
+                    analysis.has_transparency,
+                    analysis.color_count,
+                    analysis.average_complexity
+                );
+                selected
+            }
+            CompressionAlgorithm::AutoBest => return self.compress_smallest_candidate(image, &analysis, options),
+            CompressionAlgorithm::ByAlpha => {
+                let selected = if analysis.has_transparency {
+                    options.transparent_algorithm
+                } else {
+                    options.opaque_algorithm
+                };
+                debug!("ByAlpha selected {} (transparency {})", selected, analysis.has_transparency);
+                selected
+            }
             other => other,
         };
         
-        match algorithm {
-            CompressionAlgorithm::Auto => unreachable!(),
+        let fallback = algorithm.fallback();
+        if fallback != algorithm {
+            warn!("{} isn't built in; using {} instead", algorithm, fallback);
+        }
+        let algorithm = fallback;
+        
+        // Target size searches leave room for the profile added afterwards; AVIF isn't tagged
+        let options = match options.output_profile().filter(|_| algorithm != CompressionAlgorithm::Avif) {
+            Some(profile) if options.target_size.is_some() => CompressionOptions {
+                target_size: options.target_size
+                    .map(|target| target.saturating_sub(profile.len() as u64 + icc::EMBED_OVERHEAD)),
+                ..options
+            },
+            _ => options,
+        };
+        
+        // Built once from the image being compressed, with room left for it in the same way
+        let thumbnail = self.thumbnail_exif(image, algorithm, &options);
+        let options = match &thumbnail {
+            Some(exif) if options.target_size.is_some() => CompressionOptions {
+                target_size: options.target_size
+                    .map(|target| target.saturating_sub(exif.len() as u64 + thumbnail::EMBED_OVERHEAD)),
+                ..options
+            },
+            _ => options,
+        };
+        
+        // And for the preserved EXIF and XMP, minus the EXIF a thumbnail replaces
+        let carries_metadata = ImageFormat::from_extension(algorithm.file_extension()).is_some_and(metadata::supports);
+        let metadata_len = options.output_metadata()
+            .filter(|_| carries_metadata)
+            .map(|metadata| metadata.embedded_len(thumbnail.is_none()));
+        let options = match (options.target_size, metadata_len) {
+            (Some(target), Some(len)) => CompressionOptions {
+                target_size: Some(target.saturating_sub(len + metadata::EMBED_OVERHEAD)),
+                ..options
+            },
+            _ => options,
+        };
+        
+        // And for the density tag, in the formats that carry one
+        let tags_density = ImageFormat::from_extension(algorithm.file_extension()).is_some_and(density::supports);
+        let options = match options.target_size {
+            Some(target) if options.dpi.is_some() && tags_density => CompressionOptions {
+                target_size: Some(target.saturating_sub(density::EMBED_OVERHEAD)),
+                ..options
+            },
+            _ => options,
+        };
+        
+        let options = if options.adaptive_quality && options.target_size.is_none() && algorithm.adapts_quality() {
+            let quality = adaptive_quality(&analysis);
+            debug!("adaptive quality {} for complexity {:.1}", quality, analysis.average_complexity);
+            CompressionOptions {
+                quality: Some(quality),
+                ..options
+            }
+        } else {
+            options
+        };
+        
+        let lossless_png = matches!(
+            algorithm,
+            CompressionAlgorithm::StandardPng | CompressionAlgorithm::OptiPng | CompressionAlgorithm::OxiPng
+        );
+        
+        let reduced = if options.reduce_16bit_png && (lossless_png || algorithm == CompressionAlgorithm::PngQuant) {
+            self.reduce_to_8bit(image)
+        } else {
+            None
+        };
+        let image = reduced.as_ref().unwrap_or(image);
+        let keeps_depth = options.keep_bit_depth && is_16_bit(image);
+        let palette = if options.minimal_palette && lossless_png && analysis.color_count <= 256 && !keeps_depth {
+            self.compress_minimal_palette(image, algorithm, &options)?
+        } else {
+            None
+        };
+        
+        debug!(
+            "Encoding {}×{} with {} (quality {:?}, target {:?})",
+            image.width(),
+            image.height(),
+            algorithm,
+            options.quality,
+            options.target_size
+        );
+        let result = match algorithm {
+            CompressionAlgorithm::Auto | CompressionAlgorithm::AutoBest | CompressionAlgorithm::ByAlpha => unreachable!(),
             CompressionAlgorithm::Simple => self.compress_standard_jpeg(image, &options),
-            CompressionAlgorithm::StandardJpeg => self.compress_standard_jpeg(image, &options),
-            CompressionAlgorithm::MozJpeg => self.compress_mozjpeg(image, &options),
+            CompressionAlgorithm::Jpeg => match options.jpeg_encoder {
+                JpegEncoder::ImageRs => self.compress_standard_jpeg(image, &options),
+                #[cfg(feature = "mozjpeg")]
+                JpegEncoder::MozJpeg => self.compress_mozjpeg(image, &options),
+                #[cfg(not(feature = "mozjpeg"))]
+                JpegEncoder::MozJpeg => {
+                    warn!("MozJPEG isn't built in; using the image-rs JPEG encoder instead");
+                    self.compress_standard_jpeg(image, &options)
+                }
+            },
             CompressionAlgorithm::StandardPng => self.compress_standard_png(image, &options),
             CompressionAlgorithm::OptiPng => self.compress_optipng(image, &options),
+            #[cfg(feature = "oxipng")]
             CompressionAlgorithm::OxiPng => self.compress_oxipng(image, &options),
             CompressionAlgorithm::PngQuant => self.compress_pngquant(image, &options),
+            #[cfg(feature = "webp")]
             CompressionAlgorithm::WebPLossy => self.compress_webp_lossy(image, &options),
+            #[cfg(feature = "webp")]
             CompressionAlgorithm::WebPLossless => self.compress_webp_lossless(image, &options),
+            #[cfg(feature = "webp")]
+            CompressionAlgorithm::LosslessFirst => self.compress_lossless_first(image, &options),
+            #[cfg(feature = "avif")]
             CompressionAlgorithm::Avif => self.compress_avif(image, &options),
+            // Algorithms left out of the build, which fallback() has already replaced
+            #[cfg(not(all(feature = "oxipng", feature = "webp", feature = "avif")))]
+            _ => unreachable!(),
+        }?;
+        
+        // The exact palette only wins when it's actually smaller than the encoder's own output
+        let result = match palette {
+            Some(palette) if palette.data.len() < result.data.len() => palette,
+            _ => result,
+        };
+        self.finish_output(result, &options, thumbnail.as_deref())
+    }
+    
+    // Container-level changes made after encoding: interlacing, density, the color
+    // profile, the preserved metadata and the embedded thumbnail
+    fn finish_output(
+        &self,
+        result: CompressionResult,
+        options: &CompressionOptions,
+        thumbnail: Option<&[u8]>,
+    ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
+        let mut result = self.apply_color_profile(self.apply_dpi(self.apply_interlace(result, options)?, options.dpi), options);
+        if let Some(preserved) = options.output_metadata() {
+            result.data = metadata::embed(result.data, result.format, preserved, thumbnail.is_none());
+        }
+        if let Some(exif) = thumbnail {
+            result.data = thumbnail::embed_thumbnail(result.data, result.format, exif);
+        }
+        Ok(result)
+    }
+    
+    // EXIF block with the preview, when one is wanted and the algorithm's format
+    // can carry it
+    fn thumbnail_exif(&self, image: &DynamicImage, algorithm: CompressionAlgorithm, options: &CompressionOptions) -> Option<Vec<u8>> {
+        let format = ImageFormat::from_extension(algorithm.file_extension())?;
+        if !options.embed_thumbnail || !thumbnail::supports(format) {
+            return None;
+        }
+        let edge = thumbnail::THUMBNAIL_EDGE;
+        let preview = if image.width().max(image.height()) > edge {
+            image.thumbnail(edge, edge)
+        } else {
+            image.clone()
+        };
+        thumbnail::exif_thumbnail(&flatten_alpha(&preview, options.jpeg_background))
+    }
+    
+    // Decodes encoded bytes in any format the image crate reads and compresses them without
+    // touching disk; `compress` itself only ever returns bytes as well. Behind `--stdin`.
+    pub fn compress_bytes(
+        &self,
+        input: &[u8],
+        options: CompressionOptions,
+    ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
+        let image = image::load_from_memory(input)?;
+        self.compress(&image, options)
+    }
+    
+    // OxiPNG interlaces its own output; the other PNG encoders get an interlace-only pass.
+    // An output that fit the target size is only interlaced if it still fits afterwards.
+    #[cfg_attr(not(feature = "oxipng"), allow(unused_mut))]
+    fn apply_interlace(
+        &self,
+        mut result: CompressionResult,
+        options: &CompressionOptions,
+    ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
+        if options.interlace && result.format == ImageFormat::Png && !is_interlaced_png(&result.data) {
+            #[cfg(feature = "oxipng")]
+            {
+                let mut oxipng_options = OxiOptions::from_preset(0);
+                oxipng_options.interlace = Some(Interlacing::Adam7);
+                oxipng_options.strip = StripChunks::None;
+                // OxiPNG otherwise keeps its input whenever interlacing makes it larger
+                oxipng_options.force = true;
+                let interlaced = oxipng::optimize_from_memory(&result.data, &oxipng_options)?;
+                let fits = |data: &[u8]| options.target_size.is_none_or(|target| data.len() as u64 <= target);
+                if fits(&interlaced) || !fits(&result.data) {
+                    result.data = interlaced;
+                } else {
+                    warn!("Interlacing would put the PNG over the target size; it's left non-interlaced");
+                }
+            }
+            #[cfg(not(feature = "oxipng"))]
+            warn!("Interlacing needs OxiPNG, which isn't built in; the PNG is left non-interlaced");
+        }
+        Ok(result)
+    }
+    
+    fn apply_dpi(&self, mut result: CompressionResult, dpi: Option<u16>) -> CompressionResult {
+        if let Some(dpi) = dpi {
+            result.data = density::set_dpi(result.data, result.format, dpi);
         }
+        result
     }
     
-    fn analyze_image(&self, image: &DynamicImage) -> ImageAnalysis {
+    fn apply_color_profile(&self, mut result: CompressionResult, options: &CompressionOptions) -> CompressionResult {
+        if let Some(profile) = options.output_profile() {
+            result.data = icc::embed_profile(result.data, result.format, profile);
+        }
+        result
+    }
+    
+    pub fn analyze_image(&self, image: &DynamicImage) -> ImageAnalysis {
         let (width, height) = image.dimensions();
         let rgba = image.to_rgba8();
         
@@ -117,13 +565,13 @@ impl SmartCompressor {
         let has_transparency = self.has_alpha_channel(&rgba);
         
         // Count colors
-        let color_count = self.count_unique_colors(&rgba, 10000); // Sample up to 10k colors
+        let color_count = self.count_unique_colors(&rgba, self.config.color_sample_size);
         
         // Detect gradients and complexity
         let (has_gradients, complexity) = self.analyze_complexity(&rgba);
         
         // Detect if photograph (high color count, gradients)
-        let is_photograph = color_count > 1000 && has_gradients;
+        let is_photograph = color_count > self.config.photo_color_threshold && has_gradients;
         
         // Get dominant colors
         let dominant_colors = self.get_dominant_colors(&rgba, 5);
@@ -141,13 +589,13 @@ impl SmartCompressor {
     fn select_best_algorithm(&self, analysis: &ImageAnalysis) -> CompressionAlgorithm {
         match (analysis.has_transparency, analysis.is_photograph, analysis.color_count) {
             // Photos without transparency -> JPEG
-            (false, true, _) => CompressionAlgorithm::MozJpeg,
+            (false, true, _) => CompressionAlgorithm::Jpeg,
             
             // Images with transparency and many colors -> WebP
-            (true, _, colors) if colors > 256 => CompressionAlgorithm::WebPLossy,
+            (true, _, colors) if colors > self.config.palette_color_threshold => CompressionAlgorithm::WebPLossy,
             
             // Simple graphics with few colors -> PNG
-            (_, false, colors) if colors <= 256 => CompressionAlgorithm::OxiPng,
+            (_, false, colors) if colors <= self.config.palette_color_threshold => CompressionAlgorithm::OxiPng,
             
             // Complex images with transparency -> WebP
             (true, _, _) => CompressionAlgorithm::WebPLossy,
@@ -157,21 +605,109 @@ impl SmartCompressor {
         }
     }
     
+    // Encoders worth trying for this kind of image, most promising first. Ones that
+    // aren't built in are swapped for their fallback, without repeats
+    fn candidate_algorithms(&self, analysis: &ImageAnalysis) -> Vec<CompressionAlgorithm> {
+        let candidates = if analysis.has_transparency {
+            vec![
+                CompressionAlgorithm::WebPLossy,
+                CompressionAlgorithm::WebPLossless,
+                CompressionAlgorithm::OxiPng,
+                CompressionAlgorithm::Avif,
+            ]
+        } else if analysis.is_photograph {
+            vec![
+                CompressionAlgorithm::Jpeg,
+                CompressionAlgorithm::WebPLossy,
+                CompressionAlgorithm::Avif,
+            ]
+        } else if analysis.color_count <= self.config.palette_color_threshold {
+            vec![
+                CompressionAlgorithm::OxiPng,
+                CompressionAlgorithm::WebPLossless,
+                CompressionAlgorithm::PngQuant,
+            ]
+        } else {
+            vec![
+                CompressionAlgorithm::Jpeg,
+                CompressionAlgorithm::WebPLossy,
+                CompressionAlgorithm::WebPLossless,
+                CompressionAlgorithm::OxiPng,
+            ]
+        };
+        
+        let mut available: Vec<CompressionAlgorithm> = Vec::new();
+        for candidate in candidates.into_iter().map(|candidate| candidate.fallback()) {
+            if !available.contains(&candidate) {
+                available.push(candidate);
+            }
+        }
+        available.into_iter().take(self.config.max_auto_best_candidates).collect()
+    }
+    
+    // AutoBest: encodes with every candidate and keeps the smallest output. With a target
+    // size, results over the target only win when no candidate meets it.
+    fn compress_smallest_candidate(
+        &self,
+        image: &DynamicImage,
+        analysis: &ImageAnalysis,
+        options: CompressionOptions,
+    ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
+        let fits = |r: &CompressionResult| options.target_size.is_none_or(|target| r.data.len() as u64 <= target);
+        let mut best: Option<CompressionResult> = None;
+        let mut last_error = None;
+        
+        for candidate in self.candidate_algorithms(analysis) {
+            let candidate_options = CompressionOptions {
+                algorithm: candidate,
+                ..options.clone()
+            };
+            
+            let result = match self.compress(image, candidate_options) {
+                Ok(result) => result,
+                Err(e) => {
+                    debug!("AutoBest candidate {} failed: {}", candidate, e);
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            debug!("AutoBest candidate {}: {} bytes", candidate, result.data.len());
+            
+            let better = match &best {
+                None => true,
+                Some(current) => match (fits(&result), fits(current)) {
+                    (true, false) => true,
+                    (false, true) => false,
+                    _ => result.data.len() < current.data.len(),
+                },
+            };
+            if better {
+                best = Some(result);
+            }
+        }
+        
+        match best {
+            Some(result) => Ok(result),
+            None => Err(last_error.unwrap_or_else(|| "No candidate encoders to try".into())),
+        }
+    }
+    
     // JPEG Compression Methods
     fn compress_standard_jpeg(
         &self,
         image: &DynamicImage,
         options: &CompressionOptions,
     ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
-        let rgb_image = image.to_rgb8();
+        let rgb_image = flatten_alpha(image, options.jpeg_background);
         let (width, height) = rgb_image.dimensions();
         
         let quality = options.quality.unwrap_or(85);
         let mut result_data = Vec::new();
+        let mut search_capped = false;
         
         if let Some(target_size) = options.target_size {
             // Binary search for target size
-            result_data = self.jpeg_target_size(&rgb_image, target_size)?;
+            (result_data, search_capped) = self.jpeg_target_size(&rgb_image, target_size, options)?;
         } else {
             // Single pass with specified quality
             let mut cursor = Cursor::new(&mut result_data);
@@ -189,18 +725,20 @@ impl SmartCompressor {
         Ok(CompressionResult {
             data: result_data,
             format: ImageFormat::Jpeg,
-            algorithm_used: CompressionAlgorithm::StandardJpeg,
+            algorithm_used: CompressionAlgorithm::Jpeg,
             final_quality: Some(quality),
+            search_capped,
             compression_ratio,
         })
     }
     
+    #[cfg(feature = "mozjpeg")]
     fn compress_mozjpeg(
         &self,
         image: &DynamicImage,
         options: &CompressionOptions,
     ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
-        let rgb_image = image.to_rgb8();
+        let rgb_image = flatten_alpha(image, options.jpeg_background);
         let (width, height) = rgb_image.dimensions();
         let quality = options.quality.unwrap_or(85);
         
@@ -208,15 +746,7 @@ impl SmartCompressor {
         let moz_quality = quality as f32;
         
         // Create MozJPEG compressor
-        let mut compress = Compress::new(ColorSpace::JCS_RGB);
-        compress.set_size(width as usize, height as usize);
-        compress.set_quality(moz_quality);
-        
-        // Enable progressive encoding for better web performance
-        if options.optimize_for_web {
-            compress.set_scan_optimization_mode(ScanMode::AllComponentsTogether);
-            compress.set_progressive_mode();
-        }
+        let compress = mozjpeg_compressor(width, height, moz_quality, options.jpeg_mode, options.jpeg_chroma_subsampling);
         
         // Create a buffer to write to
         let mut output_data = Vec::new();
@@ -240,10 +770,10 @@ impl SmartCompressor {
         compress_started.finish_compress()?;
         
         // Handle target size if specified
-        let final_data = if let Some(target_size) = options.target_size {
-            self.mozjpeg_target_size(&rgb_image, target_size, options.optimize_for_web)?
+        let (final_data, search_capped) = if let Some(target_size) = options.target_size {
+            self.mozjpeg_target_size(&rgb_image, target_size, options)?
         } else {
-            output_data
+            (output_data, false)
         };
         
         let compression_ratio = self.calculate_ratio(image, &final_data);
@@ -251,13 +781,91 @@ impl SmartCompressor {
         Ok(CompressionResult {
             data: final_data,
             format: ImageFormat::Jpeg,
-            algorithm_used: CompressionAlgorithm::MozJpeg,
+            algorithm_used: CompressionAlgorithm::Jpeg,
             final_quality: Some(quality),
+            search_capped,
             compression_ratio,
         })
     }
     
     // PNG Compression Methods
+    
+    // Exact palette of every RGBA value in the image, written as an indexed PNG at the
+    // smallest bit depth that fits and then run through OxiPNG when that's the
+    // algorithm. Lossless; None when there turn out to be over 256 colors (the
+    // analysis only samples).
+    fn compress_minimal_palette(
+        &self,
+        image: &DynamicImage,
+        algorithm: CompressionAlgorithm,
+        #[cfg_attr(not(feature = "oxipng"), allow(unused_variables))]
+        options: &CompressionOptions,
+    ) -> Result<Option<CompressionResult>, Box<dyn std::error::Error>> {
+        let rgba = image.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        
+        let mut palette: IndexSet<[u8; 4]> = IndexSet::new();
+        let mut indices = Vec::with_capacity((width * height) as usize);
+        for pixel in rgba.pixels() {
+            let (index, _) = palette.insert_full(pixel.0);
+            if palette.len() > 256 {
+                return Ok(None);
+            }
+            indices.push(index as u8);
+        }
+        
+        let bits: u8 = match palette.len() {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8,
+        };
+        let per_byte = (8 / bits) as usize;
+        let row_bytes = (width as usize).div_ceil(per_byte);
+        let mut packed = vec![0u8; row_bytes * height as usize];
+        for (i, &index) in indices.iter().enumerate() {
+            let (y, x) = (i / width as usize, i % width as usize);
+            let shift = 8 - bits as usize * (x % per_byte + 1);
+            packed[y * row_bytes + x / per_byte] |= index << shift;
+        }
+        
+        let mut result_data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut result_data, width, height);
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(match bits {
+                1 => png::BitDepth::One,
+                2 => png::BitDepth::Two,
+                4 => png::BitDepth::Four,
+                _ => png::BitDepth::Eight,
+            });
+            encoder.set_compression(png::Compression::Best);
+            encoder.set_palette(palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect::<Vec<u8>>());
+            if palette.iter().any(|c| c[3] < 255) {
+                encoder.set_trns(palette.iter().map(|c| c[3]).collect::<Vec<u8>>());
+            }
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&packed)?;
+        }
+        
+        #[cfg(feature = "oxipng")]
+        let result_data = if algorithm == CompressionAlgorithm::OxiPng {
+            self.optimize_png(&result_data, options)?
+        } else {
+            result_data
+        };
+        
+        let compression_ratio = self.calculate_ratio(image, &result_data);
+        
+        Ok(Some(CompressionResult {
+            data: result_data,
+            format: ImageFormat::Png,
+            algorithm_used: algorithm,
+            final_quality: None,
+            search_capped: false,
+            compression_ratio,
+        }))
+    }
     fn compress_standard_png(
         &self,
         image: &DynamicImage,
@@ -281,6 +889,7 @@ impl SmartCompressor {
             format: ImageFormat::Png,
             algorithm_used: CompressionAlgorithm::StandardPng,
             final_quality: None,
+            search_capped: false,
             compression_ratio,
         })
     }
@@ -331,10 +940,12 @@ impl SmartCompressor {
             format: ImageFormat::Png,
             algorithm_used: CompressionAlgorithm::OptiPng,
             final_quality: None,
+            search_capped: false,
             compression_ratio,
         })
     }
     
+    #[cfg(feature = "oxipng")]
     fn compress_oxipng(
         &self,
         image: &DynamicImage,
@@ -345,8 +956,24 @@ impl SmartCompressor {
         let mut cursor = Cursor::new(&mut png_data);
         image.write_to(&mut cursor, ImageFormat::Png)?;
         
+        let optimized_data = self.optimize_png(&png_data, options)?;
+        let compression_ratio = self.calculate_ratio(image, &optimized_data);
+        
+        Ok(CompressionResult {
+            data: optimized_data,
+            format: ImageFormat::Png,
+            algorithm_used: CompressionAlgorithm::OxiPng,
+            final_quality: None,
+            search_capped: false,
+            compression_ratio,
+        })
+    }
+    
+    // OxiPNG over an already encoded PNG
+    #[cfg(feature = "oxipng")]
+    fn optimize_png(&self, png_data: &[u8], options: &CompressionOptions) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         // Configure OxiPNG options
-        let mut oxipng_options = OxiOptions::from_preset(3); // Good balance of speed/compression
+        let mut oxipng_options = OxiOptions::from_preset(options.oxipng_level.min(6));
         
         if options.optimize_for_web {
             oxipng_options.strip = StripChunks::Safe;
@@ -365,18 +992,35 @@ impl SmartCompressor {
         filter_set.insert(RowFilter::Paeth);
         oxipng_options.filter = filter_set;
         
-        // Optimize the PNG data
-        let optimized_data = oxipng::optimize_from_memory(&png_data, &oxipng_options)?;
+        if options.interlace {
+            oxipng_options.interlace = Some(Interlacing::Adam7);
+            oxipng_options.force = true;
+        }
         
-        let compression_ratio = self.calculate_ratio(image, &optimized_data);
+        // Optimize the PNG data; single-threaded runs get a one-thread pool of their own
+        let optimize = |oxipng_options: &OxiOptions| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            if options.oxipng_multithreaded {
+                Ok(oxipng::optimize_from_memory(png_data, oxipng_options)?)
+            } else {
+                Ok(rayon::ThreadPoolBuilder::new()
+                    .num_threads(1)
+                    .build()?
+                    .install(|| oxipng::optimize_from_memory(png_data, oxipng_options))?)
+            }
+        };
+        let mut optimized_data = optimize(&oxipng_options)?;
+        
+        // Interlacing never pushes the output over the target size on its own
+        if options.interlace && options.target_size.is_some_and(|target| optimized_data.len() as u64 > target) {
+            oxipng_options.interlace = Some(Interlacing::None);
+            let plain = optimize(&oxipng_options)?;
+            if options.target_size.is_some_and(|target| plain.len() as u64 <= target) {
+                warn!("Interlacing would put the PNG over the target size; it's left non-interlaced");
+                optimized_data = plain;
+            }
+        }
         
-        Ok(CompressionResult {
-            data: optimized_data,
-            format: ImageFormat::Png,
-            algorithm_used: CompressionAlgorithm::OxiPng,
-            final_quality: None,
-            compression_ratio,
-        })
+        Ok(optimized_data)
     }
     
     fn compress_pngquant(
@@ -388,15 +1032,47 @@ impl SmartCompressor {
         let max_colors = 256;
         let quantized = self.quantize_image(image, max_colors);
         
-        // Now compress with OxiPNG for best results
-        self.compress_oxipng(&quantized, options)
+        // Now compress with OxiPNG for best results, or the filter search without it
+        #[cfg(feature = "oxipng")]
+        let compressed = self.compress_oxipng(&quantized, options);
+        #[cfg(not(feature = "oxipng"))]
+        let compressed = self.compress_optipng(&quantized, options);
+        compressed
             .map(|mut result| {
                 result.algorithm_used = CompressionAlgorithm::PngQuant;
                 result
             })
     }
     
+    // Lossless unless that is too big: WebP lossless is kept when it fits under the
+    // size limit and the target size, otherwise WebP lossy is searched down to fit.
+    // algorithm_used reports whichever path produced the data.
+    #[cfg(feature = "webp")]
+    fn compress_lossless_first(
+        &self,
+        image: &DynamicImage,
+        options: &CompressionOptions,
+    ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
+        let limit = match (options.target_size, options.lossless_size_limit) {
+            (Some(target), Some(limit)) => Some(target.min(limit)),
+            (target, limit) => target.or(limit),
+        };
+        
+        let lossless = self.compress_webp_lossless(image, options)?;
+        match limit {
+            Some(limit) if lossless.data.len() as u64 > limit => {
+                let lossy_options = CompressionOptions {
+                    target_size: Some(limit),
+                    ..options.clone()
+                };
+                self.compress_webp_lossy(image, &lossy_options)
+            }
+            _ => Ok(lossless),
+        }
+    }
+    
     // WebP Compression Methods
+    #[cfg(feature = "webp")]
     fn compress_webp_lossy(
         &self,
         image: &DynamicImage,
@@ -406,24 +1082,16 @@ impl SmartCompressor {
         
         // Convert to RGBA for WebP encoder
         let rgba_image = image.to_rgba8();
-        let (width, height) = rgba_image.dimensions();
-        
-        // Create WebP encoder
-        let encoder = WebPEncoder::from_rgba(
-            rgba_image.as_raw(),
-            width,
-            height,
-        );
+        let alpha_quality = options.alpha_quality.filter(|_| self.has_alpha_channel(&rgba_image));
         
         // Encode with specified quality
-        let memory = encoder.encode(quality);
-        let data = memory.to_vec();
+        let data = self.encode_webp_lossy(&rgba_image, quality, alpha_quality, options)?;
         
         // Handle target size if specified
-        let final_data = if let Some(target_size) = options.target_size {
-            self.webp_target_size(&rgba_image, target_size, true)?
+        let (final_data, search_capped) = if let Some(target_size) = options.target_size {
+            self.webp_target_size(&rgba_image, target_size, true, alpha_quality, options)?
         } else {
-            data
+            (data, false)
         };
         
         let compression_ratio = self.calculate_ratio(image, &final_data);
@@ -433,14 +1101,16 @@ impl SmartCompressor {
             format: ImageFormat::WebP,
             algorithm_used: CompressionAlgorithm::WebPLossy,
             final_quality: Some(quality as u8),
+            search_capped,
             compression_ratio,
         })
     }
     
+    #[cfg(feature = "webp")]
     fn compress_webp_lossless(
         &self,
         image: &DynamicImage,
-        _options: &CompressionOptions,
+        options: &CompressionOptions,
     ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
         // Convert to RGBA for WebP encoder
         let rgba_image = image.to_rgba8();
@@ -453,8 +1123,8 @@ impl SmartCompressor {
             height,
         );
         
-        // Encode losslessly (quality 100 triggers lossless mode in libwebp)
-        let memory = encoder.encode_lossless();
+        let memory = encoder.encode_advanced(&webp_config(true, 75.0, options.webp_method)?)
+            .map_err(|e| format!("WebP encoding failed: {:?}", e))?;
         let data = memory.to_vec();
         
         let compression_ratio = self.calculate_ratio(image, &data);
@@ -464,17 +1134,19 @@ impl SmartCompressor {
             format: ImageFormat::WebP,
             algorithm_used: CompressionAlgorithm::WebPLossless,
             final_quality: None,
+            search_capped: false,
             compression_ratio,
         })
     }
     
     // AVIF Compression
+    #[cfg(feature = "avif")]
     fn compress_avif(
         &self,
         image: &DynamicImage,
         options: &CompressionOptions,
     ) -> Result<CompressionResult, Box<dyn std::error::Error>> {
-        let quality = options.quality.unwrap_or(80) as f32 / 100.0; // ravif uses 0.0-1.0 scale
+        let quality = options.quality.unwrap_or(80).clamp(1, 100) as f32; // ravif uses 1-100
         
         // Convert to RGBA8 for AVIF encoder
         let rgba_image = image.to_rgba8();
@@ -494,54 +1166,52 @@ impl SmartCompressor {
         let img = ImgVec::new(pixels, width as usize, height as usize);
         
         // Create encoder and encode - ravif has a simple API
-        let encoder = AvifEncoder::new();
-        let encoded = encoder.encode_rgba(img.as_ref())?;
-        
-        let data = encoded.avif_file;
-        
-        // Handle target size if specified
-        let final_data = if let Some(target_size) = options.target_size {
-            // For simplicity, we'll use the standard AVIF encoding
-            // as ravif doesn't easily support quality adjustment
-            data
+        let alpha_quality = options.alpha_quality
+            .filter(|_| self.has_alpha_channel(&rgba_image))
+            .map_or(quality, |alpha| alpha.clamp(1, 100) as f32);
+        let encoder = AvifEncoder::new()
+            .with_quality(quality)
+            .with_alpha_quality(alpha_quality)
+            .with_speed(options.avif_speed.clamp(1, 10));
+        let encoded = if options.keep_bit_depth && is_16_bit(image) {
+            encode_avif_16bit(&encoder, &image.to_rgba16())?
         } else {
-            data
+            encoder.encode_rgba(img.as_ref())?
         };
         
+        // A target size doesn't change the encoding, as ravif has no quality search
+        let final_data = encoded.avif_file;
+        
         let compression_ratio = self.calculate_ratio(image, &final_data);
         
         Ok(CompressionResult {
             data: final_data,
             format: ImageFormat::Avif,
             algorithm_used: CompressionAlgorithm::Avif,
-            final_quality: Some((quality * 100.0) as u8),
+            final_quality: Some(quality.round() as u8),
+            search_capped: false,
             compression_ratio,
         })
     }
     
     // Helper methods for target size compression
+    #[cfg(feature = "mozjpeg")]
     fn mozjpeg_target_size(
         &self,
         image: &RgbImage,
         target_bytes: u64,
-        optimize_for_web: bool,
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        options: &CompressionOptions,
+    ) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
         let (width, height) = image.dimensions();
-        let mut low = 10u8;
+        let min_quality = options.min_quality.clamp(1, 95);
+        let mut low = min_quality;
         let mut high = 95u8;
-        let mut best_result = Vec::new();
+        let mut search = TargetSearch::new(target_bytes, options.max_search_iterations);
         
-        while low <= high {
+        while low <= high && search.next_probe() {
             let quality = (low + high) / 2;
             
-            let mut compress = Compress::new(ColorSpace::JCS_RGB);
-            compress.set_size(width as usize, height as usize);
-            compress.set_quality(quality as f32);
-            
-            if optimize_for_web {
-                compress.set_scan_optimization_mode(ScanMode::AllComponentsTogether);
-                compress.set_progressive_mode();
-            }
+            let compress = mozjpeg_compressor(width, height, quality as f32, options.jpeg_mode, options.jpeg_chroma_subsampling);
             
             let mut output_data = Vec::new();
             let mut compress_started = compress.start_compress(&mut output_data)?;
@@ -557,93 +1227,98 @@ impl SmartCompressor {
             }
             
             compress_started.finish_compress()?;
+            trace!("MozJPEG target search: quality {} → {} bytes (target {})", quality, output_data.len(), target_bytes);
             
-            if output_data.len() as u64 <= target_bytes {
-                best_result = output_data;
+            if search.record(output_data) {
                 low = quality + 1;
             } else {
                 high = quality - 1;
             }
         }
         
-        if best_result.is_empty() {
-            Err("Could not achieve target file size with MozJPEG".into())
-        } else {
-            Ok(best_result)
+        match search.finish() {
+            Some((data, capped)) => {
+                debug!("MozJPEG target search: best fit {} bytes (target {}, capped {})", data.len(), target_bytes, capped);
+                Ok((data, capped))
+            }
+            None => Err(format!("Could not reach the target size with MozJPEG at quality {} or above", min_quality).into()),
         }
     }
     
+    #[cfg(feature = "webp")]
     fn webp_target_size(
         &self,
         image: &RgbaImage,
         target_bytes: u64,
         lossy: bool,
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        alpha_quality: Option<u8>,
+        options: &CompressionOptions,
+    ) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
         let (width, height) = image.dimensions();
         
         if lossy {
-            let mut low = 10.0f32;
+            let min_quality = options.min_quality.clamp(1, 95);
+            let mut low = min_quality as f32;
             let mut high = 95.0f32;
-            let mut best_result = Vec::new();
+            let mut search = TargetSearch::new(target_bytes, options.max_search_iterations);
             
-            while high - low > 1.0 {
+            while high - low > 1.0 && search.next_probe() {
                 let quality = (low + high) / 2.0;
                 
-                let encoder = WebPEncoder::from_rgba(image.as_raw(), width, height);
-                let memory = encoder.encode(quality);
-                let data = memory.to_vec();
+                let data = self.encode_webp_lossy(image, quality, alpha_quality, options)?;
+                trace!("WebP target search: quality {:.1} → {} bytes (target {})", quality, data.len(), target_bytes);
                 
-                if data.len() as u64 <= target_bytes {
-                    best_result = data;
+                if search.record(data) {
                     low = quality;
                 } else {
                     high = quality;
                 }
             }
             
-            if best_result.is_empty() {
-                Err("Could not achieve target file size with WebP".into())
-            } else {
-                Ok(best_result)
+            // The bisection only ever probes above low, so try the floor itself last
+            if !search.has_fit() && search.next_probe() {
+                let data = self.encode_webp_lossy(image, low, alpha_quality, options)?;
+                search.record(data);
+            }
+            
+            match search.finish() {
+                Some((data, capped)) => {
+                    debug!("WebP target search: best fit {} bytes (target {}, capped {})", data.len(), target_bytes, capped);
+                    Ok((data, capped))
+                }
+                None => Err(format!("Could not reach the target size with WebP at quality {} or above", min_quality).into()),
             }
         } else {
             // For lossless, we can't adjust quality, so just return the lossless result
             let encoder = WebPEncoder::from_rgba(image.as_raw(), width, height);
-            let memory = encoder.encode_lossless();
-            Ok(memory.to_vec())
+            let memory = encoder.encode_advanced(&webp_config(true, 75.0, options.webp_method)?)
+                .map_err(|e| format!("WebP encoding failed: {:?}", e))?;
+            Ok((memory.to_vec(), false))
         }
     }
     
-    fn avif_target_size(
+    // Without an explicit alpha quality libwebp ties the alpha plane to the main quality
+    #[cfg(feature = "webp")]
+    fn encode_webp_lossy(
         &self,
-        image: &DynamicImage,
-        target_bytes: u64,
+        image: &RgbaImage,
+        quality: f32,
+        alpha_quality: Option<u8>,
+        options: &CompressionOptions,
     ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        // Since ravif doesn't easily support quality adjustment,
-        // we'll just return a single encoding
-        let rgba_image = image.to_rgba8();
-        let (width, height) = rgba_image.dimensions();
-        
-        let pixels: Vec<RGBA8> = rgba_image
-            .pixels()
-            .map(|p| RGBA8 {
-                r: p[0],
-                g: p[1],
-                b: p[2],
-                a: p[3],
-            })
-            .collect();
-        
-        let img = ImgVec::new(pixels, width as usize, height as usize);
-        
-        let encoder = AvifEncoder::new();
-        let encoded = encoder.encode_rgba(img.as_ref())?;
+        let (width, height) = image.dimensions();
+        let encoder = WebPEncoder::from_rgba(image.as_raw(), width, height);
         
-        if encoded.avif_file.len() as u64 <= target_bytes {
-            Ok(encoded.avif_file)
-        } else {
-            Err("AVIF file exceeds target size".into())
+        let mut config = webp_config(false, quality, options.webp_method)?;
+        config.filter_strength = options.webp_filter_strength.min(100) as i32;
+        config.filter_sharpness = options.webp_sharpness.min(7) as i32;
+        if let Some(alpha_quality) = alpha_quality {
+            config.alpha_quality = alpha_quality.min(100) as i32;
         }
+        
+        let memory = encoder.encode_advanced(&config)
+            .map_err(|e| format!("WebP encoding failed: {:?}", e))?;
+        Ok(memory.to_vec())
     }
     
     // Existing helper methods remain the same...
@@ -653,15 +1328,12 @@ impl SmartCompressor {
     
     fn count_unique_colors(&self, image: &image::RgbaImage, max_sample: usize) -> usize {
         let mut colors = HashSet::new();
-        let pixels: Vec<&Rgba<u8>> = image.pixels().collect();
-        let step = (pixels.len() / max_sample).max(1);
-        
-        for (i, pixel) in pixels.iter().enumerate() {
-            if i % step == 0 {
-                colors.insert([pixel[0], pixel[1], pixel[2]]);
-                if colors.len() >= max_sample {
-                    break;
-                }
+        let step = (image.pixels().len() / max_sample).max(1);
+        
+        for pixel in image.pixels().step_by(step) {
+            colors.insert([pixel[0], pixel[1], pixel[2]]);
+            if colors.len() >= max_sample {
+                break;
             }
         }
         
@@ -674,34 +1346,47 @@ impl SmartCompressor {
         let mut total_diff = 0.0;
         let mut sample_count = 0;
         
-        // Sample pixels to detect gradients
-        for y in 0..height.saturating_sub(1) {
-            for x in 0..width.saturating_sub(1) {
-                // Sample every 4th pixel for performance
-                if x % 4 == 0 && y % 4 == 0 {
-                    let p1 = image.get_pixel(x, y);
-                    let p2 = image.get_pixel(x + 1, y);
-                    let p3 = image.get_pixel(x, y + 1);
-                    
-                    let diff1 = self.color_distance(p1, p2);
-                    let diff2 = self.color_distance(p1, p3);
-                    
-                    total_diff += diff1 + diff2;
-                    sample_count += 2;
-                    
-                    if diff1 > 10.0 || diff2 > 10.0 {
-                        gradient_pixels += 1;
-                    }
+        let stride = self.complexity_stride(width, height) as usize;
+        
+        // Sample every Nth pixel in each direction to detect gradients
+        for y in (0..height.saturating_sub(1)).step_by(stride) {
+            for x in (0..width.saturating_sub(1)).step_by(stride) {
+                let p1 = image.get_pixel(x, y);
+                let p2 = image.get_pixel(x + 1, y);
+                let p3 = image.get_pixel(x, y + 1);
+                
+                let diff1 = self.color_distance(p1, p2);
+                let diff2 = self.color_distance(p1, p3);
+                
+                total_diff += diff1 + diff2;
+                sample_count += 2;
+                
+                let threshold = self.config.gradient_pixel_difference;
+                if diff1 > threshold || diff2 > threshold {
+                    gradient_pixels += 1;
                 }
             }
         }
         
-        let has_gradients = gradient_pixels > (sample_count / 10);
+        let has_gradients = gradient_pixels as f32 > sample_count as f32 * self.config.gradient_ratio;
         let complexity = total_diff / sample_count as f32;
         
         (has_gradients, complexity)
     }
     
+    // The configured stride, or the one that samples about complexity_sample_target
+    // pixels: 1 for small images, growing with the square root of the pixel count
+    fn complexity_stride(&self, width: u32, height: u32) -> u32 {
+        match self.config.complexity_sample_stride {
+            Some(stride) => stride.max(1),
+            None => {
+                let pixels = width as f64 * height as f64;
+                let target = self.config.complexity_sample_target.max(1) as f64;
+                (pixels / target).sqrt().ceil().max(1.0) as u32
+            }
+        }
+    }
+    
     fn color_distance(&self, c1: &Rgba<u8>, c2: &Rgba<u8>) -> f32 {
         let dr = c1[0] as f32 - c2[0] as f32;
         let dg = c1[1] as f32 - c2[1] as f32;
@@ -748,29 +1433,37 @@ impl SmartCompressor {
         &self,
         image: &image::RgbImage,
         target_bytes: u64,
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        options: &CompressionOptions,
+    ) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
         let (width, height) = image.dimensions();
-        let mut low = 10u8;
+        let min_quality = options.min_quality.clamp(1, 95);
+        let mut low = min_quality;
         let mut high = 95u8;
-        let mut best_result = Vec::new();
+        let mut search = TargetSearch::new(target_bytes, options.max_search_iterations);
         
-        while low <= high {
+        while low <= high && search.next_probe() {
             let quality = (low + high) / 2;
             let mut temp_data = Vec::new();
             let mut cursor = Cursor::new(&mut temp_data);
             
             let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut cursor, quality);
             encoder.encode(image, width, height, image::ColorType::Rgb8)?;
+            trace!("JPEG target search: quality {} → {} bytes (target {})", quality, temp_data.len(), target_bytes);
             
-            if temp_data.len() as u64 <= target_bytes {
-                best_result = temp_data;
+            if search.record(temp_data) {
                 low = quality + 1;
             } else {
                 high = quality - 1;
             }
         }
         
-        Ok(best_result)
+        match search.finish() {
+            Some((data, capped)) => {
+                debug!("JPEG target search: best fit {} bytes (target {}, capped {})", data.len(), target_bytes, capped);
+                Ok((data, capped))
+            }
+            None => Err(format!("Could not reach the target size with JPEG at quality {} or above", min_quality).into()),
+        }
     }
     
     fn calculate_ratio(&self, original: &DynamicImage, compressed: &[u8]) -> f32 {
@@ -785,26 +1478,56 @@ impl SmartCompressor {
             DynamicImage::ImageLumaA8(_) => 2,
             DynamicImage::ImageRgb8(_) => 3,
             DynamicImage::ImageRgba8(_) => 4,
+            DynamicImage::ImageLuma16(_) => 2,
+            DynamicImage::ImageLumaA16(_) => 4,
+            DynamicImage::ImageRgb16(_) => 6,
+            DynamicImage::ImageRgba16(_) => 8,
             _ => 4,
         };
         (width * height * bytes_per_pixel) as usize
     }
+    
+    // The 8-bit equivalent of a 16-bit-per-channel image, keeping its channels;
+    // None for anything that is already 8-bit
+    fn reduce_to_8bit(&self, image: &DynamicImage) -> Option<DynamicImage> {
+        match image {
+            DynamicImage::ImageLuma16(_) => Some(DynamicImage::ImageLuma8(image.to_luma8())),
+            DynamicImage::ImageLumaA16(_) => Some(DynamicImage::ImageLumaA8(image.to_luma_alpha8())),
+            DynamicImage::ImageRgb16(_) => Some(DynamicImage::ImageRgb8(image.to_rgb8())),
+            DynamicImage::ImageRgba16(_) => Some(DynamicImage::ImageRgba8(image.to_rgba8())),
+            _ => None,
+        }
+    }
 }
 
 // Algorithm descriptions for UI
 impl CompressionAlgorithm {
+    // Every concrete encoder; Auto, AutoBest, ByAlpha and Simple only choose between these
+    pub const ENCODERS: [CompressionAlgorithm; 8] = [
+        Self::Jpeg,
+        Self::StandardPng,
+        Self::OptiPng,
+        Self::OxiPng,
+        Self::PngQuant,
+        Self::WebPLossy,
+        Self::WebPLossless,
+        Self::Avif,
+    ];
+    
     pub fn description(&self) -> &'static str {
         match self {
             Self::Auto => "Automatically select best algorithm based on image analysis",
+            Self::AutoBest => "Try the most promising encoders and keep the smallest (slower)",
+            Self::ByAlpha => "One format for opaque images and another for transparent ones",
             Self::Simple => "Use lowest acceptable image quality",
-            Self::StandardJpeg => "Standard JPEG compression (fast, good quality)",
-            Self::MozJpeg => "Mozilla JPEG encoder (10-15% better compression)",
+            Self::Jpeg => "JPEG, with MozJPEG for 10-15% better compression or image-rs for speed",
             Self::StandardPng => "Standard PNG compression (lossless)",
             Self::OptiPng => "Optimized PNG (smaller files, lossless)",
             Self::OxiPng => "Fast optimized PNG (good balance)",
             Self::PngQuant => "Lossy PNG (up to 70% smaller, slight quality loss)",
             Self::WebPLossy => "WebP lossy (25-35% better than JPEG)",
             Self::WebPLossless => "WebP lossless (better than PNG)",
+            Self::LosslessFirst => "WebP lossless, falling back to lossy when over the size limit",
             Self::Avif => "AV1 Image Format (best compression, slower)",
         }
     }
@@ -812,27 +1535,598 @@ impl CompressionAlgorithm {
     pub fn supports_quality(&self) -> bool {
         matches!(
             self,
-            Self::StandardJpeg | Self::MozJpeg | Self::WebPLossy | Self::Avif
+            Self::ByAlpha | Self::Jpeg | Self::WebPLossy | Self::LosslessFirst | Self::Avif
+        )
+    }
+    
+    // These binary-search the quality when a target size is set, overriding the slider
+    pub fn searches_quality_for_target_size(&self) -> bool {
+        matches!(
+            self,
+            Self::Jpeg | Self::WebPLossy | Self::LosslessFirst
+        )
+    }
+    
+    // The lossy encoders adaptive_quality is tuned for
+    pub fn adapts_quality(&self) -> bool {
+        matches!(self, Self::Jpeg | Self::WebPLossy | Self::LosslessFirst)
+    }
+    
+    // Formats that older browsers may lack and that warrant a JPEG/PNG fallback
+    pub fn is_modern_format(&self) -> bool {
+        matches!(
+            self,
+            Self::WebPLossy | Self::WebPLossless | Self::LosslessFirst | Self::Avif
         )
     }
     
+    // False for JPEG, which flattens alpha onto the matte. Simple mode keeps the
+    // source's format or falls back to PNG, and only writes JPEG under a target size.
+    pub fn keeps_transparency(&self) -> bool {
+        *self != Self::Jpeg
+    }
+    
     pub fn recommended_quality(&self) -> u8 {
         match self {
-            Self::StandardJpeg | Self::MozJpeg => 85,
-            Self::WebPLossy => 90,
+            Self::ByAlpha | Self::Jpeg => 85,
+            Self::WebPLossy | Self::LosslessFirst => 90,
             Self::Avif => 80,
             _ => 100,
         }
     }
     
+    // Short names for configuration, e.g. "jpeg", "webp-lossless", "auto-best"; case,
+    // hyphens and underscores don't matter
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name: String = name
+            .trim()
+            .chars()
+            .filter(|c| !matches!(c, '-' | '_' | ' '))
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "auto" => Some(Self::Auto),
+            "autobest" => Some(Self::AutoBest),
+            "byalpha" | "bytransparency" => Some(Self::ByAlpha),
+            "simple" => Some(Self::Simple),
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            "png" | "standardpng" => Some(Self::StandardPng),
+            "optipng" => Some(Self::OptiPng),
+            "oxipng" => Some(Self::OxiPng),
+            "pngquant" => Some(Self::PngQuant),
+            "webp" | "webplossy" => Some(Self::WebPLossy),
+            "webplossless" => Some(Self::WebPLossless),
+            "losslessfirst" | "webplosslessfirst" => Some(Self::LosslessFirst),
+            "avif" => Some(Self::Avif),
+            _ => None,
+        }
+    }
+    
+    // False when the native encoder behind the algorithm was left out of the build
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::OxiPng => cfg!(feature = "oxipng"),
+            Self::WebPLossy | Self::WebPLossless | Self::LosslessFirst => cfg!(feature = "webp"),
+            Self::Avif => cfg!(feature = "avif"),
+            _ => true,
+        }
+    }
+    
+    // The algorithm compress uses in its place: itself when built in, otherwise the
+    // nearest pure-Rust encoder (lossless stays lossless)
+    pub fn fallback(&self) -> Self {
+        if self.is_available() {
+            return *self;
+        }
+        match self {
+            Self::OxiPng | Self::WebPLossless | Self::LosslessFirst => Self::OptiPng,
+            Self::WebPLossy => Self::Jpeg,
+            Self::Avif => Self::WebPLossy.fallback(),
+            other => *other,
+        }
+    }
+    
+    // The short name from_name accepts, e.g. "webp-lossless"; tags output names by format
+    pub fn name_token(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::AutoBest => "auto-best",
+            Self::ByAlpha => "by-alpha",
+            Self::Simple => "simple",
+            Self::Jpeg => "jpeg",
+            Self::StandardPng => "png",
+            Self::OptiPng => "optipng",
+            Self::OxiPng => "oxipng",
+            Self::PngQuant => "pngquant",
+            Self::WebPLossy => "webp",
+            Self::WebPLossless => "webp-lossless",
+            Self::LosslessFirst => "lossless-first",
+            Self::Avif => "avif",
+        }
+    }
+    
     pub fn file_extension(&self) -> &'static str {
         match self {
-            Self::Auto => "jpg",
+            Self::Auto | Self::AutoBest | Self::ByAlpha => "jpg",
             Self::Simple => "jpg",
-            Self::StandardJpeg | Self::MozJpeg => "jpg",
+            Self::Jpeg => "jpg",
             Self::StandardPng | Self::OptiPng | Self::OxiPng | Self::PngQuant => "png",
-            Self::WebPLossy | Self::WebPLossless => "webp",
+            Self::WebPLossy | Self::WebPLossless | Self::LosslessFirst => "webp",
             Self::Avif => "avif",
         }
     }
+}
+
+// Probe bookkeeping shared by the target-size searches: the latest output under the
+// target, which is the highest quality tried that fits, and the smallest one over it
+struct TargetSearch {
+    target_bytes: u64,
+    max_probes: Option<u8>,
+    probes: u8,
+    capped: bool,
+    best_fit: Vec<u8>,
+    smallest_miss: Vec<u8>,
+}
+
+impl TargetSearch {
+    fn new(target_bytes: u64, max_probes: Option<u8>) -> Self {
+        Self {
+            target_bytes,
+            max_probes,
+            probes: 0,
+            capped: false,
+            best_fit: Vec::new(),
+            smallest_miss: Vec::new(),
+        }
+    }
+    
+    // Counts one more probe; false, and the search is capped, once the limit is reached
+    fn next_probe(&mut self) -> bool {
+        if self.max_probes.is_some_and(|max| self.probes >= max) {
+            self.capped = true;
+            return false;
+        }
+        self.probes += 1;
+        true
+    }
+    
+    // Keeps the probe's output and returns whether it fits the target
+    fn record(&mut self, data: Vec<u8>) -> bool {
+        if data.len() as u64 <= self.target_bytes {
+            self.best_fit = data;
+            true
+        } else {
+            if self.smallest_miss.is_empty() || data.len() < self.smallest_miss.len() {
+                self.smallest_miss = data;
+            }
+            false
+        }
+    }
+    
+    fn has_fit(&self) -> bool {
+        !self.best_fit.is_empty()
+    }
+    
+    // The best fit, or a capped search's smallest miss, with whether the cap was hit.
+    // None when a search that ran to the end found nothing under the target.
+    fn finish(self) -> Option<(Vec<u8>, bool)> {
+        if self.has_fit() {
+            Some((self.best_fit, self.capped))
+        } else if self.capped && !self.smallest_miss.is_empty() {
+            Some((self.smallest_miss, true))
+        } else {
+            None
+        }
+    }
+}
+
+// The settings the webp crate's simple encoders use, plus the effort level
+#[cfg(feature = "webp")]
+fn webp_config(lossless: bool, quality: f32, method: u8) -> Result<WebPConfig, Box<dyn std::error::Error>> {
+    let mut config = WebPConfig::new().map_err(|_| "Failed to initialize WebP config")?;
+    config.lossless = lossless as i32;
+    config.alpha_compression = !lossless as i32;
+    config.quality = quality;
+    config.method = method.min(6) as i32;
+    Ok(config)
+}
+
+// MozJPEG's defaults are progressive with optimized tables. jpeg_set_defaults, which
+// set_scan_optimization_mode runs, also resets the quality, so quality is set last
+#[cfg(feature = "mozjpeg")]
+fn mozjpeg_compressor(width: u32, height: u32, quality: f32, mode: JpegMode, chroma_subsampling: bool) -> Compress {
+    let mut compress = Compress::new(ColorSpace::JCS_RGB);
+    match mode {
+        JpegMode::Baseline => {
+            compress.set_optimize_scans(false);
+            compress.set_optimize_coding(false);
+        }
+        JpegMode::BaselineOptimized => {
+            compress.set_optimize_scans(false);
+            compress.set_optimize_coding(true);
+        }
+        JpegMode::Progressive => {
+            compress.set_scan_optimization_mode(ScanMode::AllComponentsTogether);
+            compress.set_progressive_mode();
+        }
+    }
+    compress.set_size(width as usize, height as usize);
+    if !chroma_subsampling {
+        compress.set_chroma_sampling_pixel_sizes((1, 1), (1, 1));
+    }
+    compress.set_quality(quality);
+    
+    // set_quality lets low qualities scale table entries past 255, which turns the file
+    // into extended sequential (SOF1). The same default table (NRobidoux) added through
+    // set_*_qtable is clamped to 255, keeping the output true baseline.
+    if mode != JpegMode::Progressive {
+        let table = mozjpeg::qtable::NRobidoux.scaled(quality, quality);
+        compress.set_luma_qtable(&table);
+        compress.set_chroma_qtable(&table);
+    }
+    compress
+}
+
+// Every algorithm whose encoder is compiled into this build, in pick list order
+pub fn available_algorithms() -> Vec<CompressionAlgorithm> {
+    [
+        CompressionAlgorithm::Auto,
+        CompressionAlgorithm::AutoBest,
+        CompressionAlgorithm::ByAlpha,
+        CompressionAlgorithm::Simple,
+        CompressionAlgorithm::Jpeg,
+        CompressionAlgorithm::StandardPng,
+        CompressionAlgorithm::OptiPng,
+        CompressionAlgorithm::OxiPng,
+        CompressionAlgorithm::PngQuant,
+        CompressionAlgorithm::WebPLossy,
+        CompressionAlgorithm::WebPLossless,
+        CompressionAlgorithm::LosslessFirst,
+        CompressionAlgorithm::Avif,
+    ]
+    .into_iter()
+    .filter(|algorithm| algorithm.is_available())
+    .collect()
+}
+
+// JPEG/WebP quality from the image's detail, measured by analyze_complexity as the
+// mean RGB distance between neighbouring pixels (0 for a flat fill, ~441 at most):
+//   complexity <= 2   flat graphics, screenshots, clean scans    quality 70
+//   complexity 2..24  linear in between                          quality 70-92
+//   complexity >= 24  dense texture, foliage, noisy photos       quality 92
+// Fine detail is what low qualities visibly smear, so quality only rises with it
+pub fn adaptive_quality(analysis: &ImageAnalysis) -> u8 {
+    const FLAT: f32 = 2.0;
+    const DETAILED: f32 = 24.0;
+    const LOW: f32 = 70.0;
+    const HIGH: f32 = 92.0;
+    
+    let t = ((analysis.average_complexity - FLAT) / (DETAILED - FLAT)).clamp(0.0, 1.0);
+    (LOW + t * (HIGH - LOW)).round() as u8
+}
+
+// Interlace method byte of the IHDR chunk, which always directly follows the signature
+pub fn is_interlaced_png(data: &[u8]) -> bool {
+    data.len() > 28 && &data[12..16] == b"IHDR" && data[28] == 1
+}
+
+// AV1 at 10 bits from 16-bit samples, where encode_rgba only ever sees 8 bits.
+// Colour goes through the same BT.601 full-range YCbCr that ravif uses itself.
+#[cfg(feature = "avif")]
+fn encode_avif_16bit(encoder: &AvifEncoder, image: &image::ImageBuffer<Rgba<u16>, Vec<u16>>) -> Result<EncodedImage, ravif::Error> {
+    const KR: f32 = 0.299;
+    const KB: f32 = 0.114;
+    let scale = 1023.0 / 65535.0;
+    let to_ten = |value: f32| value.round().clamp(0.0, 1023.0) as u16;
+    let planes = image.pixels().map(|pixel| {
+        let [r, g, b, _] = pixel.0.map(|channel| channel as f32 * scale);
+        let y = KR * r + (1.0 - KR - KB) * g + KB * b;
+        let cb = (b - y) * 0.5 / (1.0 - KB) + 512.0;
+        let cr = (r - y) * 0.5 / (1.0 - KR) + 512.0;
+        [to_ten(y), to_ten(cb), to_ten(cr)]
+    });
+    let alpha = image.pixels().any(|pixel| pixel[3] < u16::MAX).then(|| image.pixels().map(|pixel| pixel[3] >> 6));
+    encoder.encode_raw_planes_10_bit(
+        image.width() as usize,
+        image.height() as usize,
+        planes,
+        alpha,
+        rav1e::prelude::PixelRange::Full,
+        ravif::MatrixCoefficients::BT601,
+    )
+}
+
+// True for the DynamicImage variants that store 16 bits per channel
+pub fn is_16_bit(image: &DynamicImage) -> bool {
+    matches!(
+        image,
+        DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_)
+    )
+}
+
+// Composites transparent pixels over the matte color. A plain to_rgb8() would
+// expose whatever color data sits under transparent pixels (usually black).
+pub fn flatten_alpha(image: &DynamicImage, background: [u8; 3]) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+    
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let mut rgb = RgbImage::new(width, height);
+    
+    for (src, dst) in rgba.pixels().zip(rgb.pixels_mut()) {
+        let alpha = src[3] as u16;
+        for c in 0..3 {
+            dst[c] = ((src[c] as u16 * alpha + background[c] as u16 * (255 - alpha) + 127) / 255) as u8;
+        }
+    }
+    
+    rgb
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbaImage};
+    
+    fn encoded_png(image: &RgbaImage) -> Vec<u8> {
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(image.clone()).write_to(&mut png, ImageFormat::Png).unwrap();
+        png.into_inner()
+    }
+    
+    #[test]
+    fn compresses_png_bytes_in_memory() {
+        let source = RgbaImage::from_fn(64, 48, |x, y| Rgba([(x * 4) as u8, (y * 5) as u8, 128, 255]));
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        let options = CompressionOptions {
+            algorithm: CompressionAlgorithm::StandardPng,
+            ..CompressionOptions::default()
+        };
+        let result = compressor.compress_bytes(&encoded_png(&source), options).unwrap();
+        let decoded = image::load_from_memory(&result.data).unwrap();
+        assert_eq!(decoded.to_rgba8(), source);
+    }
+    
+    #[cfg(feature = "oxipng")]
+    #[test]
+    fn interlaces_png_only_within_the_target_size() {
+        let source = DynamicImage::ImageRgb8(RgbImage::from_fn(96, 64, |x, y| {
+            Rgb([(x * 7 + y * 3) as u8, (x * y) as u8, ((x ^ y) * 4) as u8])
+        }));
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        let options = CompressionOptions {
+            algorithm: CompressionAlgorithm::OxiPng,
+            interlace: true,
+            ..CompressionOptions::default()
+        };
+        
+        let interlaced = compressor.compress(&source, options.clone()).unwrap();
+        assert!(is_interlaced_png(&interlaced.data));
+        let plain = compressor.compress(&source, CompressionOptions { interlace: false, ..options.clone() }).unwrap();
+        assert!(!is_interlaced_png(&plain.data));
+        assert!(interlaced.data.len() > plain.data.len());
+        
+        let capped = compressor
+            .compress(&source, CompressionOptions { target_size: Some(plain.data.len() as u64), ..options })
+            .unwrap();
+        assert!(!is_interlaced_png(&capped.data));
+        assert_eq!(capped.data.len(), plain.data.len());
+    }
+    
+    fn contains(data: &[u8], pattern: &[u8]) -> bool {
+        data.windows(pattern.len()).any(|window| window == pattern)
+    }
+    
+    #[test]
+    fn tags_jpeg_and_png_output_as_srgb() {
+        let source = DynamicImage::ImageRgb8(RgbImage::from_fn(32, 24, |x, y| Rgb([(x * 8) as u8, (y * 10) as u8, 90])));
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        // The profile description, as the UTF-16 text of its mluc tag
+        let srgb_name = b"\0s\0R\0G\0B";
+        for (algorithm, marker) in [
+            (CompressionAlgorithm::Jpeg, b"ICC_PROFILE\0".as_slice()),
+            (CompressionAlgorithm::StandardPng, b"iCCP".as_slice()),
+        ] {
+            let options = CompressionOptions {
+                algorithm,
+                embed_srgb: true,
+                ..CompressionOptions::default()
+            };
+            let result = compressor.compress(&source, options).unwrap();
+            assert!(contains(&result.data, marker), "{} has no profile", algorithm);
+            if algorithm == CompressionAlgorithm::Jpeg {
+                assert!(contains(&result.data, srgb_name));
+            }
+        }
+    }
+    
+    #[test]
+    fn preserved_exif_and_xmp_reach_the_output() {
+        let source = DynamicImage::ImageRgb8(RgbImage::from_pixel(32, 24, Rgb([200, 120, 40])));
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        let preserved = metadata::SourceMetadata {
+            exif: Some(b"II*\0\x08\0\0\0\0\0\0\0\0\0".to_vec()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+        };
+        let options = CompressionOptions {
+            algorithm: CompressionAlgorithm::Jpeg,
+            preserve_metadata: true,
+            source_metadata: Some(Arc::new(preserved.clone())),
+            ..CompressionOptions::default()
+        };
+        let result = compressor.compress(&source, options.clone()).unwrap();
+        assert!(contains(&result.data, b"Exif\0\0II*\0"));
+        assert!(contains(&result.data, b"<x:xmpmeta/>"));
+        
+        let dropped = compressor.compress(&source, CompressionOptions { preserve_metadata: false, ..options }).unwrap();
+        assert!(!contains(&dropped.data, b"<x:xmpmeta/>"));
+    }
+    
+    // A smooth ramp with noise, like a photograph at any resolution
+    fn photo_like(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let noise = ((x.wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503)) >> 7) as u8 % 48;
+            let base = ((x as u64 * 160 / width as u64) + (y as u64 * 60 / height as u64)) as u8;
+            Rgba([base.saturating_add(noise), base / 2 + noise, 200 - base / 2, 255])
+        })
+    }
+    
+    #[test]
+    fn complexity_sampling_is_bounded_by_the_target() {
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        let target = compressor.config.complexity_sample_target as u64;
+        for (width, height) in [(64, 48), (4000, 3000), (8660, 5774), (20000, 15000)] {
+            let stride = compressor.complexity_stride(width, height) as u64;
+            let samples = (width as u64).div_ceil(stride) * (height as u64).div_ceil(stride);
+            assert!(samples <= target * 11 / 10, "{}×{} samples {}", width, height, samples);
+        }
+        assert_eq!(compressor.complexity_stride(64, 48), 1);
+    }
+    
+    // 50 MP against 2 MP: the sampled pixel count is the same, so the analysis time
+    // should be too. Run with `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn bench_complexity_analysis_at_50_megapixels() {
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        let fixed = SmartCompressor::new(SmartCompressorConfig {
+            complexity_sample_stride: Some(4),
+            ..SmartCompressorConfig::default()
+        });
+        let time = |compressor: &SmartCompressor, image: &RgbaImage| {
+            let started = Instant::now();
+            let mut result = (false, 0.0);
+            for _ in 0..5 {
+                result = compressor.analyze_complexity(image);
+            }
+            (result, started.elapsed() / 5)
+        };
+        
+        let small = photo_like(1732, 1155);
+        let large = photo_like(8660, 5774);
+        let (small_result, small_time) = time(&compressor, &small);
+        let (large_result, large_time) = time(&compressor, &large);
+        let (fixed_result, fixed_time) = time(&fixed, &large);
+        
+        assert_eq!(large_result.0, fixed_result.0);
+        assert_eq!(small_result.0, large_result.0);
+        assert!(large_time < fixed_time, "50 MP adaptive {:?} vs every 4th pixel {:?}", large_time, fixed_time);
+        assert!(large_time < small_time * 4, "50 MP adaptive {:?} vs 2 MP adaptive {:?}", large_time, small_time);
+    }
+    
+    // The default libwebp effort (method 4) with lossless on is what encode_lossless
+    // uses, so routing lossless WebP through webp_config didn't change its output
+    #[cfg(feature = "webp")]
+    #[test]
+    fn default_webp_method_matches_encode_lossless() {
+        let source = photo_like(80, 60);
+        let (width, height) = source.dimensions();
+        let encoder = WebPEncoder::from_rgba(source.as_raw(), width, height);
+        let plain = encoder.encode_lossless().to_vec();
+        let configured = encoder.encode_advanced(&webp_config(true, 75.0, 4).unwrap()).unwrap().to_vec();
+        assert_eq!(configured, plain);
+        
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        let options = CompressionOptions {
+            algorithm: CompressionAlgorithm::WebPLossless,
+            ..CompressionOptions::default()
+        };
+        let result = compressor.compress_bytes(&encoded_png(&source), options).unwrap();
+        assert_eq!(result.data, plain);
+    }
+    
+    #[cfg(feature = "avif")]
+    #[test]
+    fn avif_reports_the_quality_it_was_given() {
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        let options = CompressionOptions {
+            algorithm: CompressionAlgorithm::Avif,
+            quality: Some(60),
+            avif_speed: 10,
+            ..CompressionOptions::default()
+        };
+        let result = compressor.compress(&DynamicImage::ImageRgba8(photo_like(32, 24)), options).unwrap();
+        assert_eq!(result.final_quality, Some(60));
+    }
+    
+    #[test]
+    fn minimal_palette_shrinks_a_sixteen_color_diagram_losslessly() {
+        let colors: Vec<Rgba<u8>> = (0..16u8).map(|i| Rgba([i * 16, 255 - i * 16, i * 13, 255])).collect();
+        let source = RgbaImage::from_fn(96, 64, |x, y| {
+            if x % 24 == 0 || y % 16 == 0 {
+                colors[15]
+            } else {
+                colors[((x / 24 + y / 16 * 4) % 15) as usize]
+            }
+        });
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        let encode = |minimal_palette| {
+            let options = CompressionOptions {
+                algorithm: CompressionAlgorithm::StandardPng,
+                minimal_palette,
+                ..CompressionOptions::default()
+            };
+            compressor.compress(&DynamicImage::ImageRgba8(source.clone()), options).unwrap().data
+        };
+        let plain = encode(false);
+        let reduced = encode(true);
+        assert!(reduced.len() < plain.len(), "{} vs {}", reduced.len(), plain.len());
+        assert_eq!(image::load_from_memory(&reduced).unwrap().to_rgba8(), source);
+    }
+    
+    // Every field written out, so a new option has to be placed here too; at their
+    // defaults they encode exactly like CompressionOptions::default()
+    #[test]
+    fn every_field_spelled_out() {
+        let options = CompressionOptions {
+            algorithm: CompressionAlgorithm::WebPLossless,
+            quality: None,
+            target_size: None,
+            preserve_metadata: false,
+            optimize_for_web: true,
+            jpeg_background: [255, 255, 255],
+            lossless_size_limit: None,
+            opaque_algorithm: CompressionAlgorithm::Jpeg,
+            transparent_algorithm: CompressionAlgorithm::OxiPng,
+            alpha_quality: None,
+            dpi: None,
+            minimal_palette: false,
+            reduce_16bit_png: false,
+            keep_bit_depth: false,
+            oxipng_level: 3,
+            oxipng_multithreaded: true,
+            interlace: false,
+            min_quality: 10,
+            max_search_iterations: None,
+            jpeg_encoder: JpegEncoder::default(),
+            jpeg_mode: JpegMode::default(),
+            jpeg_chroma_subsampling: true,
+            webp_method: 4,
+            webp_filter_strength: 60,
+            webp_sharpness: 0,
+            avif_speed: 4,
+            adaptive_quality: false,
+            embed_srgb: false,
+            source_icc_profile: None,
+            source_metadata: None,
+            embed_thumbnail: false,
+        };
+        let defaults = CompressionOptions {
+            algorithm: CompressionAlgorithm::WebPLossless,
+            ..CompressionOptions::default()
+        };
+        
+        let source = encoded_png(&photo_like(40, 30));
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        let spelled_out = compressor.compress_bytes(&source, options).unwrap();
+        let default = compressor.compress_bytes(&source, defaults).unwrap();
+        assert_eq!(spelled_out.data, default.data);
+        assert_eq!(spelled_out.format, default.format);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,47 @@
+// dedupe.rs - Perceptual-hash near-duplicate detection across a batch
+
+use image::DynamicImage;
+use image_hasher::{HasherConfig, ImageHash};
+use std::sync::Mutex;
+
+// Remembers the hash of every image checked in a batch. Shared by all of the
+// batch's file tasks, so one instance is created per Process run.
+pub struct DuplicateFilter {
+    threshold: u32,
+    seen: Mutex<Vec<(String, ImageHash)>>,
+}
+
+impl DuplicateFilter {
+    // `threshold` is the largest Hamming distance (out of 64 bits) still treated as a duplicate
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            seen: Mutex::new(Vec::new()),
+        }
+    }
+    
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+    
+    // Name of an earlier image within the threshold; otherwise records this one and returns None
+    pub fn check(&self, name: &str, image: &DynamicImage) -> Option<String> {
+        let hash = HasherConfig::new().to_hasher().hash_image(image);
+        let mut seen = self.seen.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        
+        if let Some((original, _)) = seen.iter().find(|(_, other)| hash.dist(other) <= self.threshold) {
+            return Some(original.clone());
+        }
+        
+        seen.push((name.to_string(), hash));
+        None
+    }
+}
+
+impl std::fmt::Debug for DuplicateFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DuplicateFilter")
+            .field("threshold", &self.threshold)
+            .finish_non_exhaustive()
+    }
+}
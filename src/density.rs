@@ -0,0 +1,271 @@
+// density.rs - Print density (DPI) tags for encoded JPEG and PNG data
+//
+// Only the density metadata changes; the pixel data is copied through untouched.
+// Print sizes entered in inches or centimeters are turned into pixels here too.
+
+use image::ImageFormat;
+
+// Above this the output would be wider than JPEG and WebP can store
+const MAX_PRINT_PIXELS: f64 = 65_535.0;
+
+// Upper bound on what tagging adds (a pHYs chunk, or a JFIF segment for a JPEG
+// that has none), which target size searches leave room for
+pub const EMBED_OVERHEAD: u64 = 21;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrintUnit {
+    #[default]
+    Inches,
+    Centimeters,
+}
+
+impl PrintUnit {
+    pub const ALL: [PrintUnit; 2] = [PrintUnit::Inches, PrintUnit::Centimeters];
+    
+    fn per_inch(self) -> f64 {
+        match self {
+            Self::Inches => 1.0,
+            Self::Centimeters => 2.54,
+        }
+    }
+}
+
+impl std::fmt::Display for PrintUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Inches => write!(f, "in"),
+            Self::Centimeters => write!(f, "cm"),
+        }
+    }
+}
+
+// A print size and the density it's printed at, e.g. 4×6 in at 300 DPI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalSize {
+    pub width: f64,
+    pub height: f64,
+    pub unit: PrintUnit,
+    pub dpi: u16,
+}
+
+impl PhysicalSize {
+    // 4 in at 300 DPI is 1200 px; 10 cm at 300 DPI is 1181 px
+    pub fn pixels(&self) -> (u32, u32) {
+        (to_pixels(self.width, self.unit, self.dpi), to_pixels(self.height, self.unit, self.dpi))
+    }
+}
+
+fn to_pixels(length: f64, unit: PrintUnit, dpi: u16) -> u32 {
+    (length / unit.per_inch() * dpi as f64).round() as u32
+}
+
+// None when both sides are blank. Sizes must be positive numbers ("," works as the
+// decimal point) and come out between 1 and 65535 pixels at a DPI of 1 or more.
+pub fn parse_physical_size(width: &str, height: &str, unit: PrintUnit, dpi: &str) -> Result<Option<PhysicalSize>, String> {
+    let (width, height) = (width.trim(), height.trim());
+    if width.is_empty() && height.is_empty() {
+        return Ok(None);
+    }
+    
+    let length = |value: &str, side: &str| match value.replace(',', ".").parse::<f64>() {
+        Ok(length) if length.is_finite() && length > 0.0 => Ok(length),
+        _ if value.is_empty() => Err(format!("enter a {} too", side)),
+        _ => Err(format!("\"{}\" isn't a {} in {}", value, side, unit)),
+    };
+    let width = length(width, "width")?;
+    let height = length(height, "height")?;
+    let dpi = match dpi.trim().parse::<u16>() {
+        Ok(dpi) if dpi > 0 => dpi,
+        _ => return Err(String::from("needs a DPI between 1 and 65535")),
+    };
+    
+    for (side, length) in [("width", width), ("height", height)] {
+        let pixels = length / unit.per_inch() * dpi as f64;
+        if pixels.round() < 1.0 {
+            return Err(format!("{} {} {} is under a pixel at {} DPI", side, length, unit, dpi));
+        }
+        if pixels.round() > MAX_PRINT_PIXELS {
+            return Err(format!("{} {} {} at {} DPI is over 65535 px", side, length, unit, dpi));
+        }
+    }
+    
+    Ok(Some(PhysicalSize { width, height, unit, dpi }))
+}
+
+// Formats with a density tag
+pub fn supports(format: ImageFormat) -> bool {
+    matches!(format, ImageFormat::Jpeg | ImageFormat::Png)
+}
+
+// Returns the data with its density set to `dpi`, or unchanged for other formats
+// and for data that doesn't parse as the claimed format
+pub fn set_dpi(data: Vec<u8>, format: ImageFormat, dpi: u16) -> Vec<u8> {
+    match format {
+        ImageFormat::Jpeg => set_jpeg_dpi(data, dpi),
+        ImageFormat::Png => set_png_dpi(data, dpi),
+        _ => data,
+    }
+}
+
+// Rewrites the JFIF APP0 density fields, inserting a JFIF segment after SOI
+// when the encoder didn't write one
+fn set_jpeg_dpi(mut data: Vec<u8>, dpi: u16) -> Vec<u8> {
+    if data.len() < 4 || data[0..2] != [0xFF, 0xD8] {
+        return data;
+    }
+    
+    let [hi, lo] = dpi.to_be_bytes();
+    let has_jfif = data.len() >= 18 && data[2..4] == [0xFF, 0xE0] && &data[6..11] == b"JFIF\0";
+    if has_jfif {
+        // units, X density, Y density follow the 2-byte version
+        data[13] = 1;
+        data[14..16].copy_from_slice(&[hi, lo]);
+        data[16..18].copy_from_slice(&[hi, lo]);
+        return data;
+    }
+    
+    let segment = [
+        0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00, 0x01, 0x01,
+        0x01, hi, lo, hi, lo, 0x00, 0x00,
+    ];
+    data.splice(2..2, segment);
+    data
+}
+
+// Replaces any existing pHYs chunk with one placed directly after IHDR
+fn set_png_dpi(data: Vec<u8>, dpi: u16) -> Vec<u8> {
+    const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    if data.len() < 8 || data[0..8] != SIGNATURE {
+        return data;
+    }
+    
+    let pixels_per_meter = (dpi as f64 / 0.0254).round() as u32;
+    let mut phys = Vec::with_capacity(9);
+    phys.extend_from_slice(&pixels_per_meter.to_be_bytes());
+    phys.extend_from_slice(&pixels_per_meter.to_be_bytes());
+    phys.push(1); // unit: meter
+    
+    let mut output = Vec::with_capacity(data.len() + 21);
+    output.extend_from_slice(&SIGNATURE);
+    
+    let mut offset = 8;
+    while offset + 12 <= data.len() {
+        let length = u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+        let end = offset + 12 + length;
+        if end > data.len() {
+            return data;
+        }
+        
+        let chunk_type = &data[offset + 4..offset + 8];
+        if chunk_type != b"pHYs" {
+            output.extend_from_slice(&data[offset..end]);
+        }
+        if chunk_type == b"IHDR" {
+            write_png_chunk(&mut output, b"pHYs", &phys);
+        }
+        offset = end;
+    }
+    
+    output
+}
+
+pub fn write_png_chunk(output: &mut Vec<u8>, chunk_type: &[u8; 4], payload: &[u8]) {
+    output.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    output.extend_from_slice(chunk_type);
+    output.extend_from_slice(payload);
+    
+    let mut crc = Crc32::new();
+    crc.update(chunk_type);
+    crc.update(payload);
+    output.extend_from_slice(&crc.finish().to_be_bytes());
+}
+
+// The CRC-32 PNG chunks are checked with (ISO 3309 polynomial)
+struct Crc32(u32);
+
+impl Crc32 {
+    fn new() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+    
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u32;
+            for _ in 0..8 {
+                self.0 = if self.0 & 1 != 0 { (self.0 >> 1) ^ 0xEDB8_8320 } else { self.0 >> 1 };
+            }
+        }
+    }
+    
+    fn finish(self) -> u32 {
+        self.0 ^ 0xFFFF_FFFF
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbImage};
+    use std::io::Cursor;
+    
+    fn encoded(format: ImageFormat) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(RgbImage::from_fn(8, 8, |x, y| image::Rgb([(x * 30) as u8, (y * 30) as u8, 90])))
+            .write_to(&mut data, format)
+            .unwrap();
+        data.into_inner()
+    }
+    
+    // Units and X/Y density from the JFIF segment right after SOI
+    fn jfif_density(data: &[u8]) -> (u8, u16, u16) {
+        assert_eq!(&data[6..11], b"JFIF\0");
+        (data[13], u16::from_be_bytes([data[14], data[15]]), u16::from_be_bytes([data[16], data[17]]))
+    }
+    
+    // Payloads of the pHYs chunks, in order
+    fn phys_chunks(data: &[u8]) -> Vec<&[u8]> {
+        let mut chunks = Vec::new();
+        let mut offset = 8;
+        while offset + 12 <= data.len() {
+            let length = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+            if &data[offset + 4..offset + 8] == b"pHYs" {
+                chunks.push(&data[offset + 8..offset + 8 + length]);
+            }
+            offset += 12 + length;
+        }
+        chunks
+    }
+    
+    #[test]
+    fn jpeg_density_reads_back() {
+        let tagged = set_dpi(encoded(ImageFormat::Jpeg), ImageFormat::Jpeg, 300);
+        assert_eq!(jfif_density(&tagged), (1, 300, 300));
+        assert!(image::load_from_memory(&tagged).is_ok());
+    }
+    
+    #[test]
+    fn jpeg_without_jfif_gets_a_segment() {
+        let data = encoded(ImageFormat::Jpeg);
+        let app0_end = 4 + u16::from_be_bytes([data[4], data[5]]) as usize;
+        let bare = [&data[0..2], &data[app0_end..]].concat();
+        
+        let tagged = set_dpi(bare.clone(), ImageFormat::Jpeg, 300);
+        assert_eq!(jfif_density(&tagged), (1, 300, 300));
+        assert!(tagged.len() as u64 <= bare.len() as u64 + EMBED_OVERHEAD);
+        assert!(image::load_from_memory(&tagged).is_ok());
+    }
+    
+    #[test]
+    fn png_density_reads_back() {
+        // Tagging twice replaces the first chunk rather than adding another
+        let data = encoded(ImageFormat::Png);
+        let tagged = set_dpi(set_dpi(data.clone(), ImageFormat::Png, 72), ImageFormat::Png, 300);
+        let chunks = phys_chunks(&tagged);
+        assert_eq!(chunks.len(), 1);
+        // 300 DPI is 11811 pixels per meter
+        assert_eq!(chunks[0], &[0, 0, 0x2E, 0x23, 0, 0, 0x2E, 0x23, 1]);
+        assert!(tagged.len() as u64 <= data.len() as u64 + EMBED_OVERHEAD);
+        assert!(image::load_from_memory(&tagged).is_ok());
+    }
+}
\ No newline at end of file
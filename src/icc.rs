@@ -0,0 +1,352 @@
+// icc.rs - ICC color profiles for encoded JPEG, PNG and WebP data
+//
+// Untagged images are shown as sRGB by some browsers and as the display's native
+// space by others, so output can carry a profile: a compact built-in sRGB one,
+// or the source's own when metadata is preserved. Only the profile segments
+// change; the pixel data is copied through untouched.
+
+use image::codecs::jpeg::JpegDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{ImageDecoder, ImageFormat};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::OnceLock;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const ICC_MARKER: &[u8; 12] = b"ICC_PROFILE\0";
+// Segment length field, marker name and sequence bytes leave this much per APP2
+const JPEG_CHUNK: usize = 65_535 - 2 - ICC_MARKER.len() - 2;
+
+// Upper bound on what embedding adds besides the profile itself, which target
+// size searches leave room for
+pub const EMBED_OVERHEAD: u64 = 64;
+
+// ICC v4 display profile for sRGB: D50-adapted primaries and the exact piecewise
+// sRGB curve as a parametric curve, about 500 bytes
+pub fn srgb_profile() -> &'static [u8] {
+    static PROFILE: OnceLock<Vec<u8>> = OnceLock::new();
+    PROFILE.get_or_init(build_srgb_profile)
+}
+
+fn build_srgb_profile() -> Vec<u8> {
+    let s15 = |value: f64| ((value * 65536.0).round() as i32).to_be_bytes();
+    let xyz = |values: [f64; 3]| {
+        let mut data = b"XYZ \0\0\0\0".to_vec();
+        values.iter().for_each(|value| data.extend_from_slice(&s15(*value)));
+        data
+    };
+    let mluc = |text: &str| {
+        let mut data = b"mluc\0\0\0\0".to_vec();
+        let utf16: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&12u32.to_be_bytes());
+        data.extend_from_slice(b"enUS");
+        data.extend_from_slice(&(utf16.len() as u32).to_be_bytes());
+        data.extend_from_slice(&28u32.to_be_bytes());
+        data.extend_from_slice(&utf16);
+        data
+    };
+
+    // IEC 61966-2.1: Y = ((X + 0.055) / 1.055)^2.4 above 0.04045, X / 12.92 below
+    let mut curve = b"para\0\0\0\0".to_vec();
+    curve.extend_from_slice(&3u16.to_be_bytes());
+    curve.extend_from_slice(&[0, 0]);
+    for value in [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045] {
+        curve.extend_from_slice(&s15(value));
+    }
+
+    // Bradford adaptation from D65 to the D50 connection space
+    let mut chad = b"sf32\0\0\0\0".to_vec();
+    for value in [
+        1.047882, 0.022918, -0.050217,
+        0.029586, 0.990478, -0.017075,
+        -0.009247, 0.015083, 0.751678,
+    ] {
+        chad.extend_from_slice(&s15(value));
+    }
+
+    let tags: [(&[u8; 4], Vec<u8>); 7] = [
+        (b"desc", mluc("sRGB")),
+        (b"cprt", mluc("No copyright, use freely")),
+        (b"wtpt", xyz([0.9642, 1.0, 0.8249])),
+        (b"chad", chad),
+        (b"rXYZ", xyz([0.436066, 0.222488, 0.013916])),
+        (b"gXYZ", xyz([0.385147, 0.716873, 0.097076])),
+        (b"bXYZ", xyz([0.143066, 0.060608, 0.714096])),
+    ];
+
+    // The three channel curves share one curve element
+    let table_len = 4 + (tags.len() + 3) * 12;
+    let mut offset = 128 + table_len;
+    let mut table = ((tags.len() + 3) as u32).to_be_bytes().to_vec();
+    let mut elements = Vec::new();
+    let mut add_entry = |signature: &[u8; 4], offset: usize, size: usize| {
+        table.extend_from_slice(signature);
+        table.extend_from_slice(&(offset as u32).to_be_bytes());
+        table.extend_from_slice(&(size as u32).to_be_bytes());
+    };
+    for (signature, data) in &tags {
+        add_entry(signature, offset, data.len());
+        elements.extend_from_slice(data);
+        while elements.len() % 4 != 0 {
+            elements.push(0);
+        }
+        offset = 128 + table_len + elements.len();
+    }
+    for signature in [b"rTRC", b"gTRC", b"bTRC"] {
+        add_entry(signature, offset, curve.len());
+    }
+    elements.extend_from_slice(&curve);
+
+    let size = 128 + table.len() + elements.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend_from_slice(&(size as u32).to_be_bytes());
+    profile.extend_from_slice(&[0; 4]); // preferred CMM
+    profile.extend_from_slice(&[4, 0x30, 0, 0]); // version 4.3
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    profile.extend_from_slice(&[0x07, 0xE6, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0]); // 2022-01-01
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 24]); // platform, flags, device, attributes
+    profile.extend_from_slice(&[0; 4]); // perceptual intent
+    for value in [0.9642, 1.0, 0.8249] {
+        profile.extend_from_slice(&s15(value));
+    }
+    profile.extend_from_slice(&[0; 4]); // creator
+    profile.extend_from_slice(&[0; 16]); // profile ID, optional
+    profile.extend_from_slice(&[0; 28]);
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&elements);
+    profile
+}
+
+// The RGB or gray profile embedded in a JPEG, PNG or WebP file, if it has one
+pub fn read_profile(path: &Path) -> Option<Vec<u8>> {
+    let reader = BufReader::new(File::open(path).ok()?);
+    let profile = match crate::sniff::detect_format(path)? {
+        ImageFormat::Jpeg => JpegDecoder::new(reader).ok()?.icc_profile(),
+        ImageFormat::Png => PngDecoder::new(reader).ok()?.icc_profile(),
+        ImageFormat::WebP => WebPDecoder::new(reader).ok()?.icc_profile(),
+        _ => None,
+    };
+    // A CMYK profile doesn't describe the RGB pixels the source is decoded to
+    profile.filter(|profile| matches!(profile.get(16..20), Some(b"RGB ") | Some(b"GRAY")))
+}
+
+// Returns the data with `profile` embedded, replacing any profile it had. Data in
+// other formats, that doesn't parse, or whose color model (RGB or gray) doesn't
+// match the profile's is returned unchanged.
+pub fn embed_profile(data: Vec<u8>, format: ImageFormat, profile: &[u8]) -> Vec<u8> {
+    let gray = match format {
+        ImageFormat::Jpeg => jpeg_components(&data).map(|components| components == 1),
+        ImageFormat::Png => png_color_type(&data).map(|color_type| color_type == 0 || color_type == 4),
+        ImageFormat::WebP => Some(false),
+        _ => None,
+    };
+    let matches_profile = matches!((gray, profile.get(16..20)), (Some(false), Some(b"RGB ")) | (Some(true), Some(b"GRAY")));
+    if !matches_profile {
+        return data;
+    }
+
+    match format {
+        ImageFormat::Jpeg => embed_jpeg(data, profile),
+        ImageFormat::Png => embed_png(data, profile),
+        ImageFormat::WebP => embed_webp(data, profile),
+        _ => data,
+    }
+}
+
+// Component count from the frame header
+fn jpeg_components(data: &[u8]) -> Option<u8> {
+    if data.get(0..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+    let mut offset = 2;
+    loop {
+        let end = segment_end(data, offset)?;
+        let marker = data[offset + 1];
+        // SOF0-SOF15, excluding DHT (C4), JPG (C8) and DAC (CC)
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            return data.get(offset + 9).copied();
+        }
+        if marker == 0xDA {
+            return None;
+        }
+        offset = end;
+    }
+}
+
+fn png_color_type(data: &[u8]) -> Option<u8> {
+    if data.get(0..8)? != PNG_SIGNATURE || data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    data.get(25).copied()
+}
+
+// APP2 "ICC_PROFILE" segments placed after SOI and a leading JFIF segment
+fn embed_jpeg(data: Vec<u8>, profile: &[u8]) -> Vec<u8> {
+    let chunks: Vec<&[u8]> = profile.chunks(JPEG_CHUNK).collect();
+    if data.get(0..2) != Some([0xFF, 0xD8].as_slice()) || chunks.is_empty() || chunks.len() > 255 {
+        return data;
+    }
+
+    let mut output = Vec::with_capacity(data.len() + profile.len() + chunks.len() * 18);
+    output.extend_from_slice(&data[0..2]);
+    let mut offset = 2;
+    if let Some(end) = segment_end(&data, offset) {
+        if data[offset + 1] == 0xE0 && data.get(offset + 4..offset + 9) == Some(b"JFIF\0".as_slice()) {
+            output.extend_from_slice(&data[offset..end]);
+            offset = end;
+        }
+    }
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        output.extend_from_slice(&[0xFF, 0xE2]);
+        output.extend_from_slice(&((2 + ICC_MARKER.len() + 2 + chunk.len()) as u16).to_be_bytes());
+        output.extend_from_slice(ICC_MARKER);
+        output.extend_from_slice(&[index as u8 + 1, chunks.len() as u8]);
+        output.extend_from_slice(chunk);
+    }
+
+    // Segments up to the scan data, minus any profile the encoder wrote
+    while let Some(end) = segment_end(&data, offset) {
+        if data[offset + 1] == 0xDA {
+            break;
+        }
+        let old_profile = data[offset + 1] == 0xE2 && data.get(offset + 4..offset + 16) == Some(ICC_MARKER.as_slice());
+        if !old_profile {
+            output.extend_from_slice(&data[offset..end]);
+        }
+        offset = end;
+    }
+    output.extend_from_slice(&data[offset..]);
+    output
+}
+
+// End of the marker segment starting at `offset`, if one does
+pub fn segment_end(data: &[u8], offset: usize) -> Option<usize> {
+    if *data.get(offset)? != 0xFF {
+        return None;
+    }
+    let length = u16::from_be_bytes([*data.get(offset + 2)?, *data.get(offset + 3)?]) as usize;
+    let end = offset + 2 + length;
+    (length >= 2 && end <= data.len()).then_some(end)
+}
+
+// An iCCP chunk directly after IHDR; any iCCP or sRGB chunk already there is dropped
+fn embed_png(data: Vec<u8>, profile: &[u8]) -> Vec<u8> {
+    let mut payload = b"ICC profile\0\0".to_vec();
+    payload.extend_from_slice(&miniz_oxide::deflate::compress_to_vec_zlib(profile, 9));
+
+    let mut output = Vec::with_capacity(data.len() + payload.len() + 12);
+    output.extend_from_slice(&PNG_SIGNATURE);
+
+    let mut offset = 8;
+    while offset + 12 <= data.len() {
+        let length = u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+        let end = offset + 12 + length;
+        if end > data.len() {
+            return data;
+        }
+
+        let chunk_type = &data[offset + 4..offset + 8];
+        if chunk_type != b"iCCP" && chunk_type != b"sRGB" {
+            output.extend_from_slice(&data[offset..end]);
+        }
+        if chunk_type == b"IHDR" {
+            crate::density::write_png_chunk(&mut output, b"iCCP", &payload);
+        }
+        offset = end;
+    }
+
+    output
+}
+
+fn embed_webp(data: Vec<u8>, profile: &[u8]) -> Vec<u8> {
+    set_webp_chunk(data, b"ICCP", 0x20, profile)
+}
+
+// Replaces the `fourcc` chunk and sets its `flag` in the VP8X header. ICCP goes
+// right after the header and anything else last, in the container's chunk order.
+// Simple (VP8/VP8L) files become extended ones so the chunk has a VP8X header to
+// be announced in.
+pub fn set_webp_chunk(data: Vec<u8>, fourcc: &[u8; 4], flag: u8, payload: &[u8]) -> Vec<u8> {
+    if data.len() < 20 || &data[0..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return data;
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let chunk_type: [u8; 4] = data[offset..offset + 4].try_into().unwrap();
+        let size = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let end = offset + 8 + size;
+        if end > data.len() {
+            return data;
+        }
+        chunks.push((chunk_type, &data[offset + 8..end]));
+        offset = end + (size & 1);
+    }
+
+    let mut header = match chunks.first() {
+        Some((chunk_type, chunk)) if chunk_type == b"VP8X" && chunk.len() >= 10 => chunk.to_vec(),
+        Some((chunk_type, chunk)) => match simple_webp_header(chunk_type, chunk) {
+            Some(header) => header,
+            None => return data,
+        },
+        None => return data,
+    };
+    header[0] |= flag;
+
+    let mut body = Vec::with_capacity(data.len() + payload.len() + 32);
+    write_riff_chunk(&mut body, b"VP8X", &header);
+    if fourcc == b"ICCP" {
+        write_riff_chunk(&mut body, fourcc, payload);
+    }
+    for (existing, existing_payload) in &chunks {
+        if existing != b"VP8X" && existing != fourcc {
+            write_riff_chunk(&mut body, existing, existing_payload);
+        }
+    }
+    if fourcc != b"ICCP" {
+        write_riff_chunk(&mut body, fourcc, payload);
+    }
+
+    let mut output = Vec::with_capacity(body.len() + 12);
+    output.extend_from_slice(b"RIFF");
+    output.extend_from_slice(&((body.len() + 4) as u32).to_le_bytes());
+    output.extend_from_slice(b"WEBP");
+    output.extend_from_slice(&body);
+    output
+}
+
+// VP8X payload for a lone VP8 or VP8L bitstream: flags, then the canvas size minus one
+fn simple_webp_header(fourcc: &[u8; 4], payload: &[u8]) -> Option<Vec<u8>> {
+    let (width, height, alpha) = match fourcc {
+        b"VP8 " if payload.len() >= 10 && payload[3..6] == [0x9D, 0x01, 0x2A] => (
+            u16::from_le_bytes([payload[6], payload[7]]) as u32 & 0x3FFF,
+            u16::from_le_bytes([payload[8], payload[9]]) as u32 & 0x3FFF,
+            false,
+        ),
+        b"VP8L" if payload.len() >= 5 && payload[0] == 0x2F => {
+            let bits = u32::from_le_bytes(payload[1..5].try_into().unwrap());
+            ((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1, bits >> 28 & 1 == 1)
+        }
+        _ => return None,
+    };
+
+    let mut header = vec![if alpha { 0x10 } else { 0 }, 0, 0, 0];
+    header.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+    header.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+    Some(header)
+}
+
+fn write_riff_chunk(output: &mut Vec<u8>, fourcc: &[u8; 4], payload: &[u8]) {
+    output.extend_from_slice(fourcc);
+    output.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    output.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        output.push(0);
+    }
+}
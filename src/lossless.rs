@@ -0,0 +1,360 @@
+// lossless.rs - Lossless optimization of files already in their final format
+//
+// JPEGs are transcoded jpegtran-style: the DCT coefficients are copied as they are
+// and only the entropy coding is redone (optimized Huffman tables, progressive
+// scans), so the decoded pixels are identical. PNGs go through OxiPNG. Either way,
+// a file is only replaced when the result is smaller. Builds without MozJPEG or
+// OxiPNG report the matching files as failed rather than re-encoding them.
+
+#[cfg(feature = "mozjpeg")]
+use mozjpeg_sys as ffi;
+#[cfg(feature = "oxipng")]
+use oxipng::{Options as OxiOptions, StripChunks};
+use std::fs;
+#[cfg(feature = "mozjpeg")]
+use std::mem;
+#[cfg(feature = "mozjpeg")]
+use std::os::raw::{c_char, c_int, c_ulong};
+#[cfg(feature = "mozjpeg")]
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+#[cfg(feature = "mozjpeg")]
+use std::ptr;
+
+use crate::compression::{CompressionAlgorithm, CompressionOptions};
+use crate::{backup_original, empty_file_error, error_chain, sniff, stage_error, write_file, InternalResult, ProcessingOptions, ResultStatus, Stage};
+
+// APPn and COM segments are carried over, so EXIF and ICC profiles survive
+#[cfg(feature = "mozjpeg")]
+const APP0: c_int = 0xE0;
+#[cfg(feature = "mozjpeg")]
+const COM: c_int = 0xFE;
+// JMSG_LENGTH_MAX in jerror.h
+#[cfg(feature = "mozjpeg")]
+const MESSAGE_LENGTH: usize = 200;
+#[cfg(feature = "mozjpeg")]
+const OUTPUT_CHUNK: usize = 64 * 1024;
+
+// Both libjpeg objects and their error managers, destroyed together even when an
+// error unwinds out of the middle of a transcode
+#[cfg(feature = "mozjpeg")]
+struct Transcoder {
+    source: ffi::jpeg_decompress_struct,
+    destination: ffi::jpeg_compress_struct,
+    source_errors: Box<ffi::jpeg_error_mgr>,
+    destination_errors: Box<ffi::jpeg_error_mgr>,
+    output: VecDestination,
+}
+
+#[cfg(feature = "mozjpeg")]
+impl Drop for Transcoder {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::jpeg_destroy_compress(&mut self.destination);
+            ffi::jpeg_destroy_decompress(&mut self.source);
+        }
+    }
+}
+
+// The compressed output, collected in Rust-owned memory so an error that unwinds out
+// of the middle of a transcode leaves nothing to free by hand
+#[cfg(feature = "mozjpeg")]
+#[repr(C)]
+struct VecDestination {
+    manager: ffi::jpeg_destination_mgr,
+    chunk: Vec<u8>,
+    data: Vec<u8>,
+}
+
+#[cfg(feature = "mozjpeg")]
+impl VecDestination {
+    fn new() -> Self {
+        VecDestination {
+            manager: ffi::jpeg_destination_mgr {
+                next_output_byte: ptr::null_mut(),
+                free_in_buffer: 0,
+                init_destination: Some(init_destination),
+                empty_output_buffer: Some(empty_output_buffer),
+                term_destination: Some(term_destination),
+            },
+            chunk: vec![0; OUTPUT_CHUNK],
+            data: Vec::new(),
+        }
+    }
+}
+
+// The manager is the first field, so libjpeg's pointer to it is one to the whole struct
+#[cfg(feature = "mozjpeg")]
+unsafe fn vec_destination(cinfo: &mut ffi::jpeg_compress_struct) -> &mut VecDestination {
+    &mut *cinfo.dest.cast::<VecDestination>()
+}
+
+#[cfg(feature = "mozjpeg")]
+unsafe extern "C-unwind" fn init_destination(cinfo: &mut ffi::jpeg_compress_struct) {
+    let destination = vec_destination(cinfo);
+    destination.manager.next_output_byte = destination.chunk.as_mut_ptr();
+    destination.manager.free_in_buffer = destination.chunk.len();
+}
+
+// Called with the chunk full, whatever free_in_buffer says
+#[cfg(feature = "mozjpeg")]
+unsafe extern "C-unwind" fn empty_output_buffer(cinfo: &mut ffi::jpeg_compress_struct) -> ffi::boolean {
+    let destination = vec_destination(cinfo);
+    destination.data.extend_from_slice(&destination.chunk);
+    destination.manager.next_output_byte = destination.chunk.as_mut_ptr();
+    destination.manager.free_in_buffer = destination.chunk.len();
+    1
+}
+
+#[cfg(feature = "mozjpeg")]
+unsafe extern "C-unwind" fn term_destination(cinfo: &mut ffi::jpeg_compress_struct) {
+    let destination = vec_destination(cinfo);
+    let used = destination.chunk.len() - destination.manager.free_in_buffer;
+    destination.data.extend_from_slice(&destination.chunk[..used]);
+}
+
+#[cfg(feature = "mozjpeg")]
+unsafe extern "C-unwind" fn error_exit(cinfo: &mut ffi::jpeg_common_struct) {
+    let mut buffer = [0u8; MESSAGE_LENGTH];
+    let errors = &*cinfo.err;
+    let message = match errors.format_message {
+        Some(format) => {
+            // The binding declares the buffer as &[u8; 80], but libjpeg takes a char
+            // pointer and writes up to JMSG_LENGTH_MAX bytes through it
+            let format = mem::transmute::<
+                unsafe extern "C-unwind" fn(&mut ffi::jpeg_common_struct, &[u8; 80]),
+                unsafe extern "C-unwind" fn(&mut ffi::jpeg_common_struct, *mut c_char),
+            >(format);
+            format(cinfo, buffer.as_mut_ptr().cast());
+            let end = buffer.iter().position(|&byte| byte == 0).unwrap_or(buffer.len());
+            String::from_utf8_lossy(&buffer[..end]).into_owned()
+        }
+        None => format!("libjpeg error {}", errors.msg_code),
+    };
+    panic::resume_unwind(Box::new(message));
+}
+
+#[cfg(feature = "mozjpeg")]
+unsafe extern "C-unwind" fn silence_message(_cinfo: &mut ffi::jpeg_common_struct, _level: c_int) {}
+
+#[cfg(feature = "mozjpeg")]
+fn error_manager() -> Box<ffi::jpeg_error_mgr> {
+    unsafe {
+        let mut errors: Box<ffi::jpeg_error_mgr> = Box::new(mem::zeroed());
+        ffi::jpeg_std_error(&mut errors);
+        errors.error_exit = Some(error_exit);
+        errors.emit_message = Some(silence_message);
+        errors
+    }
+}
+
+// Losslessly re-encodes a JPEG with optimized Huffman tables and progressive scans
+#[cfg(feature = "mozjpeg")]
+pub fn optimize_jpeg(data: &[u8]) -> Result<Vec<u8>, String> {
+    panic::catch_unwind(AssertUnwindSafe(|| unsafe { transcode_jpeg(data) })).map_err(|panic| {
+        panic
+            .downcast::<String>()
+            .map(|message| *message)
+            .unwrap_or_else(|_| String::from("libjpeg failed"))
+    })
+}
+
+#[cfg(feature = "mozjpeg")]
+unsafe fn transcode_jpeg(data: &[u8]) -> Vec<u8> {
+    let mut transcoder = Box::new(Transcoder {
+        source: mem::zeroed(),
+        destination: mem::zeroed(),
+        source_errors: error_manager(),
+        destination_errors: error_manager(),
+        output: VecDestination::new(),
+    });
+    let transcoder = &mut *transcoder;
+    transcoder.source.common.err = &mut *transcoder.source_errors;
+    transcoder.destination.common.err = &mut *transcoder.destination_errors;
+    ffi::jpeg_create_decompress(&mut transcoder.source);
+    ffi::jpeg_create_compress(&mut transcoder.destination);
+    transcoder.destination.dest = &mut transcoder.output.manager;
+
+    let source = &mut transcoder.source;
+    ffi::jpeg_mem_src(source, data.as_ptr(), data.len() as c_ulong);
+    for marker in APP0..APP0 + 16 {
+        ffi::jpeg_save_markers(source, marker, 0xFFFF);
+    }
+    ffi::jpeg_save_markers(source, COM, 0xFFFF);
+    ffi::jpeg_read_header(source, 1);
+    let coefficients = ffi::jpeg_read_coefficients(source);
+
+    let destination = &mut transcoder.destination;
+    ffi::jpeg_copy_critical_parameters(&transcoder.source, destination);
+    destination.optimize_coding = 1;
+    ffi::jpeg_simple_progression(destination);
+
+    ffi::jpeg_write_coefficients(destination, coefficients);
+    copy_markers(&transcoder.source, destination);
+    ffi::jpeg_finish_compress(destination);
+    ffi::jpeg_finish_decompress(&mut transcoder.source);
+    mem::take(&mut transcoder.output.data)
+}
+
+// The JFIF and Adobe segments are written by libjpeg itself, so copying them
+// would duplicate them
+#[cfg(feature = "mozjpeg")]
+unsafe fn copy_markers(source: &ffi::jpeg_decompress_struct, destination: &mut ffi::jpeg_compress_struct) {
+    let mut marker = source.marker_list;
+    while let Some(current) = marker.as_ref() {
+        let data = std::slice::from_raw_parts(current.data, current.data_length as usize);
+        let written_by_libjpeg = (current.marker as c_int == APP0 && data.starts_with(b"JFIF\0"))
+            || (current.marker as c_int == APP0 + 14 && data.starts_with(b"Adobe"));
+        if !written_by_libjpeg {
+            ffi::jpeg_write_marker(destination, current.marker as c_int, current.data, current.data_length);
+        }
+        marker = current.next;
+    }
+}
+
+// OxiPNG at the configured level, keeping every chunk
+#[cfg(feature = "oxipng")]
+pub fn optimize_png(data: &[u8], compression_options: &CompressionOptions) -> Result<Vec<u8>, String> {
+    let mut oxipng_options = OxiOptions::from_preset(compression_options.oxipng_level.min(6));
+    oxipng_options.strip = StripChunks::None;
+
+    let optimized = if compression_options.oxipng_multithreaded {
+        oxipng::optimize_from_memory(data, &oxipng_options)
+    } else {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .map_err(|e| e.to_string())?
+            .install(|| oxipng::optimize_from_memory(data, &oxipng_options))
+    };
+    optimized.map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "mozjpeg"))]
+pub fn optimize_jpeg(_data: &[u8]) -> Result<Vec<u8>, String> {
+    Err(String::from("MozJPEG isn't built in"))
+}
+
+#[cfg(not(feature = "oxipng"))]
+pub fn optimize_png(_data: &[u8], _compression_options: &CompressionOptions) -> Result<Vec<u8>, String> {
+    Err(String::from("OxiPNG isn't built in"))
+}
+
+// Optimizes the file where it is and reports the bytes saved. A file that doesn't
+// get smaller is left untouched.
+pub fn optimize_in_place(
+    input_path: &Path,
+    compression_options: &CompressionOptions,
+    options: &ProcessingOptions,
+) -> InternalResult {
+    let original = match fs::read(input_path) {
+        Ok(data) => data,
+        Err(e) => return InternalResult::failed(0, stage_error(Stage::Read, &e), CompressionAlgorithm::Auto),
+    };
+    if original.is_empty() {
+        return InternalResult::failed(0, empty_file_error(), CompressionAlgorithm::Auto);
+    }
+    let original_size = original.len() as u64;
+
+    let (algorithm, optimized) = match sniff::detect_format(input_path) {
+        Some(image::ImageFormat::Jpeg) => (CompressionAlgorithm::Jpeg, optimize_jpeg(&original)),
+        Some(image::ImageFormat::Png) => (CompressionAlgorithm::OxiPng, optimize_png(&original, compression_options)),
+        _ => {
+            return InternalResult {
+                original_size,
+                new_size: original_size,
+                success: true,
+                status: ResultStatus::Processed,
+                message: String::from("Left unchanged (not a JPEG or PNG)"),
+                algorithm_used: CompressionAlgorithm::Auto,
+                compression_ratio: 1.0,
+                output_path: None,
+                quality_score: None,
+                final_quality: None,
+            }
+        }
+    };
+    let optimized = match optimized {
+        Ok(data) => data,
+        Err(message) => return InternalResult::failed(original_size, format!("{} failed: {}", Stage::Encode, message), algorithm),
+    };
+
+    if optimized.len() as u64 >= original_size {
+        return InternalResult {
+            original_size,
+            new_size: original_size,
+            success: true,
+            status: ResultStatus::Processed,
+            message: String::from("Already optimal, left unchanged"),
+            algorithm_used: algorithm,
+            compression_ratio: 1.0,
+            output_path: None,
+            quality_score: None,
+            final_quality: None,
+        };
+    }
+
+    if options.backup_originals {
+        let source_dir = input_path.parent().unwrap_or(Path::new("."));
+        if let Err(e) = backup_original(input_path, source_dir) {
+            return InternalResult::failed(original_size, format!("{} failed: backup: {}", Stage::Write, error_chain(&e)), algorithm);
+        }
+    }
+    if let Err(e) = options.ensure_running()
+        .and_then(|_| write_file(input_path, &optimized, options.atomic_writes, options.scratch_dir.as_deref()))
+    {
+        return InternalResult::failed(original_size, stage_error(Stage::Write, &e), algorithm);
+    }
+
+    let new_size = optimized.len() as u64;
+    let saved = original_size - new_size;
+    InternalResult {
+        original_size,
+        new_size,
+        success: true,
+        status: ResultStatus::Processed,
+        message: format!(
+            "Saved {:.1} KB ({:.1}%) losslessly",
+            saved as f64 / 1024.0,
+            saved as f64 / original_size as f64 * 100.0
+        ),
+        algorithm_used: algorithm,
+        compression_ratio: new_size as f32 / original_size as f32,
+        output_path: Some(input_path.to_path_buf()),
+        quality_score: None,
+        final_quality: None,
+    }
+}
+
+#[cfg(all(test, feature = "mozjpeg"))]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{Rgb, RgbImage};
+    
+    fn baseline_jpeg() -> Vec<u8> {
+        let img = RgbImage::from_fn(96, 64, |x, y| Rgb([(x * 2) as u8, (y * 3) as u8, ((x + y) % 256) as u8]));
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, 90).encode_image(&img).unwrap();
+        data
+    }
+    
+    #[test]
+    fn optimized_jpeg_decodes_to_the_same_pixels() {
+        let original = baseline_jpeg();
+        let optimized = optimize_jpeg(&original).unwrap();
+        assert_ne!(optimized, original);
+        let before = image::load_from_memory(&original).unwrap().to_rgb8();
+        let after = image::load_from_memory(&optimized).unwrap().to_rgb8();
+        assert_eq!(before.dimensions(), after.dimensions());
+        assert!(before.pixels().zip(after.pixels()).all(|(a, b)| a == b));
+    }
+    
+    #[test]
+    fn broken_jpeg_reports_libjpegs_message() {
+        let mut truncated = baseline_jpeg();
+        truncated.truncate(20);
+        let message = optimize_jpeg(&truncated).unwrap_err();
+        assert!(!message.is_empty() && message != "libjpeg failed", "{}", message);
+    }
+}
\ No newline at end of file
@@ -1,16 +1,42 @@
 // Advanced Image Resizer with Beautiful UI
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod adjust;
+mod archive;
+mod animation;
+mod budget;
+mod cmyk;
 mod compression;
+mod dedupe;
+mod density;
+mod icc;
+mod lossless;
+mod manifest;
+mod metadata;
+mod pipeline;
+mod quality;
+mod sidecar;
+mod presets;
 mod simple;
+mod sniff;
+mod thumbnail;
+mod watermark;
 
-use compression::{CompressionAlgorithm, CompressionOptions, SmartCompressor};
+use compression::{CompressionAlgorithm, CompressionOptions, CompressionResult, JpegEncoder, JpegMode, SmartCompressor, SmartCompressorConfig};
+use filetime::FileTime;
+use image::DynamicImage;
 use iced::widget::{button, column, container, pick_list, progress_bar, row, scrollable, text, text_input, checkbox, slider, Space, radio, horizontal_rule, vertical_rule};
 use iced::{executor, Application, Command, Element, Length, Settings, Theme, Font, Color, Background};
 use iced::theme;
 use iced::font::{Family, Weight};
+use log::debug;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use walkdir::WalkDir;
 
 const HEADING_FONT: Font = Font {
@@ -42,6 +68,40 @@ const ERROR_COLOR: Color = Color::from_rgb(0.9, 0.2, 0.2);
 const BACKGROUND_COLOR: Color = Color::from_rgb(0.97, 0.97, 0.98);
 const CARD_COLOR: Color = Color::WHITE;
 
+// Algorithms offered by the Advanced pick lists, when built in (see algorithm_choices)
+const ALGORITHM_CHOICES: [CompressionAlgorithm; 12] = [
+    CompressionAlgorithm::Auto,
+    CompressionAlgorithm::AutoBest,
+    CompressionAlgorithm::ByAlpha,
+    CompressionAlgorithm::Simple,
+    CompressionAlgorithm::Jpeg,
+    CompressionAlgorithm::StandardPng,
+    CompressionAlgorithm::OptiPng,
+    CompressionAlgorithm::OxiPng,
+    CompressionAlgorithm::PngQuant,
+    CompressionAlgorithm::WebPLossy,
+    CompressionAlgorithm::WebPLossless,
+    CompressionAlgorithm::LosslessFirst,
+];
+
+// ALGORITHM_CHOICES minus the ones this build has no encoder for
+fn algorithm_choices() -> Vec<CompressionAlgorithm> {
+    compression::available_algorithms()
+        .into_iter()
+        .filter(|algorithm| ALGORITHM_CHOICES.contains(algorithm))
+        .collect()
+}
+
+// The algorithms By Transparency can route to: no auto modes, and for transparent
+// images nothing that writes JPEG
+fn by_alpha_choices(transparent: bool) -> Vec<CompressionAlgorithm> {
+    algorithm_choices()
+        .into_iter()
+        .filter(|algorithm| CompressionAlgorithm::ENCODERS.contains(algorithm) || *algorithm == CompressionAlgorithm::LosslessFirst)
+        .filter(|algorithm| !transparent || algorithm.keeps_transparency())
+        .collect()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum CompressionMode {
     Simple,
@@ -55,6 +115,15 @@ impl Default for CompressionMode {
 }
 
 pub fn main() -> iced::Result {
+    // Silent unless RUST_LOG is set, e.g. RUST_LOG=debug or RUST_LOG=image_resizer_advanced=trace
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("off")).init();
+    
+    // `--stdin` compresses the image piped in to stdout without opening a window,
+    // using the IMGRESIZE_ALGO and IMGRESIZE_QUALITY defaults
+    if std::env::args().skip(1).any(|arg| arg == "--stdin") {
+        std::process::exit(compress_stdin());
+    }
+    
     ImageResizer::run(Settings {
         window: iced::window::Settings {
             size: (580, 650),
@@ -69,22 +138,187 @@ pub fn main() -> iced::Result {
     })
 }
 
+// Exit code 0, or 1 with the error on stderr
+fn compress_stdin() -> i32 {
+    use std::io::{Read, Write};
+    
+    let (app, _) = ImageResizer::new(());
+    let mut input = Vec::new();
+    if let Err(e) = std::io::stdin().read_to_end(&mut input) {
+        eprintln!("{}", stage_error(Stage::Read, &e));
+        return 1;
+    }
+    let options = CompressionOptions {
+        algorithm: app.compression_algorithm,
+        quality: Some(app.quality_slider),
+        ..CompressionOptions::default()
+    };
+    let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+    let written = compressor.compress_bytes(&input, options)
+        .map_err(|e| error_chain(&*e))
+        .and_then(|result| std::io::stdout().lock().write_all(&result.data).map_err(|e| stage_error(Stage::Write, &e)));
+    match written {
+        Ok(()) => 0,
+        Err(message) => {
+            eprintln!("{}", message);
+            1
+        }
+    }
+}
+
 #[derive(Default)]
 struct ImageResizer {
     selected_path: Option<PathBuf>,
+    // Hand-picked files, processed in this order; replaces selected_path when set
+    selected_files: Vec<PathBuf>,
+    // Entries of a loaded file list that can't be processed, reported as failures
+    list_rejects: Vec<(PathBuf, String)>,
+    file_info: Option<FileInfo>,
     target_size: String,
+    target_per_megapixel: String,
+    // File whose size the target was taken from, with that size in bytes
+    size_reference: Option<(PathBuf, u64)>,
+    // Total for a folder or file list in MB, split into per-file targets
+    folder_budget: String,
+    budget_by_detail: bool,
     width: String,
     height: String,
     maintain_ratio: bool,
+    dimension_preset: Option<presets::DimensionPreset>,
+    crop_to_fill: bool,
+    edge: Edge,
+    edge_length: String,
+    // Print size in inches or centimeters, turned into pixels with the DPI field
+    print_width: String,
+    print_height: String,
+    print_unit: density::PrintUnit,
     compression_mode: CompressionMode,
     compression_algorithm: CompressionAlgorithm,
+    // The two sides of By Transparency
+    opaque_algorithm: CompressionAlgorithm,
+    transparent_algorithm: CompressionAlgorithm,
     quality_slider: u8,
+    // Last quality used with each algorithm this session
+    algorithm_qualities: HashMap<CompressionAlgorithm, u8>,
+    alpha_quality: u8,
+    webp_filter_strength: u8,
+    webp_sharpness: u8,
     optimize_for_web: bool,
+    embed_srgb: bool,
+    embed_thumbnail: bool,
+    measure_quality: bool,
+    preserve_metadata: bool,
+    minimal_palette: bool,
+    reduce_16bit_png: bool,
+    keep_bit_depth: bool,
+    oxipng_level: u8,
+    oxipng_multithreaded: bool,
+    use_mozjpeg: bool,
+    jpeg_mode: JpegMode,
+    interlace_png: bool,
+    // One switch for progressive JPEG and interlaced PNG, over the two settings above
+    progressive_loading: bool,
+    min_quality: u8,
+    // Quality probes per target-size search; SEARCH_PROBES_ALL searches to the end
+    max_search_probes: u8,
+    adaptive_quality: bool,
     auto_scale: bool,
+    output_dir: Option<PathBuf>,
+    zip_output: bool,
+    zip_path: Option<PathBuf>,
+    zip_keep_structure: bool,
+    backup_originals: bool,
+    safe_mode: bool,
+    preserve_timestamps: bool,
+    output_suffix: String,
+    format_in_name: bool,
+    write_report: bool,
+    write_sidecars: bool,
+    fail_fast: bool,
+    copy_non_images: bool,
+    format_filter: String,
+    png_to_webp: bool,
+    lossless_optimize: bool,
+    gif_to_webp: bool,
+    explode_frames: bool,
+    trim_borders: bool,
+    trim_tolerance: u8,
+    sharpen_amount: u8,
+    sharpen_radius: u8,
+    max_megapixels: String,
+    downscale_megapixels: String,
+    min_size_to_process: String,
+    incremental: bool,
+    linear_resize: bool,
+    auto_orient: bool,
+    grayscale: bool,
+    auto_levels: bool,
+    levels_per_channel: bool,
+    redactions: String,
+    redact_blur: bool,
+    watermark_text: String,
+    // Stamped instead of the text when set
+    watermark_image: Option<PathBuf>,
+    watermark_position: watermark::Position,
+    watermark_margin: String,
+    watermark_opacity: u8,
+    watermark_size: u32,
+    watermark_color: String,
+    pipeline: pipeline::Pipeline,
+    convert_only: bool,
+    jpeg_background: String,
+    dpi: String,
+    lossless_limit: String,
+    responsive_widths: String,
+    width_naming: WidthNaming,
+    dual_export: bool,
+    skip_near_duplicates: bool,
+    duplicate_threshold: u8,
+    use_extension_rules: bool,
+    extension_rules: Vec<(String, RuleTarget)>,
+    new_rule_extension: String,
     is_processing: bool,
+    batch_id: u64,
+    batch: Option<BatchSettings>,
+    // Settings of the last finished, stopped or cancelled run, reused by Retry Failed
+    last_batch: Option<BatchSettings>,
+    // The running batch re-processes failed files, replacing their rows
+    retrying: bool,
+    queue: Vec<Job>,
+    // Jobs are taken from the queue one after another until it is empty
+    queue_running: bool,
+    // Where each queued job's rows begin in the results, with its label
+    job_groups: Vec<(usize, String)>,
+    result_sort: ResultSort,
+    sort_descending: bool,
+    pending_files: VecDeque<PathBuf>,
+    total_files: usize,
+    timing: BatchTiming,
+    is_benchmarking: bool,
+    benchmark: Option<Result<Vec<BenchmarkEntry>, String>>,
+    is_comparing_filters: bool,
+    filter_comparison: Option<Result<Vec<FilterSample>, String>>,
+    is_diffing: bool,
+    diff_preview: Option<Result<DiffPreview, String>>,
+    quality_curve: Option<QualityCurveRun>,
+    quality_curve_id: u64,
+    // How the last sweep ended, shown beside its button
+    quality_curve_note: Option<String>,
     progress: f32,
     status_message: String,
     results: Vec<ProcessResult>,
+    // What the last Clear removed, until the next Process run
+    cleared_results: Option<ClearedResults>,
+    expanded_result: Option<usize>,
+    stopped_on_failure: bool,
+    log: Vec<String>,
+    show_log: bool,
+    show_settings: bool,
+    // Files processed at the same time; 1 keeps batches sequential
+    max_threads: u8,
+    atomic_writes: bool,
+    scratch_dir: Option<PathBuf>,
+    in_flight: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -92,30 +326,692 @@ enum Message {
     SelectFile,
     SelectFolder,
     FileSelected(Option<PathBuf>),
+    AddFiles,
+    FilesAdded(Vec<PathBuf>),
+    LoadList,
+    ListLoaded(Option<(PathBuf, Result<manifest::FileList, String>)>),
+    RemoveFile(usize),
+    MoveFile(usize, Direction),
+    FileInfoLoaded(PathBuf, FileInfo),
     TargetSizeChanged(String),
+    TargetPerMegapixelChanged(String),
+    FolderBudgetChanged(String),
+    BudgetByDetailToggled(bool),
+    BudgetPlanned(u64, Result<(Vec<PathBuf>, HashMap<PathBuf, u64>), String>),
+    SelectSizeReference,
+    SizeReferenceSelected(Option<PathBuf>),
     WidthChanged(String),
     HeightChanged(String),
     MaintainRatioToggled(bool),
+    PresetSelected(presets::DimensionPreset),
+    CropToFillToggled(bool),
+    EdgeSelected(Edge),
+    EdgeLengthChanged(String),
+    PrintWidthChanged(String),
+    PrintHeightChanged(String),
+    PrintUnitSelected(density::PrintUnit),
     ModeChanged(CompressionMode),
     AlgorithmSelected(CompressionAlgorithm),
+    OpaqueAlgorithmSelected(CompressionAlgorithm),
+    TransparentAlgorithmSelected(CompressionAlgorithm),
     QualityChanged(u8),
+    AlphaQualityChanged(u8),
+    WebPFilterStrengthChanged(u8),
+    WebPSharpnessChanged(u8),
     OptimizeForWebToggled(bool),
+    EmbedSrgbToggled(bool),
+    EmbedThumbnailToggled(bool),
+    MeasureQualityToggled(bool),
+    PreserveMetadataToggled(bool),
+    MinimalPaletteToggled(bool),
+    Reduce16BitPngToggled(bool),
+    KeepBitDepthToggled(bool),
+    OxiPngLevelChanged(u8),
+    OxiPngMultithreadedToggled(bool),
+    UseMozJpegToggled(bool),
+    JpegModeSelected(JpegMode),
+    InterlacePngToggled(bool),
+    ProgressiveLoadingToggled(bool),
+    MinQualityChanged(u8),
+    MaxSearchProbesChanged(u8),
+    AdaptiveQualityToggled(bool),
     AutoScaleToggled(bool),
+    SelectOutputFolder,
+    OutputFolderSelected(Option<PathBuf>),
+    ZipOutputToggled(bool),
+    SelectZipFile,
+    ZipFileSelected(Option<PathBuf>),
+    ZipKeepStructureToggled(bool),
+    BackupOriginalsToggled(bool),
+    SafeModeToggled(bool),
+    PreserveTimestampsToggled(bool),
+    OutputSuffixChanged(String),
+    FormatInNameToggled(bool),
+    WriteReportToggled(bool),
+    WriteSidecarsToggled(bool),
+    FailFastToggled(bool),
+    CopyNonImagesToggled(bool),
+    FormatFilterChanged(String),
+    PngToWebPToggled(bool),
+    LosslessOptimizeToggled(bool),
+    GifToWebPToggled(bool),
+    ExplodeFramesToggled(bool),
+    TrimBordersToggled(bool),
+    TrimToleranceChanged(u8),
+    SharpenAmountChanged(u8),
+    SharpenRadiusChanged(u8),
+    MaxMegapixelsChanged(String),
+    DownscaleMegapixelsChanged(String),
+    MinSizeToProcessChanged(String),
+    IncrementalToggled(bool),
+    LinearResizeToggled(bool),
+    AutoOrientToggled(bool),
+    GrayscaleToggled(bool),
+    AutoLevelsToggled(bool),
+    LevelsPerChannelToggled(bool),
+    RedactionsChanged(String),
+    RedactBlurToggled(bool),
+    WatermarkTextChanged(String),
+    SelectWatermarkImage,
+    WatermarkImageSelected(Option<PathBuf>),
+    WatermarkPositionSelected(watermark::Position),
+    WatermarkMarginChanged(String),
+    WatermarkOpacityChanged(u8),
+    WatermarkSizeChanged(u32),
+    WatermarkColorChanged(String),
+    MovePipelineStep(usize, Direction),
+    ResetPipelineOrder,
+    ConvertOnlyToggled(bool),
+    JpegBackgroundChanged(String),
+    DpiChanged(String),
+    LosslessLimitChanged(String),
+    ResponsiveWidthsChanged(String),
+    WidthNamingSelected(WidthNaming),
+    DualExportToggled(bool),
+    SkipNearDuplicatesToggled(bool),
+    DuplicateThresholdChanged(u8),
+    ExtensionRulesToggled(bool),
+    ExtensionRuleTargetSelected(usize, RuleTarget),
+    ExtensionRuleRemoved(usize),
+    NewRuleExtensionChanged(String),
+    ExtensionRuleAdded,
     Process,
-    ProcessingComplete(Vec<ProcessResult>),
+    ImagesCollected(u64, Vec<PathBuf>),
+    FileProcessed(u64, Vec<ProcessResult>),
+    PrepareForWeb,
+    FlattenToJpeg,
+    CancelProcessing,
+    ProcessingComplete,
+    Benchmark,
+    BenchmarkComplete(PathBuf, Result<Vec<BenchmarkEntry>, String>),
+    CompareFilters,
+    FilterComparisonComplete(PathBuf, Result<Vec<FilterSample>, String>),
+    ShowDiff,
+    DiffComplete(PathBuf, Result<DiffPreview, String>),
+    QualityCurve,
+    QualityCurveFileSelected(Option<PathBuf>),
+    QualityCurveLoaded(u64, Result<Arc<DynamicImage>, String>),
+    QualityCurvePointEncoded(u64, CurvePoint),
+    ResultDetailsToggled(usize),
+    ResultSortSelected(ResultSort),
+    SortDirectionToggled,
+    ActivityLogToggled,
+    SettingsToggled,
+    MaxThreadsChanged(u8),
+    AtomicWritesToggled(bool),
+    SelectScratchFolder,
+    ScratchFolderSelected(Option<PathBuf>),
     OpenOutputFolder,
+    RevealOutput(PathBuf),
+    OutputRevealed(PathBuf, Result<(), String>),
     ClearResults,
+    UndoClearResults,
+    RetryFailed,
+    AddToQueue,
+    RemoveQueuedJob(usize),
+    ClearQueue,
+    RunQueue,
+}
+
+// What a result row stands for, so rows other than compressed outputs are told
+// apart without reading their messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultStatus {
+    // Compressed and written, or failed on the way
+    Processed,
+    // Left out on purpose: a near-duplicate, under the size threshold or already up
+    // to date; nothing was written and the row counts in no totals
+    Skipped,
+    // A non-image file copied to the output folder as it is
+    CopiedThrough,
 }
 
 #[derive(Debug, Clone)]
 pub struct ProcessResult {
     pub filename: String,
+    pub source_path: PathBuf,
     pub original_size: u64,
     pub new_size: u64,
     pub success: bool,
+    pub status: ResultStatus,
     pub message: String,
     pub algorithm_used: CompressionAlgorithm,
     pub compression_ratio: f32,
+    // Where the output was written; None for failures and files left in place
+    pub output_path: Option<PathBuf>,
+    // Output against the pixels it was encoded from, when Measure quality is on
+    pub quality_score: Option<quality::QualityScore>,
+    // Quality the encoder settled on; None for lossless encoders and rows without output
+    pub final_quality: Option<u8>,
+}
+
+impl ProcessResult {
+    // Share of the original size saved, 0 when nothing was written
+    pub fn saved_fraction(&self) -> f64 {
+        if !self.success || self.status == ResultStatus::Skipped || self.original_size == 0 {
+            return 0.0;
+        }
+        1.0 - self.new_size as f64 / self.original_size as f64
+    }
+    
+    // A non-image file copied to the output folder rather than compressed
+    pub fn is_copied_through(&self) -> bool {
+        self.status == ResultStatus::CopiedThrough
+    }
+    
+    // How hard the output was squeezed, from its size relative to the raw pixels. Lossy
+    // formats reach the same look at very different ratios, so each has its own cut-offs.
+    // None where the ratio says nothing about quality.
+    pub fn compression_level(&self) -> Option<CompressionLevel> {
+        if !self.success || self.is_copied_through() || self.new_size == 0 {
+            return None;
+        }
+        
+        let (light, medium) = match self.algorithm_used {
+            CompressionAlgorithm::StandardPng
+            | CompressionAlgorithm::OptiPng
+            | CompressionAlgorithm::OxiPng
+            | CompressionAlgorithm::WebPLossless => return Some(CompressionLevel::Lossless),
+            CompressionAlgorithm::Jpeg => (0.06, 0.025),
+            CompressionAlgorithm::WebPLossy => (0.04, 0.015),
+            CompressionAlgorithm::Avif => (0.03, 0.01),
+            CompressionAlgorithm::PngQuant => (0.2, 0.08),
+            // Simple mode's ratio is against the source file, and these never end up
+            // as the algorithm used
+            CompressionAlgorithm::Simple
+            | CompressionAlgorithm::Auto
+            | CompressionAlgorithm::AutoBest
+            | CompressionAlgorithm::ByAlpha
+            | CompressionAlgorithm::LosslessFirst => return None,
+        };
+        
+        Some(if self.compression_ratio >= light {
+            CompressionLevel::Light
+        } else if self.compression_ratio >= medium {
+            CompressionLevel::Medium
+        } else {
+            CompressionLevel::Aggressive
+        })
+    }
+}
+
+// Per-row badge in the results list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionLevel {
+    Lossless,
+    Light,
+    Medium,
+    Aggressive,
+}
+
+impl CompressionLevel {
+    fn color(self) -> Color {
+        match self {
+            Self::Lossless | Self::Light => SUCCESS_COLOR,
+            Self::Medium => Color::from_rgb(0.85, 0.55, 0.1),
+            Self::Aggressive => ERROR_COLOR,
+        }
+    }
+}
+
+impl std::fmt::Display for CompressionLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lossless => write!(f, "Lossless"),
+            Self::Light => write!(f, "Light compression"),
+            Self::Medium => write!(f, "Medium compression"),
+            Self::Aggressive => write!(f, "Aggressive compression"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum FileInfo {
+    Image {
+        width: u32,
+        height: u32,
+        format: String,
+        file_size: u64,
+        has_alpha: Option<bool>,
+    },
+    Folder {
+        image_count: usize,
+    },
+    Unreadable,
+}
+
+// One row of the "compare algorithms" table
+#[derive(Debug, Clone)]
+struct BenchmarkEntry {
+    algorithm: CompressionAlgorithm,
+    outcome: Result<u64, String>,
+    duration: Duration,
+}
+
+// One downscale of the "compare filters" preview
+#[derive(Debug, Clone)]
+struct FilterSample {
+    name: &'static str,
+    outcome: Result<u64, String>,
+    // A 100% crop from the middle, where the filters' differences are visible
+    crop: iced::widget::image::Handle,
+}
+
+// Middle of the encoded output beside its amplified difference from the source
+#[derive(Debug, Clone)]
+struct DiffPreview {
+    output_size: u64,
+    // Largest channel difference anywhere in the image, before amplifying
+    max_difference: u8,
+    output_crop: iced::widget::image::Handle,
+    diff_crop: iced::widget::image::Handle,
+}
+
+const DIFF_CROP_SIZE: u32 = 200;
+// Differences are multiplied by this, so an error of 32 levels is already white
+const DIFF_GAIN: u8 = 8;
+
+// A quality sweep in progress. The decoded image stays loaded between encodes, which
+// run one at a time so that each finished point moves the progress on.
+struct QualityCurveRun {
+    id: u64,
+    csv_path: PathBuf,
+    template: CompressionOptions,
+    measure: bool,
+    image: Option<Arc<DynamicImage>>,
+    points: Vec<CurvePoint>,
+}
+
+// One row of the quality curve CSV: the output size and, when measured, its score
+#[derive(Debug, Clone)]
+struct CurvePoint {
+    quality: u8,
+    outcome: Result<(u64, Option<quality::QualityScore>), String>,
+}
+
+const CURVE_QUALITIES: std::ops::RangeInclusive<u8> = 10..=100;
+
+// A target-size search over qualities 1-95 converges within this many probes
+const SEARCH_PROBES_ALL: u8 = 8;
+
+// The filters worth comparing, from blockiest to sharpest
+const COMPARED_FILTERS: [(&str, image::imageops::FilterType); 4] = [
+    ("Nearest", image::imageops::FilterType::Nearest),
+    ("Triangle", image::imageops::FilterType::Triangle),
+    ("CatmullRom", image::imageops::FilterType::CatmullRom),
+    ("Lanczos3", image::imageops::FilterType::Lanczos3),
+];
+const FILTER_CROP_SIZE: u32 = 120;
+
+// Pipeline-level settings shared by the simple and advanced processing paths
+#[derive(Debug, Clone, Default)]
+pub struct ProcessingOptions {
+    pub output_dir: Option<PathBuf>,
+    pub backup_originals: bool,
+    // Refuse to write anything at or below the source file's folder
+    pub safe_mode: bool,
+    // Outputs get the source's modified and accessed times instead of the time of writing
+    pub preserve_timestamps: bool,
+    // Added to each output's stem; empty keeps the source's name in another folder
+    pub suffix: String,
+    // "_webp", "_oxipng"... after the suffix, from the encoder that wrote the output.
+    // A dual export's pair leaves it out; their extensions already tell them apart.
+    pub format_in_name: bool,
+    pub gif_to_webp: bool,
+    // Animated GIFs and WebPs become one numbered still per frame
+    pub explode_frames: bool,
+    pub trim_borders: bool,
+    pub trim_tolerance: u8,
+    // Unsharp mask after downscaling; an amount of 0.0 turns it off
+    pub sharpen_amount: f32,
+    pub sharpen_radius: f32,
+    pub max_megapixels: Option<f32>,
+    // Each image's target size follows the pixels it is encoded with, in place of one fixed target
+    pub target_bytes_per_mpx: Option<u64>,
+    // Fit the longest or shortest edge to a length; takes precedence over dimensions
+    pub edge_constraint: Option<(Edge, u32)>,
+    // Width×height in print units at a DPI; replaces the pixel dimensions when set
+    pub physical_size: Option<density::PhysicalSize>,
+    // Scale down, keeping the aspect ratio, until the image has at most this many megapixels
+    pub downscale_megapixels: Option<f32>,
+    // Resize in linear light instead of on the sRGB values; more accurate, off by default
+    pub linear_resize: bool,
+    // Turn the pixels upright using the EXIF orientation tag
+    pub auto_orient: bool,
+    pub grayscale: bool,
+    // Stretch the histogram to the full range, on luminance unless per channel
+    pub auto_levels: bool,
+    pub levels_per_channel: bool,
+    // Regions hidden right after decoding, in the source's own pixel coordinates
+    pub redactions: Vec<adjust::Rect>,
+    pub redact_style: adjust::RedactStyle,
+    // JPEG outputs are flattened onto their background before resizing. Resampling straight alpha
+    // pulls the color hidden under transparent pixels (usually black) into anti-aliased edges
+    pub matte: Option<[u8; 3]>,
+    // Stamped after the pixel steps, right before encoding
+    pub watermark: Option<watermark::Watermark>,
+    // The order the pixel steps run in; see pipeline.rs for the default
+    pub pipeline: pipeline::Pipeline,
+    pub convert_only: bool,
+    pub responsive_widths: Vec<u32>,
+    // How the outputs of a multi-width export are told apart
+    pub width_naming: WidthNaming,
+    // Modern formats (WebP/AVIF) also get a JPEG or PNG fallback with the same stem
+    pub dual_export: bool,
+    // Shared by every file of a batch; images close to an earlier one are skipped
+    pub near_duplicates: Option<Arc<dedupe::DuplicateFilter>>,
+    // Stop the batch at the first failed file instead of continuing past it
+    pub fail_fast: bool,
+    // Folder runs copy every non-image file to the output folder unchanged
+    pub copy_non_images: bool,
+    // Lowercase input extension -> what those files are written as instead of the global choice
+    pub extension_rules: HashMap<String, RuleTarget>,
+    // Width×height is filled exactly, cropping the overflow evenly from both sides
+    pub crop_to_fill: bool,
+    // Write through a temp file and rename, so an interrupted write never leaves a truncated output
+    pub atomic_writes: bool,
+    // Where those temp files go; None writes them next to each output
+    pub scratch_dir: Option<PathBuf>,
+    // Replace JPEGs and PNGs with a losslessly optimized copy instead of processing them
+    pub lossless_optimize: bool,
+    // Outputs are added to this archive instead of being written to the output folder
+    pub zip_output: Option<Arc<archive::ZipOutput>>,
+    // Decode each output again and score it against the encoder's input
+    pub measure_quality: bool,
+    // Images smaller than this many bytes are skipped without being read or written
+    pub min_size_to_process: Option<u64>,
+    // Images whose output exists and is no older than the source are skipped as up to date
+    pub incremental: bool,
+    // Every output gets "{output}.json" with its processing details beside it
+    pub write_sidecars: bool,
+    // Set once the batch is cancelled or stopped at a failure, so the files still in
+    // flight finish without writing anything
+    pub stopped: Arc<AtomicBool>,
+}
+
+impl ProcessingOptions {
+    // Whether a decoded image would come out of the pixel steps any different. The
+    // convert-only copy is taken only when it wouldn't, so every new step belongs here.
+    pub fn changes_pixels(&self) -> bool {
+        self.edge_constraint.is_some()
+            || self.physical_size.is_some()
+            || self.downscale_megapixels.is_some()
+            || self.target_bytes_per_mpx.is_some()
+            || self.trim_borders
+            || self.grayscale
+            || self.auto_levels
+            || !self.redactions.is_empty()
+            || self.watermark.is_some()
+            || self.sharpen_amount > 0.0
+    }
+    
+    // Checked right before each write of a file that may have outlived its batch
+    pub fn ensure_running(&self) -> std::io::Result<()> {
+        if self.stopped.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "the batch was stopped before this file was written"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Edge {
+    #[default]
+    Longest,
+    Shortest,
+}
+
+impl std::fmt::Display for Edge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Longest => write!(f, "Longest edge"),
+            Self::Shortest => write!(f, "Shortest edge"),
+        }
+    }
+}
+
+// Output layout of a multi-width export: "photo-640w.jpg" beside each other, or
+// "640/photo.jpg" with one folder per width
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WidthNaming {
+    #[default]
+    Suffix,
+    Folder,
+}
+
+impl WidthNaming {
+    const ALL: [WidthNaming; 2] = [WidthNaming::Suffix, WidthNaming::Folder];
+}
+
+impl std::fmt::Display for WidthNaming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Suffix => write!(f, "name-640w.jpg"),
+            Self::Folder => write!(f, "640/name.jpg"),
+        }
+    }
+}
+
+// What an extension rule writes its files as: a chosen algorithm, or the file's own
+// format through the encoder for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleTarget {
+    KeepFormat,
+    Algorithm(CompressionAlgorithm),
+}
+
+impl std::fmt::Display for RuleTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::KeepFormat => write!(f, "Keep original format"),
+            Self::Algorithm(algorithm) => write!(f, "{}", algorithm),
+        }
+    }
+}
+
+// Order of the results list within each job. Failures always lead, sorted the
+// same way; "By order" is the order the files finished in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ResultSort {
+    #[default]
+    Order,
+    Name,
+    OriginalSize,
+    NewSize,
+    Saved,
+    Algorithm,
+}
+
+impl ResultSort {
+    const ALL: [ResultSort; 6] = [
+        ResultSort::Order,
+        ResultSort::Name,
+        ResultSort::OriginalSize,
+        ResultSort::NewSize,
+        ResultSort::Saved,
+        ResultSort::Algorithm,
+    ];
+    
+    // Worked out once per row, so sorting doesn't allocate on every comparison
+    fn key(self, result: &ProcessResult) -> SortKey {
+        match self {
+            Self::Order => SortKey::None,
+            Self::Name => SortKey::Text(result.filename.to_lowercase()),
+            Self::OriginalSize => SortKey::Size(result.original_size),
+            Self::NewSize => SortKey::Size(result.new_size),
+            Self::Saved => SortKey::Fraction(result.saved_fraction()),
+            Self::Algorithm => SortKey::Text(result.algorithm_used.to_string()),
+        }
+    }
+}
+
+// A result's value in the chosen sort
+enum SortKey {
+    None,
+    Text(String),
+    Size(u64),
+    Fraction(f64),
+}
+
+impl SortKey {
+    // Ties fall back to the finishing order
+    fn compare(a: (&SortKey, usize), b: (&SortKey, usize)) -> std::cmp::Ordering {
+        let ordering = match (a.0, b.0) {
+            (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+            (SortKey::Size(a), SortKey::Size(b)) => a.cmp(b),
+            (SortKey::Fraction(a), SortKey::Fraction(b)) => a.total_cmp(b),
+            _ => std::cmp::Ordering::Equal,
+        };
+        ordering.then(a.1.cmp(&b.1))
+    }
+}
+
+impl std::fmt::Display for ResultSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Order => write!(f, "By order"),
+            Self::Name => write!(f, "Name"),
+            Self::OriginalSize => write!(f, "Original size"),
+            Self::NewSize => write!(f, "New size"),
+            Self::Saved => write!(f, "% saved"),
+            Self::Algorithm => write!(f, "Algorithm"),
+        }
+    }
+}
+
+// The results view as it was before Clear, for Undo
+#[derive(Debug, Clone)]
+struct ClearedResults {
+    results: Vec<ProcessResult>,
+    job_groups: Vec<(usize, String)>,
+    status_message: String,
+    progress: f32,
+}
+
+// Everything one Process run needs, captured when it starts and cloned into each file task
+#[derive(Debug, Clone)]
+struct BatchSettings {
+    // In bytes
+    target_size: Option<u64>,
+    // Folder budget in KB; once planned, every file's share in bytes overrides target_size
+    budget_kb: Option<u64>,
+    budget_by_detail: bool,
+    budget_targets: Arc<HashMap<PathBuf, u64>>,
+    dimensions: Option<(u32, u32)>,
+    maintain_ratio: bool,
+    auto_scale: bool,
+    compression_options: CompressionOptions,
+    options: ProcessingOptions,
+    max_threads: usize,
+    // report.csv of this run, when reports are on
+    report_path: Option<PathBuf>,
+}
+
+// A run waiting in the queue: its selection, and its settings as they were when it
+// was added. Its ZIP archive, if any, is only created when the job starts.
+#[derive(Debug, Clone)]
+struct Job {
+    label: String,
+    selected_path: Option<PathBuf>,
+    selected_files: Vec<PathBuf>,
+    list_rejects: Vec<(PathBuf, String)>,
+    batch: BatchSettings,
+    // Archive path, and the folder whose structure is kept inside it
+    zip_plan: Option<(PathBuf, Option<PathBuf>)>,
+    only_formats: &'static [image::ImageFormat],
+    format_filter: FormatFilter,
+    copy_non_images: bool,
+}
+
+// Elapsed time and throughput of the running batch
+#[derive(Debug, Default)]
+struct BatchTiming {
+    started: Option<Instant>,
+    last_completed: Option<Instant>,
+    files_done: usize,
+    bytes_done: u64,
+}
+
+impl BatchTiming {
+    fn start(&mut self) {
+        *self = Self {
+            started: Some(Instant::now()),
+            ..Self::default()
+        };
+    }
+
+    fn record(&mut self, bytes: u64) {
+        self.last_completed = Some(Instant::now());
+        self.files_done += 1;
+        self.bytes_done += bytes;
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    // "12 of 40 | 2.1 files/s | 3.4 MB/s | ETA 0:13", once at least one file has finished
+    fn summary(&self, total_files: usize) -> Option<String> {
+        let started = self.started?;
+        let last_completed = self.last_completed?;
+        let elapsed = last_completed.duration_since(started).as_secs_f64();
+        if self.files_done == 0 || elapsed <= 0.0 {
+            return None;
+        }
+        
+        let files_per_sec = self.files_done as f64 / elapsed;
+        let mb_per_sec = self.bytes_done as f64 / (1024.0 * 1024.0) / elapsed;
+        let remaining = total_files.saturating_sub(self.files_done);
+        let eta = remaining as f64 * (elapsed / self.files_done as f64);
+        
+        Some(format!(
+            "{} of {} | {:.1} files/s | {:.1} MB/s | ETA {}",
+            self.files_done,
+            total_files,
+            files_per_sec,
+            mb_per_sec,
+            format_duration(eta),
+        ))
+    }
+
+    fn elapsed_secs(&self) -> Option<f64> {
+        self.started.map(|started| started.elapsed().as_secs_f64())
+    }
+}
+
+fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
 }
 
 impl Application for ImageResizer {
@@ -127,6 +1023,28 @@ impl Application for ImageResizer {
     fn new(_flags: ()) -> (Self, Command<Message>) {
         let mut app = Self::default();
         app.quality_slider = 85;
+        app.opaque_algorithm = CompressionAlgorithm::Jpeg;
+        app.transparent_algorithm = CompressionAlgorithm::OxiPng.fallback();
+        app.trim_tolerance = 16;
+        app.sharpen_radius = 10;
+        app.duplicate_threshold = 5;
+        app.oxipng_level = 3;
+        app.webp_filter_strength = 60;
+        app.min_quality = 10;
+        app.max_search_probes = SEARCH_PROBES_ALL;
+        app.oxipng_multithreaded = true;
+        app.use_mozjpeg = true;
+        app.budget_by_detail = true;
+        app.max_threads = 1;
+        app.atomic_writes = true;
+        app.extension_rules = default_extension_rules();
+        app.jpeg_background = String::from("#FFFFFF");
+        app.watermark_margin = String::from("16");
+        app.watermark_opacity = 50;
+        app.watermark_size = 32;
+        app.watermark_color = String::from("#FFFFFF");
+        app.output_suffix = String::from(DEFAULT_SUFFIX);
+        app.apply_env_defaults();
         (app, Command::none())
     }
 
@@ -146,873 +1064,6529 @@ impl Application for ImageResizer {
                 self.auto_scale = value;
             }
             Message::FileSelected(path) => {
+                if path.is_some() {
+                    self.selected_files.clear();
+                    self.list_rejects.clear();
+                }
                 self.selected_path = path;
+                self.file_info = None;
+                self.benchmark = None;
+                self.filter_comparison = None;
+                self.diff_preview = None;
+                self.quality_curve_note = None;
+                if let Some(path) = &self.selected_path {
+                    let path = path.clone();
+                    return Command::perform(
+                        load_file_info(path.clone()),
+                        move |info| Message::FileInfoLoaded(path.clone(), info),
+                    );
+                }
             }
-            Message::TargetSizeChanged(value) => {
-                self.target_size = value;
-            }
-            Message::WidthChanged(value) => {
-                self.width = value;
+            Message::FolderBudgetChanged(value) => {
+                self.folder_budget = value;
             }
-            Message::HeightChanged(value) => {
-                self.height = value;
+            Message::BudgetByDetailToggled(value) => {
+                self.budget_by_detail = value;
             }
-            Message::MaintainRatioToggled(value) => {
-                self.maintain_ratio = value;
+            Message::AddFiles => {
+                return Command::perform(select_files(), Message::FilesAdded);
             }
-            Message::ModeChanged(mode) => {
-                self.compression_mode = mode;
-                if mode == CompressionMode::Simple {
-                    self.compression_algorithm = CompressionAlgorithm::Simple;
-                    self.quality_slider = 85;
+            Message::FilesAdded(files) => {
+                if !files.is_empty() {
+                    self.selected_path = None;
+                    self.file_info = None;
+                    self.benchmark = None;
+                    self.filter_comparison = None;
+                    self.diff_preview = None;
+                    self.quality_curve_note = None;
                 }
-            }
-            Message::AlgorithmSelected(algorithm) => {
-                self.compression_algorithm = algorithm;
-                if algorithm.supports_quality() {
-                    self.quality_slider = algorithm.recommended_quality();
+                for file in files {
+                    if !self.selected_files.contains(&file) {
+                        self.selected_files.push(file);
+                    }
                 }
             }
-            Message::QualityChanged(quality) => {
-                self.quality_slider = quality;
-            }
-            Message::OptimizeForWebToggled(value) => {
-                self.optimize_for_web = value;
+            Message::LoadList => {
+                return Command::perform(load_file_list(), Message::ListLoaded);
             }
-            Message::Process => {
-                if let Some(path) = &self.selected_path {
-                    self.is_processing = true;
-                    self.progress = 0.0;
-                    self.results.clear();
-                    
-                    let path = path.clone();
-                    let target_size = self.target_size.parse::<u64>().ok();
-                    let dimensions = parse_dimensions(&self.width, &self.height);
-                    let maintain_ratio = self.maintain_ratio;
-                    let algorithm = self.compression_algorithm;
-                    let quality = self.quality_slider;
-                    let optimize_for_web = self.optimize_for_web;
-                    let auto_scale = self.auto_scale;
-                    
-                    if algorithm == CompressionAlgorithm::Simple {
-                        return Command::perform(
-                            simple::process_images(
-                                path,
-                                target_size,
-                                dimensions,
-                                maintain_ratio,
-                                auto_scale,
-                            ),
-                            |results| Message::ProcessingComplete(
-                                results.into_iter().map(|r| ProcessResult {
-                                    filename: r.filename,
-                                    original_size: r.original_size,
-                                    new_size: r.new_size,
-                                    success: r.success,
-                                    message: r.message,
-                                    algorithm_used: CompressionAlgorithm::Simple,
-                                    compression_ratio: if r.original_size > 0 {
-                                        r.new_size as f32 / r.original_size as f32
-                                    } else {
-                                        0.0
-                                    },
-                                }).collect()
+            Message::ListLoaded(Some((list_path, loaded))) => {
+                let list_name = list_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                match loaded {
+                    Ok(list) => {
+                        self.status_message = if list.rejected.is_empty() {
+                            format!("Loaded {} files from {}", list.files.len(), list_name)
+                        } else {
+                            format!(
+                                "Loaded {} files from {}; {} listed paths can't be used",
+                                list.files.len(),
+                                list_name,
+                                list.rejected.len()
                             )
-                        );
-                    } else {
-                        return Command::perform(
-                            process_images_advanced(
-                                path,
-                                target_size,
-                                dimensions,
-                                maintain_ratio,
-                                algorithm,
-                                quality,
-                                optimize_for_web,
-                            ),
-                            Message::ProcessingComplete
-                        );
+                        };
+                        self.log(self.status_message.clone());
+                        for (path, reason) in &list.rejected {
+                            self.log(format!("{}: {}", path.display(), reason));
+                        }
+                        // The files join the selection, so the rejects join those of earlier lists
+                        for reject in list.rejected {
+                            if !self.list_rejects.iter().any(|(path, _)| *path == reject.0) {
+                                self.list_rejects.push(reject);
+                            }
+                        }
+                        if list.files.is_empty() {
+                            self.selected_path = None;
+                            self.file_info = None;
+                        }
+                        return self.update(Message::FilesAdded(list.files));
+                    }
+                    Err(message) => {
+                        self.status_message = format!("Couldn't read {}: {}", list_name, message);
+                        self.log(self.status_message.clone());
                     }
                 }
             }
-            Message::ProcessingComplete(results) => {
-                self.is_processing = false;
-                self.progress = 1.0;
-                self.results = results;
-                self.status_message = format!("Processed {} images successfully!", self.results.len());
+            Message::ListLoaded(None) => {}
+            Message::RemoveFile(index) => {
+                if index < self.selected_files.len() {
+                    self.selected_files.remove(index);
+                }
             }
-            Message::OpenOutputFolder => {
-                if let Some(path) = &self.selected_path {
-                    let output_dir = path.parent().unwrap_or(Path::new(".")).join("resized");
-                    if output_dir.exists() {
-                        let _ = open::that(output_dir);
-                    }
+            Message::MoveFile(index, direction) => {
+                let target = match direction {
+                    Direction::Up => index.checked_sub(1),
+                    Direction::Down => Some(index + 1),
+                };
+                if let Some(target) = target.filter(|target| *target < self.selected_files.len()) {
+                    self.selected_files.swap(index, target);
                 }
             }
-            Message::ClearResults => {
-                self.results.clear();
-                self.progress = 0.0;
-                self.status_message.clear();
+            Message::FileInfoLoaded(path, info) => {
+                // Ignore results for a selection that has since changed
+                if self.selected_path.as_ref() == Some(&path) {
+                    self.file_info = Some(info);
+                }
             }
-        }
-        Command::none()
-    }
-
-    fn view(&self) -> Element<Message> {
-        // Header section with gradient background
-        let header = container(
-            column![
-                text("Image Resizer Pro")
-                    .size(18)
-                    .font(HEADING_FONT)
-                    .style(Color::WHITE),
-                text("Compress and resize your images with style")
-                    .size(14)
-                    .font(LIGHT_FONT)
-                    .style(Color::from_rgba(1.0, 1.0, 1.0, 0.8)),
-            ].spacing(4)
-        )
-        .width(Length::Fill)
-        .padding([18, 26])
-        .style(theme::Container::Custom(Box::new(GradientContainer)));
-
-        // File selection card
-        let file_selection_card = container(
-            column![
-                row![
-                    icon_text("", "Select Images", 14, 14),
-                    Space::with_width(Length::Fill),
-                ].spacing(8),
-                
-                Space::with_height(12),
-                
-                row![
-                    styled_button("Select File", Message::SelectFile, ButtonStyle::Primary),
-                    styled_button("Select Folder", Message::SelectFolder, ButtonStyle::Secondary),
-                ].spacing(8),
+            Message::SelectOutputFolder => {
+                return Command::perform(select_folder(), Message::OutputFolderSelected);
+            }
+            Message::OutputFolderSelected(path) => {
+                self.output_dir = path;
+            }
+            Message::ZipOutputToggled(value) => {
+                self.zip_output = value;
+            }
+            Message::SelectZipFile => {
+                return Command::perform(select_zip_file(), Message::ZipFileSelected);
+            }
+            Message::ZipFileSelected(path) => {
+                if path.is_some() {
+                    self.zip_path = path;
+                }
+            }
+            Message::ZipKeepStructureToggled(value) => {
+                self.zip_keep_structure = value;
+            }
+            Message::WriteReportToggled(value) => {
+                self.write_report = value;
+            }
+            Message::WriteSidecarsToggled(value) => {
+                self.write_sidecars = value;
+            }
+            Message::FailFastToggled(value) => {
+                self.fail_fast = value;
+            }
+            Message::CopyNonImagesToggled(value) => {
+                self.copy_non_images = value;
+            }
+            Message::FormatFilterChanged(value) => {
+                self.format_filter = value;
+            }
+            Message::PngToWebPToggled(value) => {
+                self.png_to_webp = value;
+            }
+            Message::LosslessOptimizeToggled(value) => {
+                self.lossless_optimize = value;
+            }
+            Message::BackupOriginalsToggled(value) => {
+                self.backup_originals = value;
+            }
+            Message::SafeModeToggled(value) => {
+                self.safe_mode = value;
+            }
+            Message::PreserveTimestampsToggled(value) => {
+                self.preserve_timestamps = value;
+            }
+            Message::OutputSuffixChanged(value) => {
+                self.output_suffix = value;
+            }
+            Message::FormatInNameToggled(value) => {
+                self.format_in_name = value;
+            }
+            Message::GifToWebPToggled(value) => {
+                self.gif_to_webp = value;
+            }
+            Message::ExplodeFramesToggled(value) => {
+                self.explode_frames = value;
+            }
+            Message::TrimBordersToggled(value) => {
+                self.trim_borders = value;
+            }
+            Message::SharpenAmountChanged(value) => {
+                self.sharpen_amount = value;
+            }
+            Message::SharpenRadiusChanged(value) => {
+                self.sharpen_radius = value;
+            }
+            Message::TrimToleranceChanged(value) => {
+                self.trim_tolerance = value;
+            }
+            Message::MaxMegapixelsChanged(value) => {
+                self.max_megapixels = value;
+            }
+            Message::LinearResizeToggled(value) => {
+                self.linear_resize = value;
+            }
+            Message::AutoOrientToggled(value) => {
+                self.auto_orient = value;
+            }
+            Message::GrayscaleToggled(value) => {
+                self.grayscale = value;
+            }
+            Message::AutoLevelsToggled(value) => {
+                self.auto_levels = value;
+            }
+            Message::LevelsPerChannelToggled(value) => {
+                self.levels_per_channel = value;
+            }
+            Message::RedactionsChanged(value) => {
+                self.redactions = value;
+            }
+            Message::RedactBlurToggled(value) => {
+                self.redact_blur = value;
+            }
+            Message::WatermarkTextChanged(value) => {
+                self.watermark_text = value;
+            }
+            Message::SelectWatermarkImage => {
+                return Command::perform(select_overlay_file(), Message::WatermarkImageSelected);
+            }
+            Message::WatermarkImageSelected(path) => {
+                self.watermark_image = path;
+            }
+            Message::WatermarkPositionSelected(position) => {
+                self.watermark_position = position;
+            }
+            Message::WatermarkMarginChanged(value) => {
+                if value.chars().all(|c| c.is_ascii_digit()) {
+                    self.watermark_margin = value;
+                }
+            }
+            Message::WatermarkOpacityChanged(value) => {
+                self.watermark_opacity = value;
+            }
+            Message::WatermarkSizeChanged(value) => {
+                self.watermark_size = value;
+            }
+            Message::WatermarkColorChanged(value) => {
+                self.watermark_color = value;
+            }
+            Message::MovePipelineStep(index, direction) => {
+                self.pipeline.move_step(index, direction);
+            }
+            Message::ResetPipelineOrder => {
+                self.pipeline = pipeline::Pipeline::default();
+            }
+            Message::DownscaleMegapixelsChanged(value) => {
+                self.downscale_megapixels = value;
+            }
+            Message::MinSizeToProcessChanged(value) => {
+                self.min_size_to_process = value;
+            }
+            Message::IncrementalToggled(value) => {
+                self.incremental = value;
+            }
+            Message::DpiChanged(value) => {
+                self.dpi = value;
+            }
+            Message::LosslessLimitChanged(value) => {
+                self.lossless_limit = value;
+            }
+            Message::ResponsiveWidthsChanged(value) => {
+                self.responsive_widths = value;
+            }
+            Message::WidthNamingSelected(naming) => {
+                self.width_naming = naming;
+            }
+            Message::DualExportToggled(value) => {
+                self.dual_export = value;
+            }
+            Message::SkipNearDuplicatesToggled(value) => {
+                self.skip_near_duplicates = value;
+            }
+            Message::DuplicateThresholdChanged(value) => {
+                self.duplicate_threshold = value;
+            }
+            Message::ConvertOnlyToggled(value) => {
+                self.convert_only = value;
+            }
+            Message::JpegBackgroundChanged(value) => {
+                self.jpeg_background = value;
+            }
+            Message::TargetSizeChanged(value) => {
+                self.target_size = value;
+                self.size_reference = None;
+            }
+            Message::TargetPerMegapixelChanged(value) => {
+                self.target_per_megapixel = value;
+            }
+            Message::SelectSizeReference => {
+                return Command::perform(select_file(), Message::SizeReferenceSelected);
+            }
+            Message::SizeReferenceSelected(path) => {
+                if let Some(path) = path {
+                    match fs::metadata(&path) {
+                        Ok(metadata) if metadata.len() == 0 => {
+                            self.status_message = format!("{} is empty; pick a file with a size to match", path.display());
+                        }
+                        Ok(metadata) => {
+                            // Only shown; the run matches the exact byte count
+                            self.target_size = (metadata.len() / 1024).max(1).to_string();
+                            self.size_reference = Some((path, metadata.len()));
+                        }
+                        Err(e) => {
+                            self.status_message = format!("Could not read {}: {}", path.display(), e);
+                        }
+                    }
+                }
+            }
+            Message::WidthChanged(value) => {
+                if let Some((width, height)) = self.locked_source_dimensions() {
+                    self.height = proportional_dimension(&value, width, height);
+                }
+                self.width = value;
+                self.dimension_preset = None;
+            }
+            Message::HeightChanged(value) => {
+                if let Some((width, height)) = self.locked_source_dimensions() {
+                    self.width = proportional_dimension(&value, height, width);
+                }
+                self.height = value;
+                self.dimension_preset = None;
+            }
+            Message::MaintainRatioToggled(value) => {
+                self.maintain_ratio = value;
+            }
+            Message::PresetSelected(preset) => {
+                // Custom keeps whatever is in the fields for manual editing, but not a
+                // crop left over from a cropping preset
+                self.crop_to_fill = preset.crop;
+                if let Some((width, height)) = preset.size {
+                    self.width = width.to_string();
+                    self.height = height.to_string();
+                    self.maintain_ratio = true;
+                    // A fitted edge would take precedence over the preset size
+                    self.edge_length.clear();
+                }
+                self.dimension_preset = Some(preset).filter(|preset| preset.size.is_some());
+            }
+            Message::CropToFillToggled(value) => {
+                self.crop_to_fill = value;
+            }
+            Message::EdgeSelected(edge) => {
+                self.edge = edge;
+            }
+            Message::EdgeLengthChanged(value) => {
+                self.edge_length = value;
+            }
+            Message::PrintWidthChanged(value) => {
+                self.print_width = value;
+            }
+            Message::PrintHeightChanged(value) => {
+                self.print_height = value;
+            }
+            Message::PrintUnitSelected(unit) => {
+                self.print_unit = unit;
+            }
+            Message::ModeChanged(mode) => {
+                self.compression_mode = mode;
+                if mode == CompressionMode::Simple {
+                    self.compression_algorithm = CompressionAlgorithm::Simple;
+                    self.quality_slider = 85;
+                }
+            }
+            Message::AlgorithmSelected(algorithm) => {
+                // Each algorithm gets back the quality it was last used with, or
+                // its own sweet spot the first time, so switching never inherits
+                // another algorithm's value
+                self.compression_algorithm = algorithm;
+                self.quality_slider = self.algorithm_qualities
+                    .get(&algorithm)
+                    .copied()
+                    .unwrap_or_else(|| algorithm.recommended_quality());
+            }
+            Message::OpaqueAlgorithmSelected(algorithm) => {
+                self.opaque_algorithm = algorithm;
+            }
+            Message::TransparentAlgorithmSelected(algorithm) => {
+                self.transparent_algorithm = algorithm;
+            }
+            Message::QualityChanged(quality) => {
+                self.quality_slider = quality;
+                self.algorithm_qualities.insert(self.compression_algorithm, quality);
+            }
+            Message::WebPFilterStrengthChanged(strength) => {
+                self.webp_filter_strength = strength;
+            }
+            Message::WebPSharpnessChanged(sharpness) => {
+                self.webp_sharpness = sharpness;
+            }
+            Message::AlphaQualityChanged(quality) => {
+                self.alpha_quality = quality;
+            }
+            Message::OptimizeForWebToggled(value) => {
+                self.optimize_for_web = value;
+            }
+            Message::EmbedSrgbToggled(value) => {
+                self.embed_srgb = value;
+            }
+            Message::EmbedThumbnailToggled(value) => {
+                self.embed_thumbnail = value;
+            }
+            Message::MeasureQualityToggled(value) => {
+                self.measure_quality = value;
+            }
+            Message::PreserveMetadataToggled(value) => {
+                self.preserve_metadata = value;
+            }
+            Message::MinimalPaletteToggled(value) => {
+                self.minimal_palette = value;
+            }
+            Message::Reduce16BitPngToggled(value) => {
+                self.reduce_16bit_png = value;
+            }
+            Message::KeepBitDepthToggled(value) => {
+                self.keep_bit_depth = value;
+            }
+            Message::ExtensionRulesToggled(value) => {
+                self.use_extension_rules = value;
+            }
+            Message::ExtensionRuleTargetSelected(index, target) => {
+                if let Some(rule) = self.extension_rules.get_mut(index) {
+                    rule.1 = target;
+                }
+            }
+            Message::ExtensionRuleRemoved(index) => {
+                if index < self.extension_rules.len() {
+                    self.extension_rules.remove(index);
+                }
+            }
+            Message::NewRuleExtensionChanged(value) => {
+                self.new_rule_extension = value;
+            }
+            Message::ExtensionRuleAdded => {
+                let extension = self.new_rule_extension.trim().trim_start_matches('.').to_lowercase();
+                if !extension.is_empty() {
+                    // Adding an extension that already has a rule just re-targets it
+                    self.extension_rules.retain(|(existing, _)| *existing != extension);
+                    self.extension_rules.push((extension, RuleTarget::Algorithm(self.compression_algorithm)));
+                    self.new_rule_extension.clear();
+                }
+            }
+            Message::OxiPngLevelChanged(level) => {
+                self.oxipng_level = level;
+            }
+            Message::OxiPngMultithreadedToggled(value) => {
+                self.oxipng_multithreaded = value;
+            }
+            Message::UseMozJpegToggled(value) => {
+                self.use_mozjpeg = value;
+            }
+            Message::JpegModeSelected(mode) => {
+                self.jpeg_mode = mode;
+            }
+            Message::InterlacePngToggled(value) => {
+                self.interlace_png = value;
+            }
+            Message::ProgressiveLoadingToggled(value) => {
+                self.progressive_loading = value;
+            }
+            Message::MinQualityChanged(quality) => {
+                self.min_quality = quality;
+            }
+            Message::MaxSearchProbesChanged(probes) => {
+                self.max_search_probes = probes;
+            }
+            Message::AdaptiveQualityToggled(value) => {
+                self.adaptive_quality = value;
+            }
+            Message::Process => {
+                if !self.has_selection() {
+                    return Command::none();
+                }
+                match self.build_job() {
+                    Ok(job) => {
+                        self.job_groups.clear();
+                        return self.start_job(job);
+                    }
+                    Err(problem) => {
+                        self.status_message = problem;
+                        self.log(self.status_message.clone());
+                    }
+                }
+            }
+            Message::AddToQueue => {
+                if !self.has_selection() {
+                    return Command::none();
+                }
+                match self.build_job() {
+                    Ok(job) => {
+                        self.log(format!("Queued {}", job.label));
+                        self.queue.push(job);
+                    }
+                    Err(problem) => {
+                        self.status_message = problem;
+                        self.log(self.status_message.clone());
+                    }
+                }
+            }
+            Message::RemoveQueuedJob(index) => {
+                if index < self.queue.len() {
+                    self.queue.remove(index);
+                }
+            }
+            Message::ClearQueue => {
+                self.queue.clear();
+            }
+            Message::RunQueue => {
+                if self.is_processing || self.queue.is_empty() {
+                    return Command::none();
+                }
+                self.results.clear();
+                self.job_groups.clear();
+                self.queue_running = true;
+                let job = self.queue.remove(0);
+                self.log(format!("Starting queued job {}", job.label));
+                return self.start_job(job);
+            }
+            Message::ImagesCollected(batch_id, images) => {
+                if batch_id != self.batch_id || !self.is_processing {
+                    return Command::none();
+                }
+                self.total_files = images.len();
+                self.log(format!("Found {} images", images.len()));
                 
-                Space::with_height(12),
+                // Budget mode weighs every image before the first one is processed
+                if let Some(batch) = &self.batch {
+                    if let Some(budget_kb) = batch.budget_kb {
+                        self.status_message = String::from("Planning the size budget...");
+                        let simple_mode = batch.compression_options.algorithm == CompressionAlgorithm::Simple;
+                        let by_detail = batch.budget_by_detail;
+                        let max_megapixels = batch.options.max_megapixels;
+                        return Command::perform(
+                            plan_budget(images, budget_kb, by_detail, max_megapixels, simple_mode),
+                            move |planned| Message::BudgetPlanned(batch_id, planned)
+                        );
+                    }
+                }
                 
-                if let Some(path) = &self.selected_path {
-                    let display_path = path.display().to_string();
-                    let truncated = if display_path.len() > 50 {
-                        format!("...{}", &display_path[display_path.len()-47..])
-                    } else {
-                        display_path
-                    };
-                    container(
-                        text(format!("{}", truncated))
-                            .size(13)
-                            .font(BODY_FONT)
-                            .style(Color::from_rgb(0.4, 0.4, 0.5))
-                    )
-                    .width(Length::Fill)
-                    .padding([8, 12])
-                    .style(theme::Container::Custom(Box::new(SubtleContainer)))
+                self.pending_files = images.into();
+                return self.process_next_file();
+            }
+            Message::BudgetPlanned(batch_id, planned) => {
+                if batch_id != self.batch_id || !self.is_processing {
+                    return Command::none();
+                }
+                let (images, targets) = match planned {
+                    Ok(planned) => planned,
+                    Err(e) => {
+                        // Without the shares every file would run without a target, so nothing starts
+                        self.status_message = e;
+                        if let Some(note) = finish_archive(self.batch.as_ref()) {
+                            self.status_message.push_str(&format!(" {}", note));
+                        }
+                        self.pause_queue();
+                        self.log(self.status_message.clone());
+                        self.is_processing = false;
+                        self.batch_id += 1;
+                        self.last_batch = self.batch.take();
+                        self.pending_files.clear();
+                        self.in_flight = 0;
+                        self.timing.reset();
+                        return Command::none();
+                    }
+                };
+                for image in &images {
+                    if let Some(bytes) = targets.get(image) {
+                        self.log(format!(
+                            "Budget target for {}: {} KB",
+                            image.file_name().unwrap_or_default().to_string_lossy(),
+                            bytes / 1024
+                        ));
+                    }
+                }
+                if let Some(batch) = &mut self.batch {
+                    batch.budget_targets = Arc::new(targets);
+                }
+                self.pending_files = images.into();
+                return self.process_next_file();
+            }
+            Message::FileProcessed(batch_id, results) => {
+                // Results from a cancelled or superseded batch are dropped
+                if batch_id != self.batch_id || !self.is_processing {
+                    return Command::none();
+                }
+                self.in_flight = self.in_flight.saturating_sub(1);
+                // Every variant of a multi-size export reports the same source size
+                self.timing.record(results.first().map_or(0, |result| result.original_size));
+                for result in &results {
+                    if let Some(entry) = encode_log_entry(result) {
+                        self.log(entry);
+                    }
+                    self.log(result_log_entry(result));
+                }
+                // A retried file's new rows take the place of all its old ones
+                let count = results.len();
+                let job_start = self.last_job_start();
+                let replaced = results.first()
+                    .filter(|_| self.retrying)
+                    .and_then(|first| {
+                        let source_path = first.source_path.clone();
+                        let index = self.results[job_start..].iter().position(|result| result.source_path == source_path)?;
+                        Some((job_start + index, source_path))
+                    });
+                let first_new = match replaced {
+                    Some((index, source_path)) => {
+                        let mut job_results: Vec<ProcessResult> = self.results
+                            .drain(job_start..)
+                            .filter(|result| result.source_path != source_path)
+                            .collect();
+                        job_results.splice(index - job_start..index - job_start, results);
+                        self.results.extend(job_results);
+                        index
+                    }
+                    None => {
+                        self.results.extend(results);
+                        self.results.len() - count
+                    }
+                };
+                if self.total_files > 0 {
+                    self.progress = self.timing.files_done as f32 / self.total_files as f32;
+                }
+                
+                let fail_fast = self.batch.as_ref().is_some_and(|batch| batch.options.fail_fast);
+                let failed = self.results[first_new..first_new + count].iter().position(|result| !result.success);
+                if let (true, Some(offset)) = (fail_fast, failed) {
+                    let failure = &self.results[first_new + offset];
+                    self.status_message = format!(
+                        "Stopped at {} ({} of {}): {}",
+                        failure.filename,
+                        self.timing.files_done,
+                        self.total_files,
+                        failure.message
+                    );
+                    if let Some(note) = finish_archive(self.batch.as_ref()) {
+                        self.status_message.push_str(&format!(" {}", note));
+                    }
+                    self.pause_queue();
+                    self.log(self.status_message.clone());
+                    self.stop_in_flight();
+                    // Open the failing row's details so it isn't buried in the list
+                    self.expanded_result = Some(first_new + offset);
+                    self.stopped_on_failure = true;
+                    self.is_processing = false;
+                    self.last_batch = self.batch.take();
+                    self.pending_files.clear();
+                    self.in_flight = 0;
+                    self.timing.reset();
+                    return Command::none();
+                }
+                return self.process_next_file();
+            }
+            Message::PrepareForWeb => {
+                if !self.has_selection() || self.is_processing {
+                    return Command::none();
+                }
+                // Forum/web upload bundle: WebP under 500 KB, at most 2048px on the longest edge.
+                // Metadata is never carried over, and web optimization strips the rest.
+                self.compression_mode = CompressionMode::Advanced;
+                self.compression_algorithm = CompressionAlgorithm::WebPLossy;
+                self.quality_slider = CompressionAlgorithm::WebPLossy.recommended_quality();
+                self.optimize_for_web = true;
+                self.preserve_metadata = false;
+                self.embed_thumbnail = false;
+                self.target_size = String::from("500");
+                self.target_per_megapixel.clear();
+                self.size_reference = None;
+                self.folder_budget.clear();
+                self.width.clear();
+                self.height.clear();
+                self.print_width.clear();
+                self.print_height.clear();
+                self.edge = Edge::Longest;
+                self.edge_length = String::from("2048");
+                self.downscale_megapixels.clear();
+                self.convert_only = false;
+                self.png_to_webp = false;
+                self.lossless_optimize = false;
+                self.dual_export = false;
+                self.responsive_widths.clear();
+                self.use_extension_rules = false;
+                return self.update(Message::Process);
+            }
+            Message::FlattenToJpeg => {
+                if !self.has_selection() || self.is_processing {
+                    return Command::none();
+                }
+                // Transparent logos and cut-outs onto white JPEG. The size settings are kept;
+                // quality stays high since flat edges show JPEG ringing first
+                self.compression_mode = CompressionMode::Advanced;
+                self.compression_algorithm = CompressionAlgorithm::Jpeg;
+                self.quality_slider = 90;
+                self.jpeg_background = String::from("#FFFFFF");
+                self.convert_only = false;
+                self.png_to_webp = false;
+                self.lossless_optimize = false;
+                self.dual_export = false;
+                self.use_extension_rules = false;
+                return self.update(Message::Process);
+            }
+            Message::CancelProcessing => {
+                if self.is_processing {
+                    self.status_message = format!(
+                        "Cancelled after {} of {} images",
+                        self.timing.files_done,
+                        self.total_files
+                    );
+                    if let Some(note) = finish_archive(self.batch.as_ref()) {
+                        self.status_message.push_str(&format!(" {}", note));
+                    }
+                    self.pause_queue();
+                    self.log(self.status_message.clone());
+                    self.stop_in_flight();
+                    self.is_processing = false;
+                    self.batch_id += 1;
+                    self.last_batch = self.batch.take();
+                    self.pending_files.clear();
+                    self.in_flight = 0;
+                    self.timing.reset();
+                }
+            }
+            Message::ProcessingComplete => {
+                self.is_processing = false;
+                self.progress = 1.0;
+                let batch = self.batch.take();
+                self.last_batch = batch.clone();
+                let job_results = &self.results[self.last_job_start()..];
+                let copied = job_results.iter().filter(|result| result.is_copied_through()).count();
+                let skipped = job_results.iter().filter(|result| result.status == ResultStatus::Skipped).count();
+                let images = job_results.len() - copied - skipped;
+                self.status_message = if self.retrying {
+                    let still_failing = job_results.iter().filter(|result| !result.success).count();
+                    format!("Retried {} files; {} still failing", self.total_files, still_failing)
                 } else {
-                    container(
-                        text("No files selected yet")
-                            .size(13)
-                            .font(LIGHT_FONT)
-                            .style(Color::from_rgb(0.6, 0.6, 0.7))
-                    )
-                    .width(Length::Fill)
-                    .padding([8, 12])
-                    .style(theme::Container::Custom(Box::new(SubtleContainer)))
+                    match self.timing.elapsed_secs() {
+                        Some(secs) => format!(
+                            "Processed {} images successfully in {}!",
+                            images,
+                            format_duration(secs)
+                        ),
+                        None => format!("Processed {} images successfully!", images),
+                    }
+                };
+                if copied > 0 {
+                    self.status_message.push_str(&format!(" Copied {} other files unchanged.", copied));
+                }
+                if skipped > 0 {
+                    self.status_message.push_str(&format!(" Skipped {} files.", skipped));
+                }
+                if let Some(budget_kb) = batch.as_ref().and_then(|batch| batch.budget_kb) {
+                    self.status_message.push(' ');
+                    self.status_message.push_str(&budget_summary(job_results, budget_kb));
+                }
+                if let Some(note) = finish_archive(batch.as_ref()) {
+                    self.status_message.push_str(&format!(" {}", note));
                 }
-            ].spacing(0)
-        )
-        .width(Length::Fill)
-        .padding(12)
-        .style(theme::Container::Custom(Box::new(CardContainer)));
-
-        // Compression mode selection with visual tabs
-        let mode_selection_card = container(
-            column![
-                icon_text("", "Compression Mode", 14, 14),
                 
-                Space::with_height(12),
+                // Each job of a queue run writes the report of its own rows
+                if let Some(report_path) = batch.and_then(|batch| batch.report_path).filter(|_| !job_results.is_empty()) {
+                    let written = report_path.parent().map_or(Ok(()), fs::create_dir_all)
+                        .and_then(|_| write_atomic(&report_path, results_to_csv(job_results).as_bytes(), None));
+                    if let Err(e) = written {
+                        self.status_message.push_str(&format!(" (report.csv failed: {})", e));
+                    }
+                }
+                self.log(self.status_message.clone());
                 
-                row![
-                    mode_button("Simple", "Fast & Easy", CompressionMode::Simple, self.compression_mode),
-                    Space::with_width(12),
-                    mode_button("Advanced", "Full Control", CompressionMode::Advanced, self.compression_mode),
-                ].spacing(0),
-            ].spacing(0)
-        )
-        .width(Length::Fill)
-        .padding(12)
-        .style(theme::Container::Custom(Box::new(CardContainer)));
-
-        // Compression settings card
-        let compression_settings = match self.compression_mode {
-            CompressionMode::Simple => {
-                container(
-                    column![
-                        icon_text("", "Simple Settings", 14, 14),
-                        Space::with_height(12),
-                        styled_checkbox(
-                            "Auto Scale (resize to meet target size)",
-                            self.auto_scale,
-                            Message::AutoScaleToggled
-                        ),
-                    ].spacing(0)
-                )
-                .width(Length::Fill)
-                .padding(12)
-                .style(theme::Container::Custom(Box::new(CardContainer)))
+                if self.queue_running {
+                    if self.queue.is_empty() {
+                        self.queue_running = false;
+                        let failed = self.results.iter().filter(|result| !result.success).count();
+                        self.status_message = format!(
+                            "Finished {} queued jobs: {} results, {} failed",
+                            self.job_groups.len(),
+                            self.results.len(),
+                            failed
+                        );
+                        self.log(self.status_message.clone());
+                    } else {
+                        let job = self.queue.remove(0);
+                        self.log(format!("Starting queued job {}", job.label));
+                        return self.start_job(job);
+                    }
+                }
             }
-            CompressionMode::Advanced => {
-                container(
+            Message::Benchmark => {
+                if let Some(path) = &self.selected_path {
+                    self.is_benchmarking = true;
+                    self.benchmark = None;
+                    
+                    let path = path.clone();
+                    let template = CompressionOptions {
+                        optimize_for_web: self.optimize_for_web,
+                        jpeg_background: parse_hex_color(&self.jpeg_background).unwrap_or([255, 255, 255]),
+                        ..CompressionOptions::default()
+                    };
+                    let max_megapixels = self.max_megapixels.parse::<f32>().ok().filter(|mp| *mp > 0.0);
+                    return Command::perform(
+                        run_benchmark(path.clone(), template, max_megapixels),
+                        move |entries| Message::BenchmarkComplete(path.clone(), entries)
+                    );
+                }
+            }
+            Message::BenchmarkComplete(path, entries) => {
+                self.is_benchmarking = false;
+                if self.selected_path.as_ref() == Some(&path) {
+                    self.benchmark = Some(entries);
+                }
+            }
+            Message::CompareFilters => {
+                if let Some(path) = &self.selected_path {
+                    self.is_comparing_filters = true;
+                    self.filter_comparison = None;
+                    
+                    let path = path.clone();
+                    let template = self.preview_options();
+                    let target = match self.edge_length.parse::<u32>().ok().filter(|length| *length > 0) {
+                        Some(length) => FilterTarget::Edge(self.edge, length),
+                        None => match parse_dimensions(&self.width, &self.height) {
+                            Some(bounds) if self.maintain_ratio => FilterTarget::Within(bounds),
+                            Some(bounds) => FilterTarget::Exact(bounds),
+                            None => FilterTarget::None,
+                        },
+                    };
+                    let max_megapixels = self.max_megapixels.parse::<f32>().ok().filter(|mp| *mp > 0.0);
+                    return Command::perform(
+                        run_filter_comparison(path.clone(), target, template, max_megapixels),
+                        move |samples| Message::FilterComparisonComplete(path.clone(), samples)
+                    );
+                }
+            }
+            Message::FilterComparisonComplete(path, samples) => {
+                self.is_comparing_filters = false;
+                if self.selected_path.as_ref() == Some(&path) {
+                    self.filter_comparison = Some(samples);
+                }
+            }
+            Message::ShowDiff => {
+                if let Some(path) = &self.selected_path {
+                    self.is_diffing = true;
+                    self.diff_preview = None;
+                    
+                    let path = path.clone();
+                    let template = self.preview_options();
+                    let max_megapixels = self.max_megapixels.parse::<f32>().ok().filter(|mp| *mp > 0.0);
+                    return Command::perform(
+                        run_diff_preview(path.clone(), template, max_megapixels),
+                        move |preview| Message::DiffComplete(path.clone(), preview)
+                    );
+                }
+            }
+            Message::DiffComplete(path, preview) => {
+                self.is_diffing = false;
+                if self.selected_path.as_ref() == Some(&path) {
+                    self.diff_preview = Some(preview);
+                }
+            }
+            Message::QualityCurve => {
+                if let Some(path) = &self.selected_path {
+                    return Command::perform(select_curve_file(path.clone()), Message::QualityCurveFileSelected);
+                }
+            }
+            Message::QualityCurveFileSelected(csv_path) => {
+                if let (Some(csv_path), Some(path)) = (csv_path, self.selected_path.clone()) {
+                    self.quality_curve_id += 1;
+                    self.quality_curve_note = None;
+                    self.quality_curve = Some(QualityCurveRun {
+                        id: self.quality_curve_id,
+                        csv_path,
+                        // Adaptive quality would replace the quality being swept
+                        template: CompressionOptions {
+                            adaptive_quality: false,
+                            ..self.preview_options()
+                        },
+                        measure: self.measure_quality,
+                        image: None,
+                        points: Vec::new(),
+                    });
+                    self.log(format!("Sweeping quality {}-{} for {}", CURVE_QUALITIES.start(), CURVE_QUALITIES.end(), path.display()));
+                    
+                    let id = self.quality_curve_id;
+                    let max_megapixels = self.max_megapixels.parse::<f32>().ok().filter(|mp| *mp > 0.0);
+                    return Command::perform(
+                        async move {
+                            tokio::task::spawn_blocking(move || open_image(&path, max_megapixels).map(Arc::new))
+                                .await
+                                .unwrap_or_else(|e| Err(format!("Decoding task failed: {}", e)))
+                        },
+                        move |image| Message::QualityCurveLoaded(id, image)
+                    );
+                }
+            }
+            Message::QualityCurveLoaded(id, image) => {
+                let Some(run) = self.quality_curve.as_mut().filter(|run| run.id == id) else {
+                    return Command::none();
+                };
+                match image {
+                    Ok(image) => {
+                        run.image = Some(image);
+                        return run.encode_next();
+                    }
+                    Err(e) => {
+                        self.quality_curve = None;
+                        self.quality_curve_note = Some(format!("Quality curve failed: {}", e));
+                    }
+                }
+            }
+            Message::QualityCurvePointEncoded(id, point) => {
+                let Some(run) = self.quality_curve.as_mut().filter(|run| run.id == id) else {
+                    return Command::none();
+                };
+                run.points.push(point);
+                if run.points.len() < CURVE_QUALITIES.len() {
+                    return run.encode_next();
+                }
+                
+                let written = write_atomic(&run.csv_path, curve_to_csv(&run.points).as_bytes(), None);
+                self.quality_curve_note = Some(match written {
+                    Ok(()) => format!("Saved {} points to {}", run.points.len(), truncate_path(&run.csv_path)),
+                    Err(e) => format!("Writing {} failed: {}", run.csv_path.display(), e),
+                });
+                self.quality_curve = None;
+                if let Some(note) = self.quality_curve_note.clone() {
+                    self.log(note);
+                }
+            }
+            Message::OpenOutputFolder => {
+                if let Some(path) = self.selection_anchor() {
+                    let output_dir = match &self.output_dir {
+                        Some(dir) => dir.clone(),
+                        None => path.parent().unwrap_or(Path::new(".")).join(OUTPUT_DIR_NAME),
+                    };
+                    if output_dir.exists() {
+                        let _ = open::that(output_dir);
+                    }
+                }
+            }
+            Message::RevealOutput(path) => {
+                // dbus-send waits for the file manager's reply, so it runs off the UI thread
+                let revealed = path.clone();
+                return Command::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || reveal_in_file_manager(&revealed).map_err(|e| e.to_string()))
+                            .await
+                            .unwrap_or_else(|e| Err(e.to_string()))
+                    },
+                    move |result| Message::OutputRevealed(path, result)
+                );
+            }
+            Message::OutputRevealed(path, result) => {
+                if let Err(e) = result {
+                    self.log(format!("Couldn't show {}: {}", path.display(), e));
+                }
+            }
+            Message::ActivityLogToggled => {
+                self.show_log = !self.show_log;
+            }
+            Message::SettingsToggled => {
+                self.show_settings = !self.show_settings;
+            }
+            Message::MaxThreadsChanged(value) => {
+                self.max_threads = value;
+            }
+            Message::AtomicWritesToggled(value) => {
+                self.atomic_writes = value;
+            }
+            Message::SelectScratchFolder => {
+                return Command::perform(select_folder(), Message::ScratchFolderSelected);
+            }
+            Message::ScratchFolderSelected(path) => {
+                self.scratch_dir = path;
+            }
+            Message::ResultSortSelected(sort) => {
+                self.result_sort = sort;
+            }
+            Message::SortDirectionToggled => {
+                self.sort_descending = !self.sort_descending;
+            }
+            Message::ResultDetailsToggled(index) => {
+                self.expanded_result = if self.expanded_result == Some(index) {
+                    None
+                } else {
+                    Some(index)
+                };
+            }
+            Message::ClearResults => {
+                self.cleared_results = Some(ClearedResults {
+                    results: std::mem::take(&mut self.results),
+                    job_groups: std::mem::take(&mut self.job_groups),
+                    status_message: std::mem::take(&mut self.status_message),
+                    progress: self.progress,
+                });
+                self.expanded_result = None;
+                self.progress = 0.0;
+            }
+            Message::UndoClearResults => {
+                if let Some(cleared) = self.cleared_results.take() {
+                    self.results = cleared.results;
+                    self.job_groups = cleared.job_groups;
+                    self.status_message = cleared.status_message;
+                    self.progress = cleared.progress;
+                }
+            }
+            Message::RetryFailed => {
+                let Some(mut settings) = self.last_batch.clone().filter(|_| !self.is_processing) else {
+                    return Command::none();
+                };
+                if settings.options.zip_output.is_some() {
+                    self.status_message = String::from("Failed files can't be added to the finished ZIP archive; process them again instead");
+                    return Command::none();
+                }
+                // Each source of the last job once, even when several of its outputs failed
+                let mut failed: Vec<PathBuf> = Vec::new();
+                for result in self.results[self.last_job_start()..].iter().filter(|result| !result.success) {
+                    if !failed.contains(&result.source_path) {
+                        failed.push(result.source_path.clone());
+                    }
+                }
+                if failed.is_empty() {
+                    return Command::none();
+                }
+                
+                self.is_processing = true;
+                self.retrying = true;
+                self.progress = 0.0;
+                self.cleared_results = None;
+                self.expanded_result = None;
+                self.stopped_on_failure = false;
+                self.in_flight = 0;
+                self.total_files = failed.len();
+                self.batch_id += 1;
+                self.timing.start();
+                // The retry gets a flag of its own; the last job's may have been set when it stopped
+                settings.options.stopped = Arc::new(AtomicBool::new(false));
+                // And an empty near-duplicate filter, as the last one already holds the hash of
+                // every failed file that got as far as decoding
+                settings.options.near_duplicates = settings.options.near_duplicates.as_ref()
+                    .map(|filter| Arc::new(dedupe::DuplicateFilter::new(filter.threshold())));
+                self.batch = Some(settings);
+                self.log(format!("Retrying {} failed files", failed.len()));
+                self.pending_files = failed.into();
+                return self.process_next_file();
+            }
+        }
+        Command::none()
+    }
+
+    fn view(&self) -> Element<Message> {
+        // Header section with gradient background
+        let header = container(
+            column![
+                text("Image Resizer Pro")
+                    .size(18)
+                    .font(HEADING_FONT)
+                    .style(Color::WHITE),
+                row![
+                    text("Compress and resize your images with style")
+                        .size(14)
+                        .font(LIGHT_FONT)
+                        .style(Color::from_rgba(1.0, 1.0, 1.0, 0.8))
+                        .width(Length::Fill),
+                    button(
+                        text(if self.show_settings { "⚙ Close Settings" } else { "⚙ Settings" })
+                            .size(13)
+                            .font(BODY_FONT)
+                            .style(Color::WHITE)
+                    )
+                    .on_press(Message::SettingsToggled)
+                    .padding([4, 10])
+                    .style(theme::Button::Text),
+                ].align_items(iced::Alignment::Center),
+            ].spacing(4)
+        )
+        .width(Length::Fill)
+        .padding([18, 26])
+        .style(theme::Container::Custom(Box::new(GradientContainer)));
+
+        // File selection card
+        let file_selection_card = container(
+            column![
+                row![
+                    icon_text("", "Select Images", 14, 14),
+                    Space::with_width(Length::Fill),
+                ].spacing(8),
+                
+                Space::with_height(12),
+                
+                row![
+                    styled_button("Select File", Message::SelectFile, ButtonStyle::Primary),
+                    styled_button("Add Files", Message::AddFiles, ButtonStyle::Secondary),
+                    styled_button("Load from List", Message::LoadList, ButtonStyle::Secondary),
+                    styled_button("Select Folder", Message::SelectFolder, ButtonStyle::Secondary),
+                ].spacing(8),
+                
+                Space::with_height(12),
+                
+                if !self.selected_files.is_empty() || !self.list_rejects.is_empty() {
+                    self.file_list()
+                } else if let Some(path) = &self.selected_path {
+                    container(
+                        column![
+                            text(truncate_path(path))
+                                .size(13)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.4, 0.4, 0.5)),
+                            match &self.file_info {
+                                Some(info) => text(describe_file_info(info))
+                                    .size(12)
+                                    .font(LIGHT_FONT)
+                                    .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                None => text("Reading file info...")
+                                    .size(12)
+                                    .font(LIGHT_FONT)
+                                    .style(Color::from_rgb(0.6, 0.6, 0.7)),
+                            },
+                        ].spacing(4)
+                    )
+                    .width(Length::Fill)
+                    .padding([8, 12])
+                    .style(theme::Container::Custom(Box::new(SubtleContainer)))
+                } else {
+                    container(
+                        text("No files selected yet")
+                            .size(13)
+                            .font(LIGHT_FONT)
+                            .style(Color::from_rgb(0.6, 0.6, 0.7))
+                    )
+                    .width(Length::Fill)
+                    .padding([8, 12])
+                    .style(theme::Container::Custom(Box::new(SubtleContainer)))
+                },
+                
+                Space::with_height(12),
+                
+                row![
+                    styled_button("Output Folder", Message::SelectOutputFolder, ButtonStyle::Secondary),
+                    text(match &self.output_dir {
+                        Some(dir) => truncate_path(dir),
+                        None if self.safe_mode => String::from("Safe mode: choose a folder outside the source"),
+                        None => String::from("Default: \"resized\" next to each image"),
+                    })
+                        .size(13)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.4, 0.4, 0.5)),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Suffix")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(Length::Fixed(100.0)),
+                    text_input(DEFAULT_SUFFIX, &self.output_suffix)
+                        .on_input(Message::OutputSuffixChanged)
+                        .padding([8, 12])
+                        .size(14)
+                        .width(Length::Fixed(120.0)),
+                    text(if self.output_suffix.is_empty() {
+                        String::from("Outputs keep the source's name; needs a separate output folder")
+                    } else {
+                        format!("e.g. photo{}.jpg", self.output_suffix)
+                    })
+                        .size(12)
+                        .font(LIGHT_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Add the format to output names (photo_resized_webp.webp)",
+                    self.format_in_name,
+                    Message::FormatInNameToggled
+                ),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Output to a ZIP archive instead of separate files",
+                    self.zip_output,
+                    Message::ZipOutputToggled
+                ),
+                
+                if self.zip_output {
+                    column![
+                        Space::with_height(8),
+                        row![
+                            styled_button("ZIP File", Message::SelectZipFile, ButtonStyle::Secondary),
+                            text(match &self.zip_path {
+                                Some(path) => truncate_path(path),
+                                None => String::from("Default: resized.zip in the output folder"),
+                            })
+                                .size(13)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.4, 0.4, 0.5)),
+                        ].spacing(12).align_items(iced::Alignment::Center),
+                        Space::with_height(8),
+                        styled_checkbox(
+                            "Keep the folder structure inside the ZIP",
+                            self.zip_keep_structure,
+                            Message::ZipKeepStructureToggled
+                        ),
+                    ]
+                } else {
+                    column![]
+                },
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Keep originals in a backup folder when writing beside them",
+                    self.backup_originals,
+                    Message::BackupOriginalsToggled
+                ),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Safe mode: never write into the source folder",
+                    self.safe_mode,
+                    Message::SafeModeToggled
+                ),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Give outputs the original's modified date",
+                    self.preserve_timestamps,
+                    Message::PreserveTimestampsToggled
+                ),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Write report.csv to the output folder after each batch",
+                    self.write_report,
+                    Message::WriteReportToggled
+                ),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Write a .json file with the processing details next to each output",
+                    self.write_sidecars,
+                    Message::WriteSidecarsToggled
+                ),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Stop the batch at the first failed image",
+                    self.fail_fast,
+                    Message::FailFastToggled
+                ),
+                
+                if self.selected_path.as_deref().is_some_and(Path::is_dir) {
                     column![
-                        icon_text("", "Advanced Settings", 14, 14),
-                        
                         Space::with_height(12),
-                        
+                        styled_checkbox(
+                            "Copy non-image files to the output folder unchanged",
+                            self.copy_non_images,
+                            Message::CopyNonImagesToggled
+                        ),
+                        Space::with_height(12),
                         row![
-                            text("Algorithm")
+                            text("Formats")
                                 .size(14)
                                 .font(BODY_FONT)
                                 .style(Color::from_rgb(0.3, 0.3, 0.4))
                                 .width(100),
-                            pick_list(
-                                &[
-                                    CompressionAlgorithm::Auto,
-                                    CompressionAlgorithm::Simple,
-                                    CompressionAlgorithm::StandardJpeg,
-                                    CompressionAlgorithm::MozJpeg,
-                                    CompressionAlgorithm::StandardPng,
-                                    CompressionAlgorithm::OptiPng,
-                                    CompressionAlgorithm::OxiPng,
-                                    CompressionAlgorithm::PngQuant,
-                                    CompressionAlgorithm::WebPLossy,
-                                    CompressionAlgorithm::WebPLossless,
-                                ][..],
-                                Some(self.compression_algorithm),
-                                Message::AlgorithmSelected,
-                            )
-                            .width(Length::Fill)
-                            .padding([8, 12])
-                            .text_size(14),
+                            text_input("only: png, jpg; skip: gif", &self.format_filter)
+                                .on_input(Message::FormatFilterChanged)
+                                .width(Length::Fixed(188.0))
+                                .padding([8, 12])
+                                .size(14),
+                            text(match FormatFilter::parse(&self.format_filter) {
+                                Ok(filter) => filter.to_string(),
+                                Err(problem) => problem,
+                            })
+                                .size(12)
+                                .font(LIGHT_FONT)
+                                .style(Color::from_rgb(0.5, 0.5, 0.6)),
                         ].spacing(12).align_items(iced::Alignment::Center),
-                        
-                        if self.compression_algorithm.supports_quality() {
-                            column![
-                                Space::with_height(16),
-                                row![
-                                    text("Quality")
-                                        .size(14)
-                                        .font(BODY_FONT)
-                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
-                                        .width(100),
-                                    slider(10..=100, self.quality_slider, Message::QualityChanged)
-                                        .width(Length::Fill),
-                                    container(
-                                        text(format!("{}%", self.quality_slider))
-                                            .size(14)
-                                            .font(HEADING_FONT)
-                                            .style(PRIMARY_COLOR)
-                                    )
-                                    .width(50)
-                                    .center_x(),
-                                ].spacing(12).align_items(iced::Alignment::Center),
-                            ].spacing(0)
-                        } else {
-                            column![]
-                        },
-                        
+                    ]
+                } else {
+                    column![]
+                },
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Skip near-duplicate images (perceptual hash)",
+                    self.skip_near_duplicates,
+                    Message::SkipNearDuplicatesToggled
+                ),
+                
+                if self.skip_near_duplicates {
+                    column![
                         Space::with_height(12),
-                        
-                        styled_checkbox("Optimize for web", self.optimize_for_web, Message::OptimizeForWebToggled),
+                        row![
+                            text("Distance")
+                                .size(14)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                .width(100),
+                            slider(0..=20, self.duplicate_threshold, Message::DuplicateThresholdChanged)
+                                .width(Length::Fill),
+                            container(
+                                text(format!("{}", self.duplicate_threshold))
+                                    .size(14)
+                                    .font(HEADING_FONT)
+                                    .style(PRIMARY_COLOR)
+                            )
+                            .width(50)
+                            .center_x(),
+                        ].spacing(12).align_items(iced::Alignment::Center),
                     ].spacing(0)
-                )
-                .width(Length::Fill)
-                .padding(12)
-                .style(theme::Container::Custom(Box::new(CardContainer)))
+                } else {
+                    column![]
+                },
+            ].spacing(0)
+        )
+        .width(Length::Fill)
+        .padding(12)
+        .style(theme::Container::Custom(Box::new(CardContainer)));
+
+        // Compression mode selection with visual tabs
+        let mode_selection_card = container(
+            column![
+                icon_text("", "Compression Mode", 14, 14),
+                
+                Space::with_height(12),
+                
+                row![
+                    mode_button("Simple", "Fast & Easy", CompressionMode::Simple, self.compression_mode),
+                    Space::with_width(12),
+                    mode_button("Advanced", "Full Control", CompressionMode::Advanced, self.compression_mode),
+                ].spacing(0),
+            ].spacing(0)
+        )
+        .width(Length::Fill)
+        .padding(12)
+        .style(theme::Container::Custom(Box::new(CardContainer)));
+
+        // Compression settings card
+        let compression_settings = match self.compression_mode {
+            CompressionMode::Simple => {
+                container(
+                    column![
+                        icon_text("", "Simple Settings", 14, 14),
+                        Space::with_height(12),
+                        styled_checkbox(
+                            "Auto Scale (resize to meet target size)",
+                            self.auto_scale,
+                            Message::AutoScaleToggled
+                        ),
+                    ].spacing(0)
+                )
+                .width(Length::Fill)
+                .padding(12)
+                .style(theme::Container::Custom(Box::new(CardContainer)))
+            }
+            CompressionMode::Advanced => {
+                container(
+                    column![
+                        icon_text("", "Advanced Settings", 14, 14),
+                        
+                        Space::with_height(12),
+                        
+                        row![
+                            text("Algorithm")
+                                .size(14)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                .width(100),
+                            pick_list(
+                                self.input_algorithm_choices(),
+                                Some(self.compression_algorithm),
+                                Message::AlgorithmSelected,
+                            )
+                            .width(Length::Fill)
+                            .padding([8, 12])
+                            .text_size(14),
+                        ].spacing(12).align_items(iced::Alignment::Center),
+                        
+                        if self.compression_algorithm == CompressionAlgorithm::ByAlpha {
+                            column![
+                                Space::with_height(12),
+                                row![
+                                    text("Opaque")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    pick_list(
+                                        by_alpha_choices(false),
+                                        Some(self.opaque_algorithm),
+                                        Message::OpaqueAlgorithmSelected,
+                                    )
+                                    .width(Length::Fill)
+                                    .padding([8, 12])
+                                    .text_size(14),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                Space::with_height(12),
+                                row![
+                                    text("Transparent")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    pick_list(
+                                        by_alpha_choices(true),
+                                        Some(self.transparent_algorithm),
+                                        Message::TransparentAlgorithmSelected,
+                                    )
+                                    .width(Length::Fill)
+                                    .padding([8, 12])
+                                    .text_size(14),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        if self.compression_algorithm.supports_quality()
+                            && self.compression_algorithm.searches_quality_for_target_size()
+                            && self.target_size.parse::<u64>().is_ok()
+                        {
+                            column![
+                                Space::with_height(16),
+                                row![
+                                    text("Quality")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.6, 0.6, 0.7))
+                                        .width(100),
+                                    text("quality auto-selected for target size")
+                                        .size(12)
+                                        .font(LIGHT_FONT)
+                                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                Space::with_height(16),
+                                row![
+                                    text("Min Quality")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    slider(1..=95, self.min_quality, Message::MinQualityChanged)
+                                        .width(Length::Fill),
+                                    container(
+                                        text(format!("{}%", self.min_quality))
+                                            .size(14)
+                                            .font(HEADING_FONT)
+                                            .style(PRIMARY_COLOR)
+                                    )
+                                    .width(50)
+                                    .center_x(),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                Space::with_height(16),
+                                row![
+                                    text("Max Probes")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    slider(2..=SEARCH_PROBES_ALL, self.max_search_probes, Message::MaxSearchProbesChanged)
+                                        .width(Length::Fill),
+                                    container(
+                                        text(if self.max_search_probes >= SEARCH_PROBES_ALL {
+                                            String::from("All")
+                                        } else {
+                                            self.max_search_probes.to_string()
+                                        })
+                                            .size(14)
+                                            .font(HEADING_FONT)
+                                            .style(PRIMARY_COLOR)
+                                    )
+                                    .width(50)
+                                    .center_x(),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                Space::with_height(4),
+                                text("Fewer encodes per image; a capped search may land slightly over the target")
+                                    .size(12)
+                                    .font(LIGHT_FONT)
+                                    .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                            ].spacing(0)
+                        } else if self.compression_algorithm.supports_quality() {
+                            let adapts = self.compression_algorithm.adapts_quality();
+                            column![
+                                Space::with_height(16),
+                                if adapts && self.adaptive_quality {
+                                    row![
+                                        text("Quality")
+                                            .size(14)
+                                            .font(BODY_FONT)
+                                            .style(Color::from_rgb(0.6, 0.6, 0.7))
+                                            .width(100),
+                                        text("picked per image from its detail (70-92%)")
+                                            .size(12)
+                                            .font(LIGHT_FONT)
+                                            .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                    ].spacing(12).align_items(iced::Alignment::Center)
+                                } else {
+                                    row![
+                                        text("Quality")
+                                            .size(14)
+                                            .font(BODY_FONT)
+                                            .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                            .width(100),
+                                        slider(10..=100, self.quality_slider, Message::QualityChanged)
+                                            .width(Length::Fill),
+                                        container(
+                                            text(format!("{}%", self.quality_slider))
+                                                .size(14)
+                                                .font(HEADING_FONT)
+                                                .style(PRIMARY_COLOR)
+                                        )
+                                        .width(50)
+                                        .center_x(),
+                                    ].spacing(12).align_items(iced::Alignment::Center)
+                                },
+                                if adapts {
+                                    column![
+                                        Space::with_height(12),
+                                        styled_checkbox(
+                                            "Adaptive quality (higher only for detailed images)",
+                                            self.adaptive_quality,
+                                            Message::AdaptiveQualityToggled
+                                        ),
+                                    ].spacing(0)
+                                } else {
+                                    column![]
+                                },
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        if matches!(
+                            self.compression_algorithm,
+                            CompressionAlgorithm::WebPLossy | CompressionAlgorithm::LosslessFirst | CompressionAlgorithm::Avif
+                        ) && matches!(self.file_info, Some(FileInfo::Image { has_alpha: Some(true), .. })) {
+                            column![
+                                Space::with_height(16),
+                                row![
+                                    text("Alpha Quality")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    slider(0..=100, self.alpha_quality, Message::AlphaQualityChanged)
+                                        .width(Length::Fill),
+                                    container(
+                                        text(if self.alpha_quality == 0 {
+                                            String::from("Same")
+                                        } else {
+                                            format!("{}%", self.alpha_quality)
+                                        })
+                                            .size(14)
+                                            .font(HEADING_FONT)
+                                            .style(PRIMARY_COLOR)
+                                    )
+                                    .width(50)
+                                    .center_x(),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        // Deblocking only applies to lossy WebP
+                        if self.compression_algorithm == CompressionAlgorithm::WebPLossy {
+                            column![
+                                Space::with_height(16),
+                                row![
+                                    text("Filter")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    slider(0..=100, self.webp_filter_strength, Message::WebPFilterStrengthChanged)
+                                        .width(Length::Fill),
+                                    container(
+                                        text(self.webp_filter_strength.to_string())
+                                            .size(14)
+                                            .font(HEADING_FONT)
+                                            .style(PRIMARY_COLOR)
+                                    )
+                                    .width(50)
+                                    .center_x(),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                Space::with_height(12),
+                                row![
+                                    text("Sharpness")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    slider(0..=7, self.webp_sharpness, Message::WebPSharpnessChanged)
+                                        .width(Length::Fill),
+                                    container(
+                                        text(self.webp_sharpness.to_string())
+                                            .size(14)
+                                            .font(HEADING_FONT)
+                                            .style(PRIMARY_COLOR)
+                                    )
+                                    .width(50)
+                                    .center_x(),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                Space::with_height(4),
+                                text("Lower the filter to keep text edges crisp; sharpness 0 is sharpest (defaults 60 and 0)")
+                                    .size(12)
+                                    .font(LIGHT_FONT)
+                                    .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        if self.compression_algorithm == CompressionAlgorithm::LosslessFirst {
+                            column![
+                                Space::with_height(16),
+                                row![
+                                    text("Lossless Limit")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    text_input("Optional (KB)", &self.lossless_limit)
+                                        .on_input(Message::LosslessLimitChanged)
+                                        .width(Length::Fixed(120.0))
+                                        .padding([8, 12])
+                                        .size(14),
+                                    text("Larger results fall back to lossy")
+                                        .size(12)
+                                        .font(LIGHT_FONT)
+                                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        // Auto modes and extension rules can pick JPEG too
+                        if (matches!(
+                            self.compression_algorithm,
+                            CompressionAlgorithm::Jpeg | CompressionAlgorithm::Auto | CompressionAlgorithm::AutoBest | CompressionAlgorithm::ByAlpha
+                        ) || self.use_extension_rules) && JpegEncoder::MozJpeg.is_available() && !self.progressive_loading {
+                            column![
+                                Space::with_height(12),
+                                styled_checkbox(
+                                    "Encode JPEG with MozJPEG (10-15% smaller, slower)",
+                                    self.use_mozjpeg,
+                                    Message::UseMozJpegToggled
+                                ),
+                                if self.use_mozjpeg {
+                                    column![
+                                        Space::with_height(12),
+                                        row![
+                                            text("JPEG Mode")
+                                                .size(14)
+                                                .font(BODY_FONT)
+                                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                                .width(100),
+                                            pick_list(
+                                                &JpegMode::ALL[..],
+                                                Some(self.jpeg_mode),
+                                                Message::JpegModeSelected,
+                                            )
+                                            .width(Length::Fill)
+                                            .padding([8, 12])
+                                            .text_size(14),
+                                        ].spacing(12).align_items(iced::Alignment::Center),
+                                    ].spacing(0)
+                                } else {
+                                    column![
+                                        Space::with_height(4),
+                                        text("image-rs always writes baseline JPEG")
+                                            .size(12)
+                                            .font(LIGHT_FONT)
+                                            .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                    ].spacing(0)
+                                },
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        if self.compression_algorithm == CompressionAlgorithm::Jpeg {
+                            column![
+                                Space::with_height(16),
+                                row![
+                                    text("Background")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    text_input("#FFFFFF", &self.jpeg_background)
+                                        .on_input(Message::JpegBackgroundChanged)
+                                        .width(Length::Fixed(120.0))
+                                        .padding([8, 12])
+                                        .size(14),
+                                    text(if parse_hex_color(&self.jpeg_background).is_some() {
+                                        "Matte for transparent areas"
+                                    } else {
+                                        "Invalid color, using white"
+                                    })
+                                        .size(12)
+                                        .font(LIGHT_FONT)
+                                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        Space::with_height(16),
+                        
+                        row![
+                            text("DPI")
+                                .size(14)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                .width(100),
+                            text_input("Optional (e.g. 300)", &self.dpi)
+                                .on_input(Message::DpiChanged)
+                                .width(Length::Fixed(120.0))
+                                .padding([8, 12])
+                                .size(14),
+                            text("Density tag for JPEG and PNG output")
+                                .size(12)
+                                .font(LIGHT_FONT)
+                                .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                        ].spacing(12).align_items(iced::Alignment::Center),
+                        
+                        Space::with_height(12),
+                        
+                        styled_checkbox("Optimize for web", self.optimize_for_web, Message::OptimizeForWebToggled),
+                        
+                        Space::with_height(12),
+                        
+                        styled_checkbox(
+                            "Embed an sRGB color profile (JPEG, PNG, WebP)",
+                            self.embed_srgb,
+                            Message::EmbedSrgbToggled
+                        ),
+                        
+                        Space::with_height(12),
+                        
+                        styled_checkbox(
+                            "Embed a 160px preview thumbnail (JPEG, WebP)",
+                            self.embed_thumbnail,
+                            Message::EmbedThumbnailToggled
+                        ),
+                        
+                        Space::with_height(12),
+                        
+                        styled_checkbox(
+                            "Load progressively (progressive JPEG, interlaced PNG; a little larger)",
+                            self.progressive_loading,
+                            Message::ProgressiveLoadingToggled
+                        ),
+                        text("WebP and AVIF have no progressive form and are written as usual")
+                            .size(12)
+                            .font(LIGHT_FONT)
+                            .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                        
+                        Space::with_height(12),
+                        
+                        styled_checkbox(
+                            "Measure quality (PSNR/SSIM against the resized source, costs a decode)",
+                            self.measure_quality,
+                            Message::MeasureQualityToggled
+                        ),
+                        
+                        Space::with_height(12),
+                        
+                        styled_checkbox(
+                            "Preserve metadata (color profile, EXIF and XMP)",
+                            self.preserve_metadata,
+                            Message::PreserveMetadataToggled
+                        ),
+                        
+                        if matches!(
+                            self.compression_algorithm,
+                            CompressionAlgorithm::Auto
+                                | CompressionAlgorithm::AutoBest
+                                | CompressionAlgorithm::ByAlpha
+                                | CompressionAlgorithm::StandardPng
+                                | CompressionAlgorithm::OptiPng
+                                | CompressionAlgorithm::OxiPng
+                        ) {
+                            column![
+                                Space::with_height(12),
+                                styled_checkbox(
+                                    "Reduce PNGs with 256 colors or fewer to an exact palette",
+                                    self.minimal_palette,
+                                    Message::MinimalPaletteToggled
+                                ),
+                                Space::with_height(12),
+                                styled_checkbox(
+                                    "Write 16-bit PNGs as 8-bit",
+                                    self.reduce_16bit_png,
+                                    Message::Reduce16BitPngToggled
+                                ),
+                                if self.progressive_loading {
+                                    column![]
+                                } else {
+                                    column![
+                                        Space::with_height(12),
+                                        styled_checkbox(
+                                            "Interlace PNGs (Adam7, progressive but larger)",
+                                            self.interlace_png,
+                                            Message::InterlacePngToggled
+                                        ),
+                                    ].spacing(0)
+                                },
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        if matches!(
+                            self.compression_algorithm,
+                            CompressionAlgorithm::Auto
+                                | CompressionAlgorithm::AutoBest
+                                | CompressionAlgorithm::ByAlpha
+                                | CompressionAlgorithm::StandardPng
+                                | CompressionAlgorithm::OptiPng
+                                | CompressionAlgorithm::OxiPng
+                                | CompressionAlgorithm::Avif
+                        ) {
+                            column![
+                                Space::with_height(12),
+                                styled_checkbox(
+                                    "Keep 16-bit depth in PNG and AVIF (larger files)",
+                                    self.keep_bit_depth,
+                                    Message::KeepBitDepthToggled
+                                ),
+                            ]
+                        } else {
+                            column![]
+                        },
+                        
+                        if matches!(
+                            self.compression_algorithm,
+                            CompressionAlgorithm::Auto
+                                | CompressionAlgorithm::AutoBest
+                                | CompressionAlgorithm::ByAlpha
+                                | CompressionAlgorithm::OxiPng
+                                | CompressionAlgorithm::PngQuant
+                        ) {
+                            column![
+                                Space::with_height(16),
+                                row![
+                                    text("OxiPNG Level")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    slider(0..=6, self.oxipng_level, Message::OxiPngLevelChanged)
+                                        .width(Length::Fill),
+                                    container(
+                                        text(self.oxipng_level.to_string())
+                                            .size(14)
+                                            .font(HEADING_FONT)
+                                            .style(PRIMARY_COLOR)
+                                    )
+                                    .width(50)
+                                    .center_x(),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                Space::with_height(12),
+                                styled_checkbox(
+                                    "Use all cores for OxiPNG",
+                                    self.oxipng_multithreaded,
+                                    Message::OxiPngMultithreadedToggled
+                                ),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        Space::with_height(12),
+                        
+                        styled_checkbox(
+                            "Convert format only (copy files already in that format)",
+                            self.convert_only,
+                            Message::ConvertOnlyToggled
+                        ),
+                        
+                        Space::with_height(12),
+                        
+                        styled_checkbox(
+                            "Only convert PNGs to lossless WebP (other files are left alone)",
+                            self.png_to_webp,
+                            Message::PngToWebPToggled
+                        ),
+                        
+                        Space::with_height(12),
+                        
+                        styled_checkbox(
+                            "Losslessly optimize JPEGs and PNGs in place (kept only if smaller)",
+                            self.lossless_optimize,
+                            Message::LosslessOptimizeToggled
+                        ),
+                        
+                        if self.compression_algorithm.is_modern_format() && self.compression_algorithm.is_available() {
+                            column![
+                                Space::with_height(12),
+                                styled_checkbox(
+                                    "Also write a JPEG/PNG fallback for <picture>",
+                                    self.dual_export,
+                                    Message::DualExportToggled
+                                ),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        if self.compression_algorithm != CompressionAlgorithm::Simple {
+                            column![
+                                Space::with_height(12),
+                                styled_checkbox(
+                                    "Choose the algorithm by file extension",
+                                    self.use_extension_rules,
+                                    Message::ExtensionRulesToggled
+                                ),
+                                self.extension_rules_editor(),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        if self.selected_path.as_deref().is_some_and(|path| path.is_dir() || has_extension(path, "gif"))
+                            && CompressionAlgorithm::WebPLossy.is_available()
+                        {
+                            column![
+                                Space::with_height(12),
+                                styled_checkbox(
+                                    "Convert GIFs to animated WebP (much smaller)",
+                                    self.gif_to_webp,
+                                    Message::GifToWebPToggled
+                                ),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                        
+                        Space::with_height(12),
+                        
+                        styled_checkbox(
+                            "Export every frame of animated GIFs and WebPs as numbered files",
+                            self.explode_frames,
+                            Message::ExplodeFramesToggled
+                        ),
+                        
+                        if matches!(self.file_info, Some(FileInfo::Image { .. })) {
+                            column![
+                                Space::with_height(16),
+                                row![
+                                    if self.is_benchmarking || self.is_processing {
+                                        styled_button("Benchmarking...", Message::Benchmark, ButtonStyle::Disabled)
+                                    } else {
+                                        styled_button("Compare Algorithms", Message::Benchmark, ButtonStyle::Secondary)
+                                    },
+                                    text("Encode this image with every algorithm")
+                                        .size(12)
+                                        .font(LIGHT_FONT)
+                                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                self.benchmark_table(),
+                                Space::with_height(12),
+                                row![
+                                    if self.is_comparing_filters || self.is_processing {
+                                        styled_button("Comparing...", Message::CompareFilters, ButtonStyle::Disabled)
+                                    } else {
+                                        styled_button("Compare Filters", Message::CompareFilters, ButtonStyle::Secondary)
+                                    },
+                                    text("Downscale to the size above with four resize filters")
+                                        .size(12)
+                                        .font(LIGHT_FONT)
+                                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                self.filter_comparison_view(),
+                                Space::with_height(12),
+                                row![
+                                    if self.is_diffing || self.is_processing {
+                                        styled_button("Diffing...", Message::ShowDiff, ButtonStyle::Disabled)
+                                    } else {
+                                        styled_button("Show Diff", Message::ShowDiff, ButtonStyle::Secondary)
+                                    },
+                                    text("Where compression at these settings changes the pixels")
+                                        .size(12)
+                                        .font(LIGHT_FONT)
+                                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                self.diff_preview_view(),
+                                Space::with_height(12),
+                                row![
+                                    if self.quality_curve.is_some() || self.is_processing {
+                                        styled_button("Sweeping...", Message::QualityCurve, ButtonStyle::Disabled)
+                                    } else {
+                                        styled_button("Quality Curve", Message::QualityCurve, ButtonStyle::Secondary)
+                                    },
+                                    text(match (&self.quality_curve, &self.quality_curve_note) {
+                                        (Some(run), _) => format!("{} of {} qualities encoded", run.points.len(), CURVE_QUALITIES.len()),
+                                        (None, Some(note)) => note.clone(),
+                                        (None, None) => format!(
+                                            "Save the size at every quality from {} to {} as CSV",
+                                            CURVE_QUALITIES.start(),
+                                            CURVE_QUALITIES.end()
+                                        ),
+                                    })
+                                        .size(12)
+                                        .font(LIGHT_FONT)
+                                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                    ].spacing(0)
+                )
+                .width(Length::Fill)
+                .padding(12)
+                .style(theme::Container::Custom(Box::new(CardContainer)))
+            }
+        };
+
+        // Size parameters card
+        let parameters_card = container(
+            column![
+                icon_text("", "Size Parameters", 14, 14),
+                
+                Space::with_height(16),
+                
+                row![
+                    text("Target Size")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(96),
+                    text_input("Optional (KB)", &self.target_size)
+                        .on_input(Message::TargetSizeChanged)
+                        .width(Length::Fixed(188.0))
+                        .padding([8, 12])
+                        .size(14),
+                    styled_button("Match File...", Message::SelectSizeReference, ButtonStyle::Subtle),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                
+                match &self.size_reference {
+                    Some((path, bytes)) => column![
+                        Space::with_height(4),
+                        text(format!(
+                            "Matching {} ({})",
+                            path.file_name().unwrap_or_default().to_string_lossy(),
+                            if *bytes < 1024 { format!("{} bytes", bytes) } else { format!("{} KB", bytes / 1024) }
+                        ))
+                            .size(12)
+                            .font(LIGHT_FONT)
+                            .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                    ],
+                    None => column![],
+                },
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Per Megapixel")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(96),
+                    text_input("Optional (KB)", &self.target_per_megapixel)
+                        .on_input(Message::TargetPerMegapixelChanged)
+                        .width(Length::Fixed(188.0))
+                        .padding([8, 12])
+                        .size(14),
+                    text("Target scales with each image's resolution")
+                        .size(12)
+                        .font(LIGHT_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                
+                if self.is_batch_selection() {
+                    column![
+                        Space::with_height(12),
+                        row![
+                            text("Total Budget")
+                                .size(14)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                .width(96),
+                            text_input("Optional (MB)", &self.folder_budget)
+                                .on_input(Message::FolderBudgetChanged)
+                                .width(Length::Fixed(188.0))
+                                .padding([8, 12])
+                                .size(14),
+                            text("split into per-file targets")
+                                .size(12)
+                                .font(LIGHT_FONT)
+                                .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                        ].spacing(12).align_items(iced::Alignment::Center),
+                        Space::with_height(8),
+                        styled_checkbox(
+                            "Give detailed images a bigger share (otherwise equal shares)",
+                            self.budget_by_detail,
+                            Message::BudgetByDetailToggled
+                        ),
+                    ]
+                } else {
+                    column![]
+                },
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Skip Under")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(96),
+                    text_input("Optional (KB)", &self.min_size_to_process)
+                        .on_input(Message::MinSizeToProcessChanged)
+                        .width(Length::Fixed(188.0))
+                        .padding([8, 12])
+                        .size(14),
+                    text("Smaller images are left untouched")
+                        .size(12)
+                        .font(LIGHT_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Incremental: skip images whose output is newer than the source",
+                    self.incremental,
+                    Message::IncrementalToggled
+                ),
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Preset")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(100),
+                    pick_list(
+                        presets::PRESETS,
+                        Some(self.dimension_preset.unwrap_or(presets::CUSTOM)),
+                        Message::PresetSelected,
+                    )
+                    .width(Length::Fill)
+                    .padding([8, 12])
+                    .text_size(14),
+                ].spacing(8).align_items(iced::Alignment::Center),
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Dimensions")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(100),
+                    text_input("Width", &self.width)
+                        .on_input(Message::WidthChanged)
+                        .width(Length::Fixed(80.0))
+                        .padding([8, 12])
+                        .size(14),
+                    text("×")
+                        .size(16)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                    text_input("Height", &self.height)
+                        .on_input(Message::HeightChanged)
+                        .width(Length::Fixed(80.0))
+                        .padding([8, 12])
+                        .size(14),
+                    text("px")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                ].spacing(8).align_items(iced::Alignment::Center),
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Print Size")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(100),
+                    text_input("Width", &self.print_width)
+                        .on_input(Message::PrintWidthChanged)
+                        .width(Length::Fixed(64.0))
+                        .padding([8, 12])
+                        .size(14),
+                    text("×")
+                        .size(16)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                    text_input("Height", &self.print_height)
+                        .on_input(Message::PrintHeightChanged)
+                        .width(Length::Fixed(64.0))
+                        .padding([8, 12])
+                        .size(14),
+                    pick_list(
+                        &density::PrintUnit::ALL[..],
+                        Some(self.print_unit),
+                        Message::PrintUnitSelected,
+                    )
+                    .width(Length::Fixed(64.0))
+                    .padding([8, 12])
+                    .text_size(14),
+                    text("at")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                    text_input("DPI", &self.dpi)
+                        .on_input(Message::DpiChanged)
+                        .width(Length::Fixed(64.0))
+                        .padding([8, 12])
+                        .size(14),
+                ].spacing(8).align_items(iced::Alignment::Center),
+                
+                match density::parse_physical_size(&self.print_width, &self.print_height, self.print_unit, &self.dpi) {
+                    Ok(Some(size)) => {
+                        let (width, height) = size.pixels();
+                        column![
+                            Space::with_height(4),
+                            text(format!("{}×{} px, replaces the dimensions above", width, height))
+                                .size(12)
+                                .font(LIGHT_FONT)
+                                .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                        ].spacing(0)
+                    }
+                    Ok(None) => column![],
+                    Err(problem) => column![
+                        Space::with_height(4),
+                        text(format!("Print size: {}", problem)).size(12).font(LIGHT_FONT).style(ERROR_COLOR),
+                    ].spacing(0),
+                },
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Or Fit")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(100),
+                    pick_list(
+                        &[Edge::Longest, Edge::Shortest][..],
+                        Some(self.edge),
+                        Message::EdgeSelected,
+                    )
+                    .width(Length::Fixed(150.0))
+                    .padding([8, 12])
+                    .text_size(14),
+                    text_input("px", &self.edge_length)
+                        .on_input(Message::EdgeLengthChanged)
+                        .width(Length::Fixed(80.0))
+                        .padding([8, 12])
+                        .size(14),
+                    text("never upscales")
+                        .size(12)
+                        .font(LIGHT_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                ].spacing(8).align_items(iced::Alignment::Center),
+                
+                if self.compression_mode == CompressionMode::Advanced {
+                    column![
+                        Space::with_height(12),
+                        row![
+                            text("Widths")
+                                .size(14)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                .width(96),
+                            text_input("e.g. 320, 640, 1280", &self.responsive_widths)
+                                .on_input(Message::ResponsiveWidthsChanged)
+                                .width(Length::Fixed(188.0))
+                                .padding([8, 12])
+                                .size(14),
+                            text("One output per width")
+                                .size(12)
+                                .font(LIGHT_FONT)
+                                .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                        ].spacing(12).align_items(iced::Alignment::Center),
+                        if parse_widths(&self.responsive_widths).is_empty() {
+                            column![]
+                        } else {
+                            column![
+                                Space::with_height(12),
+                                row![
+                                    text("Naming")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(96),
+                                    pick_list(
+                                        &WidthNaming::ALL[..],
+                                        Some(self.width_naming),
+                                        Message::WidthNamingSelected,
+                                    )
+                                    .width(Length::Fixed(188.0))
+                                    .padding([8, 12])
+                                    .text_size(14),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                            ].spacing(0)
+                        },
+                    ].spacing(0)
+                } else {
+                    column![]
+                },
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Max Pixels")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(96),
+                    text_input("Optional (megapixels)", &self.downscale_megapixels)
+                        .on_input(Message::DownscaleMegapixelsChanged)
+                        .width(Length::Fixed(188.0))
+                        .padding([8, 12])
+                        .size(14),
+                    text("Downscales larger images")
+                        .size(12)
+                        .font(LIGHT_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                
+                Space::with_height(12),
+                
+                styled_checkbox("Maintain aspect ratio", self.maintain_ratio, Message::MaintainRatioToggled),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Crop to fill the exact size (trims the overflow evenly)",
+                    self.crop_to_fill,
+                    Message::CropToFillToggled
+                ),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Resize in linear light (more accurate, slower)",
+                    self.linear_resize,
+                    Message::LinearResizeToggled
+                ),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Rotate upright using the EXIF orientation",
+                    self.auto_orient,
+                    Message::AutoOrientToggled
+                ),
+                
+                Space::with_height(12),
+                
+                styled_checkbox("Convert to grayscale", self.grayscale, Message::GrayscaleToggled),
+                
+                Space::with_height(12),
+                
+                styled_checkbox(
+                    "Auto levels (stretch to the full range, clipping 0.5% at each end)",
+                    self.auto_levels,
+                    Message::AutoLevelsToggled
+                ),
+                
+                if self.auto_levels {
+                    column![
+                        Space::with_height(12),
+                        styled_checkbox(
+                            "Level each channel separately (also removes color casts)",
+                            self.levels_per_channel,
+                            Message::LevelsPerChannelToggled
+                        ),
+                    ]
+                } else {
+                    column![]
+                },
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Redact")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(96),
+                    text_input("x,y,w,h; x,y,w,h", &self.redactions)
+                        .on_input(Message::RedactionsChanged)
+                        .width(Length::Fixed(188.0))
+                        .padding([8, 12])
+                        .size(14),
+                    text(match parse_redactions(&self.redactions) {
+                        Ok(regions) if regions.is_empty() => String::from("Regions in the original's pixels"),
+                        Ok(regions) if regions.len() == 1 => String::from("1 region, before rotating or resizing"),
+                        Ok(regions) => format!("{} regions, before rotating or resizing", regions.len()),
+                        Err(problem) => problem,
+                    })
+                        .size(12)
+                        .font(LIGHT_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                
+                if self.redactions.trim().is_empty() {
+                    column![]
+                } else {
+                    column![
+                        Space::with_height(12),
+                        styled_checkbox("Blur the regions instead of pixelating them", self.redact_blur, Message::RedactBlurToggled),
+                    ]
+                },
+                
+                Space::with_height(12),
+                
+                styled_checkbox("Trim uniform borders", self.trim_borders, Message::TrimBordersToggled),
+                
+                if self.trim_borders {
+                    column![
+                        Space::with_height(12),
+                        row![
+                            text("Tolerance")
+                                .size(14)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                .width(100),
+                            slider(0..=64, self.trim_tolerance, Message::TrimToleranceChanged)
+                                .width(Length::Fill),
+                            container(
+                                text(format!("{}", self.trim_tolerance))
+                                    .size(14)
+                                    .font(HEADING_FONT)
+                                    .style(PRIMARY_COLOR)
+                            )
+                            .width(50)
+                            .center_x(),
+                        ].spacing(12).align_items(iced::Alignment::Center),
+                    ].spacing(0)
+                } else {
+                    column![]
+                },
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Sharpen")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(100),
+                    slider(0..=200, self.sharpen_amount, Message::SharpenAmountChanged)
+                        .width(Length::Fill),
+                    container(
+                        text(if self.sharpen_amount == 0 {
+                            String::from("Off")
+                        } else {
+                            format!("{}%", self.sharpen_amount)
+                        })
+                            .size(14)
+                            .font(HEADING_FONT)
+                            .style(PRIMARY_COLOR)
+                    )
+                    .width(50)
+                    .center_x(),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                
+                if self.sharpen_amount > 0 {
+                    column![
+                        Space::with_height(12),
+                        row![
+                            text("Radius")
+                                .size(14)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                .width(100),
+                            slider(3..=30, self.sharpen_radius, Message::SharpenRadiusChanged)
+                                .width(Length::Fill),
+                            container(
+                                text(format!("{:.1}px", self.sharpen_radius as f32 / 10.0))
+                                    .size(14)
+                                    .font(HEADING_FONT)
+                                    .style(PRIMARY_COLOR)
+                            )
+                            .width(50)
+                            .center_x(),
+                        ].spacing(12).align_items(iced::Alignment::Center),
+                    ].spacing(0)
+                } else {
+                    column![]
+                },
+                
+                Space::with_height(12),
+                
+                row![
+                    text("Watermark")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(100),
+                    text_input("Text to stamp", &self.watermark_text)
+                        .on_input(Message::WatermarkTextChanged)
+                        .width(Length::Fixed(188.0))
+                        .padding([8, 12])
+                        .size(14),
+                    if self.watermark_image.is_some() {
+                        styled_button("Clear Image", Message::WatermarkImageSelected(None), ButtonStyle::Subtle)
+                    } else {
+                        styled_button("Image", Message::SelectWatermarkImage, ButtonStyle::Secondary)
+                    },
+                    text(match &self.watermark_image {
+                        Some(path) => format!("{}, in place of the text", truncate_path(path)),
+                        None => String::from("Or an overlay PNG, stamped after resizing"),
+                    })
+                        .size(12)
+                        .font(LIGHT_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                
+                if self.has_watermark() {
+                    column![
+                        Space::with_height(12),
+                        row![
+                            text("Position")
+                                .size(14)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                .width(100),
+                            pick_list(
+                                &watermark::Position::ALL[..],
+                                Some(self.watermark_position),
+                                Message::WatermarkPositionSelected,
+                            )
+                            .padding([8, 12])
+                            .text_size(14),
+                            text_input("0", &self.watermark_margin)
+                                .on_input(Message::WatermarkMarginChanged)
+                                .width(Length::Fixed(70.0))
+                                .padding([8, 12])
+                                .size(14),
+                            text("px margin from the edges")
+                                .size(12)
+                                .font(LIGHT_FONT)
+                                .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                        ].spacing(12).align_items(iced::Alignment::Center),
+                        Space::with_height(12),
+                        row![
+                            text("Opacity")
+                                .size(14)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                .width(100),
+                            slider(5..=100, self.watermark_opacity, Message::WatermarkOpacityChanged)
+                                .width(Length::Fill),
+                            container(
+                                text(format!("{}%", self.watermark_opacity))
+                                    .size(14)
+                                    .font(HEADING_FONT)
+                                    .style(PRIMARY_COLOR)
+                            )
+                            .width(50)
+                            .center_x(),
+                        ].spacing(12).align_items(iced::Alignment::Center),
+                        if self.watermark_image.is_none() {
+                            column![
+                                Space::with_height(12),
+                                row![
+                                    text("Text Size")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    slider(8..=200, self.watermark_size, Message::WatermarkSizeChanged)
+                                        .width(Length::Fill),
+                                    container(
+                                        text(format!("{}px", self.watermark_size))
+                                            .size(14)
+                                            .font(HEADING_FONT)
+                                            .style(PRIMARY_COLOR)
+                                    )
+                                    .width(50)
+                                    .center_x(),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                                Space::with_height(12),
+                                row![
+                                    text("Text Color")
+                                        .size(14)
+                                        .font(BODY_FONT)
+                                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                                        .width(100),
+                                    text_input("#FFFFFF", &self.watermark_color)
+                                        .on_input(Message::WatermarkColorChanged)
+                                        .width(Length::Fixed(120.0))
+                                        .padding([8, 12])
+                                        .size(14),
+                                    text(if parse_hex_color(&self.watermark_color).is_some() {
+                                        "Drawn in a system sans-serif font"
+                                    } else {
+                                        "Invalid color, using white"
+                                    })
+                                        .size(12)
+                                        .font(LIGHT_FONT)
+                                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                                ].spacing(12).align_items(iced::Alignment::Center),
+                            ].spacing(0)
+                        } else {
+                            column![]
+                        },
+                    ].spacing(0)
+                } else {
+                    column![]
+                },
+                
+                if self.compression_mode == CompressionMode::Advanced {
+                    column![
+                        Space::with_height(12),
+                        self.pipeline_steps(),
+                    ].spacing(0)
+                } else {
+                    column![]
+                },
+            ].spacing(0)
+        )
+        .width(Length::Fill)
+        .padding(12)
+        .style(theme::Container::Custom(Box::new(CardContainer)));
+
+        // Process button and progress
+        let process_section = column![
+            if self.is_processing {
+                row![
+                    styled_button("Processing...", Message::Process, ButtonStyle::Disabled),
+                    styled_button("Cancel", Message::CancelProcessing, ButtonStyle::Secondary),
+                ].spacing(8).into()
+            } else if self.has_selection() && 
+                     (self.has_watermark() || !self.target_size.is_empty() || !self.target_per_megapixel.is_empty() || !self.folder_budget.is_empty() || !self.width.is_empty() || !self.height.is_empty() ||
+                      !self.edge_length.is_empty() || !self.downscale_megapixels.is_empty() ||
+                      (self.compression_mode == CompressionMode::Advanced &&
+                       (self.convert_only || self.png_to_webp || self.lossless_optimize || !parse_widths(&self.responsive_widths).is_empty()))) {
+                row![
+                    styled_button("Process Images", Message::Process, ButtonStyle::Action),
+                    styled_button("Prepare for Web", Message::PrepareForWeb, ButtonStyle::Secondary),
+                    styled_button("Flatten to JPEG (white)", Message::FlattenToJpeg, ButtonStyle::Secondary),
+                    styled_button("Add to Queue", Message::AddToQueue, ButtonStyle::Subtle),
+                ].spacing(8).into()
+            } else if self.has_selection() {
+                row![
+                    styled_button("Process Images", Message::Process, ButtonStyle::Disabled),
+                    styled_button("Prepare for Web", Message::PrepareForWeb, ButtonStyle::Secondary),
+                    styled_button("Flatten to JPEG (white)", Message::FlattenToJpeg, ButtonStyle::Secondary),
+                ].spacing(8).into()
+            } else {
+                styled_button("Process Images", Message::Process, ButtonStyle::Disabled)
+            },
+            
+            self.queue_view(),
+            
+            if self.is_processing || self.progress > 0.0 {
+                column![
+                    Space::with_height(16),
+                    container(
+                        progress_bar(0.0..=1.0, self.progress)
+                            .height(Length::Fixed(8.0))
+                    )
+                    .style(theme::Container::Custom(Box::new(ProgressContainer))),
+                    Space::with_height(8),
+                    text(&self.status_message)
+                        .size(13)
+                        .font(BODY_FONT)
+                        .style(if self.stopped_on_failure { ERROR_COLOR } else { SUCCESS_COLOR }),
+                    match self.timing.summary(self.total_files) {
+                        Some(summary) if self.is_processing => column![
+                            Space::with_height(4),
+                            text(summary)
+                                .size(12)
+                                .font(LIGHT_FONT)
+                                .style(Color::from_rgb(0.4, 0.4, 0.5)),
+                        ],
+                        _ => column![],
+                    },
+                ].spacing(0)
+            } else {
+                column![]
+            },
+            
+            self.activity_log(),
+        ].spacing(0);
+
+        // Results section
+        let results_section = if !self.results.is_empty() {
+            let result_row = |index: usize, result: &ProcessResult| -> Element<Message> {
+                let (icon, color) = if result.success {
+                    ("", SUCCESS_COLOR)
+                } else {
+                    ("", ERROR_COLOR)
+                };
+                let expanded = self.expanded_result == Some(index);
+                
+                let mut summary_row = row![
+                      
+                        text(&result.filename)
+                            .size(13)
+                            .font(BODY_FONT)
+                            .style(Color::from_rgb(0.2, 0.2, 0.3))
+                            .width(Length::Fill),
+                        if result.is_copied_through() {
+                            text(format!("copied · {} KB", result.new_size / 1024))
+                                .size(13)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.4, 0.4, 0.5))
+                        } else if result.status == ResultStatus::Skipped {
+                            text(truncate_message(&result.message))
+                                .size(13)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.4, 0.4, 0.5))
+                        } else if result.success {
+                            text(format!("{} → {} KB", 
+                                result.original_size / 1024, 
+                                result.new_size / 1024
+                            ))
+                            .size(13)
+                            .font(BODY_FONT)
+                            .style(Color::from_rgb(0.4, 0.4, 0.5))
+                        } else {
+                            text(if expanded { String::from("Hide details") } else { truncate_message(&result.message) })
+                                .size(13)
+                                .font(BODY_FONT)
+                                .style(ERROR_COLOR)
+                        }
+                    ].spacing(12).align_items(iced::Alignment::Center);
+                if let Some(level) = result.compression_level() {
+                    summary_row = summary_row.push(
+                        text(level.to_string())
+                            .size(12)
+                            .font(BODY_FONT)
+                            .style(level.color())
+                    );
+                }
+                if let Some(score) = result.quality_score {
+                    summary_row = summary_row.push(
+                        text(score.to_string())
+                            .size(12)
+                            .font(LIGHT_FONT)
+                            .style(Color::from_rgb(0.4, 0.4, 0.5))
+                    );
+                }
+                if let Some(output_path) = result.output_path.as_ref().filter(|_| result.success) {
+                    summary_row = summary_row.push(
+                        button(text("Show").size(12).font(BODY_FONT))
+                            .on_press(Message::RevealOutput(output_path.clone()))
+                            .padding([2, 6])
+                            .style(theme::Button::Text)
+                    );
+                }
+                let summary = container(summary_row);
+                
+                if result.success {
+                    return summary
+                        .padding([8, 12])
+                        .style(theme::Container::Custom(Box::new(ResultItemContainer { success: true })))
+                        .into();
+                }
+                
+                let mut item = column![
+                    button(summary)
+                        .on_press(Message::ResultDetailsToggled(index))
+                        .padding(0)
+                        .style(theme::Button::Text)
+                ].spacing(6);
+                
+                if expanded {
+                    item = item.push(
+                        column![
+                            text(result.source_path.display().to_string())
+                                .size(12)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.4, 0.4, 0.5)),
+                            text(&result.message)
+                                .size(12)
+                                .font(BODY_FONT)
+                                .style(ERROR_COLOR),
+                        ].spacing(4)
+                    );
+                }
+                
+                container(item)
+                    .width(Length::Fill)
+                    .padding([8, 12])
+                    .style(theme::Container::Custom(Box::new(ResultItemContainer { success: false })))
+                    .into()
+            };
+            
+            // A queue run lists each job under its label. Within a job, failures come
+            // first so a bad batch can be triaged without scrolling, each part in the
+            // chosen sort order.
+            let mut groups: Vec<(Option<&str>, std::ops::Range<usize>)> = Vec::new();
+            if self.job_groups.first().is_none_or(|(start, _)| *start > 0) {
+                groups.push((None, 0..self.job_groups.first().map_or(self.results.len(), |(start, _)| *start)));
+            }
+            for (position, (start, label)) in self.job_groups.iter().enumerate() {
+                let end = self.job_groups.get(position + 1).map_or(self.results.len(), |(next, _)| *next);
+                groups.push((Some(label.as_str()), *start..end));
+            }
+            let mut results_list: Vec<Element<Message>> = Vec::new();
+            for (label, range) in groups {
+                if let Some(label) = label {
+                    results_list.push(
+                        container(
+                            text(label)
+                                .size(13)
+                                .font(HEADING_FONT)
+                                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        )
+                        .padding([6, 12, 2, 12])
+                        .into()
+                    );
+                }
+                let rows = self.results[range.clone()].iter().zip(range)
+                    .map(|(result, index)| (self.result_sort.key(result), result, index));
+                let (mut failed, mut succeeded): (Vec<_>, Vec<_>) = rows.partition(|(_, result, _)| !result.success);
+                for part in [&mut failed, &mut succeeded] {
+                    part.sort_by(|a, b| {
+                        let ordering = SortKey::compare((&a.0, a.2), (&b.0, b.2));
+                        if self.sort_descending { ordering.reverse() } else { ordering }
+                    });
+                }
+                results_list.extend(failed.into_iter().chain(succeeded).map(|(_, result, index)| result_row(index, result)));
+            }
+
+            container(
+                column![
+                    row![
+                        icon_text("", "Results", 14, 14),
+                        Space::with_width(Length::Fill),
+                        pick_list(
+                            &ResultSort::ALL[..],
+                            Some(self.result_sort),
+                            Message::ResultSortSelected,
+                        )
+                        .padding([4, 8])
+                        .text_size(13),
+                        styled_button(
+                            if self.sort_descending { "Descending" } else { "Ascending" },
+                            Message::SortDirectionToggled,
+                            ButtonStyle::Subtle,
+                        ),
+                    ].spacing(8).align_items(iced::Alignment::Center),
+                    Space::with_height(16),
+                    container(
+                        scrollable(
+                            column(results_list).spacing(4)
+                        ).height(Length::Fixed(150.0))
+                    )
+                    .style(theme::Container::Custom(Box::new(SubtleContainer)))
+                    .padding(4),
+                    Space::with_height(16),
+                    row![
+                        styled_button("Open Output", Message::OpenOutputFolder, ButtonStyle::Secondary),
+                        styled_button("Clear", Message::ClearResults, ButtonStyle::Subtle),
+                        if self.last_batch.is_some() && self.results.iter().any(|result| !result.success) {
+                            styled_button(
+                                "Retry Failed",
+                                Message::RetryFailed,
+                                if self.is_processing { ButtonStyle::Disabled } else { ButtonStyle::Subtle },
+                            )
+                        } else {
+                            Space::with_width(0).into()
+                        },
+                    ].spacing(12)
+                ].spacing(0)
+            )
+            .width(Length::Fill)
+            .padding(8)
+            .style(theme::Container::Custom(Box::new(CardContainer)))
+        } else if let Some(cleared) = &self.cleared_results {
+            container(
+                row![
+                    text(format!("Cleared {} results", cleared.results.len()))
+                        .size(13)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6))
+                        .width(Length::Fill),
+                    styled_button("Undo", Message::UndoClearResults, ButtonStyle::Subtle),
+                ].spacing(12).align_items(iced::Alignment::Center)
+            )
+            .width(Length::Fill)
+            .padding(8)
+            .style(theme::Container::Custom(Box::new(CardContainer)))
+        } else {
+            container(column![])
+        };
+
+        // Main layout with scrollable content
+        let content = scrollable(
+            column![
+                header,
+                container(
+                    column![
+                        self.settings_card(),
+                        file_selection_card,
+                        mode_selection_card,
+                        compression_settings,
+                        parameters_card,
+                        container(process_section)
+                            .width(Length::Fill)
+                            .padding([0, 20]),
+                        results_section,
+                        Space::with_height(20),
+                    ].spacing(8)
+                )
+                .max_width(680)
+                .center_x()
+                .padding([6, 14, 6, 6])
+            ].spacing(0)
+        );
+
+        container(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .style(theme::Container::Custom(Box::new(BackgroundContainer)))
+            .into()
+    }
+
+    fn theme(&self) -> Theme {
+        Theme::Light
+    }
+}
+
+impl ImageResizer {
+    fn has_selection(&self) -> bool {
+        self.selected_path.is_some() || !self.selected_files.is_empty() || !self.list_rejects.is_empty()
+    }
+    
+    fn has_watermark(&self) -> bool {
+        self.watermark_image.is_some() || !self.watermark_text.trim().is_empty()
+    }
+    
+    // The overlay is decoded and the font loaded once per job rather than per file
+    fn watermark(&self) -> Result<Option<watermark::Watermark>, String> {
+        let mark = if let Some(path) = &self.watermark_image {
+            let overlay = image::open(path).map_err(|e| format!("Watermark image {}: {}", path.display(), e))?;
+            watermark::Mark::Image(Arc::new(overlay.to_rgba8()))
+        } else if !self.watermark_text.trim().is_empty() {
+            watermark::Mark::Text {
+                text: self.watermark_text.trim().to_string(),
+                font: Arc::new(watermark::system_font().map_err(|problem| format!("Watermark: {}", problem))?),
+                size: self.watermark_size as f32,
+                color: parse_hex_color(&self.watermark_color).unwrap_or([255, 255, 255]),
+            }
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(watermark::Watermark {
+            mark,
+            position: self.watermark_position,
+            margin: self.watermark_margin.parse::<u32>().unwrap_or(0),
+            opacity: self.watermark_opacity as f32 / 100.0,
+        }))
+    }
+    
+    // Everything Process needs, checked and captured from the current settings without
+    // starting anything. The same job either runs right away or waits in the queue.
+    fn build_job(&self) -> Result<Job, String> {
+        if let Some(problem) = self.safe_mode_problem().or_else(|| self.suffix_problem()) {
+            return Err(problem);
+        }
+        // A region that didn't parse would leave what it covers visible, so it stops the run
+        let redactions = parse_redactions(&self.redactions).map_err(|problem| format!("Redactions: {}", problem))?;
+        // Only folder runs are filtered, so the hidden setting can't stop any other run
+        let format_filter = match FormatFilter::parse(&self.format_filter) {
+            Ok(filter) => filter,
+            Err(_) if !self.selected_path.as_deref().is_some_and(Path::is_dir) => FormatFilter::default(),
+            Err(problem) => return Err(format!("Formats: {}", problem)),
+        };
+        let physical_size = density::parse_physical_size(&self.print_width, &self.print_height, self.print_unit, &self.dpi)
+            .map_err(|problem| format!("Print size: {}", problem))?;
+        let per_megapixel_kb = self.target_per_megapixel.trim().parse::<u64>().ok().filter(|kb| *kb > 0);
+        let has_budget = self.folder_budget.trim().parse::<f64>().is_ok_and(|mb| mb > 0.0) && self.is_batch_selection();
+        if per_megapixel_kb.is_some() && (self.target_size.trim().parse::<u64>().is_ok() || has_budget) {
+            return Err(String::from("Target per megapixel can't be combined with Target Size or Total Budget"));
+        }
+        if physical_size.is_some() && self.edge_length.parse::<u32>().is_ok_and(|length| length > 0) {
+            return Err(String::from("Print size and Or Fit can't be used together"));
+        }
+        
+        // PNG → lossless WebP and lossless optimization leave pixels and
+        // dimensions exactly as they are, so no pixel step runs with them
+        let lossless_optimize = self.lossless_optimize && self.compression_mode == CompressionMode::Advanced;
+        let png_to_webp = self.png_to_webp && self.compression_mode == CompressionMode::Advanced && !lossless_optimize;
+        let keep_pixels = png_to_webp || lossless_optimize;
+        let keep_pixels_mode = if lossless_optimize { "optimizing losslessly in place" } else { "converting PNG to lossless WebP" };
+        if !redactions.is_empty() && keep_pixels {
+            return Err(format!("Redactions can't be applied when {}", keep_pixels_mode));
+        }
+        if self.has_watermark() && keep_pixels {
+            return Err(format!("A watermark can't be applied when {}", keep_pixels_mode));
+        }
+        let watermark = self.watermark()?;
+        // A reference file's size is matched to the byte
+        let target_size = match &self.size_reference {
+            Some((_, bytes)) => Some(*bytes),
+            None => self.target_size.parse::<u64>().ok().map(|kb| kb * 1024),
+        }.filter(|_| !keep_pixels);
+        let dimensions = parse_dimensions(&self.width, &self.height).filter(|_| !keep_pixels);
+        let physical_size = physical_size.filter(|_| !keep_pixels);
+        let maintain_ratio = self.maintain_ratio;
+        let algorithm = if png_to_webp { CompressionAlgorithm::WebPLossless } else { self.compression_algorithm };
+        let auto_scale = self.auto_scale;
+        let compression_options = CompressionOptions {
+            target_size: None,
+            preserve_metadata: self.preserve_metadata,
+            lossless_size_limit: self.lossless_limit.parse::<u64>().ok().map(|kb| kb * 1024),
+            // A print size is always tagged with the DPI it was sized for
+            dpi: physical_size.map(|size| size.dpi)
+                .or_else(|| self.dpi.parse::<u16>().ok().filter(|dpi| *dpi > 0)),
+            embed_thumbnail: self.embed_thumbnail,
+            source_icc_profile: None,
+            source_metadata: None,
+            ..self.encoder_options(algorithm)
+        };
+        // Lossless optimization replaces the files in place, so there's nothing to archive
+        let zip_plan = self.selection_anchor().filter(|_| self.zip_output && !lossless_optimize).map(|anchor| {
+            let zip_path = self.zip_path.clone()
+                .unwrap_or_else(|| report_dir(anchor, self.output_dir.as_deref()).join("resized.zip"));
+            let root = self.selected_path.clone().filter(|path| self.zip_keep_structure && path.is_dir());
+            (zip_path, root)
+        });
+        let options = ProcessingOptions {
+            output_dir: self.output_dir.clone(),
+            backup_originals: self.backup_originals,
+            safe_mode: self.safe_mode,
+            preserve_timestamps: self.preserve_timestamps,
+            suffix: self.output_suffix.clone(),
+            format_in_name: self.format_in_name,
+            gif_to_webp: self.gif_to_webp,
+            explode_frames: self.explode_frames && !keep_pixels,
+            trim_borders: self.trim_borders && !keep_pixels,
+            trim_tolerance: self.trim_tolerance,
+            sharpen_amount: if keep_pixels { 0.0 } else { self.sharpen_amount as f32 / 100.0 },
+            sharpen_radius: self.sharpen_radius as f32 / 10.0,
+            max_megapixels: self.max_megapixels.parse::<f32>().ok().filter(|mp| *mp > 0.0),
+            target_bytes_per_mpx: per_megapixel_kb.filter(|_| !keep_pixels).map(|kb| kb * 1024),
+            edge_constraint: self.edge_length.parse::<u32>().ok()
+                .filter(|length| *length > 0 && !keep_pixels)
+                .map(|length| (self.edge, length)),
+            physical_size,
+            downscale_megapixels: self.downscale_megapixels.parse::<f32>().ok()
+                .filter(|mp| *mp > 0.0 && !keep_pixels),
+            linear_resize: self.linear_resize,
+            auto_orient: self.auto_orient && !keep_pixels,
+            grayscale: self.grayscale && !keep_pixels,
+            auto_levels: self.auto_levels && !keep_pixels,
+            levels_per_channel: self.levels_per_channel,
+            redactions,
+            redact_style: if self.redact_blur { adjust::RedactStyle::Blur } else { adjust::RedactStyle::Pixelate },
+            matte: (algorithm == CompressionAlgorithm::Jpeg)
+                .then(|| parse_hex_color(&self.jpeg_background).unwrap_or([255, 255, 255])),
+            watermark,
+            pipeline: self.pipeline.clone(),
+            convert_only: self.convert_only,
+            responsive_widths: if algorithm == CompressionAlgorithm::Simple || keep_pixels {
+                Vec::new()
+            } else {
+                parse_widths(&self.responsive_widths)
+            },
+            width_naming: self.width_naming,
+            dual_export: self.dual_export && algorithm.is_modern_format() && algorithm.is_available() && !keep_pixels,
+            near_duplicates: if self.skip_near_duplicates {
+                Some(Arc::new(dedupe::DuplicateFilter::new(self.duplicate_threshold as u32)))
+            } else {
+                None
+            },
+            fail_fast: self.fail_fast,
+            copy_non_images: self.copy_non_images,
+            crop_to_fill: self.crop_to_fill && !keep_pixels,
+            extension_rules: if self.use_extension_rules && algorithm != CompressionAlgorithm::Simple && !keep_pixels {
+                self.extension_rules.iter().cloned().collect()
+            } else {
+                HashMap::new()
+            },
+            atomic_writes: self.atomic_writes,
+            scratch_dir: self.scratch_dir.clone().filter(|_| self.atomic_writes),
+            lossless_optimize,
+            zip_output: None,
+            measure_quality: self.measure_quality,
+            write_sidecars: self.write_sidecars,
+            min_size_to_process: self.min_size_to_process.trim().parse::<u64>().ok()
+                .filter(|kb| *kb > 0)
+                .map(|kb| kb * 1024),
+            incremental: self.incremental,
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+        
+        let budget_kb = self.folder_budget.trim().parse::<f64>().ok()
+            .filter(|mb| *mb > 0.0 && self.is_batch_selection() && !keep_pixels)
+            .map(|mb| (mb * 1024.0) as u64);
+        let report_path = self.selection_anchor()
+            .filter(|_| self.write_report)
+            .map(|anchor| report_dir(anchor, self.output_dir.as_deref()).join("report.csv"));
+        let selection = match self.selected_path.as_deref() {
+            Some(path) => path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().to_string(),
+            None => format!("{} files", self.selected_files.len()),
+        };
+        Ok(Job {
+            label: format!("{} as {}", selection, algorithm),
+            selected_path: self.selected_path.clone(),
+            selected_files: self.selected_files.clone(),
+            list_rejects: self.list_rejects.clone(),
+            batch: BatchSettings {
+                target_size,
+                budget_kb,
+                budget_by_detail: self.budget_by_detail,
+                budget_targets: Arc::new(HashMap::new()),
+                dimensions,
+                maintain_ratio,
+                auto_scale,
+                compression_options,
+                options,
+                max_threads: self.max_threads.max(1) as usize,
+                report_path,
+            },
+            zip_plan,
+            only_formats: if lossless_optimize {
+                &[image::ImageFormat::Jpeg, image::ImageFormat::Png]
+            } else if png_to_webp {
+                &[image::ImageFormat::Png]
+            } else {
+                &[]
+            },
+            format_filter,
+            copy_non_images: self.copy_non_images && !keep_pixels,
+        })
+    }
+    
+    // Starts collecting a job's images. Outside a queue run the results start over;
+    // in one, the job's rows follow the earlier jobs' under a heading of their own.
+    fn start_job(&mut self, job: Job) -> Command<Message> {
+        let options = &job.batch.options;
+        let prepared = match &options.scratch_dir {
+            Some(scratch_dir) => check_writable(scratch_dir)
+                .map_err(|e| format!("Scratch folder {} isn't writable: {}", scratch_dir.display(), e)),
+            None => Ok(()),
+        }.and_then(|_| {
+            job.zip_plan.as_ref().map(|(zip_path, root)| {
+                archive::ZipOutput::create(zip_path, root.clone(), options.atomic_writes, options.scratch_dir.as_deref())
+                    .map(Arc::new)
+                    .map_err(|e| format!("Couldn't create {}: {}", zip_path.display(), e))
+            }).transpose()
+        });
+        let zip_output = match prepared {
+            Ok(zip_output) => zip_output,
+            Err(problem) => {
+                // A queued job that can't start stays first in the queue
+                if self.queue_running {
+                    self.queue.insert(0, job);
+                }
+                self.queue_running = false;
+                self.status_message = problem;
+                self.log(self.status_message.clone());
+                return Command::none();
+            }
+        };
+        let Job { label, selected_path, selected_files, list_rejects, mut batch, only_formats, format_filter, copy_non_images, .. } = job;
+        batch.options.zip_output = zip_output;
+        
+        self.is_processing = true;
+        self.retrying = false;
+        self.progress = 0.0;
+        if self.queue_running {
+            self.job_groups.push((self.results.len(), label));
+        } else {
+            self.results.clear();
+        }
+        self.cleared_results = None;
+        // Unusable entries of a loaded file list lead the results as failures
+        if selected_path.is_none() {
+            let algorithm = batch.compression_options.algorithm;
+            self.results.extend(list_rejects.into_iter().map(|(path, reason)| ProcessResult {
+                filename: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                source_path: path,
+                original_size: 0,
+                new_size: 0,
+                success: false,
+                status: ResultStatus::Processed,
+                message: reason,
+                algorithm_used: algorithm,
+                compression_ratio: 0.0,
+                output_path: None,
+                quality_score: None,
+                final_quality: None,
+            }));
+        }
+        self.expanded_result = None;
+        self.stopped_on_failure = false;
+        self.pending_files.clear();
+        self.in_flight = 0;
+        self.total_files = 0;
+        self.batch_id += 1;
+        self.timing.start();
+        self.status_message = "Collecting images...".to_string();
+        
+        match &selected_path {
+            Some(path) => self.log(format!("Collecting images in {}", path.display())),
+            None => self.log(format!("Using {} selected files", selected_files.len())),
+        }
+        let batch_id = self.batch_id;
+        let simple_mode = batch.compression_options.algorithm == CompressionAlgorithm::Simple;
+        let output_dir = batch.options.output_dir.clone();
+        self.batch = Some(batch);
+        match selected_path {
+            Some(path) => Command::perform(
+                collect_batch(path, output_dir, simple_mode, copy_non_images, only_formats, format_filter),
+                move |images| Message::ImagesCollected(batch_id, images)
+            ),
+            None => {
+                let mut files = selected_files;
+                retain_formats(&mut files, only_formats);
+                Command::perform(async move { files }, move |images| Message::ImagesCollected(batch_id, images))
+            }
+        }
+    }
+    
+    // Jobs waiting for Run Queue, each removable until the run takes it
+    fn queue_view(&self) -> Element<'_, Message> {
+        if self.queue.is_empty() {
+            return column![].into();
+        }
+        
+        let jobs: Vec<Element<Message>> = self.queue.iter().enumerate().map(|(index, job)| {
+            row![
+                text(format!("{}. {}", index + 1, job.label))
+                    .size(13)
+                    .font(BODY_FONT)
+                    .style(Color::from_rgb(0.3, 0.3, 0.4))
+                    .width(Length::Fill),
+                button(text("Remove").size(12).font(BODY_FONT))
+                    .on_press(Message::RemoveQueuedJob(index))
+                    .padding([2, 6])
+                    .style(theme::Button::Text),
+            ].spacing(12).align_items(iced::Alignment::Center).into()
+        }).collect();
+        
+        column![
+            Space::with_height(12),
+            container(column(jobs).spacing(4))
+                .width(Length::Fill)
+                .padding([8, 12])
+                .style(theme::Container::Custom(Box::new(SubtleContainer))),
+            Space::with_height(8),
+            row![
+                styled_button(
+                    &format!("Run Queue ({})", self.queue.len()),
+                    Message::RunQueue,
+                    if self.is_processing { ButtonStyle::Disabled } else { ButtonStyle::Primary },
+                ),
+                styled_button("Clear Queue", Message::ClearQueue, ButtonStyle::Subtle),
+            ].spacing(8),
+        ].into()
+    }
+    
+    // Where the rows of the running or last job begin; the whole list outside a queue run
+    fn last_job_start(&self) -> usize {
+        self.job_groups.last().map_or(0, |(start, _)| *start)
+    }
+    
+    // Files already handed to the thread pool finish on their own; this keeps them from writing
+    fn stop_in_flight(&self) {
+        if let Some(batch) = &self.batch {
+            batch.options.stopped.store(true, Ordering::Relaxed);
+        }
+    }
+    
+    // A stopped or cancelled job halts the queue run; the jobs not yet started stay queued
+    fn pause_queue(&mut self) {
+        if self.queue_running {
+            self.queue_running = false;
+            if !self.queue.is_empty() {
+                self.status_message.push_str(&format!(" {} queued jobs not started.", self.queue.len()));
+            }
+        }
+    }
+    
+    // Without a suffix an output written beside its source would replace it
+    fn suffix_problem(&self) -> Option<String> {
+        if !self.output_suffix.is_empty() || self.zip_output || !self.has_selection() {
+            return None;
+        }
+        if self.lossless_optimize && self.compression_mode == CompressionMode::Advanced {
+            return None;
+        }
+        let output_dir = self.output_dir.as_deref()?;
+        let sources: Vec<&Path> = match self.selected_path.as_deref() {
+            Some(path) if path.is_dir() => vec![path],
+            Some(path) => path.parent().into_iter().collect(),
+            None => self.selected_files.iter().filter_map(|file| file.parent()).collect(),
+        };
+        sources
+            .into_iter()
+            .any(|source| resolve_existing(source) == resolve_existing(output_dir))
+            .then(|| String::from("An empty suffix needs an output folder apart from the sources"))
+    }
+    
+    // A folder or several hand-picked files, as opposed to one image
+    fn is_batch_selection(&self) -> bool {
+        self.selected_path.as_deref().is_some_and(Path::is_dir) || self.selected_files.len() > 1
+    }
+    
+    // Why safe mode won't let this run start: the outputs, or the ZIP archive, would land
+    // in a source folder, where the next folder run would pick them up again
+    fn safe_mode_problem(&self) -> Option<String> {
+        if !self.safe_mode || !self.has_selection() {
+            return None;
+        }
+        if self.lossless_optimize && self.compression_mode == CompressionMode::Advanced {
+            return Some(String::from("Safe mode is on: in-place optimization would overwrite the originals"));
+        }
+        let Some(output_dir) = &self.output_dir else {
+            return Some(String::from("Safe mode is on: choose an output folder outside the source"));
+        };
+        let sources: Vec<&Path> = match self.selected_path.as_deref() {
+            Some(path) if path.is_dir() => vec![path],
+            Some(path) => path.parent().into_iter().collect(),
+            None => self.selected_files.iter().filter_map(|file| file.parent()).collect(),
+        };
+        let zip_path = self.zip_output.then(|| self.zip_path.clone().unwrap_or_else(|| output_dir.join("resized.zip")));
+        let destination = zip_path.as_deref().unwrap_or(output_dir);
+        sources
+            .into_iter()
+            .find(|source| is_within(destination, source))
+            .map(|source| format!("Safe mode is on: {} is inside the source folder {}", destination.display(), source.display()))
+    }
+    
+    // Where the selection lives, for the default output and report folders
+    fn selection_anchor(&self) -> Option<&Path> {
+        self.selected_path.as_deref().or_else(|| self.selected_files.first().map(PathBuf::as_path))
+    }
+    
+    // Hand-picked files in processing order, each with move and remove controls
+    fn file_list(&self) -> iced::widget::Container<'_, Message> {
+        let last = self.selected_files.len().saturating_sub(1);
+        let control = |label: &'static str, message: Option<Message>| {
+            let control = button(text(label).size(12).font(BODY_FONT))
+                .padding([2, 6])
+                .style(theme::Button::Text);
+            match message {
+                Some(message) => control.on_press(message),
+                None => control,
+            }
+        };
+        
+        let rows: Vec<Element<Message>> = self.selected_files.iter().enumerate().map(|(index, file)| {
+            row![
+                text(file.file_name().unwrap_or_default().to_string_lossy())
+                    .size(13)
+                    .font(BODY_FONT)
+                    .style(Color::from_rgb(0.4, 0.4, 0.5))
+                    .width(Length::Fill),
+                control("Up", (index > 0).then(|| Message::MoveFile(index, Direction::Up))),
+                control("Down", (index < last).then(|| Message::MoveFile(index, Direction::Down))),
+                control("Remove", Some(Message::RemoveFile(index))),
+            ].spacing(4).align_items(iced::Alignment::Center).into()
+        }).collect();
+        
+        // About six rows before it scrolls, so a dozen files never push the page around
+        let height = (self.selected_files.len() as f32 * 26.0).min(160.0);
+        container(
+            column![
+                text(if self.list_rejects.is_empty() {
+                    format!("{} files selected", self.selected_files.len())
+                } else {
+                    format!(
+                        "{} files selected, {} listed paths will be reported as failures",
+                        self.selected_files.len(),
+                        self.list_rejects.len()
+                    )
+                })
+                    .size(12)
+                    .font(LIGHT_FONT)
+                    .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                scrollable(column(rows).spacing(2)).height(Length::Fixed(height)),
+            ].spacing(6)
+        )
+        .width(Length::Fill)
+        .padding([8, 12])
+        .style(theme::Container::Custom(Box::new(SubtleContainer)))
+    }
+    
+    // The pixel steps in the order they run, each with its state and move controls
+    fn pipeline_steps(&self) -> iced::widget::Container<'_, Message> {
+        let last = self.pipeline.steps().len().saturating_sub(1);
+        let control = |label: &'static str, message: Option<Message>| {
+            let control = button(text(label).size(12).font(BODY_FONT))
+                .padding([2, 6])
+                .style(theme::Button::Text);
+            match message {
+                Some(message) => control.on_press(message),
+                None => control,
+            }
+        };
+        
+        let rows: Vec<Element<Message>> = self.pipeline.steps().iter().enumerate().map(|(index, step)| {
+            let enabled = match step {
+                pipeline::Step::Orient => self.auto_orient,
+                pipeline::Step::Trim => self.trim_borders,
+                pipeline::Step::Levels => self.auto_levels,
+                pipeline::Step::Resize => true,
+                pipeline::Step::Sharpen => self.sharpen_amount > 0,
+                pipeline::Step::Grayscale => self.grayscale,
+            };
+            row![
+                text(format!("{}. {}", index + 1, step))
+                    .size(13)
+                    .font(BODY_FONT)
+                    .style(Color::from_rgb(0.4, 0.4, 0.5))
+                    .width(Length::Fill),
+                text(if enabled { "On" } else { "Off" })
+                    .size(12)
+                    .font(LIGHT_FONT)
+                    .style(if enabled { SUCCESS_COLOR } else { Color::from_rgb(0.6, 0.6, 0.7) }),
+                control("Up", (index > 0).then(|| Message::MovePipelineStep(index, Direction::Up))),
+                control("Down", (index < last).then(|| Message::MovePipelineStep(index, Direction::Down))),
+            ].spacing(4).align_items(iced::Alignment::Center).into()
+        }).collect();
+        
+        container(
+            column![
+                row![
+                    text("Step order (compression and metadata always come last)")
+                        .size(12)
+                        .font(LIGHT_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6))
+                        .width(Length::Fill),
+                    control("Reset", (!self.pipeline.is_default()).then_some(Message::ResetPipelineOrder)),
+                ].spacing(4).align_items(iced::Alignment::Center),
+                column(rows).spacing(2),
+            ].spacing(6)
+        )
+        .width(Length::Fill)
+        .padding([8, 12])
+        .style(theme::Container::Custom(Box::new(SubtleContainer)))
+    }
+    
+    // Source size the width and height fields are kept in proportion to; folders
+    // mix aspect ratios, so only a single image with the ratio locked has one
+    fn locked_source_dimensions(&self) -> Option<(u32, u32)> {
+        match &self.file_info {
+            Some(FileInfo::Image { width, height, .. })
+                if self.maintain_ratio && !self.crop_to_fill && *width > 0 && *height > 0 =>
+            {
+                Some((*width, *height))
+            }
+            _ => None,
+        }
+    }
+    
+    // Progressive loading needs MozJPEG, as image-rs only writes baseline
+    fn jpeg_encoder(&self) -> JpegEncoder {
+        if self.use_mozjpeg || self.progressive_loading {
+            JpegEncoder::MozJpeg
+        } else {
+            JpegEncoder::ImageRs
+        }
+    }
+    
+    fn jpeg_mode(&self) -> JpegMode {
+        if self.progressive_loading {
+            JpegMode::Progressive
+        } else {
+            self.jpeg_mode
+        }
+    }
+    
+    // How the chosen algorithm encodes, apart from anything tied to a source or a
+    // run's output; a job and the previews of it both start from these
+    fn encoder_options(&self, algorithm: CompressionAlgorithm) -> CompressionOptions {
+        CompressionOptions {
+            algorithm,
+            quality: Some(self.quality_slider),
+            optimize_for_web: self.optimize_for_web,
+            jpeg_background: parse_hex_color(&self.jpeg_background).unwrap_or([255, 255, 255]),
+            opaque_algorithm: self.opaque_algorithm,
+            transparent_algorithm: self.transparent_algorithm,
+            alpha_quality: Some(self.alpha_quality).filter(|quality| *quality > 0),
+            webp_filter_strength: self.webp_filter_strength,
+            webp_sharpness: self.webp_sharpness,
+            minimal_palette: self.minimal_palette,
+            reduce_16bit_png: self.reduce_16bit_png && !self.keep_bit_depth,
+            keep_bit_depth: self.keep_bit_depth,
+            oxipng_level: self.oxipng_level,
+            oxipng_multithreaded: self.oxipng_multithreaded,
+            jpeg_encoder: self.jpeg_encoder(),
+            jpeg_mode: self.jpeg_mode(),
+            adaptive_quality: self.adaptive_quality,
+            embed_srgb: self.embed_srgb,
+            interlace: self.interlace_png || self.progressive_loading,
+            min_quality: self.min_quality,
+            max_search_iterations: Some(self.max_search_probes).filter(|probes| *probes < SEARCH_PROBES_ALL),
+            ..CompressionOptions::default()
+        }
+    }
+    
+    // Encoder settings for compressing the selected image in a preview. Simple mode
+    // has no encoder of its own; its output is JPEG
+    fn preview_options(&self) -> CompressionOptions {
+        let algorithm = match self.compression_algorithm {
+            CompressionAlgorithm::Simple => CompressionAlgorithm::Jpeg,
+            algorithm => algorithm,
+        };
+        self.encoder_options(algorithm)
+    }
+    
+    // The built-in choices that suit the selection: a single image with transparency
+    // leaves out the ones that write JPEG, unless one of them is already picked. Simple
+    // mode writes JPEG only to reach a target size.
+    fn input_algorithm_choices(&self) -> Vec<CompressionAlgorithm> {
+        let transparent = matches!(self.file_info, Some(FileInfo::Image { has_alpha: Some(true), .. }));
+        let has_target = self.size_reference.is_some() || self.target_size.trim().parse::<u64>().is_ok();
+        let writes_jpeg = |algorithm: CompressionAlgorithm| {
+            !algorithm.keeps_transparency() || (algorithm == CompressionAlgorithm::Simple && has_target)
+        };
+        algorithm_choices()
+            .into_iter()
+            .filter(|algorithm| {
+                !transparent || !writes_jpeg(*algorithm) || *algorithm == self.compression_algorithm
+            })
+            .collect()
+    }
+    
+    // Sizes and encode times from the last benchmark, smallest output highlighted
+    fn benchmark_table(&self) -> iced::widget::Column<'_, Message> {
+        let entries = match &self.benchmark {
+            Some(Ok(entries)) => entries,
+            Some(Err(e)) => {
+                return column![
+                    Space::with_height(8),
+                    text(e).size(12).font(BODY_FONT).style(ERROR_COLOR),
+                ];
+            }
+            None => return column![],
+        };
+        
+        let smallest = entries.iter()
+            .filter_map(|entry| entry.outcome.as_ref().ok().copied())
+            .min();
+        
+        let rows: Vec<Element<Message>> = entries.iter().map(|entry| {
+            let (size_label, color) = match &entry.outcome {
+                Ok(size) if Some(*size) == smallest => (format!("{} KB", size / 1024), SUCCESS_COLOR),
+                Ok(size) => (format!("{} KB", size / 1024), Color::from_rgb(0.3, 0.3, 0.4)),
+                Err(_) => ("failed".to_string(), ERROR_COLOR),
+            };
+            
+            row![
+                text(entry.algorithm.to_string())
+                    .size(12)
+                    .font(BODY_FONT)
+                    .width(Length::Fill),
+                text(size_label)
+                    .size(12)
+                    .font(HEADING_FONT)
+                    .style(color)
+                    .width(80),
+                text(format!("{} ms", entry.duration.as_millis()))
+                    .size(12)
+                    .font(LIGHT_FONT)
+                    .style(Color::from_rgb(0.5, 0.5, 0.6))
+                    .width(70),
+            ].spacing(8).into()
+        }).collect();
+        
+        column![
+            Space::with_height(8),
+            column(rows).spacing(4),
+        ]
+    }
+    
+    // The four downscales side by side as 100% crops, each with its encoded size
+    fn filter_comparison_view(&self) -> iced::widget::Column<'_, Message> {
+        let samples = match &self.filter_comparison {
+            Some(Ok(samples)) => samples,
+            Some(Err(e)) => {
+                return column![
+                    Space::with_height(8),
+                    text(e).size(12).font(BODY_FONT).style(ERROR_COLOR),
+                ];
+            }
+            None => return column![],
+        };
+        
+        let tiles: Vec<Element<Message>> = samples.iter().map(|sample| {
+            let (size_label, color) = match &sample.outcome {
+                Ok(size) => (format!("{:.1} KB", *size as f64 / 1024.0), Color::from_rgb(0.3, 0.3, 0.4)),
+                Err(_) => ("failed".to_string(), ERROR_COLOR),
+            };
+            column![
+                iced::widget::Image::new(sample.crop.clone())
+                    .width(FILTER_CROP_SIZE as f32)
+                    .height(FILTER_CROP_SIZE as f32),
+                text(sample.name).size(12).font(BODY_FONT),
+                text(size_label).size(12).font(HEADING_FONT).style(color),
+            ].spacing(4).into()
+        }).collect();
+        
+        column![
+            Space::with_height(8),
+            row(tiles).spacing(12),
+        ]
+    }
+    
+    // The output crop and its amplified difference, side by side
+    fn diff_preview_view(&self) -> iced::widget::Column<'_, Message> {
+        let preview = match &self.diff_preview {
+            Some(Ok(preview)) => preview,
+            Some(Err(e)) => {
+                return column![
+                    Space::with_height(8),
+                    text(e).size(12).font(BODY_FONT).style(ERROR_COLOR),
+                ];
+            }
+            None => return column![],
+        };
+        
+        let tile = |handle: &iced::widget::image::Handle, label: String| -> Element<Message> {
+            column![
+                iced::widget::Image::new(handle.clone())
+                    .width(DIFF_CROP_SIZE as f32)
+                    .height(DIFF_CROP_SIZE as f32),
+                text(label).size(12).font(BODY_FONT),
+            ].spacing(4).into()
+        };
+        
+        column![
+            Space::with_height(8),
+            row![
+                tile(&preview.output_crop, format!("Output, {:.1} KB", preview.output_size as f64 / 1024.0)),
+                tile(&preview.diff_crop, format!("Difference ×{}", DIFF_GAIN)),
+            ].spacing(12),
+            text(if preview.max_difference == 0 {
+                String::from("Identical to the source")
+            } else {
+                format!("Largest change: {} of 255 levels", preview.max_difference)
+            })
+                .size(12)
+                .font(LIGHT_FONT)
+                .style(Color::from_rgb(0.5, 0.5, 0.6)),
+        ].spacing(4)
+    }
+    
+    // One row per rule; extensions without a rule use the algorithm selected above
+    fn extension_rules_editor(&self) -> iced::widget::Column<'_, Message> {
+        if !self.use_extension_rules {
+            return column![];
+        }
+        
+        let choices: Vec<RuleTarget> = std::iter::once(RuleTarget::KeepFormat)
+            .chain(algorithm_choices().into_iter().map(RuleTarget::Algorithm))
+            .collect();
+        let rows: Vec<Element<Message>> = self.extension_rules.iter().enumerate().map(|(index, (extension, target))| {
+            row![
+                text(format!(".{}", extension))
+                    .size(14)
+                    .font(BODY_FONT)
+                    .style(Color::from_rgb(0.3, 0.3, 0.4))
+                    .width(100),
+                pick_list(
+                    choices.clone(),
+                    Some(*target),
+                    move |target| Message::ExtensionRuleTargetSelected(index, target),
+                )
+                .width(Length::Fill)
+                .padding([8, 12])
+                .text_size(14),
+                styled_button("Remove", Message::ExtensionRuleRemoved(index), ButtonStyle::Subtle),
+            ].spacing(12).align_items(iced::Alignment::Center).into()
+        }).collect();
+        
+        column![
+            Space::with_height(8),
+            column(rows).spacing(4),
+            Space::with_height(8),
+            row![
+                text_input("Extension, e.g. bmp", &self.new_rule_extension)
+                    .on_input(Message::NewRuleExtensionChanged)
+                    .on_submit(Message::ExtensionRuleAdded)
+                    .width(Length::Fixed(180.0))
+                    .padding([8, 12])
+                    .size(14),
+                styled_button("Add Rule", Message::ExtensionRuleAdded, ButtonStyle::Secondary),
+                text("New rules start with the selected algorithm")
+                    .size(12)
+                    .font(LIGHT_FONT)
+                    .style(Color::from_rgb(0.5, 0.5, 0.6)),
+            ].spacing(12).align_items(iced::Alignment::Center),
+        ]
+    }
+    
+    // IMGRESIZE_ALGO and IMGRESIZE_QUALITY set the starting algorithm and quality, so
+    // shared installs can all begin from the same baseline. A value that doesn't
+    // parse is reported in the log and the built-in default kept.
+    fn apply_env_defaults(&mut self) {
+        let mut warnings = Vec::new();
+        
+        if let Ok(name) = std::env::var("IMGRESIZE_ALGO") {
+            match CompressionAlgorithm::from_name(&name) {
+                Some(algorithm) => {
+                    self.compression_algorithm = algorithm;
+                    if algorithm == CompressionAlgorithm::Simple {
+                        self.compression_mode = CompressionMode::Simple;
+                    } else {
+                        self.compression_mode = CompressionMode::Advanced;
+                        self.quality_slider = algorithm.recommended_quality();
+                    }
+                }
+                None => warnings.push(format!("IMGRESIZE_ALGO: unknown algorithm \"{}\", using {}", name, self.compression_algorithm)),
+            }
+        }
+        
+        if let Ok(value) = std::env::var("IMGRESIZE_QUALITY") {
+            match value.trim().parse::<u8>().ok().filter(|quality| (10..=100).contains(quality)) {
+                Some(quality) => {
+                    self.quality_slider = quality;
+                    self.algorithm_qualities.insert(self.compression_algorithm, quality);
+                }
+                None => warnings.push(format!("IMGRESIZE_QUALITY: \"{}\" is not a quality from 10 to 100, using {}", value, self.quality_slider)),
+            }
+        }
+        
+        for warning in warnings {
+            log::warn!("{}", warning);
+            self.status_message = warning.clone();
+            self.log(warning);
+        }
+    }
+    
+    // Appends to the activity log, stamped with the batch's elapsed time while one runs.
+    // Only the newest MAX_LOG_ENTRIES lines are kept.
+    fn log(&mut self, entry: String) {
+        let entry = match self.timing.elapsed_secs() {
+            Some(secs) => format!("[{}] {}", format_duration(secs), entry),
+            None => entry,
+        };
+        if self.log.len() >= MAX_LOG_ENTRIES {
+            self.log.remove(0);
+        }
+        self.log.push(entry);
+    }
+    
+    fn activity_log(&self) -> iced::widget::Column<'_, Message> {
+        if self.log.is_empty() {
+            return column![];
+        }
+        
+        let toggle = if self.show_log {
+            styled_button("Hide Activity", Message::ActivityLogToggled, ButtonStyle::Subtle)
+        } else {
+            styled_button("Show Activity", Message::ActivityLogToggled, ButtonStyle::Subtle)
+        };
+        if !self.show_log {
+            return column![Space::with_height(8), toggle];
+        }
+        
+        let lines: Vec<Element<Message>> = self.log.iter().map(|entry| {
+            text(entry)
+                .size(12)
+                .font(LIGHT_FONT)
+                .style(Color::from_rgb(0.3, 0.3, 0.4))
+                .into()
+        }).collect();
+        
+        column![
+            Space::with_height(8),
+            toggle,
+            container(
+                scrollable(column(lines).spacing(2)).height(Length::Fixed(150.0))
+            )
+            .width(Length::Fill)
+            .style(theme::Container::Custom(Box::new(SubtleContainer)))
+            .padding(8),
+        ]
+    }
+    
+    // Starts queued files until max_threads are running, or finishes the batch once
+    // the queue is empty and nothing is left running
+    fn process_next_file(&mut self) -> Command<Message> {
+        let settings = match &self.batch {
+            Some(settings) => settings.clone(),
+            None => return Command::none(),
+        };
+        
+        let mut started = Vec::new();
+        while self.in_flight < settings.max_threads {
+            let path = match self.pending_files.pop_front() {
+                Some(path) => path,
+                None => break,
+            };
+            self.in_flight += 1;
+            self.log(format!("Opening {}", path.display()));
+            self.status_message = format!(
+                "Processing {} ({} of {})",
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                self.timing.files_done + self.in_flight,
+                self.total_files
+            );
+            let batch_id = self.batch_id;
+            started.push(Command::perform(
+                process_file(path, settings.clone()),
+                move |result| Message::FileProcessed(batch_id, result)
+            ));
+        }
+        
+        if self.in_flight == 0 {
+            return self.update(Message::ProcessingComplete);
+        }
+        Command::batch(started)
+    }
+    
+    // Power-user settings behind the header's gear button
+    fn settings_card(&self) -> Element<'_, Message> {
+        if !self.show_settings {
+            return column![].into();
+        }
+        
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get()).clamp(1, 64) as u8;
+        container(
+            column![
+                icon_text("", "Settings", 14, 14),
+                Space::with_height(12),
+                row![
+                    text("Parallel Files")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(96),
+                    slider(1..=cores.max(2), self.max_threads.clamp(1, cores.max(2)), Message::MaxThreadsChanged)
+                        .width(Length::Fill),
+                    container(
+                        text(self.max_threads.to_string())
+                            .size(14)
+                            .font(HEADING_FONT)
+                            .style(PRIMARY_COLOR)
+                    )
+                    .width(50)
+                    .center_x(),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                text(format!("Files processed at once, up to {} cores; 1 works through a batch in order", cores))
+                    .size(12)
+                    .font(LIGHT_FONT)
+                    .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                Space::with_height(12),
+                row![
+                    text("Decode Limit")
+                        .size(14)
+                        .font(BODY_FONT)
+                        .style(Color::from_rgb(0.3, 0.3, 0.4))
+                        .width(96),
+                    text_input("Optional (megapixels)", &self.max_megapixels)
+                        .on_input(Message::MaxMegapixelsChanged)
+                        .width(Length::Fixed(188.0))
+                        .padding([8, 12])
+                        .size(14),
+                    text("Larger images are refused")
+                        .size(12)
+                        .font(LIGHT_FONT)
+                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
+                ].spacing(12).align_items(iced::Alignment::Center),
+                Space::with_height(12),
+                styled_checkbox(
+                    "Write outputs through a temp file (safe against interrupted writes)",
+                    self.atomic_writes,
+                    Message::AtomicWritesToggled
+                ),
+                if self.atomic_writes {
+                    column![
+                        Space::with_height(8),
+                        row![
+                            styled_button("Scratch Folder", Message::SelectScratchFolder, ButtonStyle::Secondary),
+                            text(match &self.scratch_dir {
+                                Some(dir) => truncate_path(dir),
+                                None => String::from("Default: temp files next to each output"),
+                            })
+                                .size(13)
+                                .font(BODY_FONT)
+                                .style(Color::from_rgb(0.4, 0.4, 0.5)),
+                        ].spacing(12).align_items(iced::Alignment::Center),
+                    ].spacing(0)
+                } else {
+                    column![]
+                },
+            ].spacing(4)
+        )
+        .width(Length::Fill)
+        .padding(12)
+        .style(theme::Container::Custom(Box::new(CardContainer)))
+        .into()
+    }
+}
+
+// Helper UI functions
+fn icon_text(icon: &str, label: &str, icon_size: u16, text_size: u16) -> Element<'static, Message> {
+    row![
+        //text(icon).size(icon_size),
+        text(label)
+            .size(text_size)
+            .font(HEADING_FONT)
+            .style(Color::from_rgb(0.2, 0.2, 0.3)),
+    ].spacing(8).into()
+}
+
+fn styled_button(label: &str, on_press: Message, style: ButtonStyle) -> Element<'static, Message> {
+    let btn = button(
+        text(label)
+            .size(14)
+            .font(if matches!(style, ButtonStyle::Action) { HEADING_FONT } else { BODY_FONT })
+            .horizontal_alignment(iced::alignment::Horizontal::Center)
+    )
+    .padding([10, 20]);
+    
+    match style {
+        ButtonStyle::Primary => btn.on_press(on_press).style(theme::Button::Primary),
+        ButtonStyle::Secondary => btn.on_press(on_press).style(theme::Button::Secondary),
+        ButtonStyle::Action => btn.on_press(on_press).style(theme::Button::Positive),
+        ButtonStyle::Subtle => btn.on_press(on_press).style(theme::Button::Text),
+        ButtonStyle::Disabled => btn.style(theme::Button::Secondary),
+    }.into()
+}
+
+fn mode_button(title: &str, subtitle: &str, mode: CompressionMode, current: CompressionMode) -> Element<'static, Message> {
+    let is_selected = mode == current;
+    
+    button(
+        column![
+            text(title)
+                .size(12)
+                .font(HEADING_FONT)
+                .style(if is_selected { PRIMARY_COLOR } else { Color::from_rgb(0.4, 0.4, 0.5) }),
+            text(subtitle)
+                .size(8)
+                .font(LIGHT_FONT)
+                .style(if is_selected { PRIMARY_COLOR } else { Color::from_rgb(0.6, 0.6, 0.5) }),
+        ].spacing(2).align_items(iced::Alignment::Center)
+    )
+    .on_press(Message::ModeChanged(mode))
+    .padding([8, 16])
+    .style(if is_selected {
+        theme::Button::Primary
+    } else {
+        theme::Button::Secondary
+    })
+    .into()
+}
+
+fn styled_checkbox(label: &str, is_checked: bool, on_toggle: impl Fn(bool) -> Message + 'static) -> Element<'static, Message> {
+    checkbox(label, is_checked, on_toggle)
+        .size(14)
+        .spacing(10)
+        .text_size(14)
+        .into()
+}
+
+#[derive(Clone, Copy)]
+enum ButtonStyle {
+    Primary,
+    Secondary,
+    Action,
+    Subtle,
+    Disabled,
+}
+
+// Custom container styles
+struct BackgroundContainer;
+impl container::StyleSheet for BackgroundContainer {
+    type Style = Theme;
+    
+    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            background: Some(Background::Color(BACKGROUND_COLOR)),
+            ..Default::default()
+        }
+    }
+}
+
+struct CardContainer;
+impl container::StyleSheet for CardContainer {
+    type Style = Theme;
+    
+    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            background: Some(Background::Color(CARD_COLOR)),
+            border_radius: 12.0.into(),
+            border_width: 1.0,
+            border_color: Color::from_rgba(0.0, 0.0, 0.0, 0.05),
+            ..Default::default()
+        }
+    }
+}
+
+struct GradientContainer;
+impl container::StyleSheet for GradientContainer {
+    type Style = Theme;
+    
+    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            background: Some(Background::Color(PRIMARY_COLOR)),
+            ..Default::default()
+        }
+    }
+}
+
+struct SubtleContainer;
+impl container::StyleSheet for SubtleContainer {
+    type Style = Theme;
+    
+    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            background: Some(Background::Color(SECONDARY_COLOR)),
+            border_radius: 8.0.into(),
+            border_width: 1.0,
+            border_color: Color::from_rgba(0.0, 0.0, 0.0, 0.05),
+            ..Default::default()
+        }
+    }
+}
+
+struct ProgressContainer;
+impl container::StyleSheet for ProgressContainer {
+    type Style = Theme;
+    
+    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            border_radius: 4.0.into(),
+            ..Default::default()
+        }
+    }
+}
+
+struct ResultItemContainer {
+    success: bool,
+}
+impl container::StyleSheet for ResultItemContainer {
+    type Style = Theme;
+    
+    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
+        let bg_color = if self.success {
+            Color::from_rgba(0.2, 0.7, 0.3, 0.05)
+        } else {
+            Color::from_rgba(0.9, 0.2, 0.2, 0.05)
+        };
+        
+        container::Appearance {
+            background: Some(Background::Color(bg_color)),
+            border_radius: 6.0.into(),
+            ..Default::default()
+        }
+    }
+}
+
+// Rest of the implementation remains the same
+impl std::fmt::Display for CompressionAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "Auto (Smart Selection)"),
+            Self::AutoBest => write!(f, "Auto (Try Best)"),
+            Self::ByAlpha => write!(f, "Auto (By Transparency)"),
+            Self::Simple => write!(f, "Simple (Fast)"),
+            Self::Jpeg => write!(f, "JPEG"),
+            Self::StandardPng => write!(f, "PNG Standard"),
+            Self::OptiPng => write!(f, "PNG (OptiPNG)"),
+            Self::OxiPng => write!(f, "PNG (OxiPNG)"),
+            Self::PngQuant => write!(f, "PNG (PNGQuant Lossy)"),
+            Self::WebPLossy => write!(f, "WebP Lossy"),
+            Self::WebPLossless => write!(f, "WebP Lossless"),
+            Self::LosslessFirst => write!(f, "WebP Lossless-First"),
+            Self::Avif => write!(f, "AVIF"),
+        }
+    }
+}
+
+impl std::fmt::Display for JpegMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Baseline => write!(f, "Baseline"),
+            Self::BaselineOptimized => write!(f, "Baseline (Optimized)"),
+            Self::Progressive => write!(f, "Progressive"),
+        }
+    }
+}
+
+// Helper functions
+async fn select_file() -> Option<PathBuf> {
+    rfd::AsyncFileDialog::new()
+        .add_filter("Images", &["jpg", "jpeg", "png", "gif", "bmp", "webp"])
+        .pick_file()
+        .await
+        .map(|handle| handle.path().to_path_buf())
+}
+
+async fn select_files() -> Vec<PathBuf> {
+    rfd::AsyncFileDialog::new()
+        .add_filter("Images", &["jpg", "jpeg", "png", "gif", "bmp", "webp"])
+        .pick_files()
+        .await
+        .map(|handles| handles.iter().map(|handle| handle.path().to_path_buf()).collect())
+        .unwrap_or_default()
+}
+
+// A .txt or .csv list of image paths; None when the dialog is cancelled
+async fn load_file_list() -> Option<(PathBuf, Result<manifest::FileList, String>)> {
+    let list_path = rfd::AsyncFileDialog::new()
+        .add_filter("File lists", &["txt", "csv"])
+        .pick_file()
+        .await?
+        .path()
+        .to_path_buf();
+    let read_path = list_path.clone();
+    let loaded = tokio::task::spawn_blocking(move || manifest::read_file_list(&read_path).map_err(|e| e.to_string()))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    Some((list_path, loaded))
+}
+
+// Opens the platform's file manager with `path` selected. An entry inside a ZIP
+// archive selects the archive itself.
+fn reveal_in_file_manager(path: &Path) -> std::io::Result<()> {
+    let path = path.ancestors().find(|ancestor| ancestor.exists()).unwrap_or(path);
+    
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        // Explorer wants the path quoted after the comma, not the whole argument
+        std::process::Command::new("explorer")
+            .raw_arg(format!("/select,\"{}\"", path.display()))
+            .spawn()?;
+    }
+    
+    #[cfg(target_os = "macos")]
+    std::process::Command::new("open").arg("-R").arg(path).spawn()?;
+    
+    // The freedesktop file manager interface, which Nautilus, Dolphin, Nemo and
+    // Thunar implement; without it the containing folder opens instead. Only with
+    // --print-reply does dbus-send wait for the call and fail when nothing answers.
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let uri: String = absolute
+            .to_string_lossy()
+            .bytes()
+            .map(|byte| if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+                char::from(byte).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            })
+            .collect();
+        let shown = std::process::Command::new("dbus-send")
+            .args([
+                "--session",
+                "--print-reply",
+                "--dest=org.freedesktop.FileManager1",
+                "--type=method_call",
+                "/org/freedesktop/FileManager1",
+                "org.freedesktop.FileManager1.ShowItems",
+            ])
+            .arg(format!("array:string:file://{}", uri))
+            .arg("string:")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !shown {
+            open::that(path.parent().unwrap_or(path))?;
+        }
+    }
+    
+    Ok(())
+}
+
+async fn select_overlay_file() -> Option<PathBuf> {
+    rfd::AsyncFileDialog::new()
+        .add_filter("Images", &["png", "webp"])
+        .pick_file()
+        .await
+        .map(|handle| handle.path().to_path_buf())
+}
+
+async fn select_zip_file() -> Option<PathBuf> {
+    rfd::AsyncFileDialog::new()
+        .add_filter("ZIP archives", &["zip"])
+        .set_file_name("resized.zip")
+        .save_file()
+        .await
+        .map(|handle| handle.path().to_path_buf())
+}
+
+async fn select_curve_file(image_path: PathBuf) -> Option<PathBuf> {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    rfd::AsyncFileDialog::new()
+        .add_filter("CSV files", &["csv"])
+        .set_file_name(format!("{}_quality_curve.csv", stem))
+        .save_file()
+        .await
+        .map(|handle| handle.path().to_path_buf())
+}
+
+async fn select_folder() -> Option<PathBuf> {
+    rfd::AsyncFileDialog::new()
+        .pick_folder()
+        .await
+        .map(|handle| handle.path().to_path_buf())
+}
+
+// Reads only the image header and file metadata, so it stays fast on huge files
+async fn load_file_info(path: PathBuf) -> FileInfo {
+    tokio::task::spawn_blocking(move || read_file_info(&path))
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(FileInfo::Unreadable)
+}
+
+fn read_file_info(path: &Path) -> Option<FileInfo> {
+    if path.is_dir() {
+        let image_count = collect_images(path, None).map(|images| images.len()).unwrap_or(0);
+        return Some(FileInfo::Folder { image_count });
+    }
+    
+    let file_size = fs::metadata(path).ok()?.len();
+    let reader = image::io::Reader::open(path).ok()?.with_guessed_format().ok()?;
+    let format = reader.format()?;
+    let (width, height) = reader.into_dimensions().ok()?;
+    
+    Some(FileInfo::Image {
+        width,
+        height,
+        format: format!("{:?}", format).to_uppercase(),
+        file_size,
+        has_alpha: header_has_alpha(path, format),
+    })
+}
+
+// Alpha support declared by the header; None when the format can't tell
+// without decoding every frame
+fn header_has_alpha(path: &Path, format: image::ImageFormat) -> Option<bool> {
+    use image::ImageDecoder;
+    
+    match format {
+        image::ImageFormat::Jpeg => Some(false),
+        image::ImageFormat::Png => {
+            let file = std::io::BufReader::new(fs::File::open(path).ok()?);
+            let decoder = image::codecs::png::PngDecoder::new(file).ok()?;
+            Some(decoder.color_type().has_alpha())
+        }
+        #[cfg(feature = "webp")]
+        image::ImageFormat::WebP => {
+            let mut header = vec![0u8; 64];
+            let read = std::io::Read::read(&mut fs::File::open(path).ok()?, &mut header).ok()?;
+            webp::BitstreamFeatures::new(&header[..read]).map(|features| features.has_alpha())
+        }
+        _ => None,
+    }
+}
+
+fn describe_file_info(info: &FileInfo) -> String {
+    match info {
+        FileInfo::Image { width, height, format, file_size, has_alpha } => {
+            let transparency = match has_alpha {
+                Some(true) => " · transparency",
+                Some(false) => " · no transparency",
+                None => "",
+            };
+            format!("{}×{} · {} · {} KB{}", width, height, format, file_size / 1024, transparency)
+        }
+        FileInfo::Folder { image_count } => format!("{} images", image_count),
+        FileInfo::Unreadable => String::from("Could not read image header"),
+    }
+}
+
+fn truncate_path(path: &Path) -> String {
+    let display_path = path.display().to_string();
+    let chars: Vec<char> = display_path.chars().collect();
+    if chars.len() > 50 {
+        format!("...{}", chars[chars.len() - 47..].iter().collect::<String>())
+    } else {
+        display_path
+    }
+}
+
+const MAX_LOG_ENTRIES: usize = 500;
+
+// "Encoding photo.webp with WebP Lossy q85" for outputs a lossy encoder wrote; the
+// "Wrote" entry already names the encoder of the rest
+fn encode_log_entry(result: &ProcessResult) -> Option<String> {
+    let quality = result.final_quality.filter(|_| result.success)?;
+    Some(format!("Encoding {} with {} q{}", result.filename, result.algorithm_used, quality))
+}
+
+fn result_log_entry(result: &ProcessResult) -> String {
+    if !result.success {
+        return format!("Failed {}: {}", result.filename, result.message);
+    }
+    if result.is_copied_through() {
+        return format!("Copied {} unchanged ({} KB)", result.filename, result.new_size / 1024);
+    }
+    if result.status == ResultStatus::Skipped {
+        return format!("{}: {}", result.filename, result.message);
+    }
+    
+    let mut entry = format!(
+        "Wrote {} with {} ({} → {} KB)",
+        result.filename,
+        result.algorithm_used,
+        result.original_size / 1024,
+        result.new_size / 1024
+    );
+    if !result.message.is_empty() {
+        entry.push_str(&format!(", {}", result.message));
+    }
+    entry
+}
+
+// The other field for a typed dimension, at the source's aspect ratio; blank when
+// the typed value isn't a usable size
+fn proportional_dimension(value: &str, from: u32, to: u32) -> String {
+    match value.trim().parse::<u32>() {
+        Ok(typed) if typed > 0 => {
+            let scaled = (typed as f64 * to as f64 / from as f64).round().max(1.0);
+            (scaled as u32).to_string()
+        }
+        _ => String::new(),
+    }
+}
+
+// PNG to lossless WebP and JPEG stays JPEG; every other extension keeps the global choice
+fn default_extension_rules() -> Vec<(String, RuleTarget)> {
+    vec![
+        (String::from("png"), RuleTarget::Algorithm(CompressionAlgorithm::WebPLossless)),
+        (String::from("jpg"), RuleTarget::Algorithm(CompressionAlgorithm::Jpeg)),
+        (String::from("jpeg"), RuleTarget::Algorithm(CompressionAlgorithm::Jpeg)),
+    ]
+}
+
+// Keeps a failed row to one line; the full message is shown when the row is expanded
+fn truncate_message(message: &str) -> String {
+    let chars: Vec<char> = message.chars().collect();
+    if chars.len() > 50 {
+        format!("{}...", chars[..47].iter().collect::<String>())
+    } else {
+        message.to_string()
+    }
+}
+
+// Accepts "#RRGGBB", "RRGGBB", "white" or "black"
+fn parse_hex_color(value: &str) -> Option<[u8; 3]> {
+    let value = value.trim();
+    match value.to_lowercase().as_str() {
+        "white" => return Some([255, 255, 255]),
+        "black" => return Some([0, 0, 0]),
+        _ => {}
+    }
+    
+    let hex = value.trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn parse_dimensions(width: &str, height: &str) -> Option<(u32, u32)> {
+    match (width.parse::<u32>(), height.parse::<u32>()) {
+        (Ok(w), Ok(h)) => Some((w, h)),
+        _ => None,
+    }
+}
+
+// "x,y,w,h" regions separated by ';'. Unlike the other lists a bad entry is an error
+// rather than skipped, since the region it meant would go out unredacted.
+fn parse_redactions(value: &str) -> Result<Vec<adjust::Rect>, String> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let numbers: Option<Vec<u32>> = part.split(',').map(|number| number.trim().parse::<u32>().ok()).collect();
+            match numbers.as_deref() {
+                Some(&[x, y, width, height]) if width > 0 && height > 0 => Ok(adjust::Rect { x, y, width, height }),
+                _ => Err(format!("\"{}\" isn't x,y,width,height", part)),
+            }
+        })
+        .collect()
+}
+
+// "320, 640,1280" -> [320, 640, 1280]; blanks, zeros and duplicates are dropped
+fn parse_widths(value: &str) -> Vec<u32> {
+    let mut widths = Vec::new();
+    for width in value.split(',').filter_map(|part| part.trim().parse::<u32>().ok()) {
+        if width > 0 && !widths.contains(&width) {
+            widths.push(width);
+        }
+    }
+    widths
+}
+
+// Encodes an in-memory copy with every encoder at its recommended quality; nothing is written
+async fn run_benchmark(
+    path: PathBuf,
+    template: CompressionOptions,
+    max_megapixels: Option<f32>,
+) -> Result<Vec<BenchmarkEntry>, String> {
+    tokio::task::spawn_blocking(move || {
+        let image = open_image(&path, max_megapixels)?;
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        // Skipped: encoders left out of the build, which would only rerun their fallback,
+        // and JPEG for transparent images, which it can't keep
+        let transparent = has_transparency(&image);
+        let applicable = CompressionAlgorithm::ENCODERS.iter()
+            .filter(|algorithm| algorithm.is_available())
+            .filter(|algorithm| !transparent || algorithm.keeps_transparency());
+        
+        Ok(applicable.map(|&algorithm| {
+            let options = CompressionOptions {
+                algorithm,
+                quality: Some(algorithm.recommended_quality()),
+                ..template.clone()
+            };
+            let started = Instant::now();
+            let outcome = compressor.compress(&image, options)
+                .map(|result| result.data.len() as u64)
+                .map_err(|e| e.to_string());
+            
+            BenchmarkEntry {
+                algorithm,
+                outcome,
+                duration: started.elapsed(),
+            }
+        }).collect())
+    }).await.unwrap_or_else(|e| Err(format!("Benchmark task failed: {}", e)))
+}
+
+// The size the filter comparison downscales to, taken from the resize fields
+#[derive(Debug, Clone, Copy)]
+enum FilterTarget {
+    Edge(Edge, u32),
+    Within((u32, u32)),
+    Exact((u32, u32)),
+    None,
+}
+
+// Resizes an in-memory copy with each compared filter and encodes it with the current
+// algorithm; nothing is written
+async fn run_filter_comparison(
+    path: PathBuf,
+    target: FilterTarget,
+    template: CompressionOptions,
+    max_megapixels: Option<f32>,
+) -> Result<Vec<FilterSample>, String> {
+    tokio::task::spawn_blocking(move || {
+        let image = open_image(&path, max_megapixels)?;
+        let source = (image.width(), image.height());
+        let (width, height) = match target {
+            FilterTarget::Edge(edge, length) => edge_fit(source, edge, length).unwrap_or(source),
+            FilterTarget::Within(bounds) => fit_within(source, bounds),
+            FilterTarget::Exact(bounds) => bounds,
+            FilterTarget::None => return Err(String::from("Set a width, height or edge length to compare filters at")),
+        };
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        
+        Ok(COMPARED_FILTERS.iter().map(|&(name, filter)| {
+            let resized = image.resize_exact(width.max(1), height.max(1), filter);
+            let outcome = compressor.compress(&resized, template.clone())
+                .map(|result| result.data.len() as u64)
+                .map_err(|e| e.to_string());
+            
+            let crop_width = FILTER_CROP_SIZE.min(resized.width());
+            let crop_height = FILTER_CROP_SIZE.min(resized.height());
+            let crop = resized
+                .crop_imm((resized.width() - crop_width) / 2, (resized.height() - crop_height) / 2, crop_width, crop_height)
+                .to_rgba8();
+            
+            FilterSample {
+                name,
+                outcome,
+                crop: iced::widget::image::Handle::from_pixels(crop_width, crop_height, crop.into_raw()),
+            }
+        }).collect())
+    }).await.unwrap_or_else(|e| Err(format!("Filter comparison task failed: {}", e)))
+}
+
+// Encodes an in-memory copy with the current settings, decodes it again and maps the
+// difference; nothing is written
+async fn run_diff_preview(
+    path: PathBuf,
+    template: CompressionOptions,
+    max_megapixels: Option<f32>,
+) -> Result<DiffPreview, String> {
+    tokio::task::spawn_blocking(move || {
+        let image = open_image(&path, max_megapixels)?;
+        let background = template.jpeg_background;
+        let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+        let result = compressor.compress(&image, template).map_err(|e| e.to_string())?;
+        let (reference, decoded) = quality::decode_pair(&image, &result.data, background).ok_or_else(|| {
+            format!("{} output can't be decoded here to compare", result.algorithm_used.file_extension().to_uppercase())
+        })?;
+        
+        let max_difference = reference.as_raw().iter().zip(decoded.as_raw()).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+        let difference = quality::difference_map(&reference, &decoded, DIFF_GAIN);
+        
+        // A 100% crop from the middle; scaling down would average the artifacts away
+        let crop_width = DIFF_CROP_SIZE.min(decoded.width());
+        let crop_height = DIFF_CROP_SIZE.min(decoded.height());
+        let (left, top) = ((decoded.width() - crop_width) / 2, (decoded.height() - crop_height) / 2);
+        let output_crop = DynamicImage::ImageRgb8(decoded).crop_imm(left, top, crop_width, crop_height).to_rgba8();
+        let diff_crop = DynamicImage::ImageLuma8(difference).crop_imm(left, top, crop_width, crop_height).to_rgba8();
+        
+        Ok(DiffPreview {
+            output_size: result.data.len() as u64,
+            max_difference,
+            output_crop: iced::widget::image::Handle::from_pixels(crop_width, crop_height, output_crop.into_raw()),
+            diff_crop: iced::widget::image::Handle::from_pixels(crop_width, crop_height, diff_crop.into_raw()),
+        })
+    }).await.unwrap_or_else(|e| Err(format!("Diff task failed: {}", e)))
+}
+
+impl QualityCurveRun {
+    // Encodes the next quality of the sweep off the UI thread
+    fn encode_next(&self) -> Command<Message> {
+        let Some(image) = self.image.clone() else {
+            return Command::none();
+        };
+        let quality = CURVE_QUALITIES.start() + self.points.len() as u8;
+        let options = CompressionOptions {
+            quality: Some(quality),
+            ..self.template.clone()
+        };
+        let (id, measure) = (self.id, self.measure);
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let background = options.jpeg_background;
+                    let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+                    let outcome = compressor.compress(&image, options)
+                        .map(|result| {
+                            let score = measure.then(|| quality::measure(&image, &result.data, background)).flatten();
+                            (result.data.len() as u64, score)
+                        })
+                        .map_err(|e| e.to_string());
+                    CurvePoint { quality, outcome }
+                })
+                .await
+                .unwrap_or_else(|e| CurvePoint { quality, outcome: Err(format!("Encoding task failed: {}", e)) })
+            },
+            move |point| Message::QualityCurvePointEncoded(id, point)
+        )
+    }
+}
+
+// quality,bytes,psnr,ssim,error; the scores are blank unless measured and decodable
+fn curve_to_csv(points: &[CurvePoint]) -> String {
+    let mut csv = String::from("quality,bytes,psnr,ssim,error\n");
+    for point in points {
+        csv.push_str(&match &point.outcome {
+            Ok((bytes, Some(score))) => format!("{},{},{:.3},{:.5},\n", point.quality, bytes, score.psnr, score.ssim),
+            Ok((bytes, None)) => format!("{},{},,,\n", point.quality, bytes),
+            Err(e) => format!("{},,,,\"{}\"\n", point.quality, e.replace('"', "\"\"")),
+        });
+    }
+    csv
+}
+
+// One row per result; fields containing commas, quotes or line breaks are quoted
+fn results_to_csv(results: &[ProcessResult]) -> String {
+    let field = |value: &str| {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+    
+    let mut csv = String::from("filename,original_bytes,new_bytes,ratio,algorithm,success,message,output_path,source_path\n");
+    for result in results {
+        csv.push_str(&format!(
+            "{},{},{},{:.3},{},{},{},{},{}\n",
+            field(&result.filename),
+            result.original_size,
+            result.new_size,
+            result.compression_ratio,
+            field(&result.algorithm_used.to_string()),
+            result.success,
+            field(&result.message),
+            field(&result.output_path.as_ref().map(|path| path.display().to_string()).unwrap_or_default()),
+            field(&result.source_path.display().to_string()),
+        ));
+    }
+    csv
+}
+
+// The chosen output folder, or the default "resized" folder that the top-level
+// images of the selection were written to
+fn report_dir(selected: &Path, output_dir: Option<&Path>) -> PathBuf {
+    match output_dir {
+        Some(dir) => dir.to_path_buf(),
+        None if selected.is_dir() => selected.join(OUTPUT_DIR_NAME),
+        None => selected.parent().unwrap_or(Path::new(".")).join(OUTPUT_DIR_NAME),
+    }
+}
+
+// Completes the batch's ZIP archive, if it has one; a stopped batch keeps the entries
+// written so far. Returns a note for the status line.
+fn finish_archive(batch: Option<&BatchSettings>) -> Option<String> {
+    let zip = batch?.options.zip_output.as_ref()?;
+    Some(match zip.finish() {
+        Ok(()) => format!("Saved to {}.", zip.path().display()),
+        Err(e) => format!("(ZIP archive failed: {})", e),
+    })
+}
+
+// Keeps the files whose content is one of `formats`; an empty list keeps everything
+fn retain_formats(files: &mut Vec<PathBuf>, formats: &[image::ImageFormat]) {
+    if !formats.is_empty() {
+        files.retain(|file| sniff::detect_format(file).is_some_and(|format| formats.contains(&format)));
+    }
+}
+
+// Which image formats a folder run picks up, from "only: png, jpg; skip: gif".
+// Formats are matched by content like the extension rules, so "jpeg" means "jpg".
+#[derive(Debug, Clone, Default)]
+pub struct FormatFilter {
+    only: Vec<String>,
+    skip: Vec<String>,
+}
+
+impl FormatFilter {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut filter = Self::default();
+        for part in value.split(';').map(str::trim).filter(|part| !part.is_empty()) {
+            let (list, extensions) = match part.split_once(':') {
+                Some((key, extensions)) => match key.trim().to_lowercase().as_str() {
+                    "only" => (&mut filter.only, extensions),
+                    "skip" => (&mut filter.skip, extensions),
+                    other => return Err(format!("\"{}\" isn't only or skip", other)),
+                },
+                None => return Err(format!("\"{}\" needs \"only:\" or \"skip:\"", part)),
+            };
+            for extension in extensions.split(',').map(|ext| ext.trim().trim_start_matches('.').to_lowercase()) {
+                let extension = if extension == "jpeg" { String::from("jpg") } else { extension };
+                if !extension.is_empty() && !list.contains(&extension) {
+                    list.push(extension);
+                }
+            }
+        }
+        Ok(filter)
+    }
+    
+    pub fn allows(&self, path: &Path) -> bool {
+        if self.only.is_empty() && self.skip.is_empty() {
+            return true;
+        }
+        let extension = sniff::true_extension(path);
+        (self.only.is_empty() || self.only.contains(&extension)) && !self.skip.contains(&extension)
+    }
+}
+
+impl std::fmt::Display for FormatFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.only.is_empty(), self.skip.is_empty()) {
+            (true, true) => write!(f, "Every supported format"),
+            (false, true) => write!(f, "Only {}", self.only.join(", ")),
+            (true, false) => write!(f, "All but {}", self.skip.join(", ")),
+            (false, false) => write!(f, "Only {}, but not {}", self.only.join(", "), self.skip.join(", ")),
+        }
+    }
+}
+
+// Simple mode keeps its own narrower list of supported extensions
+async fn collect_batch(
+    path: PathBuf,
+    output_dir: Option<PathBuf>,
+    simple_mode: bool,
+    copy_non_images: bool,
+    only_formats: &'static [image::ImageFormat],
+    format_filter: FormatFilter,
+) -> Vec<PathBuf> {
+    tokio::task::spawn_blocking(move || {
+        let output_dir = output_dir.as_deref();
+        let mut files = if simple_mode {
+            simple::collect_images(&path, output_dir).unwrap_or_default()
+        } else {
+            collect_images(&path, output_dir).unwrap_or_default()
+        };
+        retain_formats(&mut files, only_formats);
+        files.retain(|file| format_filter.allows(file));
+        if copy_non_images && path.is_dir() {
+            files.extend(collect_non_images(&path, output_dir, simple_mode));
+        }
+        files
+    }).await.unwrap_or_default()
+}
+
+async fn process_file(image_path: PathBuf, settings: BatchSettings) -> Vec<ProcessResult> {
+    let filename = image_path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let algorithm = settings.compression_options.algorithm;
+    
+    let task_filename = filename.clone();
+    let source_path = image_path.clone();
+    // A file's share covers every output written from it
+    let outputs = if algorithm == CompressionAlgorithm::Simple {
+        1
+    } else if settings.options.dual_export {
+        2
+    } else {
+        settings.options.responsive_widths.len().max(1) as u64
+    };
+    let budget_target = settings.budget_targets.get(&image_path).map(|bytes| (bytes / outputs).max(1));
+    let target_size = budget_target.or(settings.target_size);
+    // Read up front, since optimizing in place overwrites the source itself
+    let source_times = settings.options.preserve_timestamps
+        .then(|| fs::metadata(&image_path).ok())
+        .flatten()
+        .map(|metadata| (FileTime::from_last_access_time(&metadata), FileTime::from_last_modification_time(&metadata)));
+    let archived = settings.options.zip_output.is_some();
+    let mut results = tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let mut settings = settings;
+        if settings.compression_options.preserve_metadata {
+            settings.compression_options.source_icc_profile = icc::read_profile(&image_path).map(Arc::new);
+            settings.compression_options.source_metadata = Some(Arc::new(metadata::read(&image_path, settings.options.auto_orient)));
+        }
+        debug!("{}: processing with {}", image_path.display(), algorithm);
+        let simple_mode = algorithm == CompressionAlgorithm::Simple;
+        let is_image = if simple_mode { simple::is_image_file(&image_path) } else { is_image_file(&image_path) };
+        let skipped = is_image.then(|| {
+            under_threshold(&image_path, algorithm, &settings.options)
+                .or_else(|| up_to_date(&image_path, algorithm, &settings.compression_options, &settings.options))
+        });
+        if let Some(result) = skipped.flatten() {
+            let results = vec![result.into_process_result(task_filename, &image_path)];
+            log_file_results(&image_path, &results, started);
+            return results;
+        }
+        if settings.options.explode_frames && is_image && !simple_mode {
+            let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+            let exploded = process_frames(&image_path, &settings, &compressor);
+            if let Some(exploded) = exploded {
+                let results: Vec<ProcessResult> = exploded
+                    .into_iter()
+                    .map(|(filename, result)| result.into_process_result(filename, &image_path))
+                    .collect();
+                log_file_results(&image_path, &results, started);
+                return results;
+            }
+        }
+        let result = if settings.options.lossless_optimize {
+            lossless::optimize_in_place(&image_path, &settings.compression_options, &settings.options)
+        } else if !is_image {
+            copy_through(&image_path, algorithm, &settings.options)
+        } else if simple_mode {
+            let result = simple::process_single_image(
+                &image_path,
+                target_size,
+                settings.dimensions,
+                settings.maintain_ratio,
+                settings.auto_scale,
+                &settings.options,
+            );
+            InternalResult::from_simple(result)
+        } else if settings.options.dual_export {
+            let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+            let results: Vec<ProcessResult> = process_dual_export(
+                &image_path,
+                target_size,
+                settings.dimensions,
+                settings.maintain_ratio,
+                &settings.compression_options,
+                &settings.options,
+                &compressor,
+            )
+            .into_iter()
+            .map(|(filename, result)| result.into_process_result(filename, &image_path))
+            .collect();
+            log_file_results(&image_path, &results, started);
+            return results;
+        } else if !settings.options.responsive_widths.is_empty() {
+            let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+            let results: Vec<ProcessResult> = process_responsive_set(
+                &image_path,
+                target_size,
+                &settings.compression_options,
+                &settings.options,
+                &compressor,
+            )
+            .into_iter()
+            .map(|(filename, result)| result.into_process_result(filename, &image_path))
+            .collect();
+            log_file_results(&image_path, &results, started);
+            return results;
+        } else {
+            let compressor = SmartCompressor::new(SmartCompressorConfig::default());
+            process_single_image_advanced(
+                &image_path,
+                target_size,
+                settings.dimensions,
+                settings.maintain_ratio,
+                &settings.compression_options,
+                &settings.options,
+                &compressor,
+            )
+        };
+        
+        let results = vec![result.into_process_result(task_filename, &image_path)];
+        log_file_results(&image_path, &results, started);
+        results
+    }).await.unwrap_or_else(|e| vec![ProcessResult {
+        filename,
+        source_path,
+        original_size: 0,
+        new_size: 0,
+        success: false,
+        status: ResultStatus::Processed,
+        message: format!("Processing task failed: {}", e),
+        algorithm_used: algorithm,
+        compression_ratio: 0.0,
+        output_path: None,
+        quality_score: None,
+        final_quality: None,
+    }]);
+    
+    // Archive entries aren't files of their own; a failure here leaves the output in place
+    if let Some((accessed, modified)) = source_times.filter(|_| !archived) {
+        for result in results.iter_mut().filter(|result| result.success) {
+            let Some(output_path) = &result.output_path else { continue };
+            if let Err(e) = filetime::set_file_times(output_path, accessed, modified) {
+                log::warn!("{}: couldn't copy the timestamp: {}", output_path.display(), e);
+                let note = format!("timestamp not kept: {}", e);
+                result.message = if result.message.is_empty() { note } else { format!("{}, {}", result.message, note) };
+            }
+        }
+    }
+    
+    if let Some(bytes) = budget_target {
+        for result in results.iter_mut().filter(|result| result.success) {
+            let note = format!("budget {} KB", bytes / 1024);
+            result.message = if result.message.is_empty() { note } else { format!("{}, {}", result.message, note) };
+        }
+    }
+    results
+}
+
+async fn plan_budget(
+    images: Vec<PathBuf>,
+    budget_kb: u64,
+    by_detail: bool,
+    max_megapixels: Option<f32>,
+    simple_mode: bool,
+) -> Result<(Vec<PathBuf>, HashMap<PathBuf, u64>), String> {
+    tokio::task::spawn_blocking(move || {
+        let targets = budget::plan_targets(&images, budget_kb, by_detail, max_megapixels, |path| {
+            if simple_mode { simple::is_image_file(path) } else { is_image_file(path) }
+        });
+        (images, targets)
+    }).await.map_err(|e| format!("Planning the size budget failed: {}", e))
+}
+
+// Whether the batch's written files fit the budget, e.g. "Total 48.2 MB of 50.0 MB budget"
+fn budget_summary(results: &[ProcessResult], budget_kb: u64) -> String {
+    let total: u64 = results.iter().filter(|result| result.success).map(|result| result.new_size).sum();
+    let budget = budget_kb * 1024;
+    let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    if total <= budget {
+        format!("Total {:.1} MB of {:.1} MB budget.", mb(total), mb(budget))
+    } else {
+        format!("Total {:.1} MB is over the {:.1} MB budget by {:.1} MB.", mb(total), mb(budget), mb(total - budget))
+    }
+}
+
+// One debug line per output of a file, with the time the whole file took
+fn log_file_results(path: &Path, results: &[ProcessResult], started: Instant) {
+    for result in results {
+        if result.status == ResultStatus::Skipped {
+            debug!("{}: {}", path.display(), result.message);
+        } else if result.success {
+            debug!(
+                "{}: wrote {} with {} ({} → {} bytes) in {:.0?}",
+                path.display(),
+                result.filename,
+                result.algorithm_used,
+                result.original_size,
+                result.new_size,
+                started.elapsed()
+            );
+        } else {
+            debug!("{}: failed after {:.0?}: {}", path.display(), started.elapsed(), result.message);
+        }
+    }
+}
+
+// Pipeline step a file failed in, named at the start of its result message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Read,
+    Open,
+    Animation,
+    Encode,
+    Write,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read => write!(f, "Read"),
+            Self::Open => write!(f, "Open"),
+            Self::Animation => write!(f, "Animation"),
+            Self::Encode => write!(f, "Encode"),
+            Self::Write => write!(f, "Write"),
+        }
+    }
+}
+
+// "Encode failed: outer: cause: root cause"
+pub fn stage_error(stage: Stage, error: &dyn std::error::Error) -> String {
+    format!("{} failed: {}", stage, error_chain(error))
+}
+
+// The error followed by each of its sources, so decoder failures keep their root cause
+pub fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        let cause_text = cause.to_string();
+        // Many wrappers already repeat their source in their own message
+        if !chain.contains(&cause_text) {
+            chain.push_str(": ");
+            chain.push_str(&cause_text);
+        }
+        source = cause.source();
+    }
+    chain
+}
+
+struct InternalResult {
+    original_size: u64,
+    new_size: u64,
+    success: bool,
+    status: ResultStatus,
+    message: String,
+    algorithm_used: CompressionAlgorithm,
+    compression_ratio: f32,
+    output_path: Option<PathBuf>,
+    quality_score: Option<quality::QualityScore>,
+    final_quality: Option<u8>,
+}
+
+impl InternalResult {
+    fn failed(original_size: u64, message: String, algorithm_used: CompressionAlgorithm) -> Self {
+        Self {
+            original_size,
+            new_size: 0,
+            success: false,
+            status: ResultStatus::Processed,
+            message,
+            algorithm_used,
+            compression_ratio: 0.0,
+            output_path: None,
+            quality_score: None,
+            final_quality: None,
+        }
+    }
+    
+    fn from_simple(result: simple::InternalResult) -> Self {
+        Self {
+            original_size: result.original_size,
+            new_size: result.new_size,
+            success: result.success,
+            status: result.status,
+            message: result.message,
+            algorithm_used: CompressionAlgorithm::Simple,
+            compression_ratio: if result.original_size > 0 {
+                result.new_size as f32 / result.original_size as f32
+            } else {
+                0.0
+            },
+            output_path: result.output_path,
+            quality_score: None,
+            final_quality: None,
+        }
+    }
+    
+    fn into_process_result(self, filename: String, source_path: &Path) -> ProcessResult {
+        ProcessResult {
+            filename,
+            source_path: source_path.to_path_buf(),
+            original_size: self.original_size,
+            new_size: self.new_size,
+            success: self.success,
+            status: self.status,
+            message: self.message,
+            algorithm_used: self.algorithm_used,
+            compression_ratio: self.compression_ratio,
+            output_path: self.output_path,
+            quality_score: self.quality_score,
+            final_quality: self.final_quality,
+        }
+    }
+}
+
+fn process_single_image_advanced(
+    input_path: &Path,
+    target_bytes: Option<u64>,
+    dimensions: Option<(u32, u32)>,
+    maintain_ratio: bool,
+    compression_options: &CompressionOptions,
+    options: &ProcessingOptions,
+    compressor: &SmartCompressor,
+) -> InternalResult {
+    let ruled = apply_extension_rule(input_path, compression_options, options);
+    let compression_options = ruled.as_ref();
+    
+    // The target size is per run, everything else comes from the template
+    let algorithm = compression_options.algorithm;
+    let quality = compression_options.quality.unwrap_or(85);
+    
+    let original_size = match fs::metadata(input_path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            return InternalResult {
+                original_size: 0,
+                new_size: 0,
+                success: false,
+                status: ResultStatus::Processed,
+                message: stage_error(Stage::Read, &e),
+                algorithm_used: algorithm,
+                compression_ratio: 0.0,
+                output_path: None,
+                quality_score: None,
+                final_quality: None,
+            };
+        }
+    };
+    
+    if algorithm == CompressionAlgorithm::Simple {
+        let auto_scale = false;
+        let result = simple::process_single_image(
+            input_path,
+            target_bytes,
+            dimensions,
+            maintain_ratio,
+            auto_scale,
+            options,
+        );
+        
+        return InternalResult::from_simple(result);
+    }
+    
+    // Re-encoding a file that is already in the requested format only adds
+    // generation loss when nothing else about it changes, so copy it verbatim
+    if options.convert_only
+        && dimensions.is_none()
+        && compression_options.dpi.is_none()
+        && target_bytes.is_none()
+        && !options.changes_pixels()
+        // Auto-orient only turns sources whose tag says they aren't upright
+        && !(options.auto_orient && pipeline::read_orientation(input_path).is_some_and(|orientation| orientation > 1))
+        && is_same_format(input_path, algorithm)
+    {
+        let extension = sniff::true_extension(input_path);
+        let copied = fs::read(input_path)
+            .map_err(|e| stage_error(Stage::Read, &e))
+            .and_then(|data| write_output(input_path, copied_format(input_path, algorithm), &extension, &data, options));
+        
+        return match copied {
+            Ok(output_path) => InternalResult {
+                original_size,
+                new_size: original_size,
+                success: true,
+                status: ResultStatus::Processed,
+                message: String::from("Copied (no re-encode needed)"),
+                algorithm_used: algorithm,
+                compression_ratio: 1.0,
+                output_path: Some(output_path),
+                quality_score: None,
+                final_quality: None,
+            },
+            Err(message) => InternalResult {
+                original_size,
+                new_size: 0,
+                success: false,
+                status: ResultStatus::Processed,
+                message,
+                algorithm_used: algorithm,
+                compression_ratio: 0.0,
+                output_path: None,
+                quality_score: None,
+                final_quality: None,
+            },
+        };
+    }
+    
+    // Animations aren't redacted or watermarked frame by frame, so with either set they
+    // take the single-frame path and come out as their first frame
+    if options.redactions.is_empty() && options.watermark.is_none() {
+        if let Some(result) = process_animation(
+            input_path,
+            original_size,
+            dimensions,
+            maintain_ratio,
+            algorithm,
+            quality,
+            options,
+        ) {
+            return result;
+        }
+    }
+    
+    let mut img = match open_image(input_path, options.max_megapixels) {
+        Ok(img) => img,
+        Err(message) => {
+            return InternalResult {
+                original_size,
+                new_size: 0,
+                success: false,
+                status: ResultStatus::Processed,
+                message,
+                algorithm_used: algorithm,
+                compression_ratio: 0.0,
+                output_path: None,
+                quality_score: None,
+                final_quality: None,
+            };
+        }
+    };
+    
+    if let Some(message) = near_duplicate_message(input_path, &img, options) {
+        return InternalResult {
+            original_size,
+            new_size: 0,
+            success: true,
+            status: ResultStatus::Skipped,
+            message,
+            algorithm_used: algorithm,
+            compression_ratio: 0.0,
+            output_path: None,
+            quality_score: None,
+            final_quality: None,
+        };
+    }
+    
+    let mut notes = Vec::new();
+    notes.extend(cmyk::conversion_note(input_path));
+    
+    img = pipeline::apply(img, input_path, dimensions, maintain_ratio, options, &mut notes);
+    let target_bytes = pixel_target(target_bytes, &img, options);
+    
+    let compression_options = CompressionOptions {
+        target_size: target_bytes,
+        ..compression_options.clone()
+    };
+    
+    let reduce_16bit_png = compression_options.reduce_16bit_png;
+    let keep_bit_depth = compression_options.keep_bit_depth;
+    let embed_thumbnail = compression_options.embed_thumbnail;
+    let jpeg_background = compression_options.jpeg_background;
+    let oxipng_level = compression_options.oxipng_level.min(6);
+    let adaptive = compression_options.adaptive_quality && compression_options.target_size.is_none();
+    let jpeg_encoder = compression_options.jpeg_encoder;
+    let jpeg_mode = match jpeg_encoder {
+        JpegEncoder::MozJpeg if jpeg_encoder.is_available() => compression_options.jpeg_mode,
+        _ => JpegMode::Baseline,
+    };
+    let compression_result = match compressor.compress(&img, compression_options) {
+        Ok(result) => result,
+        Err(e) => {
+            return InternalResult {
+                original_size,
+                new_size: 0,
+                success: false,
+                status: ResultStatus::Processed,
+                message: stage_error(Stage::Encode, &*e),
+                algorithm_used: algorithm,
+                compression_ratio: 0.0,
+                output_path: None,
+                quality_score: None,
+                final_quality: None,
+            };
+        }
+    };
+    
+    if algorithm == CompressionAlgorithm::AutoBest {
+        notes.push(format!("Smallest: {}", compression_result.algorithm_used));
+    }
+    
+    if algorithm == CompressionAlgorithm::ByAlpha {
+        let side = if has_transparency(&img) {
+            "Transparent"
+        } else {
+            "Opaque"
+        };
+        notes.push(format!("{}: {}", side, compression_result.algorithm_used));
+    }
+    
+    if !algorithm.is_available() {
+        notes.push(format!("{} isn't built in; used {}", algorithm, compression_result.algorithm_used));
+    }
+    
+    if compression_result.format == image::ImageFormat::Jpeg && !jpeg_encoder.is_available() {
+        notes.push(String::from("MozJPEG isn't built in; used image-rs"));
+    }
+    
+    if matches!(compression_result.algorithm_used, CompressionAlgorithm::OxiPng | CompressionAlgorithm::PngQuant) {
+        notes.push(format!("OxiPNG level {}", oxipng_level));
+    }
+    
+    if compression_result.format == image::ImageFormat::Jpeg {
+        notes.push(format!("{} JPEG", jpeg_mode));
+    }
+    
+    if let Some(quality) = compression_result.final_quality
+        .filter(|_| adaptive && compression_result.algorithm_used.adapts_quality())
+    {
+        notes.push(format!("Adaptive quality {}", quality));
+    }
+    
+    notes.extend(search_note(&compression_result, target_bytes));
+    
+    if reduce_16bit_png && compression_result.format == image::ImageFormat::Png && compression::is_16_bit(&img) {
+        notes.push(String::from("16-bit → 8-bit"));
+    }
+    
+    if keep_bit_depth && compression_result.format == image::ImageFormat::Avif && compression::is_16_bit(&img) {
+        notes.push(String::from("10-bit from 16-bit source"));
+    }
+    
+    if embed_thumbnail && !thumbnail::supports(compression_result.format) {
+        notes.push(format!(
+            "No thumbnail embedded ({} has no thumbnail slot)",
+            compression_result.algorithm_used.file_extension().to_uppercase()
+        ));
+    }
+    
+    let quality_score = if options.measure_quality {
+        let score = quality::measure(&img, &compression_result.data, jpeg_background);
+        if score.is_none() {
+            notes.push(format!(
+                "Quality not measured ({} can't be decoded here)",
+                compression_result.algorithm_used.file_extension().to_uppercase()
+            ));
+        }
+        score
+    } else {
+        None
+    };
+    
+    let output_path = match write_output(
+        input_path,
+        compression_result.algorithm_used,
+        compression_result.algorithm_used.file_extension(),
+        &compression_result.data,
+        options,
+    ) {
+        Ok(output_path) => output_path,
+        Err(message) => {
+            return InternalResult {
+                original_size,
+                new_size: 0,
+                success: false,
+                status: ResultStatus::Processed,
+                message,
+                algorithm_used: algorithm,
+                compression_ratio: 0.0,
+                output_path: None,
+                quality_score: None,
+                final_quality: None,
+            };
+        }
+    };
+    notes.extend(write_sidecar(input_path, &output_path, &compression_result, &img, original_size, options));
+    
+    InternalResult {
+        original_size,
+        new_size: compression_result.data.len() as u64,
+        success: true,
+        status: ResultStatus::Processed,
+        message: notes.join(", "),
+        algorithm_used: compression_result.algorithm_used,
+        compression_ratio: compression_result.compression_ratio,
+        output_path: Some(output_path),
+        quality_score,
+        final_quality: compression_result.final_quality,
+    }
+}
+
+// Decodes the source once and writes one "{name}-{width}w.{ext}" output per requested
+// width, each compressed separately. Results are paired with the variant's file name.
+fn process_responsive_set(
+    input_path: &Path,
+    target_bytes: Option<u64>,
+    compression_options: &CompressionOptions,
+    options: &ProcessingOptions,
+    compressor: &SmartCompressor,
+) -> Vec<(String, InternalResult)> {
+    let ruled = apply_extension_rule(input_path, compression_options, options);
+    let compression_options = ruled.as_ref();
+    let algorithm = compression_options.algorithm;
+    let source_name = input_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    
+    let original_size = match fs::metadata(input_path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            return vec![(source_name, InternalResult::failed(0, stage_error(Stage::Read, &e), algorithm))];
+        }
+    };
+    
+    let mut img = match open_image(input_path, options.max_megapixels) {
+        Ok(img) => img,
+        Err(message) => return vec![(source_name, InternalResult::failed(original_size, message, algorithm))],
+    };
+    
+    if let Some(message) = near_duplicate_message(input_path, &img, options) {
+        let skipped = InternalResult {
+            success: true,
+            status: ResultStatus::Skipped,
+            ..InternalResult::failed(original_size, message, algorithm)
+        };
+        return vec![(source_name, skipped)];
+    }
+    
+    // The steps run on the source with no size set; each width is resized from the
+    // result, sharpened afterwards if the pipeline sharpens after resizing, and then
+    // watermarked, so the mark is the same size on every width
+    let mut notes = Vec::new();
+    img = pipeline::apply_steps(img, input_path, None, true, options, &mut notes);
+    
+    // A width past the source's is capped to it rather than upscaled, so the set
+    // tops out at one full-size variant
+    let mut widths = Vec::new();
+    for width in options.responsive_widths.iter().map(|&width| width.min(img.width())) {
+        if !widths.contains(&width) {
+            widths.push(width);
+        }
+    }
+    
+    let stem = input_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    widths.into_iter().map(|width| {
+        let resized = if options.linear_resize {
+            let (width, height) = fit_within((img.width(), img.height()), (width, u32::MAX));
+            adjust::resize_linear(&img, width, height)
+        } else {
+            img.resize(width, u32::MAX, image::imageops::FilterType::Lanczos3)
+        };
+        let resized = if options.pipeline.sharpens_after_resize() {
+            sharpen_if_downscaled(resized, (img.width(), img.height()), options)
+        } else {
+            resized
+        };
+        let resized = match &options.watermark {
+            Some(watermark) => watermark::apply(resized, watermark),
+            None => resized,
+        };
+        let target_bytes = pixel_target(target_bytes, &resized, options);
+        let variant_options = CompressionOptions {
+            target_size: target_bytes,
+            ..compression_options.clone()
+        };
+        
+        let compression_result = match compressor.compress(&resized, variant_options) {
+            Ok(result) => result,
+            Err(e) => {
+                let name = match options.width_naming {
+                    WidthNaming::Suffix => format!("{}-{}w", stem, width),
+                    WidthNaming::Folder => format!("{}/{}", width, stem),
+                };
+                return (name, InternalResult::failed(original_size, stage_error(Stage::Encode, &*e), algorithm));
             }
         };
+        
+        let extension = compression_result.algorithm_used.file_extension();
+        let (file_name, written) = match options.width_naming {
+            WidthNaming::Suffix => {
+                let file_name = format!("{}-{}w.{}", stem, width, extension);
+                let written = write_output_named(input_path, &file_name, &compression_result.data, options);
+                (file_name, written)
+            }
+            WidthNaming::Folder => {
+                let folder = width.to_string();
+                let file_name = format!("{}.{}", stem, extension);
+                let written = write_output_in_folder(input_path, &folder, &file_name, &compression_result.data, options);
+                (format!("{}/{}", folder, file_name), written)
+            }
+        };
+        let output_path = match written {
+            Ok(output_path) => output_path,
+            Err(message) => return (file_name, InternalResult::failed(original_size, message, algorithm)),
+        };
+        let sidecar_note = write_sidecar(input_path, &output_path, &compression_result, &resized, original_size, options);
+        
+        let result = InternalResult {
+            original_size,
+            new_size: compression_result.data.len() as u64,
+            success: true,
+            status: ResultStatus::Processed,
+            message: join_notes(&notes, search_note(&compression_result, target_bytes).into_iter().chain(sidecar_note)),
+            algorithm_used: compression_result.algorithm_used,
+            compression_ratio: compression_result.compression_ratio,
+            output_path: Some(output_path),
+            quality_score: None,
+            final_quality: compression_result.final_quality,
+        };
+        (file_name, result)
+    }).collect()
+}
 
-        // Size parameters card
-        let parameters_card = container(
-            column![
-                icon_text("", "Size Parameters", 14, 14),
-                
-                Space::with_height(16),
-                
-                row![
-                    text("Target Size")
-                        .size(14)
-                        .font(BODY_FONT)
-                        .style(Color::from_rgb(0.3, 0.3, 0.4))
-                        .width(96),
-                    text_input("Optional (KB)", &self.target_size)
-                        .on_input(Message::TargetSizeChanged)
-                        .width(Length::Fixed(188.0))
-                        .padding([8, 12])
-                        .size(14),
-                ].spacing(12).align_items(iced::Alignment::Center),
-                
-                Space::with_height(12),
-                
-                row![
-                    text("Dimensions")
-                        .size(14)
-                        .font(BODY_FONT)
-                        .style(Color::from_rgb(0.3, 0.3, 0.4))
-                        .width(100),
-                    text_input("Width", &self.width)
-                        .on_input(Message::WidthChanged)
-                        .width(Length::Fixed(80.0))
-                        .padding([8, 12])
-                        .size(14),
-                    text("×")
-                        .size(16)
-                        .font(BODY_FONT)
-                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
-                    text_input("Height", &self.height)
-                        .on_input(Message::HeightChanged)
-                        .width(Length::Fixed(80.0))
-                        .padding([8, 12])
-                        .size(14),
-                    text("px")
-                        .size(14)
-                        .font(BODY_FONT)
-                        .style(Color::from_rgb(0.5, 0.5, 0.6)),
-                ].spacing(8).align_items(iced::Alignment::Center),
-                
-                Space::with_height(12),
-                
-                styled_checkbox("Maintain aspect ratio", self.maintain_ratio, Message::MaintainRatioToggled),
-            ].spacing(0)
-        )
-        .width(Length::Fill)
-        .padding(12)
-        .style(theme::Container::Custom(Box::new(CardContainer)));
-
-        // Process button and progress
-        let process_section = column![
-            if self.is_processing {
-                styled_button("Processing...", Message::Process, ButtonStyle::Disabled)
-            } else if self.selected_path.is_some() && 
-                     (!self.target_size.is_empty() || !self.width.is_empty() || !self.height.is_empty()) {
-                styled_button("Process Images", Message::Process, ButtonStyle::Action)
-            } else {
-                styled_button("Process Images", Message::Process, ButtonStyle::Disabled)
-            },
-            
-            if self.is_processing || self.progress > 0.0 {
-                column![
-                    Space::with_height(16),
-                    container(
-                        progress_bar(0.0..=1.0, self.progress)
-                            .height(Length::Fixed(8.0))
-                    )
-                    .style(theme::Container::Custom(Box::new(ProgressContainer))),
-                    Space::with_height(8),
-                    text(&self.status_message)
-                        .size(13)
-                        .font(BODY_FONT)
-                        .style(SUCCESS_COLOR),
-                ].spacing(0)
-            } else {
-                column![]
+// Writes every frame of an animated GIF or WebP as "{name}-{index}.{ext}", each
+// resized and compressed like a still image. None for single-frame sources, which
+// take the regular path.
+fn process_frames(
+    input_path: &Path,
+    settings: &BatchSettings,
+    compressor: &SmartCompressor,
+) -> Option<Vec<(String, InternalResult)>> {
+    let BatchSettings { dimensions, maintain_ratio, compression_options, options, .. } = settings;
+    let ruled = apply_extension_rule(input_path, compression_options, options);
+    let compression_options = ruled.as_ref();
+    let algorithm = compression_options.algorithm;
+    let data = fs::read(input_path).ok()?;
+    let decoded = match sniff::detect_format(input_path) {
+        Some(image::ImageFormat::Gif) => animation::decode_gif_animation(&data),
+        Some(image::ImageFormat::WebP) if animation::is_animated_webp(&data) => animation::decode_webp_animation(&data),
+        _ => return None,
+    };
+    
+    let original_size = data.len() as u64;
+    let source_name = input_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let frames = match decoded {
+        Ok(animation) if animation.frames.len() > 1 => animation.frames,
+        Ok(_) => return None,
+        Err(e) => {
+            return Some(vec![(source_name, InternalResult::failed(original_size, stage_error(Stage::Animation, &*e), algorithm))]);
+        }
+    };
+    
+    // The file is compared by its first frame, so its other frames don't match it
+    let first_frame = DynamicImage::ImageRgba8(frames[0].image.clone());
+    if let Some(message) = near_duplicate_message(input_path, &first_frame, options) {
+        let skipped = InternalResult {
+            success: true,
+            status: ResultStatus::Skipped,
+            ..InternalResult::failed(original_size, message, algorithm)
+        };
+        return Some(vec![(source_name, skipped)]);
+    }
+    
+    let frame_count = frames.len();
+    // The file's budget share is split between its frames; a plain target applies to each
+    let target_bytes = settings.budget_targets.get(input_path)
+        .map(|share| (share / frame_count as u64).max(1))
+        .or(settings.target_size);
+    Some(frames.into_iter().enumerate().map(|(index, frame)| {
+        // The source's size is counted once, on the first frame
+        let original_size = if index == 0 { original_size } else { 0 };
+        let mut notes = vec![format!("Frame {} of {}", index + 1, frame_count)];
+        let img = pipeline::apply(DynamicImage::ImageRgba8(frame.image), input_path, *dimensions, *maintain_ratio, options, &mut notes);
+        let target_bytes = pixel_target(target_bytes, &img, options);
+        let frame_options = CompressionOptions {
+            target_size: target_bytes,
+            ..compression_options.clone()
+        };
+        
+        let compression_result = match compressor.compress(&img, frame_options) {
+            Ok(result) => result,
+            Err(e) => {
+                let name = format!("{}-{}", output_stem(input_path, algorithm, options), index);
+                return (name, InternalResult::failed(original_size, stage_error(Stage::Encode, &*e), algorithm));
             }
-        ].spacing(0);
+        };
+        
+        let file_name = format!(
+            "{}-{}.{}",
+            output_stem(input_path, compression_result.algorithm_used, options),
+            index,
+            compression_result.algorithm_used.file_extension()
+        );
+        let output_path = match write_output_named(input_path, &file_name, &compression_result.data, options) {
+            Ok(output_path) => output_path,
+            Err(message) => return (file_name, InternalResult::failed(original_size, message, algorithm)),
+        };
+        let sidecar_note = write_sidecar(input_path, &output_path, &compression_result, &img, original_size, options);
+        
+        let result = InternalResult {
+            original_size,
+            new_size: compression_result.data.len() as u64,
+            success: true,
+            status: ResultStatus::Processed,
+            message: join_notes(&notes, search_note(&compression_result, target_bytes).into_iter().chain(sidecar_note)),
+            algorithm_used: compression_result.algorithm_used,
+            compression_ratio: compression_result.compression_ratio,
+            output_path: Some(output_path),
+            quality_score: None,
+            final_quality: compression_result.final_quality,
+        };
+        (file_name, result)
+    }).collect())
+}
 
-        // Results section
-        let results_section = if !self.results.is_empty() {
-            let results_list: Vec<Element<Message>> = self.results.iter().map(|result| {
-                let (icon, color) = if result.success {
-                    ("", SUCCESS_COLOR)
-                } else {
-                    ("", ERROR_COLOR)
-                };
-                
-                container(
-                    row![
-                      
-                        text(&result.filename)
-                            .size(13)
-                            .font(BODY_FONT)
-                            .style(Color::from_rgb(0.2, 0.2, 0.3))
-                            .width(Length::Fill),
-                        if result.success {
-                            text(format!("{} → {} KB", 
-                                result.original_size / 1024, 
-                                result.new_size / 1024
-                            ))
-                            .size(13)
-                            .font(BODY_FONT)
-                            .style(Color::from_rgb(0.4, 0.4, 0.5))
-                        } else {
-                            text(&result.message)
-                                .size(13)
-                                .font(BODY_FONT)
-                                .style(ERROR_COLOR)
-                        }
-                    ].spacing(12).align_items(iced::Alignment::Center)
-                )
-                .padding([8, 12])
-                .style(theme::Container::Custom(Box::new(ResultItemContainer {
-                    success: result.success,
-                })))
-                .into()
-            }).collect();
+// Scales, then applies the optional post-downscale sharpen
+pub fn resize_image(
+    img: DynamicImage,
+    dimensions: Option<(u32, u32)>,
+    maintain_ratio: bool,
+    options: &ProcessingOptions,
+    notes: &mut Vec<String>,
+) -> DynamicImage {
+    let source = (img.width(), img.height());
+    let resized = scale_image(img, dimensions, maintain_ratio, options, notes);
+    sharpen_if_downscaled(resized, source, options)
+}
 
-            container(
-                column![
-                    icon_text("", "Results", 14, 14),
-                    Space::with_height(16),
-                    container(
-                        scrollable(
-                            column(results_list).spacing(4)
-                        ).height(Length::Fixed(150.0))
-                    )
-                    .style(theme::Container::Custom(Box::new(SubtleContainer)))
-                    .padding(4),
-                    Space::with_height(16),
-                    row![
-                        styled_button("Open Output", Message::OpenOutputFolder, ButtonStyle::Secondary),
-                        styled_button("Clear", Message::ClearResults, ButtonStyle::Subtle),
-                    ].spacing(12)
-                ].spacing(0)
-            )
-            .width(Length::Fill)
-            .padding(8)
-            .style(theme::Container::Custom(Box::new(CardContainer)))
-        } else {
-            container(column![])
-        };
+// Applies the edge constraint, or else the width/height box, then the megapixel cap
+pub fn scale_image(
+    img: DynamicImage,
+    dimensions: Option<(u32, u32)>,
+    maintain_ratio: bool,
+    options: &ProcessingOptions,
+    notes: &mut Vec<String>,
+) -> DynamicImage {
+    let img = match options.matte {
+        Some(background) if img.color().has_alpha() => DynamicImage::ImageRgb8(compression::flatten_alpha(&img, background)),
+        _ => img,
+    };
+    let source = (img.width(), img.height());
+    let dimensions = print_dimensions(dimensions, options);
+    debug!(
+        "resize {}×{}: edge {:?}, dimensions {:?}, linear {}",
+        source.0, source.1, options.edge_constraint, dimensions, options.linear_resize
+    );
+    let mut resized = match (options.edge_constraint, dimensions) {
+        (Some((edge, length)), _) => match edge_fit(source, edge, length) {
+            Some((width, height)) => resample(img, width, height, options),
+            None => img,
+        },
+        (None, Some((width, height))) if options.crop_to_fill => {
+            let (scaled_width, scaled_height) = cover(source, (width, height));
+            let scaled = resample(img, scaled_width, scaled_height, options);
+            notes.push(format!("Cropped to {}×{}", width, height));
+            scaled.crop_imm((scaled_width - width) / 2, (scaled_height - height) / 2, width, height)
+        }
+        (None, Some((width, height))) if maintain_ratio && options.linear_resize => {
+            let (width, height) = fit_within(source, (width, height));
+            adjust::resize_linear(&img, width, height)
+        }
+        (None, Some((width, height))) if maintain_ratio => {
+            img.resize(width, height, image::imageops::FilterType::Lanczos3)
+        }
+        (None, Some((width, height))) => resample(img, width, height, options),
+        (None, None) => img,
+    };
+    
+    if let Some((width, height)) = options.downscale_megapixels
+        .and_then(|limit| megapixel_fit((resized.width(), resized.height()), limit))
+    {
+        resized = resample(resized, width, height, options);
+        notes.push(format!("Downscaled to {}×{}", width, height));
+    }
+    
+    resized
+}
 
-        // Main layout with scrollable content
-        let content = scrollable(
-            column![
-                header,
-                container(
-                    column![
-                        file_selection_card,
-                        mode_selection_card,
-                        compression_settings,
-                        parameters_card,
-                        container(process_section)
-                            .width(Length::Fill)
-                            .padding([0, 20]),
-                        results_section,
-                        Space::with_height(20),
-                    ].spacing(8)
-                )
-                .max_width(680)
-                .center_x()
-                .padding([6, 14, 6, 6])
-            ].spacing(0)
-        );
+// The print size in pixels when one is set, otherwise the width/height box
+fn print_dimensions(dimensions: Option<(u32, u32)>, options: &ProcessingOptions) -> Option<(u32, u32)> {
+    options.physical_size.map(|size| size.pixels()).or(dimensions)
+}
 
-        container(content)
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .style(theme::Container::Custom(Box::new(BackgroundContainer)))
-            .into()
+// Lanczos3 to exactly width×height, in linear light when the option is on
+fn resample(img: DynamicImage, width: u32, height: u32, options: &ProcessingOptions) -> DynamicImage {
+    if options.linear_resize {
+        adjust::resize_linear(&img, width, height)
+    } else {
+        img.resize_exact(width, height, image::imageops::FilterType::Lanczos3)
+    }
+}
+
+// Largest size with the source's aspect ratio that fits in the box, like DynamicImage::resize
+fn fit_within(source: (u32, u32), bounds: (u32, u32)) -> (u32, u32) {
+    let (width, height) = source;
+    let scale = (bounds.0 as f64 / width as f64).min(bounds.1 as f64 / height as f64);
+    (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    )
+}
+
+// Smallest size with the source's aspect ratio that covers the whole box
+fn cover(source: (u32, u32), bounds: (u32, u32)) -> (u32, u32) {
+    let (width, height) = source;
+    let scale = (bounds.0 as f64 / width as f64).max(bounds.1 as f64 / height as f64);
+    (
+        ((width as f64 * scale).round() as u32).max(bounds.0),
+        ((height as f64 * scale).round() as u32).max(bounds.1),
+    )
+}
+
+// Dimensions with the same aspect ratio and at most `max_megapixels` pixels, or
+// None when the image is already within the limit
+fn megapixel_fit(source: (u32, u32), max_megapixels: f32) -> Option<(u32, u32)> {
+    let (width, height) = source;
+    let pixels = width as f64 * height as f64;
+    let limit = max_megapixels as f64 * 1_000_000.0;
+    if pixels <= limit {
+        return None;
+    }
+    
+    // Rounding down keeps the result under the limit
+    let scale = (limit / pixels).sqrt();
+    Some((
+        ((width as f64 * scale).floor() as u32).max(1),
+        ((height as f64 * scale).floor() as u32).max(1),
+    ))
+}
+
+// Scales so the chosen edge equals `length`, keeping the aspect ratio. None when
+// that edge is already no longer than `length`; sources are never upscaled.
+fn edge_fit(source: (u32, u32), edge: Edge, length: u32) -> Option<(u32, u32)> {
+    let (width, height) = source;
+    let current = match edge {
+        Edge::Longest => width.max(height),
+        Edge::Shortest => width.min(height),
+    };
+    if length == 0 || current <= length {
+        return None;
+    }
+    
+    let scale = length as f64 / current as f64;
+    Some((
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    ))
+}
+
+// Decodes once and writes the modern format plus a fallback for browsers without
+// it: PNG when the image has transparency, JPEG otherwise. Both share the stem
+// so they can sit side by side in a <picture> element.
+fn process_dual_export(
+    input_path: &Path,
+    target_bytes: Option<u64>,
+    dimensions: Option<(u32, u32)>,
+    maintain_ratio: bool,
+    compression_options: &CompressionOptions,
+    options: &ProcessingOptions,
+    compressor: &SmartCompressor,
+) -> Vec<(String, InternalResult)> {
+    let ruled = apply_extension_rule(input_path, compression_options, options);
+    let compression_options = ruled.as_ref();
+    let algorithm = compression_options.algorithm;
+    let source_name = input_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    
+    let original_size = match fs::metadata(input_path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            return vec![(source_name, InternalResult::failed(0, stage_error(Stage::Read, &e), algorithm))];
+        }
+    };
+    
+    let mut img = match open_image(input_path, options.max_megapixels) {
+        Ok(img) => img,
+        Err(message) => return vec![(source_name, InternalResult::failed(original_size, message, algorithm))],
+    };
+    
+    if let Some(message) = near_duplicate_message(input_path, &img, options) {
+        let skipped = InternalResult {
+            success: true,
+            status: ResultStatus::Skipped,
+            ..InternalResult::failed(original_size, message, algorithm)
+        };
+        return vec![(source_name, skipped)];
     }
+    
+    let mut notes = Vec::new();
+    img = pipeline::apply(img, input_path, dimensions, maintain_ratio, options, &mut notes);
+    let target_bytes = pixel_target(target_bytes, &img, options);
+    
+    let transparent = img.color().has_alpha() && img.to_rgba8().pixels().any(|p| p[3] < 255);
+    let fallback = if transparent {
+        CompressionAlgorithm::OxiPng
+    } else {
+        CompressionAlgorithm::Jpeg
+    };
+    
+    let variants = [
+        (algorithm, compression_options.quality),
+        (fallback, Some(fallback.recommended_quality())),
+    ];
+    variants.into_iter().map(|(variant, quality)| {
+        let variant_options = CompressionOptions {
+            algorithm: variant,
+            quality,
+            target_size: target_bytes,
+            ..compression_options.clone()
+        };
+        
+        let compression_result = match compressor.compress(&img, variant_options) {
+            Ok(result) => result,
+            Err(e) => {
+                let name = format!("{}.{}", output_stem(input_path, variant, options), variant.file_extension());
+                return (name, InternalResult::failed(original_size, stage_error(Stage::Encode, &*e), variant));
+            }
+        };
+        
+        let extension = compression_result.algorithm_used.file_extension();
+        let file_name = format!("{}.{}", output_stem(input_path, compression_result.algorithm_used, options), extension);
+        let output_path = match write_output(input_path, compression_result.algorithm_used, extension, &compression_result.data, options) {
+            Ok(output_path) => output_path,
+            Err(message) => return (file_name, InternalResult::failed(original_size, message, variant)),
+        };
+        let sidecar_note = write_sidecar(input_path, &output_path, &compression_result, &img, original_size, options);
+        
+        let result = InternalResult {
+            original_size,
+            new_size: compression_result.data.len() as u64,
+            success: true,
+            status: ResultStatus::Processed,
+            message: join_notes(&notes, search_note(&compression_result, target_bytes).into_iter().chain(sidecar_note)),
+            algorithm_used: compression_result.algorithm_used,
+            compression_ratio: compression_result.compression_ratio,
+            output_path: Some(output_path),
+            quality_score: None,
+            final_quality: compression_result.final_quality,
+        };
+        (file_name, result)
+    }).collect()
+}
 
-    fn theme(&self) -> Theme {
-        Theme::Light
+// Writes "{output}.json" beside the output when sidecars are on. The output itself is
+// already written, so a failure only becomes a note on its result.
+fn write_sidecar(
+    input_path: &Path,
+    output_path: &Path,
+    result: &CompressionResult,
+    image: &DynamicImage,
+    original_size: u64,
+    options: &ProcessingOptions,
+) -> Option<String> {
+    if !options.write_sidecars {
+        return None;
+    }
+    let json = sidecar::Sidecar {
+        source: input_path,
+        output: output_path,
+        result,
+        original_size,
+        width: image.width(),
+        height: image.height(),
     }
+    .to_json();
+    let file_name = format!("{}.json", output_path.file_name().unwrap_or_default().to_string_lossy());
+    store_output(input_path, &output_path.with_file_name(file_name), json.as_bytes(), options)
+        .err()
+        .map(|e| format!("sidecar not written: {}", error_chain(&e)))
 }
 
-// Helper UI functions
-fn icon_text(icon: &str, label: &str, icon_size: u16, text_size: u16) -> Element<'static, Message> {
-    row![
-        //text(icon).size(icon_size),
-        text(label)
-            .size(text_size)
-            .font(HEADING_FONT)
-            .style(Color::from_rgb(0.2, 0.2, 0.3)),
-    ].spacing(8).into()
+// The result message for outputs that share their notes, plus one of their own
+fn join_notes(notes: &[String], extra: impl IntoIterator<Item = String>) -> String {
+    notes.iter().cloned().chain(extra).collect::<Vec<_>>().join(", ")
 }
 
-fn styled_button(label: &str, on_press: Message, style: ButtonStyle) -> Element<'static, Message> {
-    let btn = button(
-        text(label)
-            .size(14)
-            .font(if matches!(style, ButtonStyle::Action) { HEADING_FONT } else { BODY_FONT })
-            .horizontal_alignment(iced::alignment::Horizontal::Center)
-    )
-    .padding([10, 20]);
-    
-    match style {
-        ButtonStyle::Primary => btn.on_press(on_press).style(theme::Button::Primary),
-        ButtonStyle::Secondary => btn.on_press(on_press).style(theme::Button::Secondary),
-        ButtonStyle::Action => btn.on_press(on_press).style(theme::Button::Positive),
-        ButtonStyle::Subtle => btn.on_press(on_press).style(theme::Button::Text),
-        ButtonStyle::Disabled => btn.style(theme::Button::Secondary),
-    }.into()
+// The target for an image about to be encoded: its share of the per-megapixel
+// target when one is set, otherwise the batch's target or the file's budget share
+pub fn pixel_target(target_bytes: Option<u64>, img: &DynamicImage, options: &ProcessingOptions) -> Option<u64> {
+    match options.target_bytes_per_mpx {
+        Some(bytes) => {
+            let megapixels = img.width() as f64 * img.height() as f64 / 1_000_000.0;
+            Some(((megapixels * bytes as f64).ceil() as u64).max(1))
+        }
+        None => target_bytes,
+    }
 }
 
-fn mode_button(title: &str, subtitle: &str, mode: CompressionMode, current: CompressionMode) -> Element<'static, Message> {
-    let is_selected = mode == current;
-    
-    button(
-        column![
-            text(title)
-                .size(12)
-                .font(HEADING_FONT)
-                .style(if is_selected { PRIMARY_COLOR } else { Color::from_rgb(0.4, 0.4, 0.5) }),
-            text(subtitle)
-                .size(8)
-                .font(LIGHT_FONT)
-                .style(if is_selected { PRIMARY_COLOR } else { Color::from_rgb(0.6, 0.6, 0.5) }),
-        ].spacing(2).align_items(iced::Alignment::Center)
-    )
-    .on_press(Message::ModeChanged(mode))
-    .padding([8, 16])
-    .style(if is_selected {
-        theme::Button::Primary
+// Flags an output whose target-size search hit the probe cap, with how far over it landed
+fn search_note(result: &CompressionResult, target_bytes: Option<u64>) -> Option<String> {
+    if !result.search_capped {
+        return None;
+    }
+    let over = target_bytes.map_or(0, |target| (result.data.len() as u64).saturating_sub(target));
+    Some(if over > 0 {
+        format!("Size search capped, {} KB over target", over.div_ceil(1024))
     } else {
-        theme::Button::Secondary
+        String::from("Size search capped")
     })
-    .into()
 }
 
-fn styled_checkbox(label: &str, is_checked: bool, on_toggle: impl Fn(bool) -> Message + 'static) -> Element<'static, Message> {
-    checkbox(label, is_checked, on_toggle)
-        .size(14)
-        .spacing(10)
-        .text_size(14)
-        .into()
+// A success that leaves the file alone when it's below the size threshold
+fn under_threshold(input_path: &Path, algorithm: CompressionAlgorithm, options: &ProcessingOptions) -> Option<InternalResult> {
+    let threshold = options.min_size_to_process?;
+    let original_size = fs::metadata(input_path).ok()?.len();
+    (original_size < threshold).then(|| InternalResult {
+        original_size,
+        new_size: original_size,
+        success: true,
+        status: ResultStatus::Skipped,
+        message: String::from("Skipped (under threshold)"),
+        algorithm_used: algorithm,
+        compression_ratio: 1.0,
+        output_path: None,
+        quality_score: None,
+        final_quality: None,
+    })
 }
 
-#[derive(Clone, Copy)]
-enum ButtonStyle {
-    Primary,
-    Secondary,
-    Action,
-    Subtle,
-    Disabled,
+// "Up to date" when an output this run would write already exists and is at least as
+// new as the source. Runs that write several outputs per image, into a ZIP archive
+// or over the source itself always process; one of a dual export's pair existing
+// doesn't mean the other does.
+fn up_to_date(
+    input_path: &Path,
+    algorithm: CompressionAlgorithm,
+    compression_options: &CompressionOptions,
+    options: &ProcessingOptions,
+) -> Option<InternalResult> {
+    if !options.incremental
+        || options.zip_output.is_some()
+        || options.lossless_optimize
+        || options.explode_frames
+        || options.dual_export
+        || !options.responsive_widths.is_empty()
+    {
+        return None;
+    }
+    let metadata = fs::metadata(input_path).ok()?;
+    let source_modified = metadata.modified().ok()?;
+    let output_dir = resolve_output_dir(input_path, options);
+    let (output_path, output_size) = expected_output_names(input_path, algorithm, compression_options, options)
+        .into_iter()
+        .map(|name| output_dir.join(name))
+        .find_map(|path| {
+            let output = fs::metadata(&path).ok()?;
+            (output.modified().ok()? >= source_modified).then_some((path, output.len()))
+        })?;
+    
+    let original_size = metadata.len();
+    Some(InternalResult {
+        original_size,
+        new_size: output_size,
+        success: true,
+        status: ResultStatus::Skipped,
+        message: String::from("Up to date"),
+        algorithm_used: algorithm,
+        compression_ratio: if original_size > 0 { output_size as f32 / original_size as f32 } else { 1.0 },
+        output_path: Some(output_path),
+        quality_score: None,
+        final_quality: None,
+    })
 }
 
-// Custom container styles
-struct BackgroundContainer;
-impl container::StyleSheet for BackgroundContainer {
-    type Style = Theme;
-    
-    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
-        container::Appearance {
-            background: Some(Background::Color(BACKGROUND_COLOR)),
-            ..Default::default()
+// Every file name the input's output could get. The auto modes pick the format per
+// image and Lossless First per size, so each format they can end in is a candidate;
+// simple mode keeps the source's format or writes JPEG or PNG, and convert-only can
+// copy the source as it is.
+fn expected_output_names(
+    input_path: &Path,
+    algorithm: CompressionAlgorithm,
+    compression_options: &CompressionOptions,
+    options: &ProcessingOptions,
+) -> Vec<String> {
+    let algorithm = extension_rule(input_path, options).unwrap_or(algorithm);
+    let candidates = match algorithm {
+        CompressionAlgorithm::Simple => {
+            let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+            return [sniff::true_extension(input_path).as_str(), "jpg", "png"]
+                .into_iter()
+                .map(|extension| {
+                    let format_tag = if options.format_in_name { format!("_{}", extension) } else { String::new() };
+                    format!("{}{}{}.{}", stem, options.suffix, format_tag, extension)
+                })
+                .collect();
         }
+        CompressionAlgorithm::Auto | CompressionAlgorithm::AutoBest => vec![
+            CompressionAlgorithm::Jpeg,
+            CompressionAlgorithm::StandardPng,
+            CompressionAlgorithm::OxiPng,
+            CompressionAlgorithm::OptiPng,
+            CompressionAlgorithm::PngQuant,
+            CompressionAlgorithm::WebPLossy,
+            CompressionAlgorithm::WebPLossless,
+            CompressionAlgorithm::Avif,
+        ],
+        CompressionAlgorithm::ByAlpha => [compression_options.opaque_algorithm, compression_options.transparent_algorithm]
+            .into_iter()
+            .flat_map(|side| match side {
+                CompressionAlgorithm::LosslessFirst => vec![CompressionAlgorithm::WebPLossless, CompressionAlgorithm::WebPLossy],
+                other => vec![other],
+            })
+            .collect(),
+        CompressionAlgorithm::LosslessFirst => vec![CompressionAlgorithm::WebPLossless, CompressionAlgorithm::WebPLossy],
+        other => vec![other],
+    };
+    let copy = options.convert_only.then(|| copied_format(input_path, algorithm));
+    candidates
+        .into_iter()
+        .map(|candidate| candidate.fallback())
+        .chain(copy)
+        .map(|candidate| format!("{}.{}", output_stem(input_path, candidate, options), candidate.file_extension()))
+        .collect()
+}
+
+// "Skipped (near-duplicate of X)" when the batch already saw a close match
+pub fn near_duplicate_message(input_path: &Path, img: &DynamicImage, options: &ProcessingOptions) -> Option<String> {
+    let filter = options.near_duplicates.as_ref()?;
+    let name = input_path.file_name().unwrap_or_default().to_string_lossy();
+    filter.check(&name, img)
+        .map(|original| format!("Skipped (near-duplicate of {})", original))
+}
+
+// Lanczos3 downscales come out slightly soft; upscales and unchanged sizes are left alone
+pub fn sharpen_if_downscaled(image: DynamicImage, source: (u32, u32), options: &ProcessingOptions) -> DynamicImage {
+    let downscaled = image.width() < source.0 || image.height() < source.1;
+    if options.sharpen_amount > 0.0 && downscaled {
+        adjust::unsharp_mask(&image, options.sharpen_radius, options.sharpen_amount)
+    } else {
+        image
     }
 }
 
-struct CardContainer;
-impl container::StyleSheet for CardContainer {
-    type Style = Theme;
+// Any pixel less than fully opaque; images without an alpha channel aren't scanned
+fn has_transparency(img: &DynamicImage) -> bool {
+    img.color().has_alpha() && image::GenericImageView::pixels(img).any(|(_, _, pixel)| pixel[3] < 255)
+}
+
+// Decodes an image, refusing sources above the megapixel limit before any pixel
+// memory is allocated. The header is read first (cheap), and the decoder's own
+// allocation limit is raised or lowered to match so it acts as a backstop.
+pub fn open_image(path: &Path, max_megapixels: Option<f32>) -> Result<DynamicImage, String> {
+    // Unfinished downloads and syncs leave these behind; the decoders would only
+    // report an undetermined format
+    if fs::metadata(path).is_ok_and(|metadata| metadata.len() == 0) {
+        return Err(empty_file_error());
+    }
     
-    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
-        container::Appearance {
-            background: Some(Background::Color(CARD_COLOR)),
-            border_radius: 12.0.into(),
-            border_width: 1.0,
-            border_color: Color::from_rgba(0.0, 0.0, 0.0, 0.05),
-            ..Default::default()
+    // The decoder follows the content, so mislabeled and extensionless files still open
+    let open_reader = || image::io::Reader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| stage_error(Stage::Open, &e));
+    
+    let mut reader = open_reader()?;
+    if let Some(limit) = max_megapixels {
+        let (width, height) = open_reader()?
+            .into_dimensions()
+            .map_err(|e| decode_error(&e))?;
+        let megapixels = width as f64 * height as f64 / 1_000_000.0;
+        if megapixels > limit as f64 {
+            return Err(format!(
+                "{} failed: image too large ({}×{}, {:.1} MP > {} MP limit); set a higher limit",
+                Stage::Open, width, height, megapixels, limit
+            ));
         }
+        
+        // Room for up to 16-bit RGBA at the permitted pixel count
+        let mut limits = image::io::Limits::default();
+        limits.max_alloc = Some((limit as f64 * 1_000_000.0 * 8.0) as u64);
+        reader.limits(limits);
+    }
+    
+    if cmyk::needs_plain_cmyk_decode(path) {
+        return cmyk::decode_plain_cmyk(path).map_err(|e| format!("{} failed: CMYK JPEG: {}", Stage::Open, e));
     }
+    
+    reader.decode().map_err(|e| match e {
+        image::ImageError::Limits(_) => format!("{} failed: image too large to decode ({}); set a higher limit", Stage::Open, e),
+        e => decode_error(&e),
+    })
 }
 
-struct GradientContainer;
-impl container::StyleSheet for GradientContainer {
-    type Style = Theme;
-    
-    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
-        container::Appearance {
-            background: Some(Background::Color(PRIMARY_COLOR)),
-            ..Default::default()
-        }
+pub fn empty_file_error() -> String {
+    format!("{} failed: empty file (0 bytes), it may not have finished downloading", Stage::Open)
+}
+
+// Data ending early or failing to parse is named as such rather than left to the
+// decoder's wording
+fn decode_error(error: &image::ImageError) -> String {
+    let damaged = match error {
+        image::ImageError::IoError(e) => e.kind() == std::io::ErrorKind::UnexpectedEof,
+        image::ImageError::Decoding(_) => true,
+        _ => false,
+    };
+    if damaged {
+        format!("{} failed: corrupt or truncated image ({})", Stage::Open, error_chain(error))
+    } else {
+        stage_error(Stage::Open, error)
     }
 }
 
-struct SubtleContainer;
-impl container::StyleSheet for SubtleContainer {
-    type Style = Theme;
+// Writes encoded data to "{stem}{suffix}.{extension}" in the resolved output
+// directory, backing up the original first when requested
+fn write_output(
+    input_path: &Path,
+    algorithm: CompressionAlgorithm,
+    extension: &str,
+    data: &[u8],
+    options: &ProcessingOptions,
+) -> Result<PathBuf, String> {
+    let file_name = format!("{}.{}", output_stem(input_path, algorithm, options), extension);
+    write_output_named(input_path, &file_name, data, options)
+}
+
+// "{stem}{suffix}", or "{stem}{suffix}_{format}" when outputs are named by format.
+// Both outputs of a dual export keep the same stem, so <picture> markup can pair them.
+pub fn output_stem(input_path: &Path, algorithm: CompressionAlgorithm, options: &ProcessingOptions) -> String {
+    let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+    if options.format_in_name && !options.dual_export {
+        format!("{}{}_{}", stem, options.suffix, algorithm.name_token())
+    } else {
+        format!("{}{}", stem, options.suffix)
+    }
+}
+
+fn write_output_named(
+    input_path: &Path,
+    file_name: &str,
+    data: &[u8],
+    options: &ProcessingOptions,
+) -> Result<PathBuf, String> {
+    let output_dir = prepare_output_dir(input_path, options)?;
+    store_output(input_path, &output_dir.join(file_name), data, options).map_err(|e| stage_error(Stage::Write, &e))
+}
+
+// Like write_output_named, but into a subfolder of the output directory, or under
+// that folder inside the ZIP archive
+fn write_output_in_folder(
+    input_path: &Path,
+    folder: &str,
+    file_name: &str,
+    data: &[u8],
+    options: &ProcessingOptions,
+) -> Result<PathBuf, String> {
+    let folder_dir = prepare_output_dir(input_path, options)?.join(folder);
+    if options.zip_output.is_none() {
+        fs::create_dir_all(&folder_dir).map_err(|e| stage_error(Stage::Write, &e))?;
+    }
+    store_output(input_path, &folder_dir.join(file_name), data, options).map_err(|e| stage_error(Stage::Write, &e))
+}
+
+// The resolved output directory, created and with the original backed up when
+// requested. Nothing is created when the outputs go into a ZIP archive.
+pub fn prepare_output_dir(input_path: &Path, options: &ProcessingOptions) -> Result<PathBuf, String> {
+    let output_dir = resolve_output_dir(input_path, options);
+    if options.zip_output.is_some() {
+        return Ok(output_dir);
+    }
+    check_safe_mode(input_path, &output_dir, options)?;
     
-    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
-        container::Appearance {
-            background: Some(Background::Color(SECONDARY_COLOR)),
-            border_radius: 8.0.into(),
-            border_width: 1.0,
-            border_color: Color::from_rgba(0.0, 0.0, 0.0, 0.05),
-            ..Default::default()
+    create_output_dir(&output_dir, options)?;
+    if options.backup_originals {
+        backup_original(input_path, &output_dir)
+            .map_err(|e| format!("{} failed: backup: {}", Stage::Write, error_chain(&e)))?;
+    }
+    Ok(output_dir)
+}
+
+// The default output folder is marked as this tool's when it's created
+fn create_output_dir(output_dir: &Path, options: &ProcessingOptions) -> Result<(), String> {
+    fs::create_dir_all(output_dir)
+        .and_then(|_| {
+            let marker = output_dir.join(OUTPUT_MARKER);
+            if options.output_dir.is_none() && !marker.exists() {
+                fs::write(marker, b"")?;
+            }
+            Ok(())
+        })
+        .map_err(|e| format!("{} failed: creating {}: {}", Stage::Write, output_dir.display(), error_chain(&e)))
+}
+
+// Writes one output, or adds it to the batch's ZIP archive under its path inside the
+// output directory. Returns where it went; an archive entry shows as "{archive}/{entry}".
+pub fn store_output(input_path: &Path, output_path: &Path, data: &[u8], options: &ProcessingOptions) -> std::io::Result<PathBuf> {
+    options.ensure_running()?;
+    match &options.zip_output {
+        Some(zip) => {
+            let entry_name = match output_path.strip_prefix(resolve_output_dir(input_path, options)) {
+                Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+                Err(_) => output_path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            };
+            zip.add(input_path, &entry_name, data).map(|entry| zip.path().join(entry))
+        }
+        None => {
+            // The last line behind the empty-suffix check before the run starts
+            if resolve_existing(output_path) == resolve_existing(input_path) {
+                return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, "the output would overwrite the source"));
+            }
+            write_file(output_path, data, options.atomic_writes, options.scratch_dir.as_deref())
+                .map(|_| output_path.to_path_buf())
         }
     }
 }
 
-struct ProgressContainer;
-impl container::StyleSheet for ProgressContainer {
-    type Style = Theme;
+// Output writes, atomic unless turned off in the settings
+pub fn write_file(path: &Path, data: &[u8], atomic: bool, scratch_dir: Option<&Path>) -> std::io::Result<()> {
+    if atomic {
+        write_atomic(path, data, scratch_dir)
+    } else {
+        fs::write(path, data)
+    }
+}
+
+// Writes to a temp file and renames it into place, so an interrupted write never
+// leaves a truncated file under the real name. The temp file is "{file_name}.tmp" in
+// the same directory, or goes into the scratch folder when there is one.
+pub fn write_atomic(path: &Path, data: &[u8], scratch_dir: Option<&Path>) -> std::io::Result<()> {
+    let temp_path = match scratch_dir {
+        Some(dir) => scratch_path(dir, path),
+        None => sibling_temp_path(path),
+    };
     
-    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
-        container::Appearance {
-            border_radius: 4.0.into(),
-            ..Default::default()
-        }
+    let written = fs::write(&temp_path, data).and_then(|_| move_into_place(&temp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
     }
+    written
 }
 
-struct ResultItemContainer {
-    success: bool,
+pub fn sibling_temp_path(path: &Path) -> PathBuf {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    path.with_file_name(temp_name)
 }
-impl container::StyleSheet for ResultItemContainer {
-    type Style = Theme;
+
+// Outputs from different folders can share a file name, so scratch names lead with
+// a hash of the full destination
+pub fn scratch_path(scratch_dir: &Path, path: &Path) -> PathBuf {
+    use std::hash::{Hash, Hasher};
     
-    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
-        let bg_color = if self.success {
-            Color::from_rgba(0.2, 0.7, 0.3, 0.05)
-        } else {
-            Color::from_rgba(0.9, 0.2, 0.2, 0.05)
-        };
-        
-        container::Appearance {
-            background: Some(Background::Color(bg_color)),
-            border_radius: 6.0.into(),
-            ..Default::default()
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    path.hash(&mut hasher);
+    let mut temp_name = std::ffi::OsString::from(format!("{:016x}-", hasher.finish()));
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(".tmp");
+    scratch_dir.join(temp_name)
+}
+
+// Renames a finished temp file to its real name. A scratch folder on another disk
+// can't be renamed across, so the file is then copied next to the target first and
+// renamed from there, which keeps the last step atomic.
+pub fn move_into_place(temp_path: &Path, path: &Path) -> std::io::Result<()> {
+    if fs::rename(temp_path, path).is_ok() {
+        return Ok(());
+    }
+    let sibling = sibling_temp_path(path);
+    let moved = fs::copy(temp_path, &sibling).and_then(|_| fs::rename(&sibling, path));
+    if moved.is_err() {
+        let _ = fs::remove_file(&sibling);
+    }
+    let _ = fs::remove_file(temp_path);
+    moved
+}
+
+// Creates the folder if needed and writes and removes a probe file in it
+fn check_writable(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(".image-resizer-write-test");
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
+
+// Rules match the real format, so a PNG named .jpg follows the png rule. A rule
+// keeping the format of one no encoder writes (GIF, BMP) leaves the global choice.
+fn extension_rule(input_path: &Path, options: &ProcessingOptions) -> Option<CompressionAlgorithm> {
+    let extension = sniff::true_extension(input_path);
+    let target = options.extension_rules.get(&extension)
+        .or_else(|| if extension == "jpg" { options.extension_rules.get("jpeg") } else { None })?;
+    match target {
+        RuleTarget::Algorithm(algorithm) => Some(*algorithm),
+        RuleTarget::KeepFormat => match sniff::detect_format(input_path)? {
+            image::ImageFormat::Jpeg => Some(CompressionAlgorithm::Jpeg),
+            image::ImageFormat::Png => Some(CompressionAlgorithm::OxiPng),
+            image::ImageFormat::WebP if sniff::is_lossless_webp(input_path) => Some(CompressionAlgorithm::WebPLossless),
+            image::ImageFormat::WebP => Some(CompressionAlgorithm::WebPLossy),
+            image::ImageFormat::Avif => Some(CompressionAlgorithm::Avif),
+            _ => None,
+        },
+    }
+}
+
+// An extension rule swaps in its algorithm, at that algorithm's recommended quality
+fn apply_extension_rule<'a>(
+    input_path: &Path,
+    compression_options: &'a CompressionOptions,
+    options: &ProcessingOptions,
+) -> Cow<'a, CompressionOptions> {
+    match extension_rule(input_path, options) {
+        Some(algorithm) if algorithm != compression_options.algorithm => {
+            debug!("{}: extension rule selects {}", input_path.display(), algorithm);
+            Cow::Owned(CompressionOptions {
+                algorithm,
+                quality: Some(algorithm.recommended_quality()),
+                ..compression_options.clone()
+            })
         }
+        _ => Cow::Borrowed(compression_options),
     }
 }
 
-// Rest of the implementation remains the same
-impl std::fmt::Display for CompressionAlgorithm {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Auto => write!(f, "Auto (Smart Selection)"),
-            Self::Simple => write!(f, "Simple (Fast)"),
-            Self::StandardJpeg => write!(f, "JPEG Standard"),
-            Self::MozJpeg => write!(f, "JPEG (MozJPEG)"),
-            Self::StandardPng => write!(f, "PNG Standard"),
-            Self::OptiPng => write!(f, "PNG (OptiPNG)"),
-            Self::OxiPng => write!(f, "PNG (OxiPNG)"),
-            Self::PngQuant => write!(f, "PNG (PNGQuant Lossy)"),
-            Self::WebPLossy => write!(f, "WebP Lossy"),
-            Self::WebPLossless => write!(f, "WebP Lossless"),
-            Self::Avif => write!(f, "AVIF"),
+// The auto modes decide the output format per image, so they never count as a match
+fn is_same_format(input_path: &Path, algorithm: CompressionAlgorithm) -> bool {
+    if matches!(algorithm, CompressionAlgorithm::Auto | CompressionAlgorithm::AutoBest | CompressionAlgorithm::ByAlpha) {
+        return false;
+    }
+    
+    match (
+        sniff::detect_format(input_path),
+        image::ImageFormat::from_extension(algorithm.file_extension()),
+    ) {
+        (Some(input), Some(output)) => input == output,
+        _ => false,
+    }
+}
+
+// What a verbatim copy of the source is named by: its own format, whichever
+// encoder for that format was picked
+fn copied_format(input_path: &Path, algorithm: CompressionAlgorithm) -> CompressionAlgorithm {
+    match sniff::detect_format(input_path) {
+        Some(image::ImageFormat::Jpeg) => CompressionAlgorithm::Jpeg,
+        Some(image::ImageFormat::Png) => CompressionAlgorithm::StandardPng,
+        Some(image::ImageFormat::WebP) if sniff::is_lossless_webp(input_path) => CompressionAlgorithm::WebPLossless,
+        Some(image::ImageFormat::WebP) => CompressionAlgorithm::WebPLossy,
+        Some(image::ImageFormat::Avif) => CompressionAlgorithm::Avif,
+        _ => algorithm,
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().eq_ignore_ascii_case(extension))
+        .unwrap_or(false)
+}
+
+pub const BACKUP_DIR_NAME: &str = "originals_backup";
+pub const OUTPUT_DIR_NAME: &str = "resized";
+pub const DEFAULT_SUFFIX: &str = "_resized";
+// Left in each default output folder this tool creates, so a user's own folder
+// that happens to be called "resized" is still walked
+pub const OUTPUT_MARKER: &str = ".image-resizer-output";
+
+// Folders a folder run never descends into: the backups, and the output of earlier
+// runs, which would otherwise come back as "x_resized_resized.jpg": every default
+// "resized" subfolder this tool created, and the chosen output folder when it lies
+// inside the selection.
+pub struct SkippedDirs {
+    output_dir: Option<PathBuf>,
+}
+
+impl SkippedDirs {
+    pub fn new(output_dir: Option<&Path>) -> Self {
+        Self { output_dir: output_dir.map(resolve_existing) }
+    }
+    
+    // The selected folder itself is always walked, even when it is the output folder
+    pub fn contains(&self, entry: &walkdir::DirEntry) -> bool {
+        if entry.depth() == 0 || !entry.file_type().is_dir() {
+            return false;
+        }
+        if entry.file_name() == BACKUP_DIR_NAME {
+            return true;
+        }
+        if entry.file_name() == OUTPUT_DIR_NAME && entry.path().join(OUTPUT_MARKER).is_file() {
+            return true;
         }
+        self.output_dir.as_ref().is_some_and(|output_dir| resolve_existing(entry.path()) == *output_dir)
     }
 }
 
-// Helper functions
-async fn select_file() -> Option<PathBuf> {
-    rfd::AsyncFileDialog::new()
-        .add_filter("Images", &["jpg", "jpeg", "png", "gif", "bmp", "webp"])
-        .pick_file()
-        .await
-        .map(|handle| handle.path().to_path_buf())
+// The last line of safe mode, behind the check before the run starts
+fn check_safe_mode(input_path: &Path, output_dir: &Path, options: &ProcessingOptions) -> Result<(), String> {
+    let source_dir = input_path.parent().unwrap_or(Path::new("."));
+    if options.safe_mode && is_within(output_dir, source_dir) {
+        return Err(format!(
+            "{} failed: safe mode: {} is inside the source folder {}",
+            Stage::Write,
+            output_dir.display(),
+            source_dir.display()
+        ));
+    }
+    Ok(())
 }
 
-async fn select_folder() -> Option<PathBuf> {
-    rfd::AsyncFileDialog::new()
-        .pick_folder()
-        .await
-        .map(|handle| handle.path().to_path_buf())
+// Whether `path` is `dir` or somewhere below it. Both are resolved through their
+// nearest existing ancestor, so symlinks and ".." can't hide a match for a folder
+// that hasn't been created yet.
+pub fn is_within(path: &Path, dir: &Path) -> bool {
+    resolve_existing(path).starts_with(resolve_existing(dir))
 }
 
-fn parse_dimensions(width: &str, height: &str) -> Option<(u32, u32)> {
-    match (width.parse::<u32>(), height.parse::<u32>()) {
-        (Ok(w), Ok(h)) => Some((w, h)),
-        _ => None,
+fn resolve_existing(path: &Path) -> PathBuf {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    loop {
+        let resolved = if existing.as_os_str().is_empty() { fs::canonicalize(".") } else { fs::canonicalize(existing) };
+        if let Ok(resolved) = resolved {
+            return missing.iter().rev().fold(resolved, |resolved, name| resolved.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
     }
 }
 
-async fn process_images_advanced(
-    path: PathBuf,
-    target_size_kb: Option<u64>,
-    dimensions: Option<(u32, u32)>,
-    maintain_ratio: bool,
-    algorithm: CompressionAlgorithm,
-    quality: u8,
-    optimize_for_web: bool,
-) -> Vec<ProcessResult> {
-    tokio::task::spawn_blocking(move || {
-        let compressor = SmartCompressor::new();
-        let images = collect_images(&path).unwrap_or_default();
-        let mut results = Vec::new();
-        
-        for image_path in images {
-            let filename = image_path.file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
-            
-            let result = process_single_image_advanced(
-                &image_path,
-                target_size_kb,
-                dimensions,
-                maintain_ratio,
-                algorithm,
-                quality,
-                optimize_for_web,
-                &compressor,
-            );
-            
-            results.push(ProcessResult {
-                filename,
-                original_size: result.original_size,
-                new_size: result.new_size,
-                success: result.success,
-                message: result.message,
-                algorithm_used: result.algorithm_used,
-                compression_ratio: result.compression_ratio,
-            });
-        }
-        
-        results
-    }).await.unwrap_or_default()
+// Output goes to the user-chosen folder, or a "resized" folder beside the source
+pub fn resolve_output_dir(input_path: &Path, options: &ProcessingOptions) -> PathBuf {
+    match &options.output_dir {
+        Some(dir) => dir.clone(),
+        None => input_path.parent().unwrap_or(Path::new(".")).join(OUTPUT_DIR_NAME),
+    }
 }
 
-struct InternalResult {
-    original_size: u64,
-    new_size: u64,
-    success: bool,
-    message: String,
-    algorithm_used: CompressionAlgorithm,
-    compression_ratio: f32,
+// Copies the source into an "originals_backup" subfolder when the output would
+// land in the same directory as the source. An existing backup is never
+// overwritten, so re-runs keep the true original rather than a processed copy.
+pub fn backup_original(input_path: &Path, output_dir: &Path) -> std::io::Result<()> {
+    let source_dir = input_path.parent().unwrap_or(Path::new("."));
+    let same_dir = match (fs::canonicalize(source_dir), fs::canonicalize(output_dir)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => source_dir == output_dir,
+    };
+    if !same_dir {
+        return Ok(());
+    }
+    
+    let backup_dir = source_dir.join(BACKUP_DIR_NAME);
+    let backup_path = backup_dir.join(input_path.file_name().unwrap_or_default());
+    if backup_path.exists() {
+        return Ok(());
+    }
+    
+    fs::create_dir_all(&backup_dir)?;
+    fs::copy(input_path, &backup_path)?;
+    Ok(())
 }
 
-fn process_single_image_advanced(
+// Handles sources that should stay animated: animated WebP re-encoded as WebP,
+// and GIFs when GIF-to-WebP conversion is enabled. Returns None for everything
+// else so the regular single-frame path takes over.
+fn process_animation(
     input_path: &Path,
-    target_size_kb: Option<u64>,
+    original_size: u64,
     dimensions: Option<(u32, u32)>,
     maintain_ratio: bool,
     algorithm: CompressionAlgorithm,
     quality: u8,
-    optimize_for_web: bool,
-    compressor: &SmartCompressor,
-) -> InternalResult {
-    let original_size = match fs::metadata(input_path) {
-        Ok(metadata) => metadata.len(),
-        Err(e) => {
-            return InternalResult {
-                original_size: 0,
-                new_size: 0,
-                success: false,
-                message: format!("Failed to read: {}", e),
-                algorithm_used: algorithm,
-                compression_ratio: 0.0,
-            };
-        }
-    };
+    options: &ProcessingOptions,
+) -> Option<InternalResult> {
+    let is_gif = options.gif_to_webp
+        && CompressionAlgorithm::WebPLossy.is_available()
+        && sniff::is_format(input_path, image::ImageFormat::Gif);
+    let is_webp = sniff::is_format(input_path, image::ImageFormat::WebP) && matches!(
+        algorithm,
+        CompressionAlgorithm::Auto
+            | CompressionAlgorithm::AutoBest
+            | CompressionAlgorithm::ByAlpha
+            | CompressionAlgorithm::WebPLossy
+            | CompressionAlgorithm::WebPLossless
+            | CompressionAlgorithm::LosslessFirst
+    );
+    if !is_gif && !is_webp {
+        return None;
+    }
     
-    if algorithm == CompressionAlgorithm::Simple {
-        let auto_scale = false;
-        let result = simple::process_single_image(
-            input_path,
-            target_size_kb,
-            dimensions,
-            maintain_ratio,
-            auto_scale,
-        );
-        
-        return InternalResult {
-            original_size: result.original_size,
-            new_size: result.new_size,
-            success: result.success,
-            message: result.message,
-            algorithm_used: CompressionAlgorithm::Simple,
-            compression_ratio: if result.original_size > 0 {
-                result.new_size as f32 / result.original_size as f32
-            } else {
-                0.0
-            },
-        };
+    let data = fs::read(input_path).ok()?;
+    if is_webp && !animation::is_animated_webp(&data) {
+        return None;
     }
     
-    let mut img = match image::open(input_path) {
-        Ok(img) => img,
-        Err(e) => {
-            return InternalResult {
-                original_size,
-                new_size: 0,
-                success: false,
-                message: format!("Failed to open: {}", e),
-                algorithm_used: algorithm,
-                compression_ratio: 0.0,
-            };
-        }
+    let lossless = algorithm == CompressionAlgorithm::WebPLossless;
+    let algorithm_used = if lossless {
+        CompressionAlgorithm::WebPLossless
+    } else {
+        CompressionAlgorithm::WebPLossy
     };
     
-    if let Some((width, height)) = dimensions {
-        img = if maintain_ratio {
-            img.resize(width, height, image::imageops::FilterType::Lanczos3)
-        } else {
-            img.resize_exact(width, height, image::imageops::FilterType::Lanczos3)
-        };
-    }
+    // Sized like a still from the canvas in the header; frames are resized to exactly that
+    let canvas = image::io::Reader::new(std::io::Cursor::new(&data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+    let mut notes = Vec::new();
+    let (dimensions, maintain_ratio) = match canvas {
+        Some(canvas) => (animation_size(canvas, dimensions, maintain_ratio, options, &mut notes), false),
+        None => (print_dimensions(dimensions, options), maintain_ratio),
+    };
+    let processed = if is_gif {
+        animation::process_gif_to_webp(&data, dimensions, maintain_ratio, quality, lossless)
+    } else {
+        animation::process_animated_webp(&data, dimensions, maintain_ratio, quality, lossless)
+    };
     
-    let options = CompressionOptions {
-        algorithm,
-        quality: Some(quality),
-        target_size: target_size_kb.map(|kb| kb * 1024),
-        preserve_metadata: false,
-        optimize_for_web,
+    let failure = |message: String| InternalResult {
+        original_size,
+        new_size: 0,
+        success: false,
+        status: ResultStatus::Processed,
+        message,
+        algorithm_used,
+        compression_ratio: 0.0,
+        output_path: None,
+        quality_score: None,
+        final_quality: None,
     };
     
-    let compression_result = match compressor.compress(&img, options) {
+    let (encoded, frame_count) = match processed {
         Ok(result) => result,
-        Err(e) => {
-            return InternalResult {
-                original_size,
-                new_size: 0,
-                success: false,
-                message: format!("Compression failed: {}", e),
-                algorithm_used: algorithm,
-                compression_ratio: 0.0,
-            };
-        }
+        Err(e) => return Some(failure(stage_error(Stage::Animation, &*e))),
     };
     
-    let output_dir = input_path.parent().unwrap_or(Path::new(".")).join("resized");
-    if let Err(e) = fs::create_dir_all(&output_dir) {
-        return InternalResult {
-            original_size,
-            new_size: 0,
-            success: false,
-            message: format!("Failed to create dir: {}", e),
-            algorithm_used: algorithm,
-            compression_ratio: 0.0,
-        };
-    }
-    
-    let output_path = output_dir.join(format!(
-        "{}_resized.{}",
-        input_path.file_stem().unwrap().to_string_lossy(),
-        compression_result.algorithm_used.file_extension()
-    ));
-    
-    if let Err(e) = fs::write(&output_path, &compression_result.data) {
-        return InternalResult {
-            original_size,
-            new_size: 0,
-            success: false,
-            message: format!("Save failed: {}", e),
-            algorithm_used: algorithm,
-            compression_ratio: 0.0,
-        };
-    }
+    let output_path = match write_output(input_path, algorithm_used, "webp", &encoded, options) {
+        Ok(output_path) => output_path,
+        Err(message) => return Some(failure(message)),
+    };
     
-    InternalResult {
+    Some(InternalResult {
         original_size,
-        new_size: compression_result.data.len() as u64,
+        new_size: encoded.len() as u64,
         success: true,
-        message: String::new(),
-        algorithm_used: compression_result.algorithm_used,
-        compression_ratio: compression_result.compression_ratio,
+        status: ResultStatus::Processed,
+        message: join_notes(&[format!("Animated WebP, {} frames", frame_count)], notes),
+        algorithm_used,
+        compression_ratio: encoded.len() as f32 / original_size.max(1) as f32,
+        output_path: Some(output_path),
+        quality_score: None,
+        final_quality: None,
+    })
+}
+
+// The size an animation's canvas is resized to, the way scale_image sizes a still:
+// the edge constraint or else the width/height box, then the megapixel cap. None
+// when that leaves the canvas as it is.
+fn animation_size(
+    canvas: (u32, u32),
+    dimensions: Option<(u32, u32)>,
+    maintain_ratio: bool,
+    options: &ProcessingOptions,
+    notes: &mut Vec<String>,
+) -> Option<(u32, u32)> {
+    let size = match (options.edge_constraint, print_dimensions(dimensions, options)) {
+        (Some((edge, length)), _) => edge_fit(canvas, edge, length).unwrap_or(canvas),
+        (None, Some(bounds)) if maintain_ratio => fit_within(canvas, bounds),
+        (None, Some(size)) => size,
+        (None, None) => canvas,
+    };
+    let size = match options.downscale_megapixels.and_then(|limit| megapixel_fit(size, limit)) {
+        Some((width, height)) => {
+            notes.push(format!("Downscaled to {}×{}", width, height));
+            (width, height)
+        }
+        None => size,
+    };
+    (size != canvas).then_some(size)
+}
+
+// Everything under the folder that the mode's image filter skips
+fn collect_non_images(path: &Path, output_dir: Option<&Path>, simple_mode: bool) -> Vec<PathBuf> {
+    let skipped = SkippedDirs::new(output_dir);
+    WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|e| !skipped.contains(e))
+        .filter_map(|e| e.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| {
+            path.is_file()
+                && path.file_name() != Some(OUTPUT_MARKER.as_ref())
+                && !if simple_mode { simple::is_image_file(path) } else { is_image_file(path) }
+        })
+        .collect()
+}
+
+// Non-image files keep their name; a file already in the output folder is left alone
+fn copy_through(input_path: &Path, algorithm: CompressionAlgorithm, options: &ProcessingOptions) -> InternalResult {
+    let copied = fs::read(input_path)
+        .map_err(|e| stage_error(Stage::Read, &e))
+        .and_then(|data| {
+            let output_dir = resolve_output_dir(input_path, options);
+            let output_path = output_dir.join(input_path.file_name().unwrap_or_default());
+            if options.zip_output.is_none() {
+                check_safe_mode(input_path, &output_dir, options)?;
+                create_output_dir(&output_dir, options)?;
+                
+                let same_file = match (fs::canonicalize(&output_path), fs::canonicalize(input_path)) {
+                    (Ok(a), Ok(b)) => a == b,
+                    _ => false,
+                };
+                if same_file {
+                    return Ok((data.len() as u64, output_path));
+                }
+            }
+            let output_path = store_output(input_path, &output_path, &data, options).map_err(|e| stage_error(Stage::Write, &e))?;
+            Ok((data.len() as u64, output_path))
+        });
+    
+    match copied {
+        Ok((size, output_path)) => InternalResult {
+            original_size: size,
+            new_size: size,
+            success: true,
+            status: ResultStatus::CopiedThrough,
+            message: String::from("Copied unchanged (not an image)"),
+            algorithm_used: algorithm,
+            compression_ratio: 1.0,
+            output_path: Some(output_path),
+            quality_score: None,
+            final_quality: None,
+        },
+        Err(message) => InternalResult::failed(0, message, algorithm),
     }
 }
 
-fn collect_images(path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+fn collect_images(path: &Path, output_dir: Option<&Path>) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
     let mut images = Vec::new();
     
     if path.is_file() && is_image_file(path) {
         images.push(path.to_path_buf());
     } else if path.is_dir() {
-        for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
+        let skipped = SkippedDirs::new(output_dir);
+        let walker = WalkDir::new(path)
+            .into_iter()
+            .filter_entry(|e| !skipped.contains(e));
+        for entry in walker.filter_map(|e| e.ok()) {
             let path = entry.path();
             if path.is_file() && is_image_file(path) {
                 images.push(path.to_path_buf());
@@ -1023,12 +7597,480 @@ fn collect_images(path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error
     Ok(images)
 }
 
+// By content when it's recognisable, otherwise by extension
 fn is_image_file(path: &Path) -> bool {
-    match path.extension() {
-        Some(ext) => {
-            let ext = ext.to_string_lossy().to_lowercase();
-            matches!(ext.as_str(), "jpg" | "jpeg" | "png" | "gif" | "bmp" | "webp" | "avif")
+    use image::ImageFormat::*;
+    matches!(sniff::detect_format(path), Some(Jpeg | Png | Gif | Bmp | WebP | Avif))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn switching_algorithms_starts_each_at_its_own_quality() {
+        let (mut app, _) = ImageResizer::new(());
+        let _ = app.update(Message::AlgorithmSelected(CompressionAlgorithm::WebPLossy));
+        assert_eq!(app.quality_slider, 90);
+        let _ = app.update(Message::AlgorithmSelected(CompressionAlgorithm::Jpeg));
+        assert_eq!(app.quality_slider, 85);
+        let _ = app.update(Message::QualityChanged(70));
+        let _ = app.update(Message::AlgorithmSelected(CompressionAlgorithm::OxiPng));
+        assert_eq!(app.quality_slider, 100);
+        let _ = app.update(Message::AlgorithmSelected(CompressionAlgorithm::Jpeg));
+        assert_eq!(app.quality_slider, 70);
+    }
+    
+    // A folder of its own under the temp dir, removed again when the test ends
+    struct TempDir(PathBuf);
+    
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("image-resizer-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+    
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+    
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let image = image::RgbImage::from_fn(width, height, |x, y| image::Rgb([(x * 3) as u8, (y * 5) as u8, ((x + y) * 2) as u8]));
+        let mut data = std::io::Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(image).write_to(&mut data, image::ImageFormat::Png).unwrap();
+        data.into_inner()
+    }
+    
+    #[test]
+    fn empty_file_is_reported_as_empty() {
+        let dir = TempDir::new("empty");
+        let path = dir.0.join("photo.png");
+        fs::write(&path, b"").unwrap();
+        assert_eq!(open_image(&path, None).unwrap_err(), empty_file_error());
+        assert!(empty_file_error().starts_with(&format!("{} failed", Stage::Open)));
+    }
+    
+    #[test]
+    fn half_written_png_is_reported_as_truncated() {
+        let dir = TempDir::new("truncated");
+        let path = dir.0.join("photo.png");
+        let png = png_bytes(64, 64);
+        fs::write(&path, &png[..png.len() / 2]).unwrap();
+        for limit in [None, Some(100.0)] {
+            let message = open_image(&path, limit).unwrap_err();
+            assert!(message.contains("corrupt or truncated"), "{}", message);
+        }
+    }
+    
+    // Collects the folder the way a run does and processes every file in it, in name order
+    fn run_folder(app: &ImageResizer, folder: &Path) -> Vec<ProcessResult> {
+        let settings = app.build_job().unwrap().batch;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut sources = collect_images(folder, app.output_dir.as_deref()).unwrap();
+        sources.sort();
+        let results: Vec<ProcessResult> = sources.into_iter()
+            .flat_map(|source| runtime.block_on(process_file(source, settings.clone())))
+            .collect();
+        for result in &results {
+            assert!(result.success, "{}", result.message);
+        }
+        results
+    }
+    
+    fn sources(results: &[ProcessResult]) -> Vec<PathBuf> {
+        results.iter().map(|result| result.source_path.clone()).collect()
+    }
+    
+    #[test]
+    fn second_run_skips_its_own_output_folders() {
+        let dir = TempDir::new("run-twice");
+        // A folder of the user's own that only happens to share the output folder's name
+        fs::create_dir_all(dir.0.join("nested")).unwrap();
+        fs::create_dir_all(dir.0.join("keep").join(OUTPUT_DIR_NAME)).unwrap();
+        for path in ["a.png", "nested/b.png", "keep/resized/c.png"] {
+            fs::write(dir.0.join(path), png_bytes(32, 24)).unwrap();
+        }
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        
+        let first = sources(&run_folder(&app, &dir.0));
+        assert_eq!(first.len(), 3);
+        assert!(dir.0.join(OUTPUT_DIR_NAME).join(OUTPUT_MARKER).is_file());
+        let second = sources(&run_folder(&app, &dir.0));
+        assert_eq!(second, first);
+        
+        let doubled = WalkDir::new(&dir.0).into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().contains("_resized_resized"))
+            .count();
+        assert_eq!(doubled, 0);
+    }
+    
+    #[test]
+    fn queued_job_that_cant_start_stays_queued() {
+        let dir = TempDir::new("unstartable");
+        fs::write(dir.0.join("a.png"), png_bytes(8, 8)).unwrap();
+        // A file where the scratch folder should be can't be written into
+        let scratch = dir.0.join("scratch");
+        fs::write(&scratch, b"").unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.atomic_writes = true;
+        app.scratch_dir = Some(scratch);
+        
+        let job = app.build_job().unwrap();
+        app.queue_running = true;
+        let _ = app.start_job(job);
+        assert!(!app.queue_running);
+        assert!(!app.is_processing);
+        assert_eq!(app.queue.len(), 1);
+    }
+    
+    #[test]
+    fn prepare_for_web_carries_no_metadata() {
+        let dir = TempDir::new("prepare-for-web-metadata");
+        fs::write(dir.0.join("a.png"), png_bytes(8, 8)).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.preserve_metadata = true;
+        app.embed_thumbnail = true;
+        
+        let _ = app.update(Message::PrepareForWeb);
+        let options = &app.batch.as_ref().unwrap().compression_options;
+        assert!(!options.preserve_metadata);
+        assert!(!options.embed_thumbnail);
+    }
+    
+    #[test]
+    fn cleared_results_come_back_until_the_next_run() {
+        let dir = TempDir::new("clear-undo");
+        fs::write(dir.0.join("a.png"), png_bytes(8, 8)).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        
+        let processed = run_folder(&app, &dir.0);
+        let _ = app.update(Message::Process);
+        let _ = app.update(Message::ImagesCollected(app.batch_id, sources(&processed)));
+        let _ = app.update(Message::FileProcessed(app.batch_id, processed));
+        assert!(!app.is_processing);
+        let status = app.status_message.clone();
+        assert!(status.starts_with("Processed 1 images"), "{}", status);
+        
+        let _ = app.update(Message::ClearResults);
+        assert!(app.results.is_empty());
+        assert!(app.status_message.is_empty());
+        let _ = app.update(Message::UndoClearResults);
+        assert_eq!(sources(&app.results), [dir.0.join("a.png")]);
+        assert_eq!(app.status_message, status);
+        
+        let _ = app.update(Message::ClearResults);
+        let _ = app.update(Message::Process);
+        let _ = app.update(Message::UndoClearResults);
+        assert!(app.results.is_empty());
+        assert!(app.cleared_results.is_none());
+    }
+    
+    #[test]
+    fn prepare_for_web_replaces_a_print_size() {
+        let dir = TempDir::new("prepare-for-web-print");
+        fs::write(dir.0.join("a.png"), png_bytes(8, 8)).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.print_width = String::from("4");
+        app.print_height = String::from("3");
+        app.dpi = String::from("300");
+        
+        let _ = app.update(Message::PrepareForWeb);
+        assert!(app.is_processing, "{}", app.status_message);
+        let options = &app.batch.as_ref().unwrap().options;
+        assert!(options.physical_size.is_none());
+        assert!(options.edge_constraint.is_some());
+    }
+    
+    #[test]
+    fn retried_file_isnt_its_own_near_duplicate() {
+        let dir = TempDir::new("retry-dedupe");
+        let source = dir.0.join("a.png");
+        fs::write(&source, png_bytes(8, 8)).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.skip_near_duplicates = true;
+        
+        // A file that failed after it was decoded, so the run's filter has seen it
+        let mut processed = run_folder(&app, &dir.0);
+        processed[0].success = false;
+        let _ = app.update(Message::Process);
+        let options = &app.batch.as_ref().unwrap().options;
+        assert!(near_duplicate_message(&source, &image::open(&source).unwrap(), options).is_none());
+        let _ = app.update(Message::ImagesCollected(app.batch_id, vec![source.clone()]));
+        let _ = app.update(Message::FileProcessed(app.batch_id, processed));
+        
+        let _ = app.update(Message::RetryFailed);
+        let settings = app.batch.clone().unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let retried = runtime.block_on(process_file(source, settings));
+        assert_eq!(retried[0].status, ResultStatus::Processed);
+        assert!(retried[0].success, "{}", retried[0].message);
+    }
+    
+    #[test]
+    fn simple_mode_is_offered_for_transparent_images_without_a_target() {
+        let (mut app, _) = ImageResizer::new(());
+        app.compression_algorithm = CompressionAlgorithm::OxiPng;
+        app.file_info = Some(FileInfo::Image {
+            width: 8,
+            height: 8,
+            format: String::from("PNG"),
+            file_size: 100,
+            has_alpha: Some(true),
+        });
+        let choices = app.input_algorithm_choices();
+        assert!(choices.contains(&CompressionAlgorithm::Simple));
+        assert!(!choices.contains(&CompressionAlgorithm::Jpeg));
+        
+        app.target_size = String::from("50");
+        assert!(!app.input_algorithm_choices().contains(&CompressionAlgorithm::Simple));
+    }
+    
+    fn output_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir.join(OUTPUT_DIR_NAME)).unwrap()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name != OUTPUT_MARKER)
+            .collect();
+        names.sort();
+        names
+    }
+    
+    #[test]
+    fn copied_file_is_named_by_its_own_format() {
+        let dir = TempDir::new("format-name-copy");
+        fs::write(dir.0.join("a.png"), png_bytes(8, 8)).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.compression_algorithm = CompressionAlgorithm::OxiPng;
+        app.convert_only = true;
+        app.format_in_name = true;
+        
+        run_folder(&app, &dir.0);
+        assert_eq!(output_names(&dir.0), ["a_resized_png.png"]);
+    }
+    
+    #[cfg(feature = "webp")]
+    #[test]
+    fn dual_export_pair_shares_its_stem() {
+        let dir = TempDir::new("format-name-dual");
+        fs::write(dir.0.join("a.png"), png_bytes(8, 8)).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.compression_algorithm = CompressionAlgorithm::WebPLossy;
+        app.dual_export = true;
+        app.format_in_name = true;
+        
+        run_folder(&app, &dir.0);
+        assert_eq!(output_names(&dir.0), ["a_resized.jpg", "a_resized.webp"]);
+    }
+    
+    // A red disk on pixels that are transparent black: resampled without the matte,
+    // the black bleeds into the anti-aliased edge as a dark fringe
+    #[test]
+    fn flattened_edges_have_no_dark_fringe() {
+        let dir = TempDir::new("flatten");
+        let logo = image::RgbaImage::from_fn(48, 48, |x, y| {
+            let distance = ((x as f32 - 23.5).powi(2) + (y as f32 - 23.5).powi(2)).sqrt();
+            let alpha = (18.0 - distance).clamp(0.0, 1.0);
+            if alpha > 0.0 { image::Rgba([255, 0, 0, (alpha * 255.0) as u8]) } else { image::Rgba([0, 0, 0, 0]) }
+        });
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        let _ = app.update(Message::FlattenToJpeg);
+        let options = app.build_job().unwrap().batch.options;
+        
+        let resized = scale_image(DynamicImage::ImageRgba8(logo), Some((17, 17)), true, &options, &mut Vec::new()).to_rgb8();
+        assert_eq!(resized.dimensions(), (17, 17));
+        // Red and white both have a full red channel, and so does every blend of them
+        let darkest = resized.pixels().map(|pixel| pixel[0]).min().unwrap();
+        assert!(darkest >= 250, "darkest red channel {}", darkest);
+    }
+    
+    #[test]
+    fn responsive_widths_are_watermarked_at_their_own_size() {
+        let dir = TempDir::new("responsive-watermark");
+        let marks = TempDir::new("responsive-watermark-mark");
+        let mark_path = marks.0.join("mark.png");
+        image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 255, 0, 255])).save(&mark_path).unwrap();
+        let photo = image::RgbImage::from_pixel(64, 48, image::Rgb([128, 128, 128]));
+        photo.save(dir.0.join("photo.png")).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.compression_algorithm = CompressionAlgorithm::StandardPng;
+        app.responsive_widths = String::from("32, 16");
+        app.watermark_image = Some(mark_path);
+        app.watermark_position = watermark::Position::TopLeft;
+        app.watermark_margin = String::from("0");
+        app.watermark_opacity = 100;
+        
+        let results = run_folder(&app, &dir.0);
+        assert_eq!(results.len(), 2);
+        for result in results {
+            let output = image::open(result.output_path.unwrap()).unwrap().to_rgb8();
+            assert_eq!(output.get_pixel(3, 3).0, [0, 255, 0]);
+            assert_eq!(output.get_pixel(4, 4).0, [128, 128, 128]);
+        }
+    }
+    
+    #[cfg(feature = "webp")]
+    #[test]
+    fn png_to_webp_leaves_the_pixels_alone() {
+        let dir = TempDir::new("png-to-webp-pixels");
+        fs::write(dir.0.join("a.png"), png_bytes(32, 24)).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.compression_mode = CompressionMode::Advanced;
+        app.png_to_webp = true;
+        app.grayscale = true;
+        app.auto_levels = true;
+        app.auto_orient = true;
+        app.trim_borders = true;
+        app.crop_to_fill = true;
+        app.sharpen_amount = 100;
+        
+        let results = run_folder(&app, &dir.0);
+        let output = image::open(results[0].output_path.as_ref().unwrap()).unwrap();
+        let source = image::load_from_memory(&png_bytes(32, 24)).unwrap();
+        assert_eq!(output.to_rgb8(), source.to_rgb8());
+        
+        app.watermark_text = String::from("©");
+        assert!(app.build_job().is_err());
+    }
+    
+    #[test]
+    fn responsive_widths_past_the_source_are_capped() {
+        let dir = TempDir::new("responsive-cap");
+        fs::write(dir.0.join("a.png"), png_bytes(64, 48)).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.compression_algorithm = CompressionAlgorithm::StandardPng;
+        app.responsive_widths = String::from("32, 128, 256");
+        
+        run_folder(&app, &dir.0);
+        assert_eq!(output_names(&dir.0), ["a-32w.png", "a-64w.png"]);
+        let full = image::open(dir.0.join(OUTPUT_DIR_NAME).join("a-64w.png")).unwrap();
+        assert_eq!((full.width(), full.height()), (64, 48));
+    }
+    
+    #[cfg(feature = "webp")]
+    #[test]
+    fn by_alpha_keeps_animated_webp_animated() {
+        let dir = TempDir::new("by-alpha-animation");
+        let frames = [[255, 0, 0, 255], [0, 0, 255, 128]].into_iter()
+            .map(|color| animation::AnimationFrame {
+                image: image::RgbaImage::from_pixel(16, 16, image::Rgba(color)),
+                duration_ms: 100,
+            })
+            .collect();
+        let source = animation::Animation { width: 16, height: 16, loop_count: 0, frames };
+        fs::write(dir.0.join("anim.webp"), animation::encode_webp_animation(&source, 80.0, false).unwrap()).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.compression_algorithm = CompressionAlgorithm::ByAlpha;
+        
+        let results = run_folder(&app, &dir.0);
+        assert_eq!(results.len(), 1);
+        let output = fs::read(results[0].output_path.as_ref().unwrap()).unwrap();
+        assert!(animation::is_animated_webp(&output));
+    }
+    
+    #[cfg(feature = "webp")]
+    #[test]
+    fn animations_follow_the_edge_and_megapixel_limits() {
+        let dir = TempDir::new("animation-size");
+        let frames = [[255, 0, 0, 255], [0, 0, 255, 255]].into_iter()
+            .map(|color| animation::AnimationFrame {
+                image: image::RgbaImage::from_pixel(64, 48, image::Rgba(color)),
+                duration_ms: 100,
+            })
+            .collect();
+        let source = animation::Animation { width: 64, height: 48, loop_count: 0, frames };
+        let encoded = animation::encode_webp_animation(&source, 80.0, false).unwrap();
+        
+        for (edge_length, megapixels, expected) in [("32", "", (32, 24)), ("", "0.0012", (40, 30))] {
+            fs::write(dir.0.join("anim.webp"), &encoded).unwrap();
+            let (mut app, _) = ImageResizer::new(());
+            app.selected_path = Some(dir.0.clone());
+            app.compression_algorithm = CompressionAlgorithm::WebPLossy;
+            app.edge_length = String::from(edge_length);
+            app.downscale_megapixels = String::from(megapixels);
+            
+            let results = run_folder(&app, &dir.0);
+            let output = fs::read(results[0].output_path.as_ref().unwrap()).unwrap();
+            let resized = animation::decode_webp_animation(&output).unwrap();
+            assert_eq!((resized.width, resized.height), expected);
+            assert_eq!(resized.frames.len(), 2);
+        }
+    }
+    
+    #[cfg(feature = "webp")]
+    #[test]
+    fn exploded_frames_run_the_pipeline_steps() {
+        let dir = TempDir::new("explode-pipeline");
+        let frames = [[255, 0, 0, 255], [0, 0, 255, 255]].into_iter()
+            .map(|color| animation::AnimationFrame {
+                image: image::RgbaImage::from_pixel(16, 16, image::Rgba(color)),
+                duration_ms: 100,
+            })
+            .collect();
+        let source = animation::Animation { width: 16, height: 16, loop_count: 0, frames };
+        fs::write(dir.0.join("anim.webp"), animation::encode_webp_animation(&source, 100.0, true).unwrap()).unwrap();
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.compression_algorithm = CompressionAlgorithm::StandardPng;
+        app.explode_frames = true;
+        app.grayscale = true;
+        
+        let results = run_folder(&app, &dir.0);
+        assert_eq!(results.len(), 2);
+        for result in results {
+            let frame = image::open(result.output_path.unwrap()).unwrap().to_rgb8();
+            assert!(frame.pixels().all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]));
+        }
+    }
+    
+    #[test]
+    fn incremental_run_reprocesses_only_the_changed_source() {
+        let dir = TempDir::new("incremental");
+        for name in ["a.png", "b.png"] {
+            fs::write(dir.0.join(name), png_bytes(16, 12)).unwrap();
+        }
+        let (mut app, _) = ImageResizer::new(());
+        app.selected_path = Some(dir.0.clone());
+        app.incremental = true;
+        app.format_in_name = true;
+        
+        for algorithm in [CompressionAlgorithm::StandardPng, CompressionAlgorithm::Auto] {
+            app.compression_algorithm = algorithm;
+            run_folder(&app, &dir.0);
+            let unchanged = run_folder(&app, &dir.0);
+            assert!(unchanged.iter().all(|result| {
+                result.message == "Up to date" && result.status == ResultStatus::Skipped
+            }), "{}", algorithm);
         }
-        None => false,
+        
+        // Newer than any output, whatever the file system's timestamp resolution
+        let changed = dir.0.join("b.png");
+        fs::write(&changed, png_bytes(20, 12)).unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        filetime::set_file_mtime(&changed, FileTime::from_system_time(later)).unwrap();
+        let results = run_folder(&app, &dir.0);
+        let reprocessed: Vec<_> = results.iter()
+            .filter(|result| result.message != "Up to date")
+            .map(|result| result.source_path.clone())
+            .collect();
+        assert_eq!(reprocessed, [changed]);
     }
 }
\ No newline at end of file
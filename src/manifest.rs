@@ -0,0 +1,106 @@
+// manifest.rs - File lists written by other tools
+//
+// A plain text file with one path per line, or a CSV whose first column holds the
+// path. Blank lines and lines starting with '#' are ignored, and relative paths are
+// taken from the list's own folder. Entries that can't be processed are kept with
+// the reason, so they can be reported instead of disappearing.
+
+use crate::is_image_file;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct FileList {
+    pub files: Vec<PathBuf>,
+    pub rejected: Vec<(PathBuf, String)>,
+}
+
+pub fn read_file_list(list_path: &Path) -> std::io::Result<FileList> {
+    let contents = fs::read_to_string(list_path)?;
+    let base = list_path.parent().unwrap_or(Path::new("."));
+    let is_csv = list_path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+
+    let mut list = FileList {
+        files: Vec::new(),
+        rejected: Vec::new(),
+    };
+    let entries: Vec<String> = if is_csv {
+        first_fields(&contents)
+    } else {
+        contents.lines().map(|line| line.trim().to_string()).collect()
+    };
+    for (index, entry) in entries.iter().enumerate() {
+        let entry = entry.as_str();
+        if entry.is_empty() || entry.starts_with('#') {
+            continue;
+        }
+        // A CSV header row names the column rather than a file
+        if is_csv && index == 0 && matches!(entry.to_ascii_lowercase().as_str(), "path" | "file" | "filename") {
+            continue;
+        }
+
+        let path = base.join(entry);
+        if !path.exists() {
+            list.rejected.push((path, String::from("Listed file not found")));
+        } else if path.is_dir() {
+            list.rejected.push((path, String::from("Listed path is a folder, not an image")));
+        } else if !is_image_file(&path) {
+            list.rejected.push((path, String::from("Listed file is not a supported image")));
+        } else if !list.files.contains(&path) {
+            list.files.push(path);
+        }
+    }
+    Ok(list)
+}
+
+// The first column of each CSV record, without surrounding quotes. A quoted field
+// may hold commas and line breaks, and "" inside it stands for one quote.
+fn first_fields(contents: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_first = true;
+    let mut quoted = false;
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                if in_first {
+                    field.push('"');
+                }
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => in_first = false,
+            '\n' if !quoted => {
+                fields.push(field.trim().to_string());
+                field.clear();
+                in_first = true;
+            }
+            _ if in_first => field.push(c),
+            _ => {}
+        }
+    }
+    if !field.trim().is_empty() {
+        fields.push(field.trim().to_string());
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn quoted_fields_keep_their_commas_and_quotes() {
+        let csv = "path,width\r\n\"photos/a, b.jpg\",800\r\n\"say \"\"hi\"\".png\",\"x,y\"\r\nplain.jpg , 10\r\n";
+        assert_eq!(first_fields(csv), ["path", "photos/a, b.jpg", "say \"hi\".png", "plain.jpg"]);
+    }
+    
+    #[test]
+    fn quoted_fields_may_span_lines() {
+        let csv = "\"two\nlines.jpg\",1\nlast.jpg";
+        assert_eq!(first_fields(csv), ["two\nlines.jpg", "last.jpg"]);
+    }
+}
@@ -0,0 +1,399 @@
+// metadata.rs - EXIF and XMP carried over from the source when metadata is preserved
+//
+// The color profile is handled by icc.rs; this covers the rest of what cameras and
+// editors write. Both blocks are copied as they are, except that their orientation
+// is reset to upright when auto-orient has already turned the pixels. JPEG, PNG
+// and WebP output can carry them; other formats are written without.
+
+use image::ImageFormat;
+use std::fs;
+use std::path::Path;
+
+use crate::{density, icc};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const EXIF_MARKER: &[u8; 6] = b"Exif\0\0";
+const XMP_MARKER: &[u8; 29] = b"http://ns.adobe.com/xap/1.0/\0";
+// Keyword of the iTXt chunk holding XMP in a PNG
+const XMP_KEYWORD: &[u8; 18] = b"XML:com.adobe.xmp\0";
+const ORIENTATION_TAG: u16 = 0x0112;
+
+// Upper bound on what embedding both blocks adds besides their own bytes, which
+// target size searches leave room for
+pub const EMBED_OVERHEAD: u64 = 96;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMetadata {
+    // The TIFF structure, without JPEG's "Exif\0\0" prefix
+    pub exif: Option<Vec<u8>>,
+    // The XMP packet
+    pub xmp: Option<Vec<u8>>,
+}
+
+impl SourceMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.xmp.is_none()
+    }
+
+    // Bytes the blocks take, leaving out the EXIF when a thumbnail's takes its place
+    pub fn embedded_len(&self, with_exif: bool) -> u64 {
+        let exif = self.exif.as_ref().filter(|_| with_exif).map_or(0, Vec::len);
+        (exif + self.xmp.as_ref().map_or(0, Vec::len)) as u64
+    }
+}
+
+// Formats with a place for EXIF and XMP
+pub fn supports(format: ImageFormat) -> bool {
+    matches!(format, ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP)
+}
+
+// The EXIF and XMP of a JPEG, PNG or WebP file. `upright` resets their orientation,
+// for pixels that auto-orient has already turned.
+pub fn read(path: &Path, upright: bool) -> SourceMetadata {
+    let Ok(data) = fs::read(path) else {
+        return SourceMetadata::default();
+    };
+    let mut metadata = match crate::sniff::detect_format(path) {
+        Some(ImageFormat::Jpeg) => read_jpeg(&data),
+        Some(ImageFormat::Png) => read_png(&data),
+        Some(ImageFormat::WebP) => read_webp(&data),
+        _ => SourceMetadata::default(),
+    };
+    if upright {
+        if let Some(exif) = &mut metadata.exif {
+            upright_exif(exif);
+        }
+        if let Some(xmp) = &mut metadata.xmp {
+            upright_xmp(xmp);
+        }
+    }
+    metadata
+}
+
+fn read_jpeg(data: &[u8]) -> SourceMetadata {
+    let mut metadata = SourceMetadata::default();
+    if data.get(0..2) != Some([0xFF, 0xD8].as_slice()) {
+        return metadata;
+    }
+    let mut offset = 2;
+    while let Some(end) = icc::segment_end(data, offset) {
+        let marker = data[offset + 1];
+        if marker == 0xDA {
+            break;
+        }
+        let payload = &data[offset + 4..end];
+        if marker == 0xE1 && metadata.exif.is_none() {
+            metadata.exif = payload.strip_prefix(EXIF_MARKER.as_slice()).map(<[u8]>::to_vec);
+        }
+        if marker == 0xE1 && metadata.xmp.is_none() {
+            metadata.xmp = payload.strip_prefix(XMP_MARKER.as_slice()).map(<[u8]>::to_vec);
+        }
+        offset = end;
+    }
+    metadata
+}
+
+fn read_png(data: &[u8]) -> SourceMetadata {
+    let mut metadata = SourceMetadata::default();
+    if data.get(0..8) != Some(PNG_SIGNATURE.as_slice()) {
+        return metadata;
+    }
+    let mut offset = 8;
+    while offset + 12 <= data.len() {
+        let length = u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+        let end = offset + 12 + length;
+        if end > data.len() {
+            break;
+        }
+        let payload = &data[offset + 8..end - 4];
+        match &data[offset + 4..offset + 8] {
+            b"eXIf" => metadata.exif = Some(payload.to_vec()),
+            b"iTXt" if payload.starts_with(XMP_KEYWORD) => metadata.xmp = xmp_text(&payload[XMP_KEYWORD.len()..]),
+            b"IDAT" => break,
+            _ => {}
+        }
+        offset = end;
+    }
+    metadata
+}
+
+// The text of an iTXt chunk after its keyword: compression flag and method, then
+// the language tag and translated keyword, each ending in a zero byte
+fn xmp_text(rest: &[u8]) -> Option<Vec<u8>> {
+    let (&compressed, rest) = rest.split_first()?;
+    let rest = rest.get(1..)?;
+    let language_end = rest.iter().position(|&byte| byte == 0)?;
+    let rest = &rest[language_end + 1..];
+    let keyword_end = rest.iter().position(|&byte| byte == 0)?;
+    let text = &rest[keyword_end + 1..];
+    if compressed == 1 {
+        miniz_oxide::inflate::decompress_to_vec_zlib(text).ok()
+    } else {
+        Some(text.to_vec())
+    }
+}
+
+fn read_webp(data: &[u8]) -> SourceMetadata {
+    let mut metadata = SourceMetadata::default();
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return metadata;
+    }
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let size = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let end = offset + 8 + size;
+        if end > data.len() {
+            break;
+        }
+        match &data[offset..offset + 4] {
+            // Some writers keep the JPEG-style prefix
+            b"EXIF" => {
+                let exif = &data[offset + 8..end];
+                metadata.exif = Some(exif.strip_prefix(EXIF_MARKER.as_slice()).unwrap_or(exif).to_vec());
+            }
+            b"XMP " => metadata.xmp = Some(data[offset + 8..end].to_vec()),
+            _ => {}
+        }
+        offset = end + (size & 1);
+    }
+    metadata
+}
+
+// Tag 0x0112 in IFD0 of a TIFF structure, in either byte order
+pub fn exif_orientation(tiff: &[u8]) -> Option<u16> {
+    let (offset, big_endian) = orientation_value(tiff)?;
+    let bytes = [tiff[offset], tiff[offset + 1]];
+    Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+}
+
+// Sets the Orientation entry of IFD0 to 1 where there is one
+fn upright_exif(exif: &mut [u8]) {
+    if let Some((offset, big_endian)) = orientation_value(exif) {
+        let upright = if big_endian { 1u16.to_be_bytes() } else { 1u16.to_le_bytes() };
+        exif[offset..offset + 2].copy_from_slice(&upright);
+    }
+}
+
+// Where the Orientation entry of IFD0 keeps its value, and the structure's byte order
+fn orientation_value(tiff: &[u8]) -> Option<(usize, bool)> {
+    let big_endian = match tiff.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let bytes = tiff.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+
+    let ifd = read_u32(4)? as usize;
+    let entries = read_u16(ifd)? as usize;
+    (0..entries)
+        .map(|index| ifd + 2 + index * 12)
+        .find(|&entry| read_u16(entry) == Some(ORIENTATION_TAG))
+        .map(|entry| entry + 8)
+        .filter(|&offset| offset + 2 <= tiff.len())
+        .map(|offset| (offset, big_endian))
+}
+
+// Both ways XMP writes tiff:Orientation; its values are single digits, so the
+// packet keeps its length
+fn upright_xmp(xmp: &mut [u8]) {
+    for pattern in [b"tiff:Orientation=\"".as_slice(), b"<tiff:Orientation>".as_slice()] {
+        let mut start = 0;
+        while let Some(found) = xmp[start..].windows(pattern.len()).position(|window| window == pattern) {
+            let digit = start + found + pattern.len();
+            if xmp.get(digit).is_some_and(|value| (b'1'..=b'8').contains(value)) {
+                xmp[digit] = b'1';
+            }
+            start = digit;
+        }
+    }
+}
+
+// Returns the data with the blocks embedded, replacing any the encoder wrote.
+// `with_exif` is false when a thumbnail's EXIF is embedded afterwards instead.
+// Data in other formats or that doesn't parse is returned unchanged.
+pub fn embed(mut data: Vec<u8>, format: ImageFormat, metadata: &SourceMetadata, with_exif: bool) -> Vec<u8> {
+    let exif = metadata.exif.as_deref().filter(|_| with_exif);
+    match format {
+        ImageFormat::Jpeg => {
+            // Each goes right after the JFIF header, so the EXIF is inserted last to end up first
+            if let Some(xmp) = &metadata.xmp {
+                data = set_jpeg_segment(data, 0xE1, XMP_MARKER, xmp);
+            }
+            if let Some(exif) = exif {
+                data = set_jpeg_segment(data, 0xE1, EXIF_MARKER, exif);
+            }
+        }
+        ImageFormat::Png => {
+            if let Some(xmp) = &metadata.xmp {
+                // Uncompressed, with no language tag or translated keyword
+                let mut payload = XMP_KEYWORD.to_vec();
+                payload.extend_from_slice(&[0, 0, 0, 0]);
+                payload.extend_from_slice(xmp);
+                data = set_png_chunk(data, b"iTXt", &payload, |existing| existing.starts_with(XMP_KEYWORD));
+            }
+            if let Some(exif) = exif {
+                data = set_png_chunk(data, b"eXIf", exif, |_| true);
+            }
+        }
+        ImageFormat::WebP => {
+            if let Some(xmp) = &metadata.xmp {
+                data = icc::set_webp_chunk(data, b"XMP ", 0x04, xmp);
+            }
+            if let Some(exif) = exif {
+                data = icc::set_webp_chunk(data, b"EXIF", 0x08, exif);
+            }
+        }
+        _ => {}
+    }
+    data
+}
+
+// An APPn segment after SOI and the JFIF header, ahead of any color profile. Segments
+// with the same marker and prefix are dropped.
+pub fn set_jpeg_segment(data: Vec<u8>, marker: u8, prefix: &[u8], payload: &[u8]) -> Vec<u8> {
+    if data.len() < 4 || data[0..2] != [0xFF, 0xD8] || 2 + prefix.len() + payload.len() > 65_535 {
+        return data;
+    }
+
+    let mut output = Vec::with_capacity(data.len() + prefix.len() + payload.len() + 4);
+    output.extend_from_slice(&data[0..2]);
+    let mut offset = 2;
+    if let Some(end) = icc::segment_end(&data, offset) {
+        if data[offset + 1] == 0xE0 && data.get(offset + 4..offset + 9) == Some(b"JFIF\0".as_slice()) {
+            output.extend_from_slice(&data[offset..end]);
+            offset = end;
+        }
+    }
+
+    output.extend_from_slice(&[0xFF, marker]);
+    output.extend_from_slice(&((2 + prefix.len() + payload.len()) as u16).to_be_bytes());
+    output.extend_from_slice(prefix);
+    output.extend_from_slice(payload);
+
+    // Segments up to the scan data, minus the one being replaced
+    while let Some(end) = icc::segment_end(&data, offset) {
+        if data[offset + 1] == 0xDA {
+            break;
+        }
+        let replaced = data[offset + 1] == marker && data[offset + 4..end].starts_with(prefix);
+        if !replaced {
+            output.extend_from_slice(&data[offset..end]);
+        }
+        offset = end;
+    }
+    output.extend_from_slice(&data[offset..]);
+    output
+}
+
+// A chunk directly after IHDR; chunks of the same type that `replaces` accepts are dropped
+fn set_png_chunk(data: Vec<u8>, chunk_type: &[u8; 4], payload: &[u8], replaces: impl Fn(&[u8]) -> bool) -> Vec<u8> {
+    if data.get(0..8) != Some(PNG_SIGNATURE.as_slice()) {
+        return data;
+    }
+    let mut output = Vec::with_capacity(data.len() + payload.len() + 12);
+    output.extend_from_slice(&PNG_SIGNATURE);
+
+    let mut offset = 8;
+    while offset + 12 <= data.len() {
+        let length = u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+        let end = offset + 12 + length;
+        if end > data.len() {
+            return data;
+        }
+
+        let existing = &data[offset + 4..offset + 8];
+        if existing != chunk_type || !replaces(&data[offset + 8..end - 4]) {
+            output.extend_from_slice(&data[offset..end]);
+        }
+        if existing == b"IHDR" {
+            density::write_png_chunk(&mut output, chunk_type, payload);
+        }
+        offset = end;
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{DynamicImage, Rgb, RgbImage};
+    use std::io::Cursor;
+
+    // Big-endian TIFF with IFD0 holding Orientation 6 and a Make entry after it
+    fn camera_exif() -> Vec<u8> {
+        let mut exif = b"MM\0*\0\0\0\x08".to_vec();
+        exif.extend_from_slice(&2u16.to_be_bytes());
+        exif.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0]);
+        exif.extend_from_slice(&[0x01, 0x0F, 0, 2, 0, 0, 0, 4, b'A', b'C', b'E', 0]);
+        exif.extend_from_slice(&[0; 4]);
+        exif
+    }
+
+    fn jpeg() -> Vec<u8> {
+        let img = RgbImage::from_pixel(16, 16, Rgb([40, 90, 200]));
+        let mut data = Vec::new();
+        JpegEncoder::new(&mut data).encode_image(&img).unwrap();
+        data
+    }
+
+    fn png() -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(16, 16, Rgb([40, 90, 200])))
+            .write_to(&mut data, ImageFormat::Png)
+            .unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn upright_resets_only_the_orientation() {
+        let mut exif = camera_exif();
+        assert_eq!(exif_orientation(&exif), Some(6));
+        upright_exif(&mut exif);
+        assert_eq!(exif_orientation(&exif), Some(1));
+        assert_eq!(&exif[30..34], b"ACE\0");
+
+        let mut xmp = b"<x tiff:Orientation=\"8\"/><tiff:Orientation>6</tiff:Orientation>".to_vec();
+        upright_xmp(&mut xmp);
+        assert_eq!(xmp, b"<x tiff:Orientation=\"1\"/><tiff:Orientation>1</tiff:Orientation>");
+    }
+
+    #[test]
+    fn exif_and_xmp_survive_a_jpeg_round_trip() {
+        let metadata = SourceMetadata {
+            exif: Some(camera_exif()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+        };
+        let output = embed(jpeg(), ImageFormat::Jpeg, &metadata, true);
+        assert_eq!(read_jpeg(&output), metadata);
+        assert!(image::load_from_memory(&output).is_ok());
+    }
+
+    #[test]
+    fn exif_and_xmp_survive_a_png_round_trip() {
+        let metadata = SourceMetadata {
+            exif: Some(camera_exif()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+        };
+        let output = embed(png(), ImageFormat::Png, &metadata, true);
+        assert_eq!(read_png(&output), metadata);
+        assert!(image::load_from_memory(&output).is_ok());
+    }
+
+    #[test]
+    fn a_thumbnails_exif_leaves_only_the_xmp() {
+        let metadata = SourceMetadata {
+            exif: Some(camera_exif()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+        };
+        let output = embed(jpeg(), ImageFormat::Jpeg, &metadata, false);
+        assert_eq!(read_jpeg(&output), SourceMetadata { exif: None, ..metadata });
+    }
+}
@@ -0,0 +1,207 @@
+// pipeline.rs - The pixel steps between decoding and encoding, in a chosen order
+//
+// The default order is Orient, Trim, Levels, Resize, Sharpen, Grayscale: the
+// image is turned upright before anything measures it, borders go before the
+// resize so they don't count towards the size or the histogram, and sharpening
+// follows the resize it makes up for. Each step has its own on/off setting and runs only when that is on;
+// Resize runs whenever a size is set. Metadata is never a step: the encoder
+// strips or keeps it after the last one, as Preserve metadata says. Redaction
+// isn't a step either: it always comes first, so its regions are in the pixels
+// of the file as decoded, whatever the order. The watermark goes on after the
+// last step, so the outputs' size and sharpening never touch it.
+
+use image::DynamicImage;
+use std::path::Path;
+
+use crate::{adjust, metadata, scale_image, watermark, Direction, ProcessingOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    // Applies the EXIF orientation tag to the pixels
+    Orient,
+    Trim,
+    // Stretches the histogram to the full range, see adjust::auto_levels
+    Levels,
+    Resize,
+    // Ahead of Resize this sharpens the source; after it only a downscaled image,
+    // since upscales and unchanged sizes don't come out soft
+    Sharpen,
+    Grayscale,
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Orient => write!(f, "Orient"),
+            Self::Trim => write!(f, "Trim borders"),
+            Self::Levels => write!(f, "Auto levels"),
+            Self::Resize => write!(f, "Resize"),
+            Self::Sharpen => write!(f, "Sharpen"),
+            Self::Grayscale => write!(f, "Grayscale"),
+        }
+    }
+}
+
+pub const DEFAULT_ORDER: [Step; 6] = [Step::Orient, Step::Trim, Step::Levels, Step::Resize, Step::Sharpen, Step::Grayscale];
+
+// Every step exactly once, in the order they run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self { steps: DEFAULT_ORDER.to_vec() }
+    }
+}
+
+impl Pipeline {
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    // Swaps the step with its neighbour; moving past either end does nothing
+    pub fn move_step(&mut self, index: usize, direction: Direction) {
+        let target = match direction {
+            Direction::Up => index.checked_sub(1),
+            Direction::Down => Some(index + 1),
+        };
+        if let Some(target) = target.filter(|target| *target < self.steps.len()) {
+            self.steps.swap(index, target);
+        }
+    }
+
+    // Whether sharpening waits for the resize, which the responsive widths need to
+    // know since they resize each variant themselves
+    pub fn sharpens_after_resize(&self) -> bool {
+        let position = |step| self.steps.iter().position(|candidate| *candidate == step);
+        position(Step::Sharpen) > position(Step::Resize)
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.steps == DEFAULT_ORDER
+    }
+}
+
+// Whether the step's own setting has it switched on
+pub fn is_enabled(step: Step, options: &ProcessingOptions) -> bool {
+    match step {
+        Step::Orient => options.auto_orient,
+        Step::Trim => options.trim_borders,
+        Step::Levels => options.auto_levels,
+        Step::Resize => true,
+        Step::Sharpen => options.sharpen_amount > 0.0,
+        Step::Grayscale => options.grayscale,
+    }
+}
+
+// Runs the enabled steps in order, adding a note for each one that changed the image
+pub fn apply(
+    img: DynamicImage,
+    input_path: &Path,
+    dimensions: Option<(u32, u32)>,
+    maintain_ratio: bool,
+    options: &ProcessingOptions,
+    notes: &mut Vec<String>,
+) -> DynamicImage {
+    let img = apply_steps(img, input_path, dimensions, maintain_ratio, options, notes);
+    match &options.watermark {
+        Some(watermark) => watermark::apply(img, watermark),
+        None => img,
+    }
+}
+
+// Everything apply does but the watermark, for a caller that resizes the result
+// again and stamps each size itself
+pub fn apply_steps(
+    mut img: DynamicImage,
+    input_path: &Path,
+    dimensions: Option<(u32, u32)>,
+    maintain_ratio: bool,
+    options: &ProcessingOptions,
+    notes: &mut Vec<String>,
+) -> DynamicImage {
+    if let Some((redacted, count)) = adjust::redact(&img, &options.redactions, options.redact_style) {
+        notes.push(redaction_note(count));
+        img = redacted;
+    }
+    
+    // Sharpen after Resize only acts on a downscale, which needs the size going in
+    let mut resized_from = None;
+    for &step in options.pipeline.steps() {
+        if !is_enabled(step, options) {
+            continue;
+        }
+        img = match step {
+            Step::Orient => match read_orientation(input_path).filter(|orientation| *orientation > 1) {
+                Some(orientation) => {
+                    notes.push(String::from("Rotated upright"));
+                    orient(img, orientation)
+                }
+                None => img,
+            },
+            Step::Trim => match adjust::trim_borders(&img, options.trim_tolerance) {
+                Some(trimmed) => {
+                    notes.push(format!("Trimmed to {}×{}", trimmed.width(), trimmed.height()));
+                    trimmed
+                }
+                None => img,
+            },
+            Step::Levels => match adjust::auto_levels(&img, options.levels_per_channel) {
+                Some(levelled) => {
+                    notes.push(String::from("Auto levels"));
+                    levelled
+                }
+                None => img,
+            },
+            Step::Resize => {
+                resized_from = Some((img.width(), img.height()));
+                scale_image(img, dimensions, maintain_ratio, options, notes)
+            }
+            Step::Sharpen => {
+                let downscaled = resized_from
+                    .is_none_or(|(width, height)| img.width() < width || img.height() < height);
+                if downscaled {
+                    adjust::unsharp_mask(&img, options.sharpen_radius, options.sharpen_amount)
+                } else {
+                    img
+                }
+            }
+            Step::Grayscale => {
+                if img.color().has_color() {
+                    notes.push(String::from("Converted to grayscale"));
+                }
+                img.grayscale()
+            }
+        };
+    }
+    img
+}
+
+pub fn redaction_note(count: usize) -> String {
+    if count == 1 {
+        String::from("Redacted 1 region")
+    } else {
+        format!("Redacted {} regions", count)
+    }
+}
+
+// EXIF orientation values: 2-4 mirror or turn by 180°, 5-8 involve a quarter turn
+fn orient(img: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+// The orientation tag from a JPEG, PNG or WebP file's EXIF block
+pub fn read_orientation(path: &Path) -> Option<u16> {
+    metadata::read(path, false).exif.as_deref().and_then(metadata::exif_orientation)
+}
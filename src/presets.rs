@@ -0,0 +1,55 @@
+// presets.rs - Named output sizes for social media and common screens
+//
+// Choosing a preset fills the dimension fields and picks crop or fit; the fields
+// stay editable afterwards. New presets only need a row in PRESETS.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DimensionPreset {
+    pub name: &'static str,
+    // None for Custom, which leaves the fields to manual entry
+    pub size: Option<(u32, u32)>,
+    // Crop to fill the exact size instead of fitting inside it
+    pub crop: bool,
+}
+
+const fn crop(name: &'static str, width: u32, height: u32) -> DimensionPreset {
+    DimensionPreset { name, size: Some((width, height)), crop: true }
+}
+
+const fn fit(name: &'static str, width: u32, height: u32) -> DimensionPreset {
+    DimensionPreset { name, size: Some((width, height)), crop: false }
+}
+
+pub const CUSTOM: DimensionPreset = DimensionPreset { name: "Custom", size: None, crop: false };
+
+pub const PRESETS: &[DimensionPreset] = &[
+    CUSTOM,
+    crop("Instagram square", 1080, 1080),
+    crop("Instagram portrait", 1080, 1350),
+    crop("Instagram story / Reels", 1080, 1920),
+    crop("Facebook post", 1200, 630),
+    crop("Facebook cover", 851, 315),
+    crop("X/Twitter post", 1600, 900),
+    crop("X/Twitter header", 1500, 500),
+    crop("LinkedIn banner", 1584, 396),
+    crop("YouTube thumbnail", 1280, 720),
+    fit("Full HD", 1920, 1080),
+    fit("4K UHD", 3840, 2160),
+    fit("Email attachment", 1024, 1024),
+];
+
+impl std::fmt::Display for DimensionPreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.size {
+            Some((width, height)) => write!(
+                f,
+                "{} ({}×{}, {})",
+                self.name,
+                width,
+                height,
+                if self.crop { "crop" } else { "fit" }
+            ),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
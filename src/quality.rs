@@ -0,0 +1,121 @@
+// quality.rs - Objective scores comparing an output with the image it was encoded from
+//
+// The encoded bytes are decoded again, so the scores cost a decode per file. Both
+// images are composited over the same matte the JPEG encoder uses, which keeps
+// transparent areas from counting against formats without alpha. PSNR is taken
+// over the RGB channels; SSIM over luma, in 8×8 windows moved 4 pixels at a time.
+
+use image::{DynamicImage, GrayImage, Luma, RgbImage};
+
+use crate::compression::flatten_alpha;
+
+const WINDOW: u32 = 8;
+const WINDOW_STEP: u32 = 4;
+// The usual stabilizing constants for 8-bit data, (0.01·255)² and (0.03·255)²
+const C1: f64 = 6.5025;
+const C2: f64 = 58.5225;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityScore {
+    // Infinite when the pixels are identical
+    pub psnr: f64,
+    pub ssim: f64,
+}
+
+impl std::fmt::Display for QualityScore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.psnr.is_infinite() {
+            write!(f, "PSNR ∞, SSIM {:.3}", self.ssim)
+        } else {
+            write!(f, "PSNR {:.1} dB, SSIM {:.3}", self.psnr, self.ssim)
+        }
+    }
+}
+
+// None when the output can't be decoded (AVIF) or came out at a different size
+pub fn measure(reference: &DynamicImage, encoded: &[u8], background: [u8; 3]) -> Option<QualityScore> {
+    let (reference, decoded) = decode_pair(reference, encoded, background)?;
+
+    Some(QualityScore {
+        psnr: psnr(&reference, &decoded),
+        ssim: ssim(&luma(&reference), &luma(&decoded), reference.width(), reference.height()),
+    })
+}
+
+// The reference and the decoded output over the same matte, ready to compare pixel by
+// pixel; None under the same conditions as measure
+pub fn decode_pair(reference: &DynamicImage, encoded: &[u8], background: [u8; 3]) -> Option<(RgbImage, RgbImage)> {
+    let decoded = image::load_from_memory(encoded).ok()?;
+    if decoded.width() != reference.width() || decoded.height() != reference.height() {
+        return None;
+    }
+    Some((flatten_alpha(reference, background), flatten_alpha(&decoded, background)))
+}
+
+// Each pixel's largest channel difference times `gain`, so faint artifacts show up as
+// light spots on black; identical pixels stay black. Both images must be the same size.
+pub fn difference_map(reference: &RgbImage, decoded: &RgbImage, gain: u8) -> GrayImage {
+    GrayImage::from_fn(reference.width(), reference.height(), |x, y| {
+        let (a, b) = (reference.get_pixel(x, y), decoded.get_pixel(x, y));
+        let difference = (0..3).map(|channel| a[channel].abs_diff(b[channel])).max().unwrap_or(0);
+        Luma([difference.saturating_mul(gain)])
+    })
+}
+
+fn psnr(reference: &RgbImage, decoded: &RgbImage) -> f64 {
+    let squared_error: u64 = reference
+        .as_raw()
+        .iter()
+        .zip(decoded.as_raw())
+        .map(|(a, b)| (a.abs_diff(*b) as u64).pow(2))
+        .sum();
+    if squared_error == 0 {
+        return f64::INFINITY;
+    }
+    let mse = squared_error as f64 / reference.as_raw().len() as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+// BT.601 luma, the channel SSIM is conventionally taken on
+fn luma(image: &RgbImage) -> Vec<f64> {
+    image
+        .pixels()
+        .map(|p| 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64)
+        .collect()
+}
+
+// Mean SSIM over the windows; images smaller than a window are one window
+fn ssim(reference: &[f64], decoded: &[f64], width: u32, height: u32) -> f64 {
+    let window_width = WINDOW.min(width);
+    let window_height = WINDOW.min(height);
+    let mut total = 0.0;
+    let mut windows = 0;
+
+    for top in (0..=height - window_height).step_by(WINDOW_STEP as usize) {
+        for left in (0..=width - window_width).step_by(WINDOW_STEP as usize) {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in top..top + window_height {
+                let row = (y * width) as usize;
+                for x in left..left + window_width {
+                    let a = reference[row + x as usize];
+                    let b = decoded[row + x as usize];
+                    sum_a += a;
+                    sum_b += b;
+                    sum_aa += a * a;
+                    sum_bb += b * b;
+                    sum_ab += a * b;
+                }
+            }
+
+            let n = (window_width * window_height) as f64;
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let variance_a = sum_aa / n - mean_a * mean_a;
+            let variance_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (variance_a + variance_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
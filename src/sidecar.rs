@@ -0,0 +1,98 @@
+// sidecar.rs - "{output}.json" files describing how each output was made
+//
+// One flat JSON object per output, for asset pipelines that pick up metadata next
+// to the file. Only numbers, booleans and strings appear, so it is written by hand.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::compression::CompressionResult;
+
+pub struct Sidecar<'a> {
+    pub source: &'a Path,
+    pub output: &'a Path,
+    pub result: &'a CompressionResult,
+    pub original_size: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Sidecar<'_> {
+    pub fn to_json(&self) -> String {
+        let quality = self.result.final_quality.map_or(String::from("null"), |quality| quality.to_string());
+        format!(
+            concat!(
+                "{{\n",
+                "  \"source\": {},\n",
+                "  \"output\": {},\n",
+                "  \"algorithm\": {},\n",
+                "  \"format\": {},\n",
+                "  \"quality\": {},\n",
+                "  \"original_bytes\": {},\n",
+                "  \"new_bytes\": {},\n",
+                "  \"ratio\": {:.3},\n",
+                "  \"width\": {},\n",
+                "  \"height\": {},\n",
+                "  \"created\": {}\n",
+                "}}\n"
+            ),
+            string(&self.source.display().to_string()),
+            string(&self.output.display().to_string()),
+            string(&self.result.algorithm_used.to_string()),
+            string(self.result.algorithm_used.file_extension()),
+            quality,
+            self.original_size,
+            self.result.data.len(),
+            self.result.compression_ratio,
+            self.width,
+            self.height,
+            string(&utc_timestamp(SystemTime::now())),
+        )
+    }
+}
+
+// A JSON string literal, with quotes, backslashes and control characters escaped
+fn string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// RFC 3339 in UTC, e.g. 2024-05-01T12:30:00Z
+fn utc_timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let (days, time_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Days since 1970-01-01 to a civil date, counting in 400-year eras from March 1st
+    let days = days as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day % 3600 / 60,
+        time_of_day % 60
+    )
+}
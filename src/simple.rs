@@ -2,66 +2,35 @@ use iced::widget::{button, column, container, progress_bar, row, scrollable, tex
 use iced::{executor, Application, Command, Element, Length, Settings, Theme, Font};
 use iced::font::{Family, Weight};
 use image::{DynamicImage, ImageFormat};
+use log::{debug, trace};
 use std::fs;
 use std::io::Cursor;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
-use crate::ProcessResult;
-use crate::CompressionAlgorithm;
-
-pub async fn process_images(
-    path: PathBuf,
-    target_size_kb: Option<u64>,
-    dimensions: Option<(u32, u32)>,
-    maintain_ratio: bool,
-	auto_scale: bool
-) -> Vec<ProcessResult> {
-    tokio::task::spawn_blocking(move || {
-        let images = collect_images(&path).unwrap_or_default();
-        let mut results = Vec::new();
-        
-        for image_path in images {
-            let filename = image_path.file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
-            
-            let result = process_single_image(&image_path, target_size_kb, dimensions, maintain_ratio, auto_scale);
-            
-            results.push(ProcessResult {
-                filename,
-                original_size: result.original_size,
-                new_size: result.new_size,
-                success: result.success,
-                message: result.message,
-				algorithm_used: CompressionAlgorithm::Simple,
-				compression_ratio: if result.original_size > 0 {
-					result.new_size as f32 / result.original_size as f32
-				} else {
-					0.0
-				},
-            });
-        }
-        
-        results
-    }).await.unwrap_or_default()
-}
+use crate::{adjust, cmyk, compression, density, pipeline, sniff, watermark};
+use crate::{near_duplicate_message, open_image, pixel_target, prepare_output_dir, resize_image, stage_error, store_output, ProcessingOptions, ResultStatus, SkippedDirs, Stage};
 
 // Image processing
 pub struct InternalResult {
     pub original_size: u64,
     pub new_size: u64,
     pub success: bool,
+    pub status: ResultStatus,
     pub message: String,
+    pub output_path: Option<PathBuf>,
 }
 
-fn collect_images(path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+pub fn collect_images(path: &Path, output_dir: Option<&Path>) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
     let mut images = Vec::new();
     
     if path.is_file() && is_image_file(path) {
         images.push(path.to_path_buf());
     } else if path.is_dir() {
-        for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
+        let skipped = SkippedDirs::new(output_dir);
+        let walker = WalkDir::new(path)
+            .into_iter()
+            .filter_entry(|e| !skipped.contains(e));
+        for entry in walker.filter_map(|e| e.ok()) {
             let path = entry.path();
             if path.is_file() && is_image_file(path) {
                 images.push(path.to_path_buf());
@@ -72,22 +41,18 @@ fn collect_images(path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error
     Ok(images)
 }
 
-fn is_image_file(path: &Path) -> bool {
-    match path.extension() {
-        Some(ext) => {
-            let ext = ext.to_string_lossy().to_lowercase();
-            matches!(ext.as_str(), "jpg" | "jpeg" | "png" | "gif" | "bmp" | "webp")
-        }
-        None => false,
-    }
+pub fn is_image_file(path: &Path) -> bool {
+    use image::ImageFormat::*;
+    matches!(sniff::detect_format(path), Some(Jpeg | Png | Gif | Bmp | WebP))
 }
 
 pub fn process_single_image(
     input_path: &Path,
-    target_size_kb: Option<u64>,
+    target_bytes: Option<u64>,
     dimensions: Option<(u32, u32)>,
     maintain_ratio: bool,
-	auto_scale: bool
+	auto_scale: bool,
+    options: &ProcessingOptions,
 ) -> InternalResult {
     let original_size = match fs::metadata(input_path) {
         Ok(metadata) => metadata.len(),
@@ -96,78 +61,147 @@ pub fn process_single_image(
                 original_size: 0,
                 new_size: 0,
                 success: false,
-                message: format!("Failed to read: {}", e),
+                status: ResultStatus::Processed,
+                message: stage_error(Stage::Read, &e),
+                output_path: None,
             };
         }
     };
     
-    let mut img = match image::open(input_path) {
+    let mut img = match open_image(input_path, options.max_megapixels) {
         Ok(img) => img,
-        Err(e) => {
+        Err(message) => {
             return InternalResult {
                 original_size,
                 new_size: 0,
                 success: false,
-                message: format!("Failed to open: {}", e),
+                status: ResultStatus::Processed,
+                message,
+                output_path: None,
             };
         }
     };
     
-    if let Some((width, height)) = dimensions {
-        img = if maintain_ratio {
-            img.resize(width, height, image::imageops::FilterType::Lanczos3)
-        } else {
-            img.resize_exact(width, height, image::imageops::FilterType::Lanczos3)
-        };
-    }
-    
-    let output_dir = input_path.parent().unwrap_or(Path::new(".")).join("resized");
-    if let Err(e) = fs::create_dir_all(&output_dir) {
+    if let Some(message) = near_duplicate_message(input_path, &img, options) {
         return InternalResult {
             original_size,
             new_size: 0,
-            success: false,
-            message: format!("Failed to create dir: {}", e),
+            success: true,
+            status: ResultStatus::Skipped,
+            message,
+            output_path: None,
         };
     }
     
+    let mut notes = Vec::new();
+    notes.extend(cmyk::conversion_note(input_path));
+    
+    if let Some((redacted, count)) = adjust::redact(&img, &options.redactions, options.redact_style) {
+        notes.push(pipeline::redaction_note(count));
+        img = redacted;
+    }
+    
+    if options.trim_borders {
+        if let Some(trimmed) = adjust::trim_borders(&img, options.trim_tolerance) {
+            notes.push(format!("Trimmed to {}×{}", trimmed.width(), trimmed.height()));
+            img = trimmed;
+        }
+    }
+    
+    if options.auto_levels {
+        if let Some(levelled) = adjust::auto_levels(&img, options.levels_per_channel) {
+            notes.push(String::from("Auto levels"));
+            img = levelled;
+        }
+    }
+    
+    img = resize_image(img, dimensions, maintain_ratio, options, &mut notes);
+    if let Some(watermark) = &options.watermark {
+        img = watermark::apply(img, watermark);
+    }
+    let target_bytes = pixel_target(target_bytes, &img, options);
+    
+    let output_dir = match prepare_output_dir(input_path, options) {
+        Ok(output_dir) => output_dir,
+        Err(message) => {
+            return InternalResult {
+                original_size,
+                new_size: 0,
+                success: false,
+                status: ResultStatus::Processed,
+                message,
+                output_path: None,
+            };
+        }
+    };
+    
+    // The target size search always encodes JPEG. Otherwise the source's format is
+    // kept where the image crate can write it, and PNG stands in where it can't. Its
+    // WebP encoder is lossless only, so lossy WebP becomes JPEG, or PNG to keep alpha.
+    let source_format = sniff::detect_format(input_path);
+    let format = match source_format {
+        _ if target_bytes.is_some() => ImageFormat::Jpeg,
+        Some(ImageFormat::WebP) if !sniff::is_lossless_webp(input_path) => {
+            if img.color().has_alpha() { ImageFormat::Png } else { ImageFormat::Jpeg }
+        }
+        Some(format) if format.writing_enabled() => format,
+        _ => ImageFormat::Png,
+    };
+    let extension = format.extensions_str().first().copied().unwrap_or("png");
+    if source_format != Some(format) {
+        notes.push(format!("Saved as {}", extension.to_uppercase()));
+    }
+    
+    // Simple mode has no encoder to name, so a format tag is the extension itself
+    let format_tag = if options.format_in_name { format!("_{}", extension) } else { String::new() };
     let output_path = output_dir.join(format!(
-        "{}_resized.{}",
+        "{}{}{}.{}",
         input_path.file_stem().unwrap().to_string_lossy(),
-        input_path.extension().unwrap_or_default().to_string_lossy()
+        options.suffix,
+        format_tag,
+        extension
     ));
+    // A print size tags the JPEG or PNG with the DPI it was sized for, before the
+    // target size search measures it
+    let dpi = options.physical_size.map(|size| size.dpi);
+    let store = |data: &[u8]| store_output(input_path, &output_path, data, options);
     
-    if target_size_kb.is_none() {
-        match img.save(&output_path) {
-            Ok(_) => {
-                let new_size = fs::metadata(&output_path).map(|m| m.len()).unwrap_or(0);
-                InternalResult {
-                    original_size,
-                    new_size,
-                    success: true,
-                    message: String::new(),
-                }
-            }
+    if target_bytes.is_none() {
+        match save_image(&img, format, dpi, store) {
+            Ok((new_size, stored_path)) => InternalResult {
+                original_size,
+                new_size,
+                success: true,
+                status: ResultStatus::Processed,
+                message: notes.join(", "),
+                output_path: Some(stored_path),
+            },
             Err(e) => InternalResult {
                 original_size,
                 new_size: 0,
                 success: false,
-                message: format!("Save failed: {}", e),
+                status: ResultStatus::Processed,
+                message: stage_error(Stage::Write, &*e),
+                output_path: None,
             },
         }
     } else {
-        match compress_to_size(img, target_size_kb.unwrap(), &output_path, auto_scale) {
-            Ok(new_size) => InternalResult {
+        match compress_to_size(img, target_bytes.unwrap(), auto_scale, dpi, store) {
+            Ok((new_size, stored_path)) => InternalResult {
                 original_size,
                 new_size,
                 success: true,
-                message: String::new(),
+                status: ResultStatus::Processed,
+                message: notes.join(", "),
+                output_path: Some(stored_path),
             },
             Err(e) => InternalResult {
                 original_size,
                 new_size: 0,
                 success: false,
-                message: e.to_string(),
+                status: ResultStatus::Processed,
+                message: stage_error(Stage::Encode, &*e),
+                output_path: None,
             },
         }
     }
@@ -175,19 +209,21 @@ pub fn process_single_image(
 
 fn compress_to_size(
     mut img: DynamicImage,
-    target_kb: u64,
-    output_path: &Path,
-	auto_scale: bool
-) -> Result<u64, Box<dyn std::error::Error>> {
-    let target_bytes = target_kb * 1024;
+    target_bytes: u64,
+	auto_scale: bool,
+    dpi: Option<u16>,
+    store: impl Fn(&[u8]) -> std::io::Result<PathBuf>,
+) -> Result<(u64, PathBuf), Box<dyn std::error::Error>> {
     let format = ImageFormat::Jpeg;
     
     for quality in (20..=95).rev().step_by(5) {
-        let buffer = save_to_buffer(&img, format, quality)?;
+        let buffer = save_to_buffer(&img, format, quality, dpi)?;
+        trace!("simple target search: quality {} → {} bytes (target {})", quality, buffer.len(), target_bytes);
         
         if buffer.len() <= target_bytes as usize {
-            fs::write(output_path, &buffer)?;
-            return Ok(buffer.len() as u64);
+            debug!("simple target search: settled on quality {} at {} bytes", quality, buffer.len());
+            let stored_path = store(&buffer)?;
+            return Ok((buffer.len() as u64, stored_path));
         }
     }
     
@@ -198,11 +234,13 @@ fn compress_to_size(
 			let new_height = (img.height() as f32 * scale) as u32;
 			img = img.resize(new_width, new_height, image::imageops::FilterType::Lanczos3);
 			
-			let buffer = save_to_buffer(&img, format, 75)?;
+			let buffer = save_to_buffer(&img, format, 75, dpi)?;
+			trace!("simple auto-scale: {}×{} → {} bytes (target {})", new_width, new_height, buffer.len(), target_bytes);
 			
 			if buffer.len() <= target_bytes as usize {
-				fs::write(output_path, &buffer)?;
-				return Ok(buffer.len() as u64);
+				debug!("simple auto-scale: settled on {}×{} at {} bytes", new_width, new_height, buffer.len());
+				let stored_path = store(&buffer)?;
+				return Ok((buffer.len() as u64, stored_path));
 			}
 			
 			scale *= 0.9;
@@ -212,22 +250,121 @@ fn compress_to_size(
     Err("Could not achieve target file size".into())
 }
 
+// Encodes like DynamicImage::save, but hands the data to `store`, which writes it
+// atomically or into the ZIP archive
+fn save_image(
+    img: &DynamicImage,
+    format: ImageFormat,
+    dpi: Option<u16>,
+    store: impl Fn(&[u8]) -> std::io::Result<PathBuf>,
+) -> Result<(u64, PathBuf), Box<dyn std::error::Error>> {
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, format)?;
+    let data = tag_dpi(buffer.into_inner(), format, dpi);
+    let stored_path = store(&data)?;
+    Ok((data.len() as u64, stored_path))
+}
+
 fn save_to_buffer(
     img: &DynamicImage,
     format: ImageFormat,
     quality: u8,
+    dpi: Option<u16>,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let mut buffer = Cursor::new(Vec::new());
     
     match format {
         ImageFormat::Jpeg => {
+            // The JPEG encoder refuses alpha, so transparency goes onto white
             let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, quality);
-            img.write_with_encoder(encoder)?;
+            DynamicImage::ImageRgb8(compression::flatten_alpha(img, [255, 255, 255])).write_with_encoder(encoder)?;
         }
         _ => {
             img.write_to(&mut buffer, format)?;
         }
     }
     
-    Ok(buffer.into_inner())
+    Ok(tag_dpi(buffer.into_inner(), format, dpi))
+}
+
+fn tag_dpi(data: Vec<u8>, format: ImageFormat, dpi: Option<u16>) -> Vec<u8> {
+    match dpi {
+        Some(dpi) => density::set_dpi(data, format, dpi),
+        None => data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+    
+    fn photo() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(48, 32, |x, y| Rgb([(x * 5) as u8, (y * 7) as u8, 120])))
+    }
+    
+    // Runs a WebP through simple mode into a folder of its own, returning the result
+    // and the output's extension
+    fn process_webp(name: &str, data: &[u8]) -> (InternalResult, String) {
+        let dir = std::env::temp_dir().join(format!("image-resizer-simple-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input_path = dir.join("photo.webp");
+        fs::write(&input_path, data).unwrap();
+        let options = ProcessingOptions {
+            output_dir: Some(dir.join("out")),
+            suffix: String::from("_resized"),
+            ..ProcessingOptions::default()
+        };
+        let result = process_single_image(&input_path, None, Some((24, 16)), true, false, &options);
+        let extension = result.output_path.as_ref()
+            .and_then(|path| path.extension())
+            .map(|extension| extension.to_string_lossy().to_string())
+            .unwrap_or_default();
+        fs::remove_dir_all(&dir).unwrap();
+        (result, extension)
+    }
+    
+    #[test]
+    fn lossless_webp_stays_webp() {
+        let mut data = Cursor::new(Vec::new());
+        photo().write_to(&mut data, ImageFormat::WebP).unwrap();
+        let (result, extension) = process_webp("lossless", &data.into_inner());
+        assert!(result.success, "{}", result.message);
+        assert_eq!(extension, "webp");
+    }
+    
+    #[cfg(feature = "webp")]
+    #[test]
+    fn lossy_webp_is_saved_as_jpeg() {
+        let rgb = photo().to_rgb8();
+        let data = webp::Encoder::from_rgb(rgb.as_raw(), rgb.width(), rgb.height()).encode(80.0).to_vec();
+        let (result, extension) = process_webp("lossy", &data);
+        assert!(result.success, "{}", result.message);
+        assert_eq!(extension, "jpg");
+        assert!(result.message.contains("Saved as JPG"), "{}", result.message);
+    }
+    
+    // The size reported, and the one a target is checked against, includes the tag
+    #[test]
+    fn print_size_is_tagged_before_it_is_measured() {
+        let dir = std::env::temp_dir().join(format!("image-resizer-simple-dpi-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input_path = dir.join("photo.png");
+        photo().save(&input_path).unwrap();
+        let options = ProcessingOptions {
+            output_dir: Some(dir.join("out")),
+            suffix: String::from("_resized"),
+            physical_size: Some(density::PhysicalSize { width: 0.24, height: 0.16, unit: density::PrintUnit::Inches, dpi: 100 }),
+            ..ProcessingOptions::default()
+        };
+        
+        for target_bytes in [None, Some(100_000)] {
+            let result = process_single_image(&input_path, target_bytes, None, true, false, &options);
+            assert!(result.success, "{}", result.message);
+            let data = fs::read(result.output_path.unwrap()).unwrap();
+            assert_eq!(result.new_size, data.len() as u64);
+            assert_eq!(density::set_dpi(data.clone(), image::guess_format(&data).unwrap(), 100), data);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
\ No newline at end of file
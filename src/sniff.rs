@@ -0,0 +1,148 @@
+// sniff.rs - Image formats from file content instead of the extension
+//
+// A PNG saved as .jpg or an image without any extension is classified by its
+// magic bytes. When the content can't be read or isn't recognised, the extension
+// is still used, so a corrupt ".jpg" is reported as a failure rather than skipped.
+
+use image::ImageFormat;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Long enough for every signature image knows, including AVIF's ftyp box
+const HEADER_LEN: usize = 32;
+
+// The format the file's bytes say it is; None for unreadable or unknown content
+pub fn sniff_format(path: &Path) -> Option<ImageFormat> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    File::open(path).ok()?.take(HEADER_LEN as u64).read_to_end(&mut header).ok()?;
+
+    // image treats any RIFF container as WebP; WAV and AVI files are RIFF too
+    if header.starts_with(b"RIFF") && header.get(8..12) != Some(b"WEBP".as_slice()) {
+        return None;
+    }
+    // "BM" alone matches plenty of text files; the DIB header size pins down a bitmap
+    if header.starts_with(b"BM") {
+        let dib_size = header.get(14..18).map(|size| u32::from_le_bytes([size[0], size[1], size[2], size[3]]));
+        if !matches!(dib_size, Some(12 | 40 | 52 | 56 | 64 | 108 | 124)) {
+            return None;
+        }
+    }
+    image::guess_format(&header).ok()
+}
+
+// Content first, then the extension
+pub fn detect_format(path: &Path) -> Option<ImageFormat> {
+    sniff_format(path).or_else(|| ImageFormat::from_path(path).ok())
+}
+
+pub fn is_format(path: &Path, format: ImageFormat) -> bool {
+    detect_format(path) == Some(format)
+}
+
+// Extension matching the file's real format, for outputs that keep the source
+// format; the file's own (lowercased) extension when the format is unknown
+pub fn true_extension(path: &Path) -> String {
+    match detect_format(path).and_then(|format| format.extensions_str().first()) {
+        Some(extension) => extension.to_string(),
+        None => path.extension().unwrap_or_default().to_string_lossy().to_lowercase(),
+    }
+}
+
+
+// Whether a WebP holds VP8L (lossless) rather than VP8 image data; for an animation,
+// what its first frame holds. False for anything that isn't a readable WebP.
+pub fn is_lossless_webp(path: &Path) -> bool {
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+    let mut header = [0u8; 12];
+    if file.read_exact(&mut header).is_err() || &header[0..4] != b"RIFF" || &header[8..12] != b"WEBP" {
+        return false;
+    }
+    
+    let mut chunk = [0u8; 8];
+    while file.read_exact(&mut chunk).is_ok() {
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as i64;
+        let skip = match &chunk[0..4] {
+            b"VP8L" => return true,
+            b"VP8 " => return false,
+            // The frame's own header, then its image chunks
+            b"ANMF" => 16,
+            // Chunks are padded to an even size
+            _ => size + (size & 1),
+        };
+        if file.seek(SeekFrom::Current(skip)).is_err() {
+            return false;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbImage};
+    use std::io::Cursor;
+    use std::path::PathBuf;
+    
+    fn encoded(format: ImageFormat) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(RgbImage::new(4, 4)).write_to(&mut data, format).unwrap();
+        data.into_inner()
+    }
+    
+    // Writes `data` under a name unique to this test run; removed again when dropped
+    struct TempFile(PathBuf);
+    
+    impl TempFile {
+        fn new(name: &str, data: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("image-resizer-sniff-{}-{}", std::process::id(), name));
+            std::fs::write(&path, data).unwrap();
+            Self(path)
+        }
+    }
+    
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+    
+    #[test]
+    fn png_named_jpg_is_a_png() {
+        let file = TempFile::new("mislabeled.jpg", &encoded(ImageFormat::Png));
+        assert_eq!(detect_format(&file.0), Some(ImageFormat::Png));
+        assert_eq!(true_extension(&file.0), "png");
+    }
+    
+    #[test]
+    fn jpeg_named_png_is_a_jpeg() {
+        let file = TempFile::new("mislabeled.png", &encoded(ImageFormat::Jpeg));
+        assert!(is_format(&file.0, ImageFormat::Jpeg));
+        assert_eq!(true_extension(&file.0), "jpg");
+    }
+    
+    #[test]
+    fn extensionless_image_is_recognised() {
+        let file = TempFile::new("no-extension", &encoded(ImageFormat::Png));
+        assert_eq!(detect_format(&file.0), Some(ImageFormat::Png));
+        assert!(crate::is_image_file(&file.0));
+    }
+    
+    #[test]
+    fn riff_that_isnt_webp_is_not_an_image() {
+        let mut wav = b"RIFF\x24\0\0\0WAVEfmt ".to_vec();
+        wav.resize(44, 0);
+        let file = TempFile::new("sound.wav", &wav);
+        assert_eq!(sniff_format(&file.0), None);
+        assert!(!crate::is_image_file(&file.0));
+    }
+    
+    #[test]
+    fn unreadable_content_falls_back_to_the_extension() {
+        let file = TempFile::new("text.jpg", b"not an image at all");
+        assert_eq!(sniff_format(&file.0), None);
+        assert_eq!(detect_format(&file.0), Some(ImageFormat::Jpeg));
+    }
+}
\ No newline at end of file
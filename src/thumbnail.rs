@@ -0,0 +1,90 @@
+// thumbnail.rs - Small previews embedded in JPEG and WebP output
+//
+// Gallery and asset-management software reads the preview from EXIF IFD1 instead
+// of decoding the whole image. The preview is a baseline JPEG whose longest edge is
+// THUMBNAIL_EDGE, written into a minimal EXIF block: an APP1 segment in JPEGs and
+// an EXIF chunk in WebP. The main image data is copied through untouched.
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{ImageFormat, RgbImage};
+
+use crate::{icc, metadata};
+
+pub const THUMBNAIL_EDGE: u32 = 160;
+const THUMBNAIL_QUALITY: u8 = 75;
+const EXIF_MARKER: &[u8; 6] = b"Exif\0\0";
+// A JPEG APP1 segment holds at most this much after its length field
+const MAX_EXIF_LEN: usize = 65_535 - 2 - EXIF_MARKER.len();
+
+// Upper bound on what embedding adds besides the EXIF block itself, which target
+// size searches leave room for
+pub const EMBED_OVERHEAD: u64 = 32;
+
+// Formats with a place for an embedded thumbnail
+pub fn supports(format: ImageFormat) -> bool {
+    matches!(format, ImageFormat::Jpeg | ImageFormat::WebP)
+}
+
+// The preview, already scaled to THUMBNAIL_EDGE, as a little-endian TIFF structure:
+// IFD0 with the orientation, IFD1 describing the JPEG thumbnail stored right after
+// it. None if it wouldn't fit.
+pub fn exif_thumbnail(preview: &RgbImage) -> Option<Vec<u8>> {
+    let mut jpeg = Vec::new();
+    preview
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, THUMBNAIL_QUALITY))
+        .ok()?;
+
+    // Header, IFD0 with 1 entry, IFD1 with 6 entries, two resolution rationals
+    const IFD0: u32 = 8;
+    const IFD1: u32 = IFD0 + 2 + 12 + 4;
+    const RESOLUTION: u32 = IFD1 + 2 + 6 * 12 + 4;
+    const THUMBNAIL: u32 = RESOLUTION + 16;
+
+    let mut exif = b"II*\0".to_vec();
+    exif.extend_from_slice(&IFD0.to_le_bytes());
+
+    exif.extend_from_slice(&1u16.to_le_bytes());
+    write_entry(&mut exif, 0x0112, 3, 1); // Orientation: the pixels are already upright
+    exif.extend_from_slice(&IFD1.to_le_bytes());
+
+    exif.extend_from_slice(&6u16.to_le_bytes());
+    write_entry(&mut exif, 0x0103, 3, 6); // Compression: JPEG
+    write_entry(&mut exif, 0x011A, 5, RESOLUTION); // XResolution
+    write_entry(&mut exif, 0x011B, 5, RESOLUTION + 8); // YResolution
+    write_entry(&mut exif, 0x0128, 3, 2); // ResolutionUnit: inch
+    write_entry(&mut exif, 0x0201, 4, THUMBNAIL); // JPEGInterchangeFormat
+    write_entry(&mut exif, 0x0202, 4, jpeg.len() as u32); // JPEGInterchangeFormatLength
+    exif.extend_from_slice(&0u32.to_le_bytes());
+
+    for _ in 0..2 {
+        exif.extend_from_slice(&72u32.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+    }
+    exif.extend_from_slice(&jpeg);
+
+    (exif.len() <= MAX_EXIF_LEN).then_some(exif)
+}
+
+// One count-1 IFD entry; SHORT values sit in the low bytes of the value field
+fn write_entry(exif: &mut Vec<u8>, tag: u16, field_type: u16, value: u32) {
+    exif.extend_from_slice(&tag.to_le_bytes());
+    exif.extend_from_slice(&field_type.to_le_bytes());
+    exif.extend_from_slice(&1u32.to_le_bytes());
+    exif.extend_from_slice(&value.to_le_bytes());
+}
+
+// Writes the EXIF block into `data`, replacing any already there. Formats without
+// a thumbnail slot and data that doesn't parse are returned unchanged.
+pub fn embed_thumbnail(data: Vec<u8>, format: ImageFormat, exif: &[u8]) -> Vec<u8> {
+    match format {
+        ImageFormat::Jpeg => embed_jpeg(data, exif),
+        ImageFormat::WebP => icc::set_webp_chunk(data, b"EXIF", 0x08, exif),
+        _ => data,
+    }
+}
+
+// An APP1 segment after SOI and the JFIF header, in place of any EXIF block the
+// encoder or the preserved metadata wrote
+fn embed_jpeg(data: Vec<u8>, exif: &[u8]) -> Vec<u8> {
+    metadata::set_jpeg_segment(data, 0xE1, EXIF_MARKER, exif)
+}
@@ -0,0 +1,168 @@
+// watermark.rs - A line of text or an overlay image stamped onto each output
+//
+// The mark goes on after the pixel steps, so it has the same size and margin on
+// every output whatever the size of its source. Text is drawn in a sans-serif
+// face from the system fonts; an overlay keeps its own colors and alpha.
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Position {
+    TopLeft,
+    TopRight,
+    Center,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl Position {
+    pub const ALL: [Position; 5] = [
+        Position::TopLeft,
+        Position::TopRight,
+        Position::Center,
+        Position::BottomLeft,
+        Position::BottomRight,
+    ];
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TopLeft => write!(f, "Top left"),
+            Self::TopRight => write!(f, "Top right"),
+            Self::Center => write!(f, "Center"),
+            Self::BottomLeft => write!(f, "Bottom left"),
+            Self::BottomRight => write!(f, "Bottom right"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Mark {
+    // Size is the line height in pixels
+    Text { text: String, font: Arc<FontVec>, size: f32, color: [u8; 3] },
+    Image(Arc<RgbaImage>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    pub mark: Mark,
+    pub position: Position,
+    // Pixels between the mark and the edges it's placed against; ignored for Center
+    pub margin: u32,
+    // 0.0 (invisible) to 1.0, on top of the mark's own alpha
+    pub opacity: f32,
+}
+
+// The first of the usual UI sans-serif faces that's installed, or else any face at all
+pub fn system_font() -> Result<FontVec, String> {
+    let mut db = fontdb::Database::new();
+    db.load_system_fonts();
+    let families = [
+        fontdb::Family::SansSerif,
+        fontdb::Family::Name("Segoe UI"),
+        fontdb::Family::Name("Helvetica"),
+        fontdb::Family::Name("DejaVu Sans"),
+        fontdb::Family::Name("Liberation Sans"),
+        fontdb::Family::Name("Noto Sans"),
+    ];
+    let id = db.query(&fontdb::Query { families: &families, ..fontdb::Query::default() })
+        .or_else(|| db.faces().next().map(|face| face.id))
+        .ok_or("no system font found")?;
+    db.with_face_data(id, |data, index| FontVec::try_from_vec_and_index(data.to_vec(), index))
+        .ok_or("the system font couldn't be read")?
+        .map_err(|e| e.to_string())
+}
+
+// Composites the mark over the image, keeping its color type. A mark that doesn't
+// fit inside the margins is scaled down until it does.
+pub fn apply(mut img: DynamicImage, watermark: &Watermark) -> DynamicImage {
+    let mut stamp = match &watermark.mark {
+        Mark::Text { text, font, size, color } => match render_text(text, font, *size, *color) {
+            Some(stamp) => stamp,
+            None => return img,
+        },
+        Mark::Image(overlay) => overlay.as_ref().clone(),
+    };
+    
+    let margin = if watermark.position == Position::Center { 0 } else { watermark.margin };
+    let room_width = img.width().saturating_sub(2 * margin);
+    let room_height = img.height().saturating_sub(2 * margin);
+    if room_width == 0 || room_height == 0 {
+        return img;
+    }
+    if stamp.width() > room_width || stamp.height() > room_height {
+        let scale = (room_width as f64 / stamp.width() as f64).min(room_height as f64 / stamp.height() as f64);
+        let width = ((stamp.width() as f64 * scale).round() as u32).max(1);
+        let height = ((stamp.height() as f64 * scale).round() as u32).max(1);
+        stamp = imageops::resize(&stamp, width, height, imageops::FilterType::Triangle);
+    }
+    
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+    for pixel in stamp.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+    }
+    
+    let (x, y) = place(watermark.position, (img.width(), img.height()), (stamp.width(), stamp.height()), margin);
+    imageops::overlay(&mut img, &stamp, x, y);
+    img
+}
+
+// One line of text in its color, with the glyph coverage as alpha. None when
+// nothing in the text has an outline.
+fn render_text(text: &str, font: &FontVec, size: f32, color: [u8; 3]) -> Option<RgbaImage> {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(scaled.scale(), point(caret, scaled.ascent())));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    
+    let width = caret.ceil() as u32;
+    let height = scaled.height().ceil() as u32;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let mut stamp = RgbaImage::from_pixel(width, height, Rgba([color[0], color[1], color[2], 0]));
+    let mut drawn = false;
+    for glyph in glyphs {
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|x, y, coverage| {
+            let x = bounds.min.x as i64 + x as i64;
+            let y = bounds.min.y as i64 + y as i64;
+            if x >= 0 && y >= 0 && x < width as i64 && y < height as i64 {
+                let alpha = &mut stamp.get_pixel_mut(x as u32, y as u32)[3];
+                *alpha = (*alpha).max((coverage.min(1.0) * 255.0).round() as u8);
+            }
+        });
+        drawn = true;
+    }
+    drawn.then_some(stamp)
+}
+
+fn place(position: Position, canvas: (u32, u32), stamp: (u32, u32), margin: u32) -> (i64, i64) {
+    let left = margin as i64;
+    let top = margin as i64;
+    let right = canvas.0 as i64 - stamp.0 as i64 - margin as i64;
+    let bottom = canvas.1 as i64 - stamp.1 as i64 - margin as i64;
+    match position {
+        Position::TopLeft => (left, top),
+        Position::TopRight => (right, top),
+        Position::Center => ((canvas.0 as i64 - stamp.0 as i64) / 2, (canvas.1 as i64 - stamp.1 as i64) / 2),
+        Position::BottomLeft => (left, bottom),
+        Position::BottomRight => (right, bottom),
+    }
+}
\ No newline at end of file